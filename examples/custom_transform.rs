@@ -0,0 +1,72 @@
+//! Per-read logic of one's own, counted without forking seqtable: a
+//! `ReadTransform` keeping the barcode between two anchors, and leaving out
+//! reads that lack either. Options after the anchors are seqtable's own
+//! (`--skip-errors 10`, `--orient ref.fa`, ...).
+//!
+//! ```bash
+//! cargo run --release --example custom_transform -- reads.fq.gz CACG TGCA
+//! ```
+
+use anyhow::{Context, Result};
+use seqtable::{CountOptions, Read, ReadTransform, Stage, count_file};
+use std::borrow::Cow;
+
+/// The bases between the first `left` anchor and the `right` one after it
+struct BetweenAnchors {
+    left: Vec<u8>,
+    right: Vec<u8>,
+}
+
+impl BetweenAnchors {
+    fn barcode<'s>(&self, seq: &'s [u8]) -> Option<&'s [u8]> {
+        let start = find(seq, &self.left)? + self.left.len();
+        let end = start + find(&seq[start..], &self.right)?;
+        Some(&seq[start..end])
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+impl ReadTransform for BetweenAnchors {
+    fn stage(&self) -> Stage {
+        Stage::Extract
+    }
+
+    fn apply<'s>(&'s self, read: &mut Read<'s>, _tally: bool) -> bool {
+        read.map(|seq| self.barcode(seq).map(Cow::Borrowed))
+    }
+}
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let usage = "usage: custom_transform READS LEFT_ANCHOR RIGHT_ANCHOR [OPTIONS...]";
+    let (reads, left, right) = match (args.next(), args.next(), args.next()) {
+        (Some(reads), Some(left), Some(right)) => (reads, left, right),
+        _ => anyhow::bail!(usage),
+    };
+
+    let options = CountOptions::from_args(args)?.with_transform(BetweenAnchors {
+        left: left.into_bytes(),
+        right: right.into_bytes(),
+    });
+    let pool = rayon::ThreadPoolBuilder::new()
+        .build()
+        .context("Failed to initialize thread pool")?;
+    let counts = count_file(reads.as_ref(), &options, &pool)?;
+
+    let mut rows: Vec<_> = counts.iter().collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (barcode, count) in rows {
+        println!("{}\t{}", barcode, count);
+    }
+    eprintln!(
+        "{} barcodes from {} reads",
+        counts.len(),
+        counts.total_reads()
+    );
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use anyhow::{Result, bail};
+use std::path::Path;
+
+/// NUMA nodes covered by the interleave mask
+#[cfg(target_os = "linux")]
+const MAX_NODES: usize = 1024;
+
+/// `set_mempolicy` mode spreading pages round-robin over the given nodes
+#[cfg(target_os = "linux")]
+const MPOL_INTERLEAVE: libc::c_int = 3;
+
+/// CPUs this process may run on, grouped by NUMA node so that consecutive
+/// workers share a node, and how many nodes they span
+pub struct CpuLayout {
+    pub cpus: Vec<usize>,
+    pub nodes: usize,
+}
+
+impl CpuLayout {
+    #[cfg(target_os = "linux")]
+    pub fn detect() -> Result<Self> {
+        // Safety: a zeroed cpu_set_t is an empty set, filled in by the kernel
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        if unsafe { libc::sched_getaffinity(0, size, &mut set) } != 0 {
+            bail!(
+                "Failed to read the CPU affinity: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        let allowed: Vec<usize> = (0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+            .collect();
+
+        // Without NUMA information every CPU counts as one node
+        let node_cpus = online_nodes()
+            .into_iter()
+            .filter_map(|node| {
+                let path = format!("/sys/devices/system/node/node{}/cpulist", node);
+                read_list(Path::new(&path))
+            })
+            .collect::<Vec<_>>();
+        let mut cpus = Vec::with_capacity(allowed.len());
+        let mut nodes = 0;
+        for node in &node_cpus {
+            let before = cpus.len();
+            cpus.extend(node.iter().filter(|cpu| allowed.contains(cpu)));
+            if cpus.len() > before {
+                nodes += 1;
+            }
+        }
+        // CPUs sysfs left out of every node go last
+        for &cpu in &allowed {
+            if !cpus.contains(&cpu) {
+                cpus.push(cpu);
+            }
+        }
+        Ok(CpuLayout {
+            cpus,
+            nodes: nodes.max(1),
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn detect() -> Result<Self> {
+        bail!("--pin-threads is only supported on Linux")
+    }
+
+    /// The CPU for worker `index`, wrapping around when there are more
+    /// workers than CPUs
+    pub fn cpu_for(&self, index: usize) -> usize {
+        self.cpus[index % self.cpus.len()]
+    }
+}
+
+/// Restrict the calling thread to `cpu`. Failure only costs locality, so it
+/// is ignored.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpu: usize) {
+    // Safety: the set is initialized before use and outlives the call
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpu: usize) {}
+
+/// Spread the memory of the calling thread and of the threads it starts
+/// afterwards over all NUMA nodes, so maps shared by every worker are not
+/// placed on one node. Returns the number of nodes interleaved over.
+#[cfg(target_os = "linux")]
+pub fn interleave_memory() -> Result<usize> {
+    let nodes: Vec<usize> = online_nodes()
+        .into_iter()
+        .filter(|&node| node < MAX_NODES)
+        .collect();
+    if nodes.is_empty() {
+        bail!("--numa-interleave found no NUMA nodes in /sys/devices/system/node");
+    }
+    let mut mask = [0u64; MAX_NODES / 64];
+    for &node in &nodes {
+        mask[node / 64] |= 1 << (node % 64);
+    }
+    // Safety: the mask holds `MAX_NODES` bits, as passed
+    let status = unsafe {
+        libc::syscall(
+            libc::SYS_set_mempolicy,
+            MPOL_INTERLEAVE,
+            mask.as_ptr(),
+            MAX_NODES as libc::c_ulong,
+        )
+    };
+    if status != 0 {
+        bail!(
+            "Failed to interleave memory across NUMA nodes: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(nodes.len())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn interleave_memory() -> Result<usize> {
+    bail!("--numa-interleave is only supported on Linux")
+}
+
+/// Online NUMA node numbers; empty if the kernel reports none
+#[cfg(target_os = "linux")]
+fn online_nodes() -> Vec<usize> {
+    read_list(Path::new("/sys/devices/system/node/online")).unwrap_or_default()
+}
+
+/// A sysfs list such as `0-3,8-11`
+#[cfg(target_os = "linux")]
+fn read_list(path: &Path) -> Option<Vec<usize>> {
+    let text = std::fs::read_to_string(path).ok()?;
+    let mut values = Vec::new();
+    for part in text.trim().split(',').filter(|part| !part.is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => values.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => values.push(part.parse().ok()?),
+        }
+    }
+    Some(values)
+}
@@ -0,0 +1,302 @@
+use crate::bases::reverse_complement;
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+/// A PCR primer, IUPAC degenerate bases allowed (`GTGYCAGCMGCCGCGGTAA`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Primer {
+    /// Bases the primer accepts at each position, as `base_mask` bits
+    masks: Vec<u8>,
+    /// The primer in upper case, for messages and metadata
+    text: String,
+}
+
+/// Bit per base a position matches: A, C, G, T
+fn base_mask(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'A' => 0b0001,
+        b'C' => 0b0010,
+        b'G' => 0b0100,
+        b'T' | b'U' => 0b1000,
+        _ => 0,
+    }
+}
+
+/// Bases of an IUPAC code, or `None` for a character that is not one
+fn iupac_mask(code: u8) -> Option<u8> {
+    Some(match code {
+        b'A' | b'C' | b'G' | b'T' | b'U' => base_mask(code),
+        b'R' => 0b0101,
+        b'Y' => 0b1010,
+        b'S' => 0b0110,
+        b'W' => 0b1001,
+        b'K' => 0b1100,
+        b'M' => 0b0011,
+        b'B' => 0b1110,
+        b'D' => 0b1101,
+        b'H' => 0b1011,
+        b'V' => 0b0111,
+        b'N' => 0b1111,
+        _ => return None,
+    })
+}
+
+/// The bases pairing with those of `mask` (A with T, C with G)
+fn complement_mask(mask: u8) -> u8 {
+    (mask & 0b0001) << 3 | (mask & 0b0010) << 1 | (mask & 0b0100) >> 1 | (mask & 0b1000) >> 3
+}
+
+impl FromStr for Primer {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim().to_ascii_uppercase();
+        if text.is_empty() {
+            return Err("primer is empty".to_string());
+        }
+        let masks = text
+            .bytes()
+            .map(|code| {
+                iupac_mask(code).ok_or_else(|| format!("'{}' is not an IUPAC base", code as char))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Primer { masks, text })
+    }
+}
+
+impl fmt::Display for Primer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// A primer pair as it reads on both strands
+struct Pair {
+    name: String,
+    forward: Vec<u8>,
+    reverse: Vec<u8>,
+    /// The primers as they read on the opposite strand
+    forward_rc: Vec<u8>,
+    reverse_rc: Vec<u8>,
+}
+
+impl Pair {
+    fn new(name: &str, forward: &Primer, reverse: &Primer) -> Self {
+        let rc = |masks: &[u8]| masks.iter().rev().map(|&m| complement_mask(m)).collect();
+        Self {
+            name: name.to_string(),
+            forward_rc: rc(&forward.masks),
+            reverse_rc: rc(&reverse.masks),
+            forward: forward.masks.clone(),
+            reverse: reverse.masks.clone(),
+        }
+    }
+}
+
+/// Primer pairs whose inserts are counted in place of the read
+/// (`--amplicon`): one given on the command line, or a named panel
+pub struct Amplicons {
+    pairs: Vec<Pair>,
+    max_mismatches: u32,
+    /// Loaded from a panel, so counts are keyed by amplicon name too
+    named: bool,
+    /// The primers (`FWD,REV`) or panel file, for metadata
+    text: String,
+}
+
+impl Amplicons {
+    pub fn new(forward: &Primer, reverse: &Primer, max_mismatches: u32) -> Self {
+        Self {
+            pairs: vec![Pair::new("", forward, reverse)],
+            max_mismatches,
+            named: false,
+            text: format!("{},{}", forward, reverse),
+        }
+    }
+
+    /// Read a panel: `NAME FORWARD REVERSE` per line (whitespace-separated;
+    /// blank lines and `#` comments are skipped)
+    pub fn load(path: &Path, max_mismatches: u32) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open primer panel: {}", path.display()))?;
+
+        let mut pairs: Vec<Pair> = Vec::new();
+        for (line_no, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.context("Failed to read primer panel")?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [name, forward, reverse] = fields[..] else {
+                anyhow::bail!(
+                    "{}:{}: expected `NAME FORWARD REVERSE`",
+                    path.display(),
+                    line_no + 1
+                );
+            };
+            let primer = |text: &str| {
+                text.parse::<Primer>()
+                    .map_err(|err| anyhow::anyhow!("{}:{}: {}", path.display(), line_no + 1, err))
+            };
+            if pairs.iter().any(|pair| pair.name == name) {
+                anyhow::bail!(
+                    "{}:{}: amplicon '{}' is listed twice",
+                    path.display(),
+                    line_no + 1,
+                    name
+                );
+            }
+            pairs.push(Pair::new(name, &primer(forward)?, &primer(reverse)?));
+        }
+        if pairs.is_empty() {
+            anyhow::bail!("No primer pairs in {}", path.display());
+        }
+
+        Ok(Self {
+            pairs,
+            max_mismatches,
+            named: true,
+            text: path.display().to_string(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Whether counts are keyed by amplicon name as well as insert
+    pub fn named(&self) -> bool {
+        self.named
+    }
+
+    /// The name of the first pair found in the read, in panel order, and the
+    /// bases between its primers, in the forward primer's orientation; `None`
+    /// unless both primers of a pair are found in order on one strand, with
+    /// bases between them
+    pub fn insert<'s>(&self, seq: &'s [u8]) -> Option<(&str, Cow<'s, [u8]>)> {
+        self.pairs.iter().find_map(|pair| {
+            if let Some(insert) = self.between(seq, &pair.forward, &pair.reverse_rc) {
+                return Some((pair.name.as_str(), Cow::Borrowed(insert)));
+            }
+            // Reads from the other end of the fragment start with the reverse primer
+            let insert = self.between(seq, &pair.reverse, &pair.forward_rc)?;
+            Some((pair.name.as_str(), Cow::Owned(reverse_complement(insert))))
+        })
+    }
+
+    /// Bases after the first match of `lead` and before the last match of
+    /// `trail` following it
+    fn between<'s>(&self, seq: &'s [u8], lead: &[u8], trail: &[u8]) -> Option<&'s [u8]> {
+        let positions = seq.len().checked_sub(lead.len())?;
+        let start = (0..=positions).find(|&at| self.matches(&seq[at..], lead))? + lead.len();
+        let last = seq.len().checked_sub(trail.len())?;
+        let end = (start..=last)
+            .rev()
+            .find(|&at| self.matches(&seq[at..], trail))?;
+        (start < end).then(|| &seq[start..end])
+    }
+
+    /// Whether `primer` matches the start of `seq` within the mismatches allowed
+    fn matches(&self, seq: &[u8], primer: &[u8]) -> bool {
+        let mut mismatches = 0;
+        for (&base, &mask) in seq.iter().zip(primer) {
+            if base_mask(base) & mask == 0 {
+                mismatches += 1;
+                if mismatches > self.max_mismatches {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+impl fmt::Display for Amplicons {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORWARD: &str = "GTGYCAGCMGCCGCGGTAA";
+    const REVERSE: &str = "GGACTACNVGGGTWTCTAAT";
+    const INSERT: &[u8] = b"TACGGAGGGTGCAAGCGTTAATCGGAATTACTGGGCGTAAAG";
+
+    fn amplicons(max_mismatches: u32) -> Amplicons {
+        Amplicons::new(
+            &FORWARD.parse().unwrap(),
+            &REVERSE.parse().unwrap(),
+            max_mismatches,
+        )
+    }
+
+    /// A read of `insert` between primers resolved at their degenerate
+    /// positions, on the forward strand
+    fn read(insert: &[u8]) -> Vec<u8> {
+        let forward = b"GTGCCAGCAGCCGCGGTAA";
+        let reverse = b"GGACTACAAGGGTATCTAAT";
+        [
+            b"NNAC".as_slice(),
+            forward,
+            insert,
+            &reverse_complement(reverse),
+            b"CTG",
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn degenerate_primers_find_the_insert() {
+        let (amplicons, read) = (amplicons(0), read(INSERT));
+        let (name, insert) = amplicons.insert(&read).unwrap();
+        assert_eq!(name, "");
+        assert_eq!(insert.as_ref(), INSERT);
+    }
+
+    #[test]
+    fn reverse_strand_reads_give_the_forward_insert() {
+        let read = reverse_complement(&read(INSERT));
+        let (_, insert) = amplicons(0).insert(&read).unwrap();
+        assert!(matches!(insert, Cow::Owned(_)));
+        assert_eq!(insert.as_ref(), INSERT);
+    }
+
+    #[test]
+    fn mismatches_count_against_the_allowance() {
+        let mut read = read(INSERT);
+        // Two changes in the forward primer, past the leading bases
+        read[6] = b'T';
+        read[10] = b'T';
+        assert!(amplicons(1).insert(&read).is_none());
+        assert_eq!(amplicons(2).insert(&read).unwrap().1.as_ref(), INSERT);
+    }
+
+    #[test]
+    fn reads_without_both_primers_or_an_insert_are_passed_over() {
+        assert!(amplicons(0).insert(&read(b"")).is_none());
+        let forward_only = [
+            FORWARD.replace('Y', "C").replace('M', "A").as_bytes(),
+            INSERT,
+        ]
+        .concat();
+        assert!(amplicons(0).insert(&forward_only).is_none());
+        assert!(amplicons(0).insert(b"ACGT").is_none());
+    }
+
+    #[test]
+    fn primers_accept_iupac_codes_only() {
+        assert_eq!("acgu".parse::<Primer>().unwrap().to_string(), "ACGU");
+        assert!("ACGX".parse::<Primer>().unwrap_err().contains("'X'"));
+        assert!(" ".parse::<Primer>().is_err());
+    }
+}
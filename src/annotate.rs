@@ -0,0 +1,212 @@
+use crate::arena::ArenaCounts;
+use ahash::{AHashMap, AHashSet};
+use anyhow::{Context, Result};
+use needletail::parse_fastx_file;
+use std::path::Path;
+
+/// Named reference sequences (e.g., miRBase mature miRNAs or spike-ins)
+pub struct Reference {
+    names: AHashMap<String, String>,
+    fuzzy: Option<KmerIndex>,
+}
+
+/// K-mer index over the reference used to find candidates for near matches
+struct KmerIndex {
+    k: usize,
+    max_mismatches: u32,
+    sequences: Vec<String>,
+    /// Keyed by bytes, as references and reads may hold any byte
+    kmers: AHashMap<Vec<u8>, Vec<u32>>,
+}
+
+impl Reference {
+    /// Load a reference FASTA, keyed by uppercase DNA sequence (U → T).
+    /// With `max_mismatches > 0`, sequences within that edit distance are also labeled.
+    pub fn load(path: &Path, max_mismatches: u32) -> Result<Self> {
+        let mut reader = parse_fastx_file(path)
+            .context(format!("Failed to open reference: {}", path.display()))?;
+
+        let mut names: AHashMap<String, String> = AHashMap::new();
+        while let Some(record) = reader.next() {
+            let record = record.context("Failed to read reference record")?;
+            let header = String::from_utf8_lossy(record.id());
+            let name = header.split_whitespace().next().unwrap_or_default();
+            let seq = normalize_sequence(&record.seq());
+
+            // Identical sequences under several names (e.g., paralogous miRNAs) keep all names
+            names
+                .entry(seq)
+                .and_modify(|existing| {
+                    existing.push(';');
+                    existing.push_str(name);
+                })
+                .or_insert_with(|| name.to_string());
+        }
+
+        let fuzzy = (max_mismatches > 0).then(|| KmerIndex::build(&names, max_mismatches));
+
+        Ok(Self { names, fuzzy })
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Total reads whose sequence exactly matches a reference sequence
+    pub fn matched_reads(&self, counts: &ArenaCounts) -> u64 {
+        counts
+            .iter()
+            .filter(|(seq, _)| self.names.contains_key(&normalize_sequence(seq.as_bytes())))
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Reads exactly matching each reference sequence, none for those no
+    /// read matched
+    pub fn sequence_counts(&self, counts: &ArenaCounts) -> Vec<u64> {
+        let mut matched: AHashMap<String, u64> = AHashMap::new();
+        for (seq, count) in counts.iter() {
+            let seq = normalize_sequence(seq.as_bytes());
+            if self.names.contains_key(&seq) {
+                *matched.entry(seq).or_insert(0) += count;
+            }
+        }
+        self.names
+            .keys()
+            .map(|seq| matched.get(seq).copied().unwrap_or(0))
+            .collect()
+    }
+
+    /// Best reference match for a read sequence (in any case, RNA or DNA)
+    pub fn assign(&self, sequence: &[u8]) -> Option<(&str, u32)> {
+        self.lookup(&normalize_sequence(sequence))
+    }
+
+    /// Best reference match and its edit distance
+    fn lookup(&self, sequence: &str) -> Option<(&str, u32)> {
+        if let Some(name) = self.names.get(sequence) {
+            return Some((name, 0));
+        }
+
+        let index = self.fuzzy.as_ref()?;
+        let (best, distance) = index.closest(sequence)?;
+        Some((&self.names[best], distance))
+    }
+}
+
+impl KmerIndex {
+    fn build(names: &AHashMap<String, String>, max_mismatches: u32) -> Self {
+        // Sort for deterministic tie-breaking between equally close references
+        let mut sequences: Vec<String> = names.keys().cloned().collect();
+        sequences.sort_unstable();
+
+        // Pigeonhole: a reference within N edits keeps at least one of its N+1
+        // disjoint segments intact, so k-mers of that length always find it
+        let shortest = sequences.iter().map(String::len).min().unwrap_or(0);
+        let k = (shortest / (max_mismatches as usize + 1)).max(1);
+
+        let mut kmers: AHashMap<Vec<u8>, Vec<u32>> = AHashMap::new();
+        for (id, seq) in sequences.iter().enumerate() {
+            for kmer in seq.as_bytes().windows(k) {
+                let ids = kmers.entry(kmer.to_vec()).or_default();
+                if ids.last() != Some(&(id as u32)) {
+                    ids.push(id as u32);
+                }
+            }
+        }
+
+        Self {
+            k,
+            max_mismatches,
+            sequences,
+            kmers,
+        }
+    }
+
+    fn closest(&self, query: &str) -> Option<(&str, u32)> {
+        if query.len() < self.k {
+            return None;
+        }
+
+        let mut candidates = AHashSet::new();
+        for kmer in query.as_bytes().windows(self.k) {
+            if let Some(ids) = self.kmers.get(kmer) {
+                candidates.extend(ids.iter().copied());
+            }
+        }
+
+        candidates
+            .into_iter()
+            .filter_map(|id| {
+                let reference = &self.sequences[id as usize];
+                bounded_edit_distance(query.as_bytes(), reference.as_bytes(), self.max_mismatches)
+                    .map(|distance| (distance, reference.as_str()))
+            })
+            .min()
+            .map(|(distance, reference)| (reference, distance))
+    }
+}
+
+/// Levenshtein distance, or `None` once it is certain to exceed `max`
+fn bounded_edit_distance(a: &[u8], b: &[u8], max: u32) -> Option<u32> {
+    if a.len().abs_diff(b.len()) > max as usize {
+        return None;
+    }
+
+    let mut previous: Vec<u32> = (0..=b.len() as u32).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, &ca) in a.iter().enumerate() {
+        current[0] = i as u32 + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + u32::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        if current.iter().min().is_some_and(|&m| m > max) {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    let distance = previous[b.len()];
+    (distance <= max).then_some(distance)
+}
+
+/// Uppercase and convert RNA to DNA so reference and reads compare equal
+fn normalize_sequence(seq: &[u8]) -> String {
+    seq.iter()
+        .map(|&b| match b.to_ascii_uppercase() {
+            b'U' => 'T',
+            other => other as char,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(sequences: &[&str], max_mismatches: u32) -> KmerIndex {
+        let names = sequences
+            .iter()
+            .map(|seq| (seq.to_string(), seq.to_string()))
+            .collect();
+        KmerIndex::build(&names, max_mismatches)
+    }
+
+    #[test]
+    fn finds_the_closest_reference() {
+        let index = index(&["ACGTACGTAC", "TTTTGGGGCC"], 1);
+        assert_eq!(index.closest("ACGTTCGTAC"), Some(("ACGTACGTAC", 1)));
+        assert_eq!(index.closest("GGGGGGGGGG"), None);
+    }
+
+    #[test]
+    fn non_ascii_bytes_do_not_panic() {
+        // Each byte past ASCII normalizes to a two-byte character
+        let index = index(&["ACGTACGTAC", "AC\u{ff}GTACGT"], 2);
+        let query = normalize_sequence(b"ACGT\xffCGTAC");
+        assert_eq!(index.closest(&query), Some(("ACGTACGTAC", 2)));
+        assert_eq!(index.closest("AC\u{ff}GTACGT"), Some(("AC\u{ff}GTACGT", 0)));
+    }
+}
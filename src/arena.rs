@@ -0,0 +1,231 @@
+use ahash::RandomState;
+use hashbrown::HashTable;
+
+/// Keys laid end to end in one buffer, so a batch costs two allocations
+/// rather than one per key
+#[derive(Default)]
+pub struct KeyBatch {
+    bytes: Vec<u8>,
+    /// End offset of each key in `bytes`
+    ends: Vec<usize>,
+}
+
+impl KeyBatch {
+    pub fn with_capacity(keys: usize) -> Self {
+        Self {
+            bytes: Vec::new(),
+            ends: Vec::with_capacity(keys),
+        }
+    }
+
+    pub fn push(&mut self, key: &[u8]) {
+        self.bytes.extend_from_slice(key);
+        self.ends.push(self.bytes.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        starts
+            .zip(&self.ends)
+            .map(|(start, &end)| &self.bytes[start..end])
+    }
+}
+
+/// Counts keyed by strings copied into one growing arena. The table holds
+/// only offsets, so 100M distinct keys are not 100M heap allocations and
+/// leave no fragmentation behind when the counter is dropped. Tables are
+/// written from the arena's keys as they are, so it holds only UTF-8.
+pub struct ArenaCounts {
+    hasher: RandomState,
+    arena: String,
+    table: HashTable<Slot>,
+}
+
+struct Slot {
+    start: usize,
+    len: usize,
+    count: u64,
+}
+
+impl ArenaCounts {
+    pub fn new() -> Self {
+        Self {
+            hasher: RandomState::new(),
+            arena: String::new(),
+            table: HashTable::new(),
+        }
+    }
+
+    pub fn add(&mut self, key: &[u8]) {
+        self.add_count(key, 1);
+    }
+
+    /// Keys that are not UTF-8 are counted under replacement characters, so
+    /// they may meet other keys there
+    pub fn add_count(&mut self, key: &[u8], count: u64) {
+        match std::str::from_utf8(key) {
+            Ok(key) => self.add_str(key, count),
+            Err(_) => self.add_str(&String::from_utf8_lossy(key), count),
+        }
+    }
+
+    pub fn add_str(&mut self, key: &str, count: u64) {
+        let Self {
+            hasher,
+            arena,
+            table,
+        } = self;
+        let hash = hasher.hash_one(key);
+        let slot_key = |slot: &Slot| &arena[slot.start..slot.start + slot.len];
+        if let Some(slot) = table.find_mut(hash, |slot| slot_key(slot) == key) {
+            slot.count += count;
+            return;
+        }
+        let start = arena.len();
+        arena.push_str(key);
+        let arena = &*arena;
+        table.insert_unique(
+            hash,
+            Slot {
+                start,
+                len: key.len(),
+                count,
+            },
+            |slot| hasher.hash_one(&arena[slot.start..slot.start + slot.len]),
+        );
+    }
+
+    pub fn get(&self, key: &str) -> Option<u64> {
+        let hash = self.hasher.hash_one(key);
+        self.table
+            .find(hash, |slot| self.key(slot) == key)
+            .map(|slot| slot.count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.table.capacity()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.table.iter().map(|slot| (self.key(slot), slot.count))
+    }
+
+    /// Combine two counters, copying the smaller into the larger
+    pub fn merge(self, other: Self) -> Self {
+        let (mut large, small) = if self.len() >= other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        for (key, count) in small.iter() {
+            large.add_str(key, count);
+        }
+        large
+    }
+
+    fn key(&self, slot: &Slot) -> &str {
+        &self.arena[slot.start..slot.start + slot.len]
+    }
+}
+
+impl Default for ArenaCounts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> FromIterator<(&'a str, u64)> for ArenaCounts {
+    fn from_iter<I: IntoIterator<Item = (&'a str, u64)>>(entries: I) -> Self {
+        let mut counts = Self::new();
+        for (key, count) in entries {
+            counts.add_str(key, count);
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(keys: &[&str]) -> KeyBatch {
+        let mut batch = KeyBatch::with_capacity(keys.len());
+        for key in keys {
+            batch.push(key.as_bytes());
+        }
+        batch
+    }
+
+    fn sorted(counts: &ArenaCounts) -> Vec<(&str, u64)> {
+        let mut rows: Vec<_> = counts.iter().collect();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn batches_give_back_their_keys_in_order() {
+        let keys = batch(&["ACGT", "", "GG", "ACGT"]);
+        assert_eq!(keys.len(), 4);
+        assert_eq!(
+            keys.iter().collect::<Vec<_>>(),
+            [&b"ACGT"[..], b"", b"GG", b"ACGT"]
+        );
+        assert!(KeyBatch::default().is_empty());
+        assert_eq!(KeyBatch::default().iter().count(), 0);
+    }
+
+    #[test]
+    fn keys_repeated_within_and_across_batches_are_counted_once() {
+        let mut counts = ArenaCounts::new();
+        for keys in [batch(&["AA", "CC", "AA"]), batch(&["CC", "GG", "AA"])] {
+            for key in keys.iter() {
+                counts.add(key);
+            }
+        }
+        assert_eq!(sorted(&counts), [("AA", 3), ("CC", 2), ("GG", 1)]);
+        assert_eq!(counts.get("AA"), Some(3));
+        assert_eq!(counts.get("TT"), None);
+        counts.add_count(b"GG", 10);
+        assert_eq!(counts.get("GG"), Some(11));
+    }
+
+    #[test]
+    fn merging_adds_counts_of_shared_keys_whichever_side_is_larger() {
+        let small: ArenaCounts = [("AA", 1), ("TT", 4)].into_iter().collect();
+        let large: ArenaCounts = [("AA", 2), ("CC", 3), ("GG", 5)].into_iter().collect();
+        let expected = [("AA", 3), ("CC", 3), ("GG", 5), ("TT", 4)];
+
+        let merged = small.merge(large);
+        assert_eq!(sorted(&merged), expected);
+
+        let small: ArenaCounts = [("AA", 1), ("TT", 4)].into_iter().collect();
+        let merged = merged.merge(small);
+        assert_eq!(merged.get("AA"), Some(4));
+        assert_eq!(merged.get("TT"), Some(8));
+        assert_eq!(merged.len(), 4);
+    }
+
+    #[test]
+    fn keys_that_are_not_utf8_are_counted_under_replacement_characters() {
+        let mut counts = ArenaCounts::new();
+        counts.add(b"A\xffC");
+        counts.add(b"A\xfeC");
+        assert_eq!(sorted(&counts), [("A\u{fffd}C", 2)]);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::contaminants::ADAPTERS;
+use serde_json::{Value, json};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Leading adapter bases looked for at the start of a read
+const ADAPTER_SEED: usize = 12;
+
+/// Bases of insert ahead of the adapter that still make a read a dimer
+const DIMER_MAX_INSERT: usize = 10;
+
+/// Reads tallied by library artifact as they are read (`--artifacts`); each
+/// read counts toward the first of adapter dimer, short read or homopolymer
+/// it is
+pub struct Artifacts {
+    min_length: usize,
+    adapter_dimers: AtomicU64,
+    short: AtomicU64,
+    homopolymers: AtomicU64,
+}
+
+/// Artifact tallies of one input, for its report and the `--summary` JSON
+pub struct ArtifactCounts {
+    pub min_length: usize,
+    pub adapter_dimers: u64,
+    pub short: u64,
+    pub homopolymers: u64,
+}
+
+impl Artifacts {
+    /// Reads shorter than `min_length` count as short
+    pub fn new(min_length: usize) -> Self {
+        Self {
+            min_length,
+            adapter_dimers: AtomicU64::new(0),
+            short: AtomicU64::new(0),
+            homopolymers: AtomicU64::new(0),
+        }
+    }
+
+    pub fn offer(&self, seq: &[u8]) {
+        let tally = if is_adapter_dimer(seq) {
+            &self.adapter_dimers
+        } else if seq.len() < self.min_length {
+            &self.short
+        } else if seq
+            .split_first()
+            .is_some_and(|(first, rest)| rest.iter().all(|base| base.eq_ignore_ascii_case(first)))
+        {
+            &self.homopolymers
+        } else {
+            return;
+        };
+        tally.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn counts(&self) -> ArtifactCounts {
+        ArtifactCounts {
+            min_length: self.min_length,
+            adapter_dimers: self.adapter_dimers.load(Ordering::Relaxed),
+            short: self.short.load(Ordering::Relaxed),
+            homopolymers: self.homopolymers.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Whether an Illumina adapter starts within the read's first
+/// `DIMER_MAX_INSERT` bases, leaving next to no insert ahead of it
+fn is_adapter_dimer(seq: &[u8]) -> bool {
+    ADAPTERS.iter().any(|(_, adapter)| {
+        let seed = &adapter.as_bytes()[..ADAPTER_SEED];
+        seq.windows(ADAPTER_SEED)
+            .take(DIMER_MAX_INSERT + 1)
+            .any(|window| window.eq_ignore_ascii_case(seed))
+    })
+}
+
+impl ArtifactCounts {
+    /// One line for the per-input report, as shares of `total_reads`
+    pub fn describe(&self, total_reads: u64) -> String {
+        let share = |reads: u64| reads as f64 / total_reads.max(1) as f64 * 100.0;
+        format!(
+            "{:.2}% adapter dimers, {:.2}% under {} bp, {:.2}% homopolymers",
+            share(self.adapter_dimers),
+            share(self.short),
+            self.min_length,
+            share(self.homopolymers)
+        )
+    }
+
+    pub fn to_json(&self, total_reads: u64) -> Value {
+        let entry = |reads: u64| {
+            json!({
+                "reads": reads,
+                "fraction": reads as f64 / total_reads.max(1) as f64,
+            })
+        };
+        json!({
+            "min_length": self.min_length,
+            "adapter_dimer": entry(self.adapter_dimers),
+            "short": entry(self.short),
+            "homopolymer": entry(self.homopolymers),
+        })
+    }
+}
@@ -0,0 +1,157 @@
+use crate::annotate::Reference;
+use crate::dedup::Encoder;
+use crate::exit::{Classify, Failure};
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Writes a TSV line per read: its name, the key it was counted under, and
+/// the reference it was annotated with, compressed by extension (.gz, .zst)
+pub struct AssignmentWriter<'a> {
+    path: PathBuf,
+    reference: Option<&'a Reference>,
+    /// Columns of a read's key (Illumina fields, then the sequence or group)
+    key_columns: usize,
+    distance: bool,
+    output: Mutex<Output>,
+}
+
+struct Output {
+    encoder: Encoder,
+    written: u64,
+    unassigned: u64,
+    /// First write error; later reads are no longer written
+    error: Option<io::Error>,
+}
+
+/// The reference columns of each key a worker has written, so a key is
+/// annotated once rather than once per read
+#[derive(Default)]
+pub struct AssignmentCache {
+    columns: AHashMap<Box<[u8]>, Box<[u8]>>,
+}
+
+/// Reads written and, of those, reads that were not counted
+pub struct Written {
+    pub reads: u64,
+    pub unassigned: u64,
+}
+
+impl<'a> AssignmentWriter<'a> {
+    /// `columns` names the key columns; `reference`, if given, adds `name`
+    /// (and with `distance`, `distance`) columns
+    pub fn create(
+        path: &Path,
+        columns: &[&str],
+        reference: Option<&'a Reference>,
+        distance: bool,
+    ) -> Result<Self> {
+        let mut encoder = Encoder::create(path)?;
+        let mut header = vec!["read_id"];
+        header.extend(columns);
+        if reference.is_some() {
+            header.push("name");
+            if distance {
+                header.push("distance");
+            }
+        }
+        writeln!(encoder.writer(), "{}", header.join("\t"))
+            .with_context(|| format!("Failed to write file: {}", path.display()))
+            .classify(Failure::Output)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            reference,
+            key_columns: columns.len(),
+            distance,
+            output: Mutex::new(Output {
+                encoder,
+                written: 0,
+                unassigned: 0,
+                error: None,
+            }),
+        })
+    }
+
+    /// Write the read's assignment under the `key` it was counted under;
+    /// reads that were not counted get empty columns
+    pub fn offer(&self, cache: &mut AssignmentCache, id: &[u8], key: Option<&[u8]>) {
+        // The read name ends at the first space, as in SAM
+        let name_end = id
+            .iter()
+            .position(|b| b.is_ascii_whitespace())
+            .unwrap_or(id.len());
+        let mut line = id[..name_end].to_vec();
+        match key {
+            Some(key) => {
+                line.push(b'\t');
+                line.extend_from_slice(key);
+            }
+            None => line.extend(std::iter::repeat_n(b'\t', self.key_columns)),
+        }
+        if let Some(reference) = self.reference {
+            match key {
+                Some(key) => {
+                    if !cache.columns.contains_key(key) {
+                        let columns = self.reference_columns(reference, key);
+                        cache.columns.insert(key.into(), columns);
+                    }
+                    line.extend_from_slice(&cache.columns[key]);
+                }
+                None => {
+                    line.push(b'\t');
+                    if self.distance {
+                        line.push(b'\t');
+                    }
+                }
+            }
+        }
+        line.push(b'\n');
+
+        let mut output = self.output.lock().unwrap();
+        if output.error.is_some() {
+            return;
+        }
+        match output.encoder.writer().write_all(&line) {
+            Ok(()) => {
+                output.written += 1;
+                output.unassigned += u64::from(key.is_none());
+            }
+            Err(e) => output.error = Some(e),
+        }
+    }
+
+    /// The `name` (and `distance`) columns of a key, each after a tab
+    fn reference_columns(&self, reference: &Reference, key: &[u8]) -> Box<[u8]> {
+        // Annotation matches the counted sequence (or group), after the fields
+        let counted = key.rsplit(|&b| b == b'\t').next().unwrap_or(key);
+        let found = reference.assign(counted);
+        let mut columns = vec![b'\t'];
+        if let Some((name, _)) = found {
+            columns.extend_from_slice(name.as_bytes());
+        }
+        if self.distance {
+            columns.push(b'\t');
+            if let Some((_, distance)) = found {
+                columns.extend_from_slice(distance.to_string().as_bytes());
+            }
+        }
+        columns.into()
+    }
+
+    /// Complete the file
+    pub fn finish(self) -> Result<Written> {
+        let output = self.output.into_inner().unwrap();
+        if let Some(e) = output.error {
+            return Err(e)
+                .with_context(|| format!("Failed to write file: {}", self.path.display()))
+                .classify(Failure::Output);
+        }
+        output.encoder.finish(&self.path)?;
+        Ok(Written {
+            reads: output.written,
+            unassigned: output.unassigned,
+        })
+    }
+}
@@ -0,0 +1,309 @@
+use crate::interrupt::PartialOutput;
+use crate::output::{Batch, OutputWriter, TableOptions};
+use anyhow::{Context, Result, bail};
+use arrow::array::{Array, ArrayRef, AsArray};
+use arrow::datatypes::{DataType, Field, Float64Type, UInt32Type, UInt64Type};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use serde_json::{Value, json};
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Encoded rows gathered before a block is compressed and written
+const BLOCK_BYTES: usize = 1024 * 1024;
+
+/// Avro object container file: a header with the schema and metadata, then
+/// blocks of rows, each followed by the file's sync marker
+pub struct AvroWriter {
+    partial: PartialOutput,
+    writer: BufWriter<File>,
+    fields: Vec<Field>,
+    codec: Codec,
+    sync: [u8; 16],
+    /// Rows encoded since the last block, and how many
+    block: Vec<u8>,
+    rows: i64,
+}
+
+/// Avro block codecs, from the Parquet `--compression` name
+#[derive(Clone, Copy)]
+enum Codec {
+    Null,
+    Deflate,
+    Snappy,
+    Zstandard,
+}
+
+impl Codec {
+    /// `gzip`, `snappy` and `zstd` have Avro counterparts; the other codecs
+    /// leave blocks uncompressed
+    fn from_compression(compression: &str) -> Self {
+        match compression.to_lowercase().as_str() {
+            "gzip" => Codec::Deflate,
+            "snappy" => Codec::Snappy,
+            "zstd" => Codec::Zstandard,
+            _ => Codec::Null,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Codec::Null => "null",
+            Codec::Deflate => "deflate",
+            Codec::Snappy => "snappy",
+            Codec::Zstandard => "zstandard",
+        }
+    }
+
+    fn compress(self, data: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(match self {
+            Codec::Null => data,
+            Codec::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&data)?;
+                encoder.finish()?
+            }
+            // A raw Snappy block, then the CRC-32 of the uncompressed data
+            // (big-endian)
+            Codec::Snappy => {
+                let mut crc = Crc::new();
+                crc.update(&data);
+                let mut block = snap::raw::Encoder::new().compress_vec(&data)?;
+                block.extend_from_slice(&crc.sum().to_be_bytes());
+                block
+            }
+            Codec::Zstandard => zstd::bulk::compress(&data, 0)?,
+        })
+    }
+}
+
+impl AvroWriter {
+    pub fn open(path: &Path, fields: Vec<Field>, options: TableOptions) -> Result<Self> {
+        let schema = schema(&fields)?;
+        let codec = Codec::from_compression(options.compression);
+
+        let partial = PartialOutput::new(path);
+        let file = File::create(partial.path())
+            .with_context(|| format!("Failed to create file: {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+
+        // Footer metadata of Parquet tables goes into the header, as it is
+        let mut metadata = vec![
+            ("avro.schema".to_string(), schema.to_string()),
+            ("avro.codec".to_string(), codec.name().to_string()),
+        ];
+        metadata.extend(
+            options
+                .metadata
+                .into_iter()
+                .map(|kv| (kv.key, kv.value.unwrap_or_default())),
+        );
+        let mut header = b"Obj\x01".to_vec();
+        write_long(&mut header, metadata.len() as i64);
+        for (key, value) in &metadata {
+            write_bytes(&mut header, key.as_bytes());
+            write_bytes(&mut header, value.as_bytes());
+        }
+        write_long(&mut header, 0);
+        let sync = sync_marker(path);
+        header.extend_from_slice(&sync);
+        writer.write_all(&header)?;
+
+        Ok(Self {
+            partial,
+            writer,
+            fields,
+            codec,
+            sync,
+            block: Vec::new(),
+            rows: 0,
+        })
+    }
+
+    fn write_block(&mut self) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let data = self.codec.compress(std::mem::take(&mut self.block))?;
+        let mut prefix = Vec::new();
+        write_long(&mut prefix, self.rows);
+        write_long(&mut prefix, data.len() as i64);
+        self.writer.write_all(&prefix)?;
+        self.writer.write_all(&data)?;
+        self.writer.write_all(&self.sync)?;
+        self.rows = 0;
+        Ok(())
+    }
+}
+
+impl OutputWriter for AvroWriter {
+    fn write_batch(&mut self, batch: &dyn Batch) -> Result<()> {
+        let arrays = (0..self.fields.len())
+            .map(|column| batch.array(column))
+            .collect::<Result<Vec<ArrayRef>>>()?;
+        for row in 0..batch.num_rows() {
+            for (field, array) in self.fields.iter().zip(&arrays) {
+                if field.is_nullable() {
+                    // Unions of ["null", type]: the branch index, then the value
+                    if array.is_null(row) {
+                        write_long(&mut self.block, 0);
+                        continue;
+                    }
+                    write_long(&mut self.block, 1);
+                }
+                write_value(&mut self.block, array, row)?;
+            }
+            self.rows += 1;
+            if self.block.len() >= BLOCK_BYTES {
+                self.write_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<()> {
+        self.write_block()?;
+        self.writer.flush()?;
+        self.partial.finish()
+    }
+}
+
+/// Avro record schema of the table's columns. Names Avro does not allow
+/// (sample names with `-` or `.`, say) have each other character replaced
+/// with `_`, keeping the column's own name as the field's `doc`.
+fn schema(fields: &[Field]) -> Result<Value> {
+    let mut names: Vec<String> = Vec::with_capacity(fields.len());
+    let fields = fields
+        .iter()
+        .map(|field| {
+            let mut name: String = field
+                .name()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+            if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                name.insert(0, '_');
+            }
+            if names.contains(&name) {
+                bail!(
+                    "Columns '{}' and another are both '{}' as Avro field names",
+                    field.name(),
+                    name
+                );
+            }
+            let avro_type = match field.data_type() {
+                DataType::Utf8 | DataType::LargeUtf8 => "string",
+                DataType::UInt32 | DataType::UInt64 => "long",
+                DataType::Float64 => "double",
+                DataType::Boolean => "boolean",
+                other => bail!(
+                    "Column '{}' is {}, which Avro output lacks",
+                    field.name(),
+                    other
+                ),
+            };
+            let avro_type = if field.is_nullable() {
+                json!(["null", avro_type])
+            } else {
+                json!(avro_type)
+            };
+            let mut entry = json!({ "name": name, "type": avro_type });
+            if name != *field.name() {
+                entry["doc"] = json!(field.name());
+            }
+            names.push(name);
+            Ok(entry)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(json!({
+        "type": "record",
+        "name": "CountTable",
+        "namespace": "seqtable",
+        "fields": fields,
+    }))
+}
+
+fn write_value(buf: &mut Vec<u8>, array: &ArrayRef, row: usize) -> Result<()> {
+    match array.data_type() {
+        DataType::Utf8 => write_bytes(buf, array.as_string::<i32>().value(row).as_bytes()),
+        DataType::LargeUtf8 => write_bytes(buf, array.as_string::<i64>().value(row).as_bytes()),
+        DataType::UInt32 => write_long(buf, array.as_primitive::<UInt32Type>().value(row).into()),
+        DataType::UInt64 => {
+            let value = array.as_primitive::<UInt64Type>().value(row);
+            let value = i64::try_from(value).context("A count exceeds Avro's long range")?;
+            write_long(buf, value);
+        }
+        DataType::Float64 => {
+            let value = array.as_primitive::<Float64Type>().value(row);
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        DataType::Boolean => buf.push(u8::from(array.as_boolean().value(row))),
+        other => bail!("Avro output lacks {} columns", other),
+    }
+    Ok(())
+}
+
+/// Zig-zag variable-length encoding of Avro `int` and `long`
+fn write_long(buf: &mut Vec<u8>, value: i64) {
+    let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+    while zigzag >= 0x80 {
+        buf.push(zigzag as u8 | 0x80);
+        zigzag >>= 7;
+    }
+    buf.push(zigzag as u8);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(buf, bytes.len() as i64);
+    buf.extend_from_slice(bytes);
+}
+
+/// 16 bytes unlikely to occur in the rows, from a randomly keyed hash of
+/// the path and time
+fn sync_marker(path: &Path) -> [u8; 16] {
+    let state = RandomState::new();
+    let mut marker = [0; 16];
+    for (half, chunk) in marker.chunks_mut(8).enumerate() {
+        let hash = state.hash_one((path, SystemTime::now(), half));
+        chunk.copy_from_slice(&hash.to_le_bytes());
+    }
+    marker
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snappy_blocks_end_with_the_crc_of_their_data() {
+        let data = b"123456789".repeat(100);
+        let block = Codec::Snappy.compress(data.clone()).unwrap();
+        let (compressed, crc) = block.split_at(block.len() - 4);
+        assert!(compressed.len() < data.len());
+        assert_eq!(
+            snap::raw::Decoder::new()
+                .decompress_vec(compressed)
+                .unwrap(),
+            data
+        );
+        let mut expected = Crc::new();
+        expected.update(&data);
+        assert_eq!(crc, expected.sum().to_be_bytes());
+
+        // CRC-32 check value of "123456789", as Avro readers compute it
+        let block = Codec::Snappy.compress(b"123456789".to_vec()).unwrap();
+        assert_eq!(block[block.len() - 4..], [0xcb, 0xf4, 0x39, 0x26]);
+    }
+
+    #[test]
+    fn codecs_follow_the_parquet_compression() {
+        assert_eq!(Codec::from_compression("SNAPPY").name(), "snappy");
+        assert_eq!(Codec::from_compression("gzip").name(), "deflate");
+        assert_eq!(Codec::from_compression("zstd").name(), "zstandard");
+        assert_eq!(Codec::from_compression("lz4").name(), "null");
+    }
+}
@@ -0,0 +1,150 @@
+/// 2-bit codes for uppercase ACGT; every other byte maps to `INVALID`
+const BASE_CODES: [u8; 256] = {
+    let mut codes = [INVALID; 256];
+    codes[b'A' as usize] = 0;
+    codes[b'C' as usize] = 1;
+    codes[b'G' as usize] = 2;
+    codes[b'T' as usize] = 3;
+    codes
+};
+const INVALID: u8 = 4;
+
+/// Whether every base is an uppercase A, C, G or T (so no `N`, lowercase
+/// or IUPAC codes). With the `simd` feature on x86_64, 16 bases are checked
+/// per instruction.
+pub fn is_acgt(seq: &[u8]) -> bool {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        sse2::is_acgt(seq)
+    }
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    {
+        is_acgt_scalar(seq)
+    }
+}
+
+/// Branch-free: random bases would defeat a per-base early exit
+fn is_acgt_scalar(seq: &[u8]) -> bool {
+    let invalid = seq
+        .iter()
+        .fold(0u8, |invalid, &base| invalid | BASE_CODES[base as usize]);
+    invalid & INVALID == 0
+}
+
+/// The stretches of `seq` between its `N`s (either case), found with
+/// `memchr`, which scans many bytes per instruction; stretches may be empty
+pub fn split_at_n(seq: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut start = 0;
+    memchr::memchr2_iter(b'N', b'n', seq)
+        .map(Some)
+        .chain([None])
+        .map(move |n| {
+            let end = n.unwrap_or(seq.len());
+            let run = &seq[start..end];
+            start = end + 1;
+            run
+        })
+}
+
+/// 2-bit code of an uppercase ACGT base (A=0, C=1, G=2, T=3), from its ASCII
+/// bits alone; other bytes give meaningless codes, so check `is_acgt` first
+pub fn code(base: u8) -> u8 {
+    ((base >> 1) ^ (base >> 2)) & 0b11
+}
+
+/// The base pairing with `base`, keeping case; bytes other than ACGT (`N`,
+/// IUPAC codes) are their own complement
+pub fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'a' => b't',
+        b'c' => b'g',
+        b'g' => b'c',
+        b't' => b'a',
+        other => other,
+    }
+}
+
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter().rev().map(|&base| complement(base)).collect()
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod sse2 {
+    use std::arch::x86_64::{
+        __m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8, _mm_or_si128, _mm_set1_epi8,
+    };
+
+    pub fn is_acgt(seq: &[u8]) -> bool {
+        let mut chunks = seq.chunks_exact(16);
+        // Safety: SSE2 is part of the x86_64 baseline, and the unaligned loads
+        // read exactly the 16 bytes of each chunk
+        unsafe {
+            let [a, c, g, t] = [b'A', b'C', b'G', b'T'].map(|base| _mm_set1_epi8(base as i8));
+            for chunk in &mut chunks {
+                let bases = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+                let matched = _mm_or_si128(
+                    _mm_or_si128(_mm_cmpeq_epi8(bases, a), _mm_cmpeq_epi8(bases, c)),
+                    _mm_or_si128(_mm_cmpeq_epi8(bases, g), _mm_cmpeq_epi8(bases, t)),
+                );
+                if _mm_movemask_epi8(matched) != 0xFFFF {
+                    return false;
+                }
+            }
+        }
+        super::is_acgt_scalar(chunks.remainder())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes from a fixed xorshift, so failures reproduce
+    fn random_bases(state: &mut u64, len: usize, alphabet: &[u8]) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                alphabet[(*state % alphabet.len() as u64) as usize]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_acgt_check_agrees_with_the_scalar_one() {
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for len in 0..100 {
+            let clean = random_bases(&mut state, len, b"ACGT");
+            assert!(is_acgt(&clean) && is_acgt_scalar(&clean), "{:?}", clean);
+            // One bad base at each position, in a full chunk or the remainder
+            for at in 0..len {
+                for bad in [b'N', b'n', b'a', b'U', 0, 0xC1] {
+                    let mut seq = clean.clone();
+                    seq[at] = bad;
+                    assert!(!is_acgt(&seq) && !is_acgt_scalar(&seq), "{:?}", seq);
+                }
+            }
+            let mixed = random_bases(&mut state, len, b"ACGTACGTACGTNacgt-");
+            assert_eq!(is_acgt(&mixed), is_acgt_scalar(&mixed), "{:?}", mixed);
+        }
+    }
+
+    #[test]
+    fn splitting_at_n_matches_a_byte_by_byte_split() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for len in (0..200).step_by(7) {
+            let seq = random_bases(&mut state, len, b"ACGTACGTNnacgt");
+            let expected: Vec<&[u8]> = seq.split(|&base| base == b'N' || base == b'n').collect();
+            assert_eq!(split_at_n(&seq).collect::<Vec<_>>(), expected);
+        }
+        assert_eq!(
+            split_at_n(b"NACN").collect::<Vec<_>>(),
+            [&b""[..], b"AC", b""]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use crate::RecordKey;
+use crate::counter::CounterBackend;
+use crate::input::{Input, ReadOptions};
+use crate::mapped;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::time::{Duration, Instant};
+
+/// Records of the input each configuration counts
+const SAMPLE_RECORDS: u64 = 200_000;
+
+/// Chunk sizes tried for inputs read through the streaming path
+const CHUNK_SIZES: [usize; 4] = [10_000, 50_000, 100_000, 500_000];
+
+/// One timed configuration
+struct Trial {
+    threads: usize,
+    /// `None` for memory-mapped inputs, which do not read in chunks
+    chunk_size: Option<usize>,
+    counter: CounterBackend,
+    elapsed: Duration,
+}
+
+/// Count the first records of `input` under each thread count, chunk size
+/// and counter backend, print the timings fastest first, and recommend the
+/// fastest. `max_threads` caps the thread counts tried (0 = all cores).
+pub fn run(input: &Input, keys: RecordKey, max_threads: usize) -> Result<()> {
+    let mapped = mapped::map_plain_text(input)?.is_some();
+    let read = ReadOptions {
+        limit: Some(SAMPLE_RECORDS),
+        ..Default::default()
+    };
+    let count = |threads: usize, chunk_size: usize, counter: CounterBackend| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .context("Failed to initialize thread pool")?;
+        let start = Instant::now();
+        let (_, reads) = pool.install(|| {
+            crate::count_sequences(input, chunk_size, counter, keys, read, false, None)
+        })?;
+        Ok::<_, anyhow::Error>((reads, start.elapsed()))
+    };
+
+    // An untimed pass warms the page cache so the first trial is not penalized
+    let (reads, _) = count(1, CHUNK_SIZES[0], CounterBackend::Chunked)?;
+    eprintln!(
+        "⏱️  Benchmarking on the first {} records of {} ({})",
+        reads,
+        input.path().display(),
+        if mapped { "memory-mapped" } else { "streaming" }
+    );
+
+    let chunk_sizes: Vec<Option<usize>> = if mapped {
+        vec![None]
+    } else {
+        // Chunks beyond the sample size all behave like one chunk
+        CHUNK_SIZES
+            .into_iter()
+            .filter(|&size| size as u64 <= reads.max(CHUNK_SIZES[0] as u64))
+            .map(Some)
+            .collect()
+    };
+    let mut trials = Vec::new();
+    for threads in thread_counts(max_threads) {
+        for &chunk_size in &chunk_sizes {
+            for &counter in CounterBackend::value_variants() {
+                let (_, elapsed) = count(threads, chunk_size.unwrap_or(0), counter)?;
+                trials.push(Trial {
+                    threads,
+                    chunk_size,
+                    counter,
+                    elapsed,
+                });
+            }
+        }
+    }
+    trials.sort_by_key(|trial| trial.elapsed);
+
+    println!(
+        "   {:>7}  {:>10}  {:<8}  {:>8}  {:>10}",
+        "threads", "chunk size", "counter", "time", "reads/s"
+    );
+    for trial in &trials {
+        println!(
+            "   {:>7}  {:>10}  {:<8}  {:>7.3}s  {:>10.0}",
+            trial.threads,
+            trial
+                .chunk_size
+                .map_or("-".to_string(), |size| size.to_string()),
+            counter_name(trial.counter),
+            trial.elapsed.as_secs_f64(),
+            reads as f64 / trial.elapsed.as_secs_f64().max(f64::EPSILON)
+        );
+    }
+
+    let best = &trials[0];
+    let mut flags = format!("-t {}", best.threads);
+    if let Some(size) = best.chunk_size {
+        flags.push_str(&format!(" -c {}", size));
+    }
+    if best.counter != CounterBackend::Chunked {
+        flags.push_str(&format!(" --counter {}", counter_name(best.counter)));
+    }
+    println!("\n💡 Recommended: {}", flags);
+    Ok(())
+}
+
+/// 1, 2, 4, ... up to the core count (or `max`), always including it
+fn thread_counts(max: usize) -> Vec<usize> {
+    let max = if max == 0 { num_cpus::get() } else { max };
+    let mut counts: Vec<usize> = std::iter::successors(Some(1), |&n| Some(n * 2))
+        .take_while(|&n| n < max)
+        .collect();
+    counts.push(max);
+    counts
+}
+
+fn counter_name(counter: CounterBackend) -> String {
+    counter
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string())
+}
@@ -0,0 +1,312 @@
+use flate2::read::GzDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use rayon::prelude::*;
+use std::io::{self, Read, Write};
+
+/// Fixed part of a BGZF block header, up to and including XLEN
+const HEADER_LEN: usize = 12;
+
+/// Uncompressed bytes per block written, as bgzip writes them
+const BLOCK_DATA: usize = 0xff00;
+
+/// Largest deflated data that fits a block beside its header and trailer
+const MAX_DEFLATED: usize = 0x10000 - HEADER_LEN - 6 - 8;
+
+/// Empty block that ends a BGZF file
+const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, b'B', b'C', 0x02, 0, 0x1b, 0, 0x03, 0, 0,
+    0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Blocks decoded per batch, per rayon thread
+const BLOCKS_PER_THREAD: usize = 4;
+
+/// Whether `header` starts a BGZF block: a gzip member with FEXTRA set and a
+/// `BC` extra subfield carrying the block size
+pub fn is_bgzf(header: &[u8]) -> bool {
+    block_size(header).is_some()
+}
+
+/// Total block size from a BGZF header (`None` if this is not BGZF)
+fn block_size(header: &[u8]) -> Option<usize> {
+    if header.len() < HEADER_LEN || header[..4] != [0x1f, 0x8b, 0x08, 0x04] {
+        return None;
+    }
+    let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+    let mut extra = header.get(HEADER_LEN..HEADER_LEN + xlen)?;
+    while extra.len() >= 4 {
+        let subfield_len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        if extra[..2] == *b"BC" && subfield_len == 2 {
+            let bsize = u16::from_le_bytes([*extra.get(4)?, *extra.get(5)?]);
+            return Some(bsize as usize + 1);
+        }
+        extra = extra.get(4 + subfield_len..)?;
+    }
+    None
+}
+
+/// Reader that inflates BGZF blocks in parallel. Blocks are read off the
+/// underlying stream in batches and decompressed on the rayon pool.
+pub struct BgzfReader<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> BgzfReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buffer: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// Read the next compressed block, or `None` at end of stream
+    fn read_block(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut block = vec![0u8; HEADER_LEN];
+        if self.inner.read(&mut block[..1])? == 0 {
+            return Ok(None);
+        }
+        self.inner.read_exact(&mut block[1..]).map_err(truncated)?;
+        let xlen = u16::from_le_bytes([block[10], block[11]]) as usize;
+        block.resize(HEADER_LEN + xlen, 0);
+        self.inner
+            .read_exact(&mut block[HEADER_LEN..])
+            .map_err(truncated)?;
+
+        let header_len = block.len();
+        // A block holds at least its header plus the CRC32 and ISIZE trailer
+        let size = block_size(&block)
+            .filter(|&size| size >= header_len + 8)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid BGZF block"))?;
+        block.resize(size, 0);
+        self.inner
+            .read_exact(&mut block[header_len..])
+            .map_err(truncated)?;
+        Ok(Some(block))
+    }
+
+    fn fill_buffer(&mut self) -> io::Result<()> {
+        let batch_size = rayon::current_num_threads() * BLOCKS_PER_THREAD;
+        let mut blocks = Vec::with_capacity(batch_size);
+        while blocks.len() < batch_size {
+            match self.read_block()? {
+                Some(block) => blocks.push(block),
+                None => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+
+        let decoded: Vec<Vec<u8>> = blocks
+            .par_iter()
+            .map(|block| {
+                // ISIZE: uncompressed length, the last four bytes of the block
+                let len = u32::from_le_bytes(block[block.len() - 4..].try_into().unwrap());
+                let mut out = Vec::with_capacity(len as usize);
+                GzDecoder::new(&block[..])
+                    .read_to_end(&mut out)
+                    .map_err(|e| io::Error::new(e.kind(), format!("Corrupt BGZF block ({})", e)))?;
+                Ok(out)
+            })
+            .collect::<io::Result<_>>()?;
+
+        self.buffer = decoded.concat();
+        self.position = 0;
+        Ok(())
+    }
+}
+
+/// Clearer error for a stream that ends inside a block
+fn truncated(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        io::Error::new(e.kind(), "BGZF stream is truncated (ends inside a block)")
+    } else {
+        e
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Empty blocks (including the EOF marker) can yield empty batches
+        while self.position >= self.buffer.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.fill_buffer()?;
+        }
+        let n = buf.len().min(self.buffer.len() - self.position);
+        buf[..n].copy_from_slice(&self.buffer[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Writer of BGZF blocks, which `gzip -d` reads as one stream and which can
+/// be entered at any block: a virtual offset names a byte by the file
+/// offset of its block (shifted 16 bits) plus its offset within the block
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    block: Vec<u8>,
+    /// Compressed bytes written so far
+    written: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            block: Vec::with_capacity(BLOCK_DATA),
+            written: 0,
+        }
+    }
+
+    /// Virtual offset of the next byte written
+    pub fn virtual_offset(&self) -> u64 {
+        self.written << 16 | self.block.len() as u64
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let mut deflated = deflate(&self.block, Compression::default())?;
+        if deflated.len() > MAX_DEFLATED {
+            // Stored blocks never outgrow the data by more than the block allows
+            deflated = deflate(&self.block, Compression::none())?;
+        }
+        let mut crc = Crc::new();
+        crc.update(&self.block);
+        let size = HEADER_LEN + 6 + deflated.len() + 8;
+
+        let mut header = [
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, b'B', b'C', 0x02, 0, 0, 0,
+        ];
+        header[16..].copy_from_slice(&((size - 1) as u16).to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&deflated)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner
+            .write_all(&(self.block.len() as u32).to_le_bytes())?;
+        self.written += size as u64;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Write the last block and the end-of-file marker
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.block.is_empty() {
+            self.write_block()?;
+        }
+        self.inner.write_all(&EOF_BLOCK)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(BLOCK_DATA - self.block.len());
+        self.block.extend_from_slice(&buf[..n]);
+        if self.block.len() == BLOCK_DATA {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    /// Flushes what is in full blocks; the open block is written by `finish`
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn deflate(data: &[u8], level: Compression) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len() / 2), level);
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+
+    /// Text of about five blocks, and the virtual offset of each line in it
+    fn write_lines() -> (Vec<u8>, Vec<u8>, Vec<(usize, u64)>) {
+        let mut writer = BgzfWriter::new(Vec::new());
+        let mut text = Vec::new();
+        let mut offsets = Vec::new();
+        for i in 0..20_000 {
+            let line = format!("line {} {}\n", i, "ACGT".repeat(i % 7));
+            offsets.push((text.len(), writer.virtual_offset()));
+            writer.write_all(line.as_bytes()).unwrap();
+            text.extend_from_slice(line.as_bytes());
+        }
+        (writer.finish().unwrap(), text, offsets)
+    }
+
+    /// Decoded bytes from `virtual_offset` on
+    fn read_from(file: &[u8], virtual_offset: u64) -> Vec<u8> {
+        let mut decoder = MultiGzDecoder::new(&file[(virtual_offset >> 16) as usize..]);
+        let mut skipped = vec![0; (virtual_offset & 0xffff) as usize];
+        decoder.read_exact(&mut skipped).unwrap();
+        let mut rest = Vec::new();
+        decoder.read_to_end(&mut rest).unwrap();
+        rest
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let (file, text, _) = write_lines();
+        assert!(is_bgzf(&file));
+        assert!(file.ends_with(&EOF_BLOCK));
+
+        let mut decoded = Vec::new();
+        BgzfReader::new(&file[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, text);
+        let mut decoded = Vec::new();
+        MultiGzDecoder::new(&file[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, text);
+    }
+
+    #[test]
+    fn virtual_offsets_name_their_bytes_in_every_block() {
+        let (file, text, offsets) = write_lines();
+        let blocks: Vec<u64> = offsets.iter().map(|(_, offset)| offset >> 16).collect();
+        assert!(blocks.last().unwrap() > &3, "the text spans several blocks");
+
+        // Lines either side of each block boundary, and every 997th
+        let boundaries = blocks
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] != pair[1]);
+        let lines: Vec<usize> = boundaries
+            .flat_map(|(line, _)| [line, line + 1])
+            .chain((0..offsets.len()).step_by(997))
+            .collect();
+        for line in lines {
+            let (start, offset) = offsets[line];
+            assert_eq!(read_from(&file, offset), &text[start..], "line {}", line);
+        }
+    }
+
+    #[test]
+    fn a_full_block_moves_offsets_to_the_next() {
+        let mut writer = BgzfWriter::new(Vec::new());
+        writer.write_all(&vec![b'A'; BLOCK_DATA - 1]).unwrap();
+        assert_eq!(writer.virtual_offset(), BLOCK_DATA as u64 - 1);
+        writer.write_all(b"AC").unwrap();
+        // The block is written once full, and `C` opens the next
+        let offset = writer.virtual_offset();
+        assert_eq!(offset & 0xffff, 1);
+        let file = writer.finish().unwrap();
+        assert_eq!(block_size(&file), Some((offset >> 16) as usize));
+        assert_eq!(read_from(&file, offset - 1), b"C");
+    }
+}
@@ -0,0 +1,242 @@
+use crate::interrupt::PartialOutput;
+use crate::merge::CountMatrix;
+use anyhow::{Context, Result};
+use hdf5_pure::{AttrValue, FileBuilder, GroupBuilder};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Write the merged matrix as a BIOM v2.1 (HDF5) table, with sequences as
+/// observations and samples as samples. Sample sheet columns become sample
+/// metadata. Normalized values go to `<stem>_normalized.biom`.
+pub fn save_biom(matrix: &CountMatrix, output_path: &Path) -> Result<()> {
+    write_table(matrix, output_path, |sample, row| {
+        matrix.counts[sample][row] as f64
+    })?;
+
+    if let Some(ref normalized) = matrix.normalized {
+        let stem = output_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("table");
+        let normalized_path = output_path.with_file_name(format!("{}_normalized.biom", stem));
+        write_table(matrix, &normalized_path, |sample, row| {
+            normalized[sample][row]
+        })?;
+    }
+
+    Ok(())
+}
+
+fn write_table(
+    matrix: &CountMatrix,
+    output_path: &Path,
+    value: impl Fn(usize, usize) -> f64,
+) -> Result<()> {
+    let n_observations = matrix.sequences.len();
+    let n_samples = matrix.samples.len();
+    let nnz: usize = matrix
+        .counts
+        .iter()
+        .map(|column| column.iter().filter(|&&c| c > 0).count())
+        .sum();
+
+    let mut builder = FileBuilder::new();
+    let table_id = output_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("table");
+    builder.set_attr("id", string(table_id));
+    builder.set_attr("type", string("OTU table"));
+    builder.set_attr("format-url", string("http://biom-format.org"));
+    builder.set_attr("format-version", AttrValue::I32Array(vec![2, 1]));
+    builder.set_attr("generated-by", string("seqtable v0.1.1"));
+    builder.set_attr("creation-date", string(&creation_date()));
+    builder.set_attr(
+        "shape",
+        AttrValue::I32Array(vec![n_observations as i32, n_samples as i32]),
+    );
+    builder.set_attr("nnz", AttrValue::I32(nnz as i32));
+
+    // Observation axis: one CSR row per sequence
+    let mut data = Vec::with_capacity(nnz);
+    let mut indices = Vec::with_capacity(nnz);
+    let mut indptr = Vec::with_capacity(n_observations + 1);
+    indptr.push(0);
+    for row in 0..n_observations {
+        for (sample, column) in matrix.counts.iter().enumerate() {
+            if column[row] > 0 {
+                data.push(value(sample, row));
+                indices.push(sample as i32);
+            }
+        }
+        indptr.push(data.len() as i32);
+    }
+    let mut observation = builder.create_group("observation");
+    write_axis(
+        &mut observation,
+        &matrix.sequences,
+        &data,
+        &indices,
+        &indptr,
+        &[],
+    );
+    builder.add_group(observation.finish());
+
+    // Sample axis: the same entries, one CSR row per sample
+    let mut data = Vec::with_capacity(nnz);
+    let mut indices = Vec::with_capacity(nnz);
+    let mut indptr = Vec::with_capacity(n_samples + 1);
+    indptr.push(0);
+    for (sample, column) in matrix.counts.iter().enumerate() {
+        for (row, _) in column.iter().enumerate().filter(|(_, c)| **c > 0) {
+            data.push(value(sample, row));
+            indices.push(row as i32);
+        }
+        indptr.push(data.len() as i32);
+    }
+    let mut sample = builder.create_group("sample");
+    write_axis(
+        &mut sample,
+        &matrix.samples,
+        &data,
+        &indices,
+        &indptr,
+        &matrix.sample_metadata,
+    );
+    builder.add_group(sample.finish());
+
+    let partial = PartialOutput::new(output_path);
+    builder
+        .write(partial.path())
+        .with_context(|| format!("Failed to write BIOM file: {}", output_path.display()))?;
+    partial.finish()?;
+    Ok(())
+}
+
+fn string(value: &str) -> AttrValue {
+    AttrValue::VarLenString(value.to_string())
+}
+
+/// `ids`, `matrix/{data,indices,indptr}`, and the metadata groups of one axis
+fn write_axis(
+    group: &mut GroupBuilder,
+    ids: &[String],
+    data: &[f64],
+    indices: &[i32],
+    indptr: &[i32],
+    metadata: &[(String, Vec<String>)],
+) {
+    let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+    group.create_dataset("ids").with_vlen_strings(&ids);
+
+    let mut sparse = group.create_group("matrix");
+    sparse.create_dataset("data").with_f64_data(data);
+    sparse.create_dataset("indices").with_i32_data(indices);
+    sparse.create_dataset("indptr").with_i32_data(indptr);
+    group.add_group(sparse.finish());
+
+    let mut categories = group.create_group("metadata");
+    for (name, values) in metadata {
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        categories.create_dataset(name).with_vlen_strings(&values);
+    }
+    group.add_group(categories.finish());
+
+    let group_metadata = group.create_group("group-metadata");
+    group.add_group(group_metadata.finish());
+}
+
+/// Current UTC time as an ISO 8601 timestamp (YYYY-MM-DDTHH:MM:SS)
+fn creation_date() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, time) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_axes_hold_the_nonzero_counts_and_samples_their_metadata() {
+        let matrix = CountMatrix {
+            key_column: "sequence".to_string(),
+            samples: vec!["a".to_string(), "b".to_string()],
+            sequences: vec!["AAA".to_string(), "CCC".to_string(), "GGG".to_string()],
+            counts: vec![vec![5, 0, 2], vec![0, 3, 1]],
+            normalized: Some(vec![vec![0.5, 0.0, 0.2], vec![0.0, 0.75, 0.25]]),
+            sample_metadata: vec![(
+                "site".to_string(),
+                vec!["gut".to_string(), "skin".to_string()],
+            )],
+        };
+        let dir = std::env::temp_dir().join(format!("seqtable-biom-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        save_biom(&matrix, &dir.join("table.biom")).unwrap();
+
+        let file = hdf5_pure::File::open(dir.join("table.biom")).unwrap();
+        let read_f64 = |name: &str| file.dataset(name).unwrap().read_f64().unwrap();
+        let read_i64 = |name: &str| file.dataset(name).unwrap().read_i64().unwrap();
+        let read_strings = |name: &str| file.dataset(name).unwrap().read_string().unwrap();
+
+        let attrs = file.root().attrs().unwrap();
+        assert_eq!(attrs.get("shape"), Some(&AttrValue::I32Array(vec![3, 2])));
+        assert_eq!(attrs.get("nnz"), Some(&AttrValue::I32(4)));
+        assert_eq!(
+            attrs.get("format-version"),
+            Some(&AttrValue::I32Array(vec![2, 1]))
+        );
+
+        // Sequences by row, then samples by row, over the same four entries
+        assert_eq!(read_strings("observation/ids"), ["AAA", "CCC", "GGG"]);
+        assert_eq!(read_f64("observation/matrix/data"), [5.0, 3.0, 2.0, 1.0]);
+        assert_eq!(read_i64("observation/matrix/indices"), [0, 1, 0, 1]);
+        assert_eq!(read_i64("observation/matrix/indptr"), [0, 1, 2, 4]);
+        assert_eq!(read_strings("sample/ids"), ["a", "b"]);
+        assert_eq!(read_f64("sample/matrix/data"), [5.0, 2.0, 3.0, 1.0]);
+        assert_eq!(read_i64("sample/matrix/indices"), [0, 2, 1, 2]);
+        assert_eq!(read_i64("sample/matrix/indptr"), [0, 2, 4]);
+        assert_eq!(read_strings("sample/metadata/site"), ["gut", "skin"]);
+
+        let normalized = hdf5_pure::File::open(dir.join("table_normalized.biom")).unwrap();
+        let data = normalized
+            .dataset("sample/matrix/data")
+            .unwrap()
+            .read_f64()
+            .unwrap();
+        assert_eq!(data, [0.5, 0.2, 0.75, 0.25]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn creation_dates_are_iso_timestamps() {
+        let date = creation_date();
+        assert_eq!(date.len(), 19);
+        assert_eq!((&date[4..5], &date[10..11], &date[13..14]), ("-", "T", ":"));
+        assert!(date.as_str() > "2024");
+    }
+}
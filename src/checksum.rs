@@ -0,0 +1,222 @@
+use crate::input::{self, Input};
+use crate::interrupt::PartialOutput;
+use crate::retry::RetryingFile;
+use ahash::AHashMap;
+use anyhow::{Context, Result, bail};
+use md5::{Digest, Md5};
+use sha2::Sha256;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Read size when hashing the part of a file the parser did not read
+const TAIL_BUFFER_SIZE: usize = 1024 * 1024; // 1MB
+
+/// Checksum manifest name, in the output directory unless given
+pub const MANIFEST_FILE: &str = "checksums.tsv";
+
+/// Digests of an input file's bytes as stored on disk
+pub struct Digests {
+    pub md5: String,
+    pub sha256: Option<String>,
+}
+
+/// Hashes of an input, fed with its bytes as the counting pass reads them
+pub struct Checksum {
+    state: Mutex<State>,
+}
+
+struct State {
+    md5: Md5,
+    sha256: Option<Sha256>,
+    /// Bytes hashed so far, always a prefix of the file
+    hashed: u64,
+}
+
+impl Checksum {
+    /// MD5, plus SHA-256 when `sha256` is set
+    pub fn new(sha256: bool) -> Self {
+        Self {
+            state: Mutex::new(State {
+                md5: Md5::new(),
+                sha256: sha256.then(Sha256::new),
+                hashed: 0,
+            }),
+        }
+    }
+
+    /// Hash the next bytes of the file
+    pub fn update(&self, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.md5.update(data);
+        if let Some(ref mut sha256) = state.sha256 {
+            sha256.update(data);
+        }
+        state.hashed += data.len() as u64;
+    }
+
+    /// Digests of the whole of `path`, hashing any bytes the read stopped
+    /// short of (e.g. trailing data after the last compressed member)
+    pub fn finish(&self, path: &Path) -> Result<Digests> {
+        let hashed = self.state.lock().unwrap().hashed;
+        // A pipe cannot be read again; its digests cover the bytes counted
+        if !input::is_stream(path) {
+            self.hash_tail(path, hashed)?;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let md5 = std::mem::take(&mut state.md5);
+        Ok(Digests {
+            md5: hex(&md5.finalize()),
+            sha256: state.sha256.take().map(|sha256| hex(&sha256.finalize())),
+        })
+    }
+
+    fn hash_tail(&self, path: &Path, hashed: u64) -> Result<()> {
+        let mut file = RetryingFile::open_at(path, hashed)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let mut buffer = vec![0; TAIL_BUFFER_SIZE];
+        loop {
+            let n = file
+                .read(&mut buffer)
+                .with_context(|| format!("Failed to checksum file: {}", path.display()))?;
+            if n == 0 {
+                break;
+            }
+            self.update(&buffer[..n]);
+        }
+        Ok(())
+    }
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// An input, its checksums, and the outputs written from it
+pub struct ManifestEntry {
+    input: PathBuf,
+    size: u64,
+    pub digests: Digests,
+    outputs: Vec<PathBuf>,
+}
+
+impl ManifestEntry {
+    pub fn new(input: &Input, digests: Digests, outputs: Vec<PathBuf>) -> Result<Self> {
+        let size = input.file_size()?.unwrap_or_else(|| input.streamed_bytes());
+        Ok(Self {
+            input: input.path().to_path_buf(),
+            size,
+            digests,
+            outputs,
+        })
+    }
+}
+
+/// Write the manifest as TSV: input, size, md5, sha256, and outputs
+/// (comma-separated)
+pub fn write_manifest(path: &Path, entries: &[ManifestEntry]) -> Result<()> {
+    let partial = PartialOutput::new(path);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_path(partial.path())
+        .with_context(|| format!("Failed to create file: {}", path.display()))?;
+    writer.write_record(["input", "size", "md5", "sha256", "outputs"])?;
+    for entry in entries {
+        let outputs = entry
+            .outputs
+            .iter()
+            .map(|output| output.display().to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writer.write_record([
+            entry.input.display().to_string(),
+            entry.size.to_string(),
+            entry.digests.md5.clone(),
+            entry.digests.sha256.clone().unwrap_or_default(),
+            outputs,
+        ])?;
+    }
+    writer.flush()?;
+    drop(writer);
+    partial.finish()
+}
+
+/// MD5s an input is expected to have, from an `md5sum` listing of file names
+pub struct ExpectedMd5 {
+    source: PathBuf,
+    by_name: AHashMap<String, String>,
+}
+
+impl ExpectedMd5 {
+    /// Read `HASH  NAME` lines as `md5sum` writes them (`NAME` may be a path,
+    /// and binary mode marks it `*NAME`)
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open MD5 list: {}", path.display()))?;
+        let mut by_name = AHashMap::new();
+        for (idx, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = line
+                .split_once(char::is_whitespace)
+                .and_then(|(hash, name)| {
+                    let name = name.trim_start().trim_start_matches('*');
+                    let valid = hash.len() == 32 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+                    valid.then(|| (file_name(Path::new(name)), hash.to_ascii_lowercase()))
+                });
+            let Some((name, hash)) = parsed else {
+                bail!(
+                    "{} line {}: expected an MD5 and a file name, got '{}'",
+                    path.display(),
+                    idx + 1,
+                    line
+                );
+            };
+            by_name.insert(name, hash);
+        }
+        Ok(Self {
+            source: path.to_path_buf(),
+            by_name,
+        })
+    }
+
+    /// The MD5 listed for `input`'s file name
+    pub fn get(&self, input: &Path) -> Result<&str> {
+        match self.by_name.get(&file_name(input)) {
+            Some(hash) => Ok(hash),
+            None => bail!(
+                "{} is not listed in {}",
+                input.display(),
+                self.source.display()
+            ),
+        }
+    }
+
+    /// Fail unless the bytes counted from `input` hash to its listed MD5
+    pub fn verify(&self, input: &Path, digests: &Digests) -> Result<()> {
+        let expected = self.get(input)?;
+        if digests.md5 != expected {
+            bail!(
+                "MD5 mismatch for {}: read {}, expected {} ({}); the file is \
+                 corrupt or was not read back as stored",
+                input.display(),
+                digests.md5,
+                expected,
+                self.source.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,116 @@
+use ahash::AHashMap;
+use rayon::prelude::*;
+
+/// Most abundant rows a sequence is checked against as parents; chimeras
+/// form from the templates PCR copies most, so rarer rows add time but
+/// hardly any chimeras
+const MAX_PARENTS: usize = 512;
+
+/// Which of count-sorted `(header fields, sequence, count)` rows are chimeras: sequences whose start is
+/// that of one parent and whose remainder the end of another, as PCR
+/// template switching joins them (the bimeras of DADA2's de novo check).
+/// Parents are the `MAX_PARENTS` most abundant rows with the same header
+/// fields at least `fold` times as abundant; a row is compared on exact
+/// bases, without gaps.
+pub fn find(rows: &[(&str, &str, u64)], fold: f64) -> Vec<bool> {
+    // The most abundant rows of each set of header fields, in count order
+    let mut candidates: AHashMap<&str, Vec<usize>> = AHashMap::new();
+    for (index, row) in rows.iter().enumerate() {
+        let group = candidates.entry(row.0).or_default();
+        if group.len() < MAX_PARENTS {
+            group.push(index);
+        }
+    }
+    rows.par_iter()
+        .map(|&(fields, sequence, count)| {
+            let group = &candidates[fields];
+            let abundant = group.partition_point(|&i| rows[i].2 as f64 >= fold * count as f64);
+            let parents = group[..abundant].iter().map(|&i| rows[i].1.as_bytes());
+            is_bimera(sequence.as_bytes(), parents)
+        })
+        .collect()
+}
+
+/// Whether a prefix of one parent and a suffix of another make up `query`
+fn is_bimera<'p>(query: &[u8], parents: impl Iterator<Item = &'p [u8]>) -> bool {
+    // Best and second-best prefix and suffix matches, with their parents
+    let mut prefixes = [(0, usize::MAX); 2];
+    let mut suffixes = [(0, usize::MAX); 2];
+    for (index, parent) in parents.enumerate() {
+        let prefix = query.iter().zip(parent).take_while(|(a, b)| a == b).count();
+        let suffix = query
+            .iter()
+            .rev()
+            .zip(parent.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        // A query lying whole at either end of a parent is a truncated copy
+        // of it, not a join of two parents
+        if prefix == query.len() || suffix == query.len() {
+            continue;
+        }
+        for (best, length) in [(&mut prefixes, prefix), (&mut suffixes, suffix)] {
+            if length > best[0].0 {
+                best[1] = best[0];
+                best[0] = (length, index);
+            } else if length > best[1].0 {
+                best[1] = (length, index);
+            }
+        }
+    }
+    let joined = if prefixes[0].1 != suffixes[0].1 {
+        prefixes[0].0 + suffixes[0].0
+    } else {
+        (prefixes[0].0 + suffixes[1].0).max(prefixes[1].0 + suffixes[0].0)
+    };
+    prefixes[0].0 > 0 && suffixes[0].0 > 0 && joined >= query.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT: &str = "ACGTTGCAAGGCTTACCGATAGCTAGGCTA";
+    const RIGHT: &str = "TTGACCAGTACGGATCCATGCAATGCGTAC";
+
+    /// The first half of one parent joined to the second half of the other
+    fn chimera() -> String {
+        format!("{}{}", &LEFT[..15], &RIGHT[15..])
+    }
+
+    #[test]
+    fn a_join_of_two_abundant_parents_is_flagged() {
+        let chimera = chimera();
+        let rows = [("", LEFT, 100), ("", RIGHT, 80), ("", chimera.as_str(), 5)];
+        assert_eq!(find(&rows, 2.0), [false, false, true]);
+    }
+
+    #[test]
+    fn sequences_no_two_parents_make_up_are_not_flagged() {
+        // One base off the join, and a truncated copy of a parent
+        let mut mutant = chimera().into_bytes();
+        mutant[15] = b'A';
+        let mutant = String::from_utf8(mutant).unwrap();
+        let rows = [
+            ("", LEFT, 100),
+            ("", RIGHT, 80),
+            ("", mutant.as_str(), 5),
+            ("", &LEFT[..20], 5),
+        ];
+        assert_eq!(find(&rows, 2.0), [false; 4]);
+    }
+
+    #[test]
+    fn parents_must_be_abundant_enough_and_share_the_header_fields() {
+        let chimera = chimera();
+        let rows = [("", LEFT, 100), ("", RIGHT, 8), ("", chimera.as_str(), 5)];
+        assert_eq!(find(&rows, 2.0), [false; 3]);
+
+        let rows = [
+            ("a", LEFT, 100),
+            ("b", RIGHT, 80),
+            ("a", chimera.as_str(), 5),
+        ];
+        assert_eq!(find(&rows, 2.0), [false; 3]);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::Args;
+use clap::{ArgAction, ArgMatches, Command, ValueEnum};
+use serde_json::{Map, Value, json};
+use std::path::Path;
+
+/// The effective settings of a run: every option with the value it was given
+/// or defaults to, and the inputs resolved from the command line, file list
+/// and manifest. Values are as written on a command line, so the run can be
+/// replayed from them. Each job lists the options its input is counted with
+/// apart from the run's (`--output` and `--sample-name` values, manifest
+/// cells), as `job_options` renders them.
+pub fn resolved(
+    command: &Command,
+    matches: &ArgMatches,
+    args: &Args,
+    jobs: &[(&Path, &Args)],
+) -> Value {
+    let mut options = Map::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if matches!(arg.get_action(), ArgAction::Help | ArgAction::Version) || id == "input" {
+            continue;
+        }
+        let name = arg.get_long().unwrap_or(id).to_string();
+        let values: Option<Vec<String>> = matches.get_raw(id).map(|raw| {
+            raw.map(|value| value.to_string_lossy().into_owned())
+                .collect()
+        });
+        let value = match values {
+            None => Value::Null,
+            Some(values) => match arg.get_action() {
+                ArgAction::SetTrue | ArgAction::SetFalse => {
+                    Value::Bool(values.first().is_some_and(|value| value == "true"))
+                }
+                ArgAction::Append => json!(values),
+                // Options whose value may be left out (`--length-hist`) were
+                // given bare
+                _ => values
+                    .into_iter()
+                    .next()
+                    .map_or(Value::Bool(true), Value::String),
+            },
+        };
+        options.insert(name, value);
+    }
+    let run = job_options(args);
+    let jobs: Vec<Value> = jobs
+        .iter()
+        .map(|(input, job_args)| {
+            let mut own = job_options(job_args);
+            own.retain(|name, value| run.get(name) != Some(value));
+            json!({ "input": input.display().to_string(), "options": own })
+        })
+        .collect();
+    json!({
+        "inputs": jobs.iter().map(|job| job["input"].clone()).collect::<Vec<_>>(),
+        "options": options,
+        "jobs": jobs,
+    })
+}
+
+/// The options one input may be counted with apart from the run's
+fn job_options(args: &Args) -> Map<String, Value> {
+    let text = |value: Option<String>| value.map_or(Value::Null, Value::String);
+    let format: Vec<String> = args
+        .format
+        .iter()
+        .filter_map(|format| format.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    [
+        ("sample-name", text(args.sample_name.clone())),
+        (
+            "output",
+            text(args.output_file.as_ref().map(|p| p.display().to_string())),
+        ),
+        ("output-name", text(args.output_name.clone())),
+        ("format", json!(format)),
+        ("rpm", Value::Bool(args.rpm)),
+        (
+            "group-by-header",
+            text(
+                args.group_by_header
+                    .as_ref()
+                    .map(|r| r.as_str().to_string()),
+            ),
+        ),
+        ("skip-errors", text(args.skip_errors.map(|n| n.to_string()))),
+        ("chunk-size", Value::String(args.chunk_size.to_string())),
+        ("threads", Value::String(args.threads.to_string())),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value))
+    .collect()
+}
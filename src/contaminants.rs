@@ -0,0 +1,119 @@
+use crate::bases::reverse_complement;
+use crate::orient::kmers;
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use needletail::parse_fastx_file;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Length of the k-mers a read must share with a contaminant to match it
+pub const CONTAMINANT_K: usize = 19;
+
+/// `--contaminants` name of the built-in set
+pub const BUILTIN: &str = "adapters";
+
+/// Illumina adapter and flow-cell sequences screened for by `adapters`
+pub const ADAPTERS: [(&str, &str); 6] = [
+    ("truseq_read1", "AGATCGGAAGAGCACACGTCTGAACTCCAGTCA"),
+    ("truseq_read2", "AGATCGGAAGAGCGTCGTGTAGGGAAAGAGTGT"),
+    ("nextera", "CTGTCTCTTATACACATCT"),
+    ("small_rna", "TGGAATTCTCGGGTGCCAAGG"),
+    ("p5", "AATGATACGGCGACCACCGA"),
+    ("p7", "CAAGCAGAAGACGGCATACGA"),
+];
+
+/// What happens to reads matching a contaminant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ContaminantAction {
+    /// Leave them out of the counts
+    Remove,
+    /// Count them, naming the contaminant in a `contaminant` column
+    Flag,
+}
+
+/// Sequences screened for before counting (`--contaminants`): a read
+/// sharing a k-mer with one, on either strand, matches it
+pub struct Contaminants {
+    /// Contaminant each k-mer belongs to, the first listed where several share it
+    kmers: AHashMap<u64, usize>,
+    names: Vec<String>,
+    /// Reads matching each contaminant since `take_counts`
+    hits: Vec<AtomicU64>,
+    pub action: ContaminantAction,
+}
+
+impl Contaminants {
+    /// Load each source: the built-in set by its name, else a FASTA file
+    /// whose records are named by their first word
+    pub fn load(sources: &[String], action: ContaminantAction) -> Result<Self> {
+        let mut contaminants = Self {
+            kmers: AHashMap::new(),
+            names: Vec::new(),
+            hits: Vec::new(),
+            action,
+        };
+        for source in sources {
+            if source == BUILTIN {
+                for (name, seq) in ADAPTERS {
+                    contaminants.add(name, seq.as_bytes());
+                }
+                continue;
+            }
+            let path = Path::new(source);
+            let mut reader = parse_fastx_file(path)
+                .with_context(|| format!("Failed to open contaminants: {}", path.display()))?;
+            let known = contaminants.kmers.len();
+            while let Some(record) = reader.next() {
+                let record = record.context("Failed to read contaminant record")?;
+                let id = String::from_utf8_lossy(record.id());
+                let name = id.split_whitespace().next().unwrap_or_default().to_string();
+                contaminants.add(&name, &record.seq());
+            }
+            if contaminants.kmers.len() == known {
+                anyhow::bail!(
+                    "{} has no new {}-mers of ACGT to screen reads for",
+                    path.display(),
+                    CONTAMINANT_K
+                );
+            }
+        }
+        Ok(contaminants)
+    }
+
+    fn add(&mut self, name: &str, seq: &[u8]) {
+        // Records of one name (a multi-contig genome) are one contaminant
+        let index = match self.names.iter().position(|known| known == name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name.to_string());
+                self.hits.push(AtomicU64::new(0));
+                self.names.len() - 1
+            }
+        };
+        for kmer in kmers(seq, CONTAMINANT_K).chain(kmers(&reverse_complement(seq), CONTAMINANT_K))
+        {
+            self.kmers.entry(kmer).or_insert(index);
+        }
+    }
+
+    /// Name of the contaminant `seq` shares its first k-mer hit with, if
+    /// any. With `tally`, the read counts toward `take_counts`.
+    pub fn screen(&self, seq: &[u8], tally: bool) -> Option<&str> {
+        let index = kmers(seq, CONTAMINANT_K).find_map(|kmer| self.kmers.get(&kmer).copied())?;
+        if tally {
+            self.hits[index].fetch_add(1, Ordering::Relaxed);
+        }
+        Some(&self.names[index])
+    }
+
+    /// Reads matching each contaminant since the last call, by name in
+    /// listing order, which starts the tallies over for the next input
+    pub fn take_counts(&self) -> Vec<(String, u64)> {
+        self.names
+            .iter()
+            .zip(&self.hits)
+            .map(|(name, hits)| (name.clone(), hits.swap(0, Ordering::Relaxed)))
+            .collect()
+    }
+}
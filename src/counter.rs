@@ -0,0 +1,100 @@
+use crate::arena::{ArenaCounts, KeyBatch};
+use ahash::RandomState;
+use clap::ValueEnum;
+use dashmap::DashMap;
+use std::sync::Mutex;
+
+/// Shards per rayon thread for the `shard` backend, to keep lock contention low
+const SHARDS_PER_THREAD: usize = 4;
+
+/// How parallel workers combine their counts
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CounterBackend {
+    /// Per-chunk maps merged in a final reduce (best for low-cardinality inputs)
+    Chunked,
+    /// One concurrent map that workers insert into directly
+    Dashmap,
+    /// Mutex-guarded shards; each batch is split by shard and locked once per shard
+    Shard,
+}
+
+/// Concurrent count map shared by all workers, so no reduce step is needed
+pub enum SharedCounter {
+    Dashmap(DashMap<String, u64, RandomState>),
+    Shard {
+        hasher: RandomState,
+        shards: Vec<Mutex<ArenaCounts>>,
+    },
+}
+
+impl SharedCounter {
+    /// Shared counter for a concurrent backend (`None` for `chunked`)
+    pub fn new(backend: CounterBackend) -> Option<Self> {
+        match backend {
+            CounterBackend::Chunked => None,
+            CounterBackend::Dashmap => Some(SharedCounter::Dashmap(DashMap::with_hasher(
+                RandomState::new(),
+            ))),
+            CounterBackend::Shard => {
+                let n_shards = rayon::current_num_threads() * SHARDS_PER_THREAD;
+                Some(SharedCounter::Shard {
+                    hasher: RandomState::new(),
+                    shards: (0..n_shards)
+                        .map(|_| Mutex::new(ArenaCounts::new()))
+                        .collect(),
+                })
+            }
+        }
+    }
+
+    /// Count a batch of keys
+    pub fn add(&self, keys: KeyBatch) {
+        match self {
+            SharedCounter::Dashmap(map) => {
+                for key in keys.iter() {
+                    let key = String::from_utf8_lossy(key);
+                    // Only keys seen for the first time are allocated
+                    match map.get_mut(key.as_ref()) {
+                        Some(mut count) => *count += 1,
+                        None => *map.entry(key.into_owned()).or_insert(0) += 1,
+                    }
+                }
+            }
+            SharedCounter::Shard { hasher, shards } => {
+                let mut batches: Vec<KeyBatch> =
+                    (0..shards.len()).map(|_| KeyBatch::default()).collect();
+                for key in keys.iter() {
+                    let shard = hasher.hash_one(key) as usize % shards.len();
+                    batches[shard].push(key);
+                }
+                for (shard, batch) in shards.iter().zip(batches) {
+                    if batch.is_empty() {
+                        continue;
+                    }
+                    let mut counts = shard.lock().unwrap();
+                    for key in batch.iter() {
+                        counts.add(key);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn into_counts(self) -> ArenaCounts {
+        match self {
+            SharedCounter::Dashmap(map) => {
+                let mut counts = ArenaCounts::new();
+                for (key, count) in map {
+                    counts.add_str(&key, count);
+                }
+                counts
+            }
+            // Keys that are not UTF-8 may meet a shard's keys under
+            // replacement characters, so shards are merged rather than moved
+            SharedCounter::Shard { shards, .. } => shards
+                .into_iter()
+                .map(|shard| shard.into_inner().unwrap())
+                .fold(ArenaCounts::new(), ArenaCounts::merge),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::exit::{Classify, Failure};
+use crate::interrupt::PartialOutput;
+use ahash::{AHashSet, RandomState};
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Shards of the seen-sequence set, so parallel parsers rarely wait on each other
+const SHARDS: usize = 64;
+
+const WRITE_BUFFER_SIZE: usize = 512 * 1024; // 512KB
+
+/// Writes one read of each distinct sequence, as FASTQ when reads have
+/// qualities and FASTA otherwise, compressed by extension (.gz, .zst). The
+/// read kept is the first a parser offers: the first in the file when it is
+/// read as a stream, but for a memory-mapped input, whose ranges are parsed
+/// in parallel, whichever range's reader gets there first.
+pub struct DedupWriter {
+    path: PathBuf,
+    hasher: RandomState,
+    seen: Vec<Mutex<AHashSet<Box<[u8]>>>>,
+    output: Mutex<Output>,
+}
+
+struct Output {
+    encoder: Encoder,
+    written: u64,
+    /// First write error; later reads are no longer written
+    error: Option<io::Error>,
+}
+
+/// A file being written through the compression its extension names
+/// (.gz, .zst, or none), under a partial name until finished
+pub struct Encoder {
+    stream: Stream,
+    partial: PartialOutput,
+}
+
+enum Stream {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl DedupWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            hasher: RandomState::new(),
+            seen: (0..SHARDS).map(|_| Mutex::new(AHashSet::new())).collect(),
+            output: Mutex::new(Output {
+                encoder: Encoder::create(path)?,
+                written: 0,
+                error: None,
+            }),
+        })
+    }
+
+    /// Write the read if its sequence has not been seen yet
+    pub fn offer(&self, id: &[u8], seq: &[u8], qual: Option<&[u8]>) {
+        let shard = self.hasher.hash_one(seq) as usize % SHARDS;
+        {
+            let mut seen = self.seen[shard].lock().unwrap();
+            if seen.contains(seq) {
+                return;
+            }
+            seen.insert(seq.into());
+        }
+
+        let mut output = self.output.lock().unwrap();
+        if output.error.is_some() {
+            return;
+        }
+        match write_record(output.encoder.writer(), id, seq, qual) {
+            Ok(()) => output.written += 1,
+            Err(e) => output.error = Some(e),
+        }
+    }
+
+    /// Complete the file, returning the number of reads written
+    pub fn finish(self) -> Result<u64> {
+        let output = self.output.into_inner().unwrap();
+        let context = || format!("Failed to write file: {}", self.path.display());
+        if let Some(e) = output.error {
+            return Err(e).with_context(context).classify(Failure::Output);
+        }
+        output.encoder.finish(&self.path)?;
+        Ok(output.written)
+    }
+}
+
+impl Encoder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let partial = PartialOutput::new(path);
+        let file = File::create(partial.path())
+            .with_context(|| format!("Failed to create file: {}", path.display()))
+            .classify(Failure::Output)?;
+        let file = BufWriter::with_capacity(WRITE_BUFFER_SIZE, file);
+        let stream = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("gz") => {
+                Stream::Gzip(GzEncoder::new(file, Compression::default()))
+            }
+            Some(ext) if ext.eq_ignore_ascii_case("zst") => Stream::Zstd(
+                zstd::Encoder::new(file, 0)
+                    .with_context(|| format!("Failed to start zstd encoder: {}", path.display()))
+                    .classify(Failure::Output)?,
+            ),
+            _ => Stream::Plain(file),
+        };
+        Ok(Self { stream, partial })
+    }
+
+    pub fn writer(&mut self) -> &mut dyn Write {
+        match &mut self.stream {
+            Stream::Plain(file) => file,
+            Stream::Gzip(encoder) => encoder,
+            Stream::Zstd(encoder) => encoder,
+        }
+    }
+
+    /// Flush the compressed stream and move the file to `path`
+    pub fn finish(self, path: &Path) -> Result<()> {
+        let context = || format!("Failed to write file: {}", path.display());
+        let file = match self.stream {
+            Stream::Plain(file) => Ok(file),
+            Stream::Gzip(encoder) => encoder.finish(),
+            Stream::Zstd(encoder) => encoder.finish(),
+        };
+        file.and_then(|file| file.into_inner().map_err(|e| e.into_error()))
+            .with_context(context)
+            .classify(Failure::Output)?;
+        self.partial.finish().classify(Failure::Output)
+    }
+}
+
+fn write_record(
+    writer: &mut dyn Write,
+    id: &[u8],
+    seq: &[u8],
+    qual: Option<&[u8]>,
+) -> io::Result<()> {
+    match qual {
+        Some(qual) => {
+            writer.write_all(b"@")?;
+            writer.write_all(id)?;
+            writer.write_all(b"\n")?;
+            writer.write_all(seq)?;
+            writer.write_all(b"\n+\n")?;
+            writer.write_all(qual)?;
+        }
+        None => {
+            writer.write_all(b">")?;
+            writer.write_all(id)?;
+            writer.write_all(b"\n")?;
+            writer.write_all(seq)?;
+        }
+    }
+    writer.write_all(b"\n")
+}
@@ -0,0 +1,309 @@
+use crate::exit::{Classify, Failure};
+use crate::merge::{self, CountMatrix};
+use crate::output::{self, Column, ColumnData, OutputFormat};
+use crate::stats;
+use anyhow::Result;
+use clap::{Args as ClapArgs, ValueEnum};
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Compare treatment against control samples per sequence
+#[derive(ClapArgs, Debug, Clone)]
+pub struct DiffArgs {
+    /// Control count tables (.parquet/.csv/.tsv)
+    #[arg(long, required = true, num_args = 1..)]
+    pub control: Vec<PathBuf>,
+
+    /// Treatment count tables (.parquet/.csv/.tsv)
+    #[arg(long, required = true, num_args = 1..)]
+    pub treatment: Vec<PathBuf>,
+
+    /// Output file path
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Output format (default: inferred from the output extension, else parquet)
+    #[arg(short = 'f', long)]
+    pub format: Option<OutputFormat>,
+
+    /// Suffix stripped from input file stems to derive sample names
+    #[arg(short = 's', long, default_value = "_counts")]
+    pub suffix: String,
+
+    /// Pseudocount added to mean CPM before taking the fold change
+    #[arg(long, default_value = "1.0")]
+    pub pseudocount: f64,
+
+    /// Test treatment against the control proportion (adds p_value/q_value columns)
+    #[arg(long)]
+    pub test: Option<EnrichmentTest>,
+
+    #[arg(long, default_value = "snappy", help = crate::output::COMPRESSION_HELP)]
+    pub compression: String,
+
+    /// Disable progress output
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum EnrichmentTest {
+    /// Exact binomial test of treatment reads against the control proportion
+    Binomial,
+    /// Pearson chi-square test on the pooled 2×2 read table
+    ChiSquare,
+}
+
+/// Per-sequence group summaries, in matrix row order
+struct Comparison {
+    control_mean: Vec<f64>,
+    treatment_mean: Vec<f64>,
+    control_cpm: Vec<f64>,
+    treatment_cpm: Vec<f64>,
+    log2_fold_change: Vec<f64>,
+}
+
+pub fn run(args: &DiffArgs) -> Result<()> {
+    let start_time = Instant::now();
+    let format = args
+        .format
+        .clone()
+        .or_else(|| OutputFormat::from_path(&args.output))
+        .unwrap_or(OutputFormat::Parquet);
+
+    if !args.quiet {
+        eprintln!("🧬 seqtable v0.1.1 diff");
+        eprintln!("🧪 Control samples: {}", args.control.len());
+        eprintln!("🧪 Treatment samples: {}", args.treatment.len());
+        eprintln!("📊 Output format: {:?}", format);
+        eprintln!();
+    }
+
+    let inputs: Vec<PathBuf> = args
+        .control
+        .iter()
+        .chain(&args.treatment)
+        .cloned()
+        .collect();
+    let matrix = merge::load_matrix(&inputs, &args.suffix, args.quiet)?;
+    let comparison = compare(&matrix, args.control.len(), args.pseudocount);
+
+    // Order rows from most enriched to most depleted
+    let mut order: Vec<usize> = (0..matrix.sequences.len()).collect();
+    order.sort_by(|&a, &b| {
+        comparison.log2_fold_change[b].total_cmp(&comparison.log2_fold_change[a])
+    });
+
+    let z_scores = z_scores(&comparison.log2_fold_change);
+    let pick = |values: &[f64]| order.iter().map(|&i| values[i]).collect::<Vec<_>>();
+
+    let mut columns = vec![
+        Column::new(
+            &matrix.key_column,
+            ColumnData::Text(order.iter().map(|&i| matrix.sequences[i].clone()).collect()),
+        ),
+        Column::new(
+            "control_mean",
+            ColumnData::Float(pick(&comparison.control_mean), 2),
+        ),
+        Column::new(
+            "treatment_mean",
+            ColumnData::Float(pick(&comparison.treatment_mean), 2),
+        ),
+        Column::new(
+            "control_cpm",
+            ColumnData::Float(pick(&comparison.control_cpm), 2),
+        ),
+        Column::new(
+            "treatment_cpm",
+            ColumnData::Float(pick(&comparison.treatment_cpm), 2),
+        ),
+        Column::new(
+            "log2_fold_change",
+            ColumnData::Float(pick(&comparison.log2_fold_change), 4),
+        ),
+        Column::new("z_score", ColumnData::Float(pick(&z_scores), 4)),
+    ];
+
+    if let Some(test) = args.test {
+        let p_values = test_enrichment(&matrix, args.control.len(), test);
+        let q_values = stats::benjamini_hochberg(&p_values);
+
+        if !args.quiet {
+            let hits = q_values.iter().filter(|&&q| q < 0.05).count();
+            eprintln!("\n   🧮 {:?} test: {} sequences with q < 0.05", test, hits);
+        }
+
+        columns.push(Column::new(
+            "p_value",
+            ColumnData::Scientific(pick(&p_values)),
+        ));
+        columns.push(Column::new(
+            "q_value",
+            ColumnData::Scientific(pick(&q_values)),
+        ));
+    }
+
+    if !args.quiet {
+        eprint!("\n   💾 Saving to {}...", format.extension().to_uppercase());
+        std::io::Write::flush(&mut std::io::stderr()).ok();
+    }
+
+    output::save_table(&columns, &args.output, &format, &args.compression)
+        .classify(Failure::Output)?;
+
+    if !args.quiet {
+        eprintln!(" Done!");
+        eprintln!(
+            "   ✓ {} sequences compared → {}",
+            matrix.sequences.len(),
+            args.output.display()
+        );
+        eprintln!(
+            "   ⏱️  Processing time: {:.2}s",
+            start_time.elapsed().as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
+
+/// Mean raw counts, mean CPM, and log2((treatment + pc) / (control + pc)) per row;
+/// the first `n_control` matrix samples are controls, the rest treatments
+fn compare(matrix: &CountMatrix, n_control: usize, pseudocount: f64) -> Comparison {
+    let totals: Vec<f64> = matrix
+        .counts
+        .iter()
+        .map(|column| column.iter().sum::<u64>().max(1) as f64)
+        .collect();
+
+    let group_means = |row: usize, samples: std::ops::Range<usize>| {
+        let n = samples.len() as f64;
+        let (raw, cpm) = samples.fold((0.0, 0.0), |(raw, cpm), s| {
+            let count = matrix.counts[s][row] as f64;
+            (raw + count, cpm + count / totals[s] * 1_000_000.0)
+        });
+        (raw / n, cpm / n)
+    };
+
+    let n_rows = matrix.sequences.len();
+    let n_samples = matrix.samples.len();
+    let mut comparison = Comparison {
+        control_mean: Vec::with_capacity(n_rows),
+        treatment_mean: Vec::with_capacity(n_rows),
+        control_cpm: Vec::with_capacity(n_rows),
+        treatment_cpm: Vec::with_capacity(n_rows),
+        log2_fold_change: Vec::with_capacity(n_rows),
+    };
+
+    for row in 0..n_rows {
+        let (control_mean, control_cpm) = group_means(row, 0..n_control);
+        let (treatment_mean, treatment_cpm) = group_means(row, n_control..n_samples);
+        comparison.control_mean.push(control_mean);
+        comparison.treatment_mean.push(treatment_mean);
+        comparison.control_cpm.push(control_cpm);
+        comparison.treatment_cpm.push(treatment_cpm);
+        comparison
+            .log2_fold_change
+            .push(((treatment_cpm + pseudocount) / (control_cpm + pseudocount)).log2());
+    }
+
+    comparison
+}
+
+/// Per-row p-values from pooled group read counts. The control proportion gets
+/// a +0.5 offset so sequences absent from controls can still be tested; with
+/// no control reads at all there is no proportion, and every p-value is NaN.
+fn test_enrichment(matrix: &CountMatrix, n_control: usize, test: EnrichmentTest) -> Vec<f64> {
+    let pooled = |row: usize, samples: std::ops::Range<usize>| -> u64 {
+        samples.map(|s| matrix.counts[s][row]).sum()
+    };
+    let n_samples = matrix.samples.len();
+    let total = |samples: std::ops::Range<usize>| -> u64 {
+        samples.map(|s| matrix.counts[s].iter().sum::<u64>()).sum()
+    };
+    let control_total = total(0..n_control);
+    let treatment_total = total(n_control..n_samples);
+    if control_total == 0 {
+        return vec![f64::NAN; matrix.sequences.len()];
+    }
+
+    (0..matrix.sequences.len())
+        .map(|row| {
+            let control = pooled(row, 0..n_control);
+            let treatment = pooled(row, n_control..n_samples);
+            match test {
+                EnrichmentTest::Binomial => {
+                    let p = (control as f64 + 0.5) / (control_total as f64 + 1.0);
+                    stats::binomial_test(treatment, treatment_total, p)
+                }
+                EnrichmentTest::ChiSquare => {
+                    stats::chi_square_test(control, control_total, treatment, treatment_total)
+                }
+            }
+        })
+        .collect()
+}
+
+/// Standardize values against their own mean and standard deviation
+fn z_scores(values: &[f64]) -> Vec<f64> {
+    let n = values.len().max(1) as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let sd = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+    values
+        .iter()
+        .map(|v| if sd > 0.0 { (v - mean) / sd } else { 0.0 })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Controls first, then treatments, one row per sequence
+    fn matrix(rows: &[[u64; 2]]) -> CountMatrix {
+        CountMatrix {
+            key_column: "sequence".to_string(),
+            samples: vec!["control".to_string(), "treated".to_string()],
+            sequences: (0..rows.len()).map(|i| format!("seq{}", i)).collect(),
+            counts: (0..2)
+                .map(|s| rows.iter().map(|row| row[s]).collect())
+                .collect(),
+            normalized: None,
+            sample_metadata: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reads_absent_from_controls_are_tested_against_the_offset_proportion() {
+        // seq0 is new in treatment: one read is chance, fifty are not
+        let few = matrix(&[[0, 1], [10_000, 9_999]]);
+        let many = matrix(&[[0, 50], [10_000, 9_950]]);
+        for test in [EnrichmentTest::Binomial, EnrichmentTest::ChiSquare] {
+            let p = test_enrichment(&few, 1, test)[0];
+            assert!(p > 0.05, "{:?}: {}", test, p);
+            let p = test_enrichment(&many, 1, test)[0];
+            assert!(p > 0.0 && p < 1e-6, "{:?}: {}", test, p);
+        }
+    }
+
+    #[test]
+    fn enrichment_stands_out_from_unchanged_sequences() {
+        let rows = matrix(&[[100, 300], [100, 100], [9_800, 9_600]]);
+        let p = test_enrichment(&rows, 1, EnrichmentTest::Binomial);
+        assert!(p[0] < 1e-10, "{:?}", p);
+        assert!(p[1] > 0.5, "{:?}", p);
+
+        let comparison = compare(&rows, 1, 1.0);
+        assert!(comparison.log2_fold_change[0] > 1.5 && comparison.log2_fold_change[0] < 1.6);
+        assert!(comparison.log2_fold_change[1].abs() < 0.01);
+    }
+
+    #[test]
+    fn no_control_reads_give_no_p_values() {
+        let rows = matrix(&[[0, 5], [0, 7]]);
+        for test in [EnrichmentTest::Binomial, EnrichmentTest::ChiSquare] {
+            assert!(test_enrichment(&rows, 1, test).iter().all(|p| p.is_nan()));
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use serde_json::{Value, json};
+
+/// Alpha diversity and skew of one sample's counts (`--diversity`)
+pub struct Diversity {
+    pub sample: String,
+    /// Distinct sequences counted
+    pub richness: usize,
+    /// Bias-corrected Chao1 estimate of the richness, sequences not yet
+    /// seen included, from how many were seen once and twice
+    pub chao1: f64,
+    /// Shannon entropy, in nats
+    pub shannon: f64,
+    /// Gini-Simpson index: the chance two reads drawn at random differ
+    pub simpson: f64,
+    /// Inverse Simpson index, the effective number of equally common sequences
+    pub inverse_simpson: f64,
+    /// Pielou's evenness, Shannon entropy over its maximum for the richness;
+    /// undefined below two sequences
+    pub evenness: Option<f64>,
+    /// What the skew metrics cover
+    pub skew_over: SkewPopulation,
+    /// Gini coefficient: 0 when every sequence has the same count, toward 1
+    /// as a few take all the reads
+    pub gini: f64,
+    /// 90th over 10th percentile count; infinite when a tenth of a library
+    /// got no reads
+    pub ratio_90_10: f64,
+    /// Area under the Lorenz curve (reads held by the rarest share of
+    /// sequences), 0.5 for a perfectly even library
+    pub lorenz_auc: f64,
+}
+
+/// Sequences the skew metrics are computed over
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SkewPopulation {
+    /// Those counted at least once
+    Observed,
+    /// The members of the expected library or reference, those without
+    /// reads included
+    Library(usize),
+}
+
+impl Diversity {
+    /// Diversity and skew of the sequences observed
+    pub fn from_counts(sample: &str, counts: impl Iterator<Item = u64>) -> Self {
+        let mut counts: Vec<u64> = counts.filter(|&count| count > 0).collect();
+        counts.sort_unstable();
+        let total = counts.iter().sum::<u64>().max(1) as f64;
+        let (mut shannon, mut dominance) = (0.0, 0.0);
+        for &count in &counts {
+            let p = count as f64 / total;
+            shannon -= p * p.ln();
+            dominance += p * p;
+        }
+        let richness = counts.len();
+        let singletons = counts.iter().filter(|&&count| count == 1).count() as f64;
+        let doubletons = counts.iter().filter(|&&count| count == 2).count() as f64;
+        let (gini, ratio_90_10, lorenz_auc) = skew(&counts);
+        Self {
+            sample: sample.to_string(),
+            richness,
+            chao1: richness as f64 + singletons * (singletons - 1.0) / (2.0 * (doubletons + 1.0)),
+            shannon,
+            simpson: if richness > 0 { 1.0 - dominance } else { 0.0 },
+            inverse_simpson: if richness > 0 { 1.0 / dominance } else { 0.0 },
+            evenness: (richness > 1).then(|| shannon / (richness as f64).ln()),
+            skew_over: SkewPopulation::Observed,
+            gini,
+            ratio_90_10,
+            lorenz_auc,
+        }
+    }
+
+    /// Skew over the reads of each library member instead, so members that
+    /// dropped out count toward it
+    pub fn with_library_skew(mut self, mut members: Vec<u64>) -> Self {
+        members.sort_unstable();
+        (self.gini, self.ratio_90_10, self.lorenz_auc) = skew(&members);
+        self.skew_over = SkewPopulation::Library(members.len());
+        self
+    }
+
+    /// One line for the per-input report
+    pub fn describe(&self) -> String {
+        let mut line = format!(
+            "{} sequences (Chao1 {:.1}), Shannon {:.3}, Simpson {:.4}, inverse Simpson {:.2}",
+            self.richness, self.chao1, self.shannon, self.simpson, self.inverse_simpson
+        );
+        if let Some(evenness) = self.evenness {
+            line.push_str(&format!(", evenness {:.3}", evenness));
+        }
+        line
+    }
+
+    /// The skew line of the per-input report
+    pub fn describe_skew(&self) -> String {
+        let mut line = format!(
+            "Gini {:.3}, 90/10 ratio {:.2}, Lorenz AUC {:.3}",
+            self.gini, self.ratio_90_10, self.lorenz_auc
+        );
+        if let SkewPopulation::Library(members) = self.skew_over {
+            line.push_str(&format!(" (over {} library members)", members));
+        }
+        line
+    }
+
+    pub fn to_json(&self) -> Value {
+        let (skew_over, library_size) = match self.skew_over {
+            SkewPopulation::Observed => ("observed", None),
+            SkewPopulation::Library(members) => ("library", Some(members)),
+        };
+        json!({
+            "sample": self.sample,
+            "richness": self.richness,
+            "chao1": self.chao1,
+            "shannon": self.shannon,
+            "simpson": self.simpson,
+            "inverse_simpson": self.inverse_simpson,
+            "evenness": self.evenness,
+            "skew_over": skew_over,
+            "library_size": library_size,
+            "gini": self.gini,
+            // Infinity has no JSON form
+            "ratio_90_10": self.ratio_90_10.is_finite().then_some(self.ratio_90_10),
+            "lorenz_auc": self.lorenz_auc,
+        })
+    }
+}
+
+/// Gini coefficient, 90/10 ratio and Lorenz AUC of ascending `counts`
+fn skew(counts: &[u64]) -> (f64, f64, f64) {
+    // Nothing to measure without reads
+    let total = counts.iter().sum::<u64>() as f64;
+    if total == 0.0 {
+        return (0.0, 0.0, 0.0);
+    }
+    // Trapezoids under the Lorenz curve, sequences taken rarest first
+    let mut lorenz_auc = 0.0;
+    let mut held = 0.0;
+    for &count in counts {
+        let next = held + count as f64 / total;
+        lorenz_auc += (held + next) / 2.0 / counts.len() as f64;
+        held = next;
+    }
+    let ratio_90_10 = percentile(counts, 0.9) / percentile(counts, 0.1);
+    (1.0 - 2.0 * lorenz_auc, ratio_90_10, lorenz_auc)
+}
+
+/// The `q` quantile of ascending `counts`, interpolating between ranks
+fn percentile(counts: &[u64], q: f64) -> f64 {
+    let rank = q * (counts.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    let fraction = rank - low as f64;
+    counts[low] as f64 + (counts[high] as f64 - counts[low] as f64) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-12,
+            "{} vs {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn alpha_diversity_matches_hand_computed_values() {
+        // Proportions 1/4, 1/4, 1/2; the zero is not a sequence seen
+        let diversity = Diversity::from_counts("a", [1, 0, 2, 1].into_iter());
+        assert_eq!(diversity.richness, 3);
+        assert_close(diversity.shannon, 1.5 * 2f64.ln());
+        assert_close(diversity.simpson, 1.0 - (0.0625 + 0.0625 + 0.25));
+        assert_close(diversity.inverse_simpson, 1.0 / 0.375);
+        assert_close(diversity.evenness.unwrap(), 1.5 * 2f64.ln() / 3f64.ln());
+        // Two singletons and one doubleton: 3 + 2 × 1 / (2 × 2)
+        assert_close(diversity.chao1, 3.5);
+    }
+
+    #[test]
+    fn chao1_adds_nothing_without_repeated_singletons() {
+        let diversity = Diversity::from_counts("a", [5, 1, 3].into_iter());
+        assert_close(diversity.chao1, 3.0);
+        // Three singletons, no doubletons: 4 + 3 × 2 / 2
+        let diversity = Diversity::from_counts("a", [1, 1, 1, 9].into_iter());
+        assert_close(diversity.chao1, 7.0);
+    }
+
+    #[test]
+    fn one_sequence_has_no_evenness_and_no_reads_no_diversity() {
+        let single = Diversity::from_counts("a", [7].into_iter());
+        assert_eq!(
+            (single.shannon, single.simpson, single.evenness),
+            (0.0, 0.0, None)
+        );
+        assert_close(single.inverse_simpson, 1.0);
+
+        let empty = Diversity::from_counts("a", std::iter::empty());
+        assert_eq!(
+            (empty.richness, empty.simpson, empty.inverse_simpson),
+            (0, 0.0, 0.0)
+        );
+        assert_eq!((empty.gini, empty.lorenz_auc), (0.0, 0.0));
+    }
+
+    #[test]
+    fn skew_is_zero_for_an_even_library_and_counts_dropouts() {
+        let even = Diversity::from_counts("a", [5, 5, 5, 5].into_iter());
+        assert_close(even.gini, 0.0);
+        assert_close(even.lorenz_auc, 0.5);
+        assert_close(even.ratio_90_10, 1.0);
+
+        // One of four members holds every read: the Lorenz curve rises only
+        // over the last quarter, from 0 to 1
+        let skewed = even.with_library_skew(vec![0, 0, 0, 20]);
+        assert_eq!(skewed.skew_over, SkewPopulation::Library(4));
+        assert_close(skewed.lorenz_auc, 0.125);
+        assert_close(skewed.gini, 0.75);
+        assert!(skewed.ratio_90_10.is_infinite());
+        assert!(skewed.to_json()["ratio_90_10"].is_null());
+    }
+}
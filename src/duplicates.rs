@@ -0,0 +1,129 @@
+use crate::checksum::Checksum;
+use crate::input;
+use crate::retry::RetryingFile;
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use md5::{Digest, Md5};
+use std::collections::hash_map::Entry;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Leading bytes compared before files of the same size are hashed whole
+const PREFIX_LEN: u64 = 64 * 1024;
+
+/// What to do with an input listed more than once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DuplicateInputs {
+    /// Count it once, warning about the repeats
+    Skip,
+    /// Warn, but count every listing
+    Warn,
+    /// Fail before counting anything
+    Error,
+}
+
+/// An input repeating an earlier one
+pub struct Duplicate {
+    pub index: usize,
+    /// Index of the earlier input
+    pub first: usize,
+    /// Same bytes under another name (a copy), rather than the same file
+    pub copy: bool,
+}
+
+impl Duplicate {
+    /// How the input repeats the earlier one, for messages
+    pub fn describe(&self, paths: &[&Path]) -> String {
+        format!(
+            "{} {} {}",
+            paths[self.index].display(),
+            if self.copy {
+                "has the same contents as"
+            } else {
+                "is the same file as"
+            },
+            paths[self.first].display()
+        )
+    }
+}
+
+/// Inputs repeating an earlier one, in input order: the same file by
+/// canonical path (`./a.fq` and `a.fq`, symlinks), or a copy, found by MD5
+/// among files of the same size whose first 64 KiB match. Pipes, and paths that cannot be resolved,
+/// are left for the count to read or report.
+pub fn find(paths: &[&Path]) -> Result<Vec<Duplicate>> {
+    let mut seen: AHashMap<PathBuf, usize> = AHashMap::new();
+    let mut by_size: AHashMap<u64, Vec<usize>> = AHashMap::new();
+    let mut duplicates = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        if input::is_stream(path) {
+            continue;
+        }
+        let Ok(canonical) = std::fs::canonicalize(path) else {
+            continue;
+        };
+        match seen.entry(canonical) {
+            Entry::Occupied(entry) => duplicates.push(Duplicate {
+                index,
+                first: *entry.get(),
+                copy: false,
+            }),
+            Entry::Vacant(entry) => {
+                entry.insert(index);
+                // Empty files are all alike, and cost nothing to count twice
+                if let Ok(Some(size)) = input::file_size(path) {
+                    if size > 0 {
+                        by_size.entry(size).or_default().push(index);
+                    }
+                }
+            }
+        }
+    }
+
+    // Runs of one sequencer can share a size, but rarely their first reads,
+    // so only files alike in both are hashed whole
+    for (&size, group) in by_size.iter().filter(|(_, group)| group.len() > 1) {
+        let mut by_prefix: AHashMap<Vec<u8>, Vec<usize>> = AHashMap::new();
+        for &index in group {
+            by_prefix
+                .entry(prefix_md5(paths[index])?)
+                .or_default()
+                .push(index);
+        }
+        for (prefix, candidates) in by_prefix.iter().filter(|(_, group)| group.len() > 1) {
+            let mut by_md5: AHashMap<Vec<u8>, usize> = AHashMap::new();
+            for &index in candidates {
+                // A file no longer than the prefix was hashed whole already
+                let md5 = if size <= PREFIX_LEN {
+                    prefix.clone()
+                } else {
+                    Checksum::new(false).finish(paths[index])?.md5.into_bytes()
+                };
+                match by_md5.entry(md5) {
+                    Entry::Occupied(entry) => duplicates.push(Duplicate {
+                        index,
+                        first: *entry.get(),
+                        copy: true,
+                    }),
+                    Entry::Vacant(entry) => {
+                        entry.insert(index);
+                    }
+                }
+            }
+        }
+    }
+    duplicates.sort_by_key(|duplicate| duplicate.index);
+    Ok(duplicates)
+}
+
+/// MD5 of the first `PREFIX_LEN` bytes of `path`
+fn prefix_md5(path: &Path) -> Result<Vec<u8>> {
+    let file = RetryingFile::open(path)
+        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+    let mut prefix = Vec::new();
+    file.take(PREFIX_LEN)
+        .read_to_end(&mut prefix)
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+    Ok(Md5::digest(&prefix).to_vec())
+}
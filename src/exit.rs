@@ -0,0 +1,121 @@
+use crate::interrupt::PartialOutput;
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Exit status for a failure of no particular kind
+const GENERAL_EXIT_CODE: i32 = 1;
+
+/// Why a run failed, each with its own exit status so orchestration layers
+/// can tell a bad input from a full disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Options that contradict each other or name nothing; also clap's status
+    /// for arguments it cannot parse
+    Usage,
+    /// An input could not be read or parsed
+    Input,
+    /// An output could not be written
+    Output,
+    /// `--keep-going` counted some inputs but others failed
+    PartialBatch,
+}
+
+impl Failure {
+    pub fn code(self) -> i32 {
+        match self {
+            Failure::Usage => 2,
+            Failure::Input => 3,
+            Failure::Output => 4,
+            Failure::PartialBatch => 5,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Failure::Usage => "usage",
+            Failure::Input => "input",
+            Failure::Output => "output",
+            Failure::PartialBatch => "partial_batch",
+        }
+    }
+}
+
+/// An error tagged with its kind of failure; it displays as the error it
+/// wraps, so the tag never shows in messages
+#[derive(Debug)]
+struct Classified {
+    failure: Failure,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for Classified {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Tag an error with the kind of failure it is, as `.context` adds a message
+pub trait Classify<T> {
+    fn classify(self, failure: Failure) -> Result<T>;
+}
+
+impl<T> Classify<T> for Result<T> {
+    fn classify(self, failure: Failure) -> Result<T> {
+        self.map_err(|error| {
+            // The innermost tag is the most specific, so it is kept
+            if failure_of(&error).is_some() {
+                error
+            } else {
+                Classified { failure, error }.into()
+            }
+        })
+    }
+}
+
+/// Fail as `Failure::Usage`: `anyhow::bail!` for options that cannot be used
+/// as given
+pub fn usage<T>(message: String) -> Result<T> {
+    Err(anyhow::Error::msg(message)).classify(Failure::Usage)
+}
+
+/// The kind of failure tagged anywhere in the error's chain
+pub fn failure_of(error: &anyhow::Error) -> Option<Failure> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<Classified>())
+        .map(|classified| classified.failure)
+}
+
+/// Exit status for a run that stopped with `error`
+pub fn code(error: &anyhow::Error) -> i32 {
+    failure_of(error).map_or(GENERAL_EXIT_CODE, Failure::code)
+}
+
+/// Write `--error-json`: the failure kind, exit status, message, and the
+/// chain of causes
+pub fn write_error_json(path: &Path, error: &anyhow::Error) -> Result<()> {
+    let report = json!({
+        "kind": failure_of(error).map_or("other", Failure::name),
+        "exit_code": code(error),
+        "message": error.to_string(),
+        "causes": error.chain().skip(1).map(|cause| cause.to_string()).collect::<Vec<_>>(),
+    });
+    let partial = PartialOutput::new(path);
+    let file = File::create(partial.path())
+        .with_context(|| format!("Failed to create file: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, &report).context("Failed to write error report")?;
+    writeln!(writer)?;
+    writer.flush()?;
+    partial.finish()
+}
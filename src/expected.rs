@@ -0,0 +1,108 @@
+use crate::arena::ArenaCounts;
+use crate::interrupt::PartialOutput;
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Sequences a library is expected to contain (guides, barcodes, ...)
+pub struct ExpectedSet {
+    entries: Vec<(String, String)>,
+}
+
+/// Detection summary for one input
+pub struct Recovery {
+    pub detected: usize,
+    pub total: usize,
+    pub reads: u64,
+}
+
+impl ExpectedSet {
+    /// Load one entry per line: `SEQUENCE` or `NAME<whitespace>SEQUENCE`.
+    /// Blank lines and `#` comments are ignored.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open expected list: {}", path.display()))?;
+
+        let mut entries = Vec::new();
+        for (line_no, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.context("Failed to read expected list")?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (name, sequence) = match fields.as_slice() {
+                [sequence] => (*sequence, *sequence),
+                [name, sequence] => (*name, *sequence),
+                _ => anyhow::bail!(
+                    "{}:{}: expected `SEQUENCE` or `NAME SEQUENCE`",
+                    path.display(),
+                    line_no + 1
+                ),
+            };
+            entries.push((name.to_string(), sequence.to_ascii_uppercase()));
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Write a per-sequence detection table and return the overall recovery
+    pub fn write_report(&self, counts: &ArenaCounts, output_path: &Path) -> Result<Recovery> {
+        let partial = PartialOutput::new(output_path);
+        let file = File::create(partial.path())
+            .with_context(|| format!("Failed to create file: {}", output_path.display()))?;
+        let mut writer = BufWriter::new(file);
+
+        writeln!(writer, "name\tsequence\tcount\tdetected")?;
+
+        let mut recovery = Recovery {
+            detected: 0,
+            total: self.entries.len(),
+            reads: 0,
+        };
+        for ((name, sequence), count) in self.entries.iter().zip(self.entry_counts(counts)) {
+            if count > 0 {
+                recovery.detected += 1;
+                recovery.reads += count;
+            }
+            writeln!(writer, "{}\t{}\t{}\t{}", name, sequence, count, count > 0)?;
+        }
+
+        writer.flush()?;
+        partial.finish()?;
+        Ok(recovery)
+    }
+}
+
+impl ExpectedSet {
+    /// Reads of each entry in listing order, none for entries no read matched
+    pub fn entry_counts(&self, counts: &ArenaCounts) -> Vec<u64> {
+        // Library sequences are uppercased; soft-masked (lowercase) reads
+        // count toward the same entries
+        let mut folded: AHashMap<String, u64> = AHashMap::new();
+        for (sequence, count) in counts.iter() {
+            if sequence.bytes().any(|b| b.is_ascii_lowercase()) {
+                *folded.entry(sequence.to_ascii_uppercase()).or_insert(0) += count;
+            }
+        }
+        self.entries
+            .iter()
+            .map(|(_, sequence)| {
+                counts.get(sequence).unwrap_or(0) + folded.get(sequence).copied().unwrap_or(0)
+            })
+            .collect()
+    }
+}
+
+impl Recovery {
+    pub fn rate(&self) -> f64 {
+        self.detected as f64 / self.total.max(1) as f64 * 100.0
+    }
+}
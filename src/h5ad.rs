@@ -0,0 +1,224 @@
+use crate::interrupt::PartialOutput;
+use crate::merge::CountMatrix;
+use crate::output::{Column, ColumnData};
+use anyhow::{Context, Result};
+use hdf5_pure::{AttrValue, FileBuilder};
+use std::path::Path;
+
+/// Write the merged matrix as an AnnData `.h5ad` file: samples are
+/// observations (`obs`), sequences are variables (`var`), and `X` holds raw
+/// counts as a CSR matrix. Normalized values go to `layers/normalized`.
+pub fn save_h5ad(matrix: &CountMatrix, output_path: &Path) -> Result<()> {
+    let mut builder = FileBuilder::new();
+    builder.set_attr("encoding-type", string("anndata"));
+    builder.set_attr("encoding-version", string("0.1.0"));
+
+    let n_obs = matrix.samples.len();
+    let n_var = matrix.sequences.len();
+    let shape = AttrValue::I64Array(vec![n_obs as i64, n_var as i64]);
+
+    let mut x = builder.create_group("X");
+    let (data, indices, indptr) = csr(matrix, |sample, row| matrix.counts[sample][row] as f64);
+    write_csr(&mut x, shape.clone(), &data, &indices, &indptr);
+    builder.add_group(x.finish());
+
+    let mut layers = builder.create_group("layers");
+    set_encoding(&mut layers, "dict", "0.1.0");
+    if let Some(ref normalized) = matrix.normalized {
+        let mut layer = layers.create_group("normalized");
+        let (data, indices, indptr) = csr(matrix, |sample, row| normalized[sample][row]);
+        write_csr(&mut layer, shape, &data, &indices, &indptr);
+        layers.add_group(layer.finish());
+    }
+    builder.add_group(layers.finish());
+
+    // Per-sample and per-sequence summaries, named as Scanpy's calculate_qc_metrics does
+    let mut obs_columns = vec![
+        Column::new(
+            "total_counts",
+            ColumnData::Count(matrix.counts.iter().map(|c| c.iter().sum()).collect()),
+        ),
+        Column::new(
+            "n_genes_by_counts",
+            ColumnData::Count(
+                matrix
+                    .counts
+                    .iter()
+                    .map(|c| c.iter().filter(|&&v| v > 0).count() as u64)
+                    .collect(),
+            ),
+        ),
+    ];
+    for (name, values) in &matrix.sample_metadata {
+        obs_columns.push(Column::new(name, ColumnData::Text(values.clone())));
+    }
+
+    let var_columns = vec![
+        Column::new(
+            "total_counts",
+            ColumnData::Count(
+                (0..n_var)
+                    .map(|row| matrix.counts.iter().map(|c| c[row]).sum())
+                    .collect(),
+            ),
+        ),
+        Column::new(
+            "n_cells_by_counts",
+            ColumnData::Count(
+                (0..n_var)
+                    .map(|row| matrix.counts.iter().filter(|c| c[row] > 0).count() as u64)
+                    .collect(),
+            ),
+        ),
+    ];
+
+    let mut obs = builder.create_group("obs");
+    write_dataframe(&mut obs, &matrix.samples, &obs_columns);
+    builder.add_group(obs.finish());
+
+    let mut var = builder.create_group("var");
+    write_dataframe(&mut var, &matrix.sequences, &var_columns);
+    builder.add_group(var.finish());
+
+    for name in ["obsm", "varm", "obsp", "varp", "uns"] {
+        let mut group = builder.create_group(name);
+        set_encoding(&mut group, "dict", "0.1.0");
+        builder.add_group(group.finish());
+    }
+
+    let partial = PartialOutput::new(output_path);
+    builder
+        .write(partial.path())
+        .with_context(|| format!("Failed to write h5ad file: {}", output_path.display()))?;
+    partial.finish()?;
+    Ok(())
+}
+
+fn string(value: &str) -> AttrValue {
+    AttrValue::VarLenString(value.to_string())
+}
+
+fn set_encoding(group: &mut hdf5_pure::GroupBuilder, encoding: &str, version: &str) {
+    group.set_attr("encoding-type", string(encoding));
+    group.set_attr("encoding-version", string(version));
+}
+
+/// Nonzero entries of the samples × sequences matrix in CSR order
+fn csr(
+    matrix: &CountMatrix,
+    value: impl Fn(usize, usize) -> f64,
+) -> (Vec<f64>, Vec<i64>, Vec<i64>) {
+    let mut data = Vec::new();
+    let mut indices = Vec::new();
+    let mut indptr = Vec::with_capacity(matrix.samples.len() + 1);
+    indptr.push(0);
+    for (sample, column) in matrix.counts.iter().enumerate() {
+        for (row, _) in column.iter().enumerate().filter(|(_, c)| **c > 0) {
+            data.push(value(sample, row));
+            indices.push(row as i64);
+        }
+        indptr.push(data.len() as i64);
+    }
+    (data, indices, indptr)
+}
+
+fn write_csr(
+    group: &mut hdf5_pure::GroupBuilder,
+    shape: AttrValue,
+    data: &[f64],
+    indices: &[i64],
+    indptr: &[i64],
+) {
+    set_encoding(group, "csr_matrix", "0.1.0");
+    group.set_attr("shape", shape);
+    group.create_dataset("data").with_f64_data(data);
+    group.create_dataset("indices").with_i64_data(indices);
+    group.create_dataset("indptr").with_i64_data(indptr);
+}
+
+/// AnnData dataframe: string index plus one dataset per column
+fn write_dataframe(group: &mut hdf5_pure::GroupBuilder, index: &[String], columns: &[Column]) {
+    set_encoding(group, "dataframe", "0.2.0");
+    group.set_attr("_index", string("_index"));
+    group.set_attr(
+        "column-order",
+        AttrValue::VarLenStringArray(columns.iter().map(|c| c.name.clone()).collect()),
+    );
+    write_strings(group, "_index", index);
+
+    for column in columns {
+        match &column.data {
+            ColumnData::Text(values) => write_strings(group, &column.name, values),
+            ColumnData::Count(values) => {
+                let values: Vec<i64> = values.iter().map(|&v| v as i64).collect();
+                group
+                    .create_dataset(&column.name)
+                    .with_i64_data(&values)
+                    .set_attr("encoding-type", string("array"))
+                    .set_attr("encoding-version", string("0.2.0"));
+            }
+            ColumnData::Float(values, _) | ColumnData::Scientific(values) => {
+                group
+                    .create_dataset(&column.name)
+                    .with_f64_data(values)
+                    .set_attr("encoding-type", string("array"))
+                    .set_attr("encoding-version", string("0.2.0"));
+            }
+        }
+    }
+}
+
+fn write_strings(group: &mut hdf5_pure::GroupBuilder, name: &str, values: &[String]) {
+    let values: Vec<&str> = values.iter().map(String::as_str).collect();
+    group
+        .create_dataset(name)
+        .with_vlen_strings(&values)
+        .set_attr("encoding-type", string("string-array"))
+        .set_attr("encoding-version", string("0.2.0"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matrices_read_back_as_anndata_lays_them_out() {
+        let matrix = CountMatrix {
+            key_column: "sequence".to_string(),
+            samples: vec!["a".to_string(), "b".to_string()],
+            sequences: vec!["AAA".to_string(), "CCC".to_string(), "GGG".to_string()],
+            counts: vec![vec![5, 0, 2], vec![0, 3, 1]],
+            normalized: Some(vec![vec![0.5, 0.0, 0.2], vec![0.0, 0.75, 0.25]]),
+            sample_metadata: vec![(
+                "treatment".to_string(),
+                vec!["dmso".to_string(), String::new()],
+            )],
+        };
+        let dir = std::env::temp_dir().join(format!("seqtable-h5ad-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("matrix.h5ad");
+        save_h5ad(&matrix, &path).unwrap();
+
+        let file = hdf5_pure::File::open(&path).unwrap();
+        let read_f64 = |name: &str| file.dataset(name).unwrap().read_f64().unwrap();
+        let read_i64 = |name: &str| file.dataset(name).unwrap().read_i64().unwrap();
+        let read_strings = |name: &str| file.dataset(name).unwrap().read_string().unwrap();
+
+        // Samples are rows of X, holding only the nonzero counts
+        let x = file.group("X").unwrap().attrs().unwrap();
+        assert_eq!(x.get("encoding-type"), Some(&string("csr_matrix")));
+        assert_eq!(x.get("shape"), Some(&AttrValue::I64Array(vec![2, 3])));
+        assert_eq!(read_f64("X/data"), [5.0, 2.0, 3.0, 1.0]);
+        assert_eq!(read_i64("X/indices"), [0, 2, 1, 2]);
+        assert_eq!(read_i64("X/indptr"), [0, 2, 4]);
+        assert_eq!(read_f64("layers/normalized/data"), [0.5, 0.2, 0.75, 0.25]);
+
+        assert_eq!(read_strings("obs/_index"), ["a", "b"]);
+        assert_eq!(read_strings("obs/treatment"), ["dmso", ""]);
+        assert_eq!(read_i64("obs/total_counts"), [7, 4]);
+        assert_eq!(read_strings("var/_index"), ["AAA", "CCC", "GGG"]);
+        assert_eq!(read_i64("var/n_cells_by_counts"), [1, 1, 2]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use crate::exit::{Classify, Failure};
+use crate::interrupt::PartialOutput;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Read lengths counted without locking; longer reads share a locked map
+const DENSE_LENGTHS: usize = 4096;
+
+/// Distributions of read properties, filled in as reads are counted
+pub struct Histograms {
+    length: Option<LengthHistogram>,
+    /// Reads per GC percentage (0-100)
+    gc: Option<Vec<AtomicU64>>,
+    quality: Option<QualityHistogram>,
+}
+
+struct LengthHistogram {
+    dense: Vec<AtomicU64>,
+    long: Mutex<BTreeMap<usize, u64>>,
+}
+
+/// Quality tallies, one shard per worker thread since every base is counted
+struct QualityHistogram {
+    /// ASCII offset of the scores
+    offset: u8,
+    shards: Vec<Mutex<QualityCounts>>,
+}
+
+#[derive(Clone)]
+struct QualityCounts {
+    /// Bases per quality character
+    scores: Vec<u64>,
+    /// Per cycle: reads reaching it and the sum of their scores there
+    cycles: Vec<(u64, u64)>,
+}
+
+/// Which histograms to fill
+#[derive(Clone, Copy, Default)]
+pub struct Selection {
+    pub length: bool,
+    pub gc: bool,
+    /// Quality score tallies, decoded with this ASCII offset
+    pub quality: Option<u8>,
+}
+
+impl Histograms {
+    pub fn new(selection: Selection) -> Self {
+        Self {
+            length: selection.length.then(|| LengthHistogram {
+                dense: (0..DENSE_LENGTHS).map(|_| AtomicU64::new(0)).collect(),
+                long: Mutex::new(BTreeMap::new()),
+            }),
+            gc: selection
+                .gc
+                .then(|| (0..=100).map(|_| AtomicU64::new(0)).collect()),
+            quality: selection.quality.map(|offset| {
+                let counts = QualityCounts {
+                    scores: vec![0; 256],
+                    cycles: Vec::new(),
+                };
+                // Threads outside the pool (the reader) share the extra shard
+                let shards = rayon::current_num_threads() + 1;
+                QualityHistogram {
+                    offset,
+                    shards: (0..shards).map(|_| Mutex::new(counts.clone())).collect(),
+                }
+            }),
+        }
+    }
+
+    /// Add a read to the histograms
+    pub fn offer(&self, seq: &[u8], qual: Option<&[u8]>) {
+        if let Some(ref length) = self.length {
+            match length.dense.get(seq.len()) {
+                Some(count) => {
+                    count.fetch_add(1, Ordering::Relaxed);
+                }
+                None => *length.long.lock().unwrap().entry(seq.len()).or_insert(0) += 1,
+            }
+        }
+        if let Some(ref gc) = self.gc {
+            // Empty reads have no composition to bin
+            if let Some(percent) = gc_percent(seq) {
+                gc[percent].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if let (Some(quality), Some(qual)) = (&self.quality, qual) {
+            let shard = rayon::current_thread_index().map_or(0, |i| i + 1);
+            let mut counts = quality.shards[shard % quality.shards.len()].lock().unwrap();
+            if counts.cycles.len() < qual.len() {
+                counts.cycles.resize(qual.len(), (0, 0));
+            }
+            for (cycle, &score) in qual.iter().enumerate() {
+                counts.scores[score as usize] += 1;
+                let (reads, sum) = &mut counts.cycles[cycle];
+                *reads += 1;
+                *sum += u64::from(score);
+            }
+        }
+    }
+
+    /// Write the length histogram as `length`/`reads` TSV rows, skipping
+    /// lengths no read had
+    pub fn save_length(&self, path: &Path) -> Result<()> {
+        let Some(ref length) = self.length else {
+            return Ok(());
+        };
+        let long = length.long.lock().unwrap();
+        let rows = length
+            .dense
+            .iter()
+            .enumerate()
+            .map(|(len, count)| (len, count.load(Ordering::Relaxed)))
+            .chain(long.iter().map(|(&len, &count)| (len, count)))
+            .filter(|&(_, count)| count > 0);
+        save_rows(path, "length", rows)
+    }
+
+    /// Write the GC histogram as `gc_percent`/`reads` TSV rows, 0 to 100
+    pub fn save_gc(&self, path: &Path) -> Result<()> {
+        let Some(ref gc) = self.gc else {
+            return Ok(());
+        };
+        let rows = gc
+            .iter()
+            .enumerate()
+            .map(|(percent, count)| (percent, count.load(Ordering::Relaxed)));
+        save_rows(path, "gc_percent", rows)
+    }
+
+    /// Write bases per Phred score as `quality`/`bases` TSV rows, skipping
+    /// scores no base had
+    pub fn save_quality(&self, path: &Path) -> Result<()> {
+        let Some(ref quality) = self.quality else {
+            return Ok(());
+        };
+        let counts = quality.merged();
+        let offset = i32::from(quality.offset);
+        let rows = counts
+            .scores
+            .iter()
+            .enumerate()
+            .filter(|&(_, &bases)| bases > 0)
+            .map(|(score, bases)| format!("{}\t{}", score as i32 - offset, bases));
+        save_lines(path, "quality\tbases", rows)
+    }
+
+    /// Write the mean Phred score at each read position as
+    /// `cycle`/`reads`/`mean_quality` TSV rows, cycles counted from 1
+    pub fn save_cycles(&self, path: &Path) -> Result<()> {
+        let Some(ref quality) = self.quality else {
+            return Ok(());
+        };
+        let counts = quality.merged();
+        let offset = f64::from(quality.offset);
+        let rows = counts
+            .cycles
+            .iter()
+            .enumerate()
+            .map(|(cycle, &(reads, sum))| {
+                let mean = sum as f64 / reads as f64 - offset;
+                format!("{}\t{}\t{:.2}", cycle + 1, reads, mean)
+            });
+        save_lines(path, "cycle\treads\tmean_quality", rows)
+    }
+}
+
+impl QualityHistogram {
+    /// Tallies of all shards combined
+    fn merged(&self) -> QualityCounts {
+        let mut merged = QualityCounts {
+            scores: vec![0; 256],
+            cycles: Vec::new(),
+        };
+        for shard in &self.shards {
+            let counts = shard.lock().unwrap();
+            for (total, bases) in merged.scores.iter_mut().zip(&counts.scores) {
+                *total += bases;
+            }
+            if merged.cycles.len() < counts.cycles.len() {
+                merged.cycles.resize(counts.cycles.len(), (0, 0));
+            }
+            for (total, &(reads, sum)) in merged.cycles.iter_mut().zip(&counts.cycles) {
+                total.0 += reads;
+                total.1 += sum;
+            }
+        }
+        merged
+    }
+}
+
+/// G and C bases as a whole percentage of the read length
+fn gc_percent(seq: &[u8]) -> Option<usize> {
+    if seq.is_empty() {
+        return None;
+    }
+    let gc = seq
+        .iter()
+        .filter(|&&b| matches!(b, b'G' | b'C' | b'g' | b'c'))
+        .count();
+    Some((gc * 100 + seq.len() / 2) / seq.len())
+}
+
+fn save_rows(path: &Path, key: &str, rows: impl Iterator<Item = (usize, u64)>) -> Result<()> {
+    let lines = rows.map(|(value, count)| format!("{}\t{}", value, count));
+    save_lines(path, &format!("{}\treads", key), lines)
+}
+
+fn save_lines(path: &Path, header: &str, lines: impl Iterator<Item = String>) -> Result<()> {
+    let partial = PartialOutput::new(path);
+    let file = File::create(partial.path())
+        .with_context(|| format!("Failed to create file: {}", path.display()))
+        .classify(Failure::Output)?;
+    let mut writer = BufWriter::new(file);
+    std::iter::once(header.to_string())
+        .chain(lines)
+        .try_for_each(|line| writeln!(writer, "{}", line))
+        .and_then(|()| writer.flush())
+        .with_context(|| format!("Failed to write file: {}", path.display()))
+        .classify(Failure::Output)?;
+    partial.finish().classify(Failure::Output)
+}
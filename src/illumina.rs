@@ -0,0 +1,118 @@
+use crate::arena::ArenaCounts;
+use ahash::AHashMap;
+use clap::ValueEnum;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Fields of an Illumina (Casava 1.8+) read name,
+/// `instrument:run:flowcell:lane:tile:x:y`
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum IlluminaField {
+    Instrument,
+    Run,
+    Flowcell,
+    Lane,
+    Tile,
+}
+
+impl IlluminaField {
+    /// Output column name
+    pub fn name(&self) -> &'static str {
+        match self {
+            IlluminaField::Instrument => "instrument",
+            IlluminaField::Run => "run",
+            IlluminaField::Flowcell => "flowcell",
+            IlluminaField::Lane => "lane",
+            IlluminaField::Tile => "tile",
+        }
+    }
+
+    /// Position in the colon-separated read name
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Colon-separated fields in a read name, up to the y coordinate
+const NAME_FIELDS: usize = 7;
+
+/// The requested fields of a record header, tab-joined, or `None` if the
+/// header is not an Illumina read name
+pub fn fields_key(id: &[u8], fields: &[IlluminaField]) -> Option<String> {
+    // The read name ends at the first space (the comment holds read/index info)
+    let name_end = id
+        .iter()
+        .position(|b| b.is_ascii_whitespace())
+        .unwrap_or(id.len());
+    let name = std::str::from_utf8(&id[..name_end]).ok()?;
+    let parts: Vec<&str> = name.splitn(NAME_FIELDS + 1, ':').collect();
+    if parts.len() < NAME_FIELDS || parts[..NAME_FIELDS].iter().any(|p| p.is_empty()) {
+        return None;
+    }
+    let values: Vec<&str> = fields.iter().map(|field| parts[field.index()]).collect();
+    Some(values.join("\t"))
+}
+
+/// Split a counting key into its leading `n` field values and the rest
+pub fn split_key(key: &str, n: usize) -> (Vec<&str>, &str) {
+    if n == 0 {
+        return (Vec::new(), key);
+    }
+    let (fields, rest) = split_fields(key, n);
+    (fields.split('\t').collect(), rest)
+}
+
+/// Split a counting key after its leading `n` field values, which stay
+/// tab-joined
+pub fn split_fields(key: &str, n: usize) -> (&str, &str) {
+    if n == 0 {
+        return ("", key);
+    }
+    match key.match_indices('\t').nth(n - 1) {
+        Some((end, _)) => (&key[..end], &key[end + 1..]),
+        None => (key, ""),
+    }
+}
+
+/// Per-field counts summed per sequence (or group)
+pub fn collapse(counts: &ArenaCounts, n: usize) -> ArenaCounts {
+    counts
+        .iter()
+        .map(|(key, count)| (split_key(key, n).1, count))
+        .collect()
+}
+
+/// Counts split by the leading field value, with it removed from the keys
+pub fn partition_counts(counts: &ArenaCounts) -> BTreeMap<String, ArenaCounts> {
+    let mut parts: BTreeMap<String, ArenaCounts> = BTreeMap::new();
+    for (key, count) in counts.iter() {
+        let (value, rest) = key.split_once('\t').unwrap_or((key, ""));
+        if !parts.contains_key(value) {
+            parts.insert(value.to_string(), ArenaCounts::new());
+        }
+        parts.get_mut(value).unwrap().add_str(rest, count);
+    }
+    parts
+}
+
+/// Per-key values split by the leading field value, with it removed from
+/// the keys
+pub fn partition<T>(values: AHashMap<String, T>) -> BTreeMap<String, AHashMap<String, T>> {
+    let mut parts: BTreeMap<String, AHashMap<String, T>> = BTreeMap::new();
+    for (key, value) in values {
+        let (field, rest) = key.split_once('\t').unwrap_or((&key, ""));
+        parts
+            .entry(field.to_string())
+            .or_default()
+            .insert(rest.to_string(), value);
+    }
+    parts
+}
+
+/// Distinct values of the `index`th of the `n` header fields across keys,
+/// sorted and comma-separated
+pub fn observed<'a>(keys: impl Iterator<Item = &'a str>, n: usize, index: usize) -> String {
+    let values: BTreeSet<&str> = keys
+        .filter_map(|key| split_fields(key, n).0.split('\t').nth(index))
+        .collect();
+    values.into_iter().collect::<Vec<_>>().join(",")
+}
@@ -0,0 +1,718 @@
+use crate::artifacts::Artifacts;
+use crate::assign::{AssignmentCache, AssignmentWriter};
+use crate::bgzf::{self, BgzfReader};
+use crate::checksum::Checksum;
+use crate::dedup::DedupWriter;
+use crate::histogram::Histograms;
+use crate::lenient::LenientReader;
+use crate::pairs::{Mate, PairedReader};
+use crate::quality::ExpectedErrors;
+use crate::retry::RetryingFile;
+use crate::skip::SkipBudget;
+use ahash::AHashMap;
+use anyhow::{Context, Result, bail};
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use needletail::{FastxReader, parse_fastx_reader};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, sync_channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// gzip member magic number
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Zstandard frame magic number (little-endian 0xFD2FB528)
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// bzip2 stream magic ("BZh", then the block size digit)
+const BZIP2_MAGIC: [u8; 3] = *b"BZh";
+
+/// xz stream magic
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Bytes read to sniff the compression format
+const SNIFF_LEN: u64 = 512;
+
+/// Decompressed bytes handed from the decoder thread to the parser at a time
+const DECODE_CHUNK_SIZE: usize = 1024 * 1024; // 1MB
+
+/// Decoded chunks buffered ahead of the parser
+const DECODE_QUEUE_DEPTH: usize = 4;
+
+/// Records read to estimate an input's mean read length
+const LENGTH_SAMPLE_RECORDS: usize = 10_000;
+
+/// Bytes of a pipe read ahead and kept, so the looks taken at an input before
+/// it is counted (compression, read length, quality encoding) can be replayed
+const PIPE_HEAD_LEN: u64 = 4 * 1024 * 1024;
+
+/// One input of a run: its path and, for a pipe, FIFO or other stream, what
+/// has been read of it. A stream can be opened only once, so the looks taken
+/// at it before the count (compression, read length, quality encoding) see
+/// its read-ahead head, and the count gets the head followed by the rest.
+/// With `--merge-pairs`, an R1 input also carries its R2 mate.
+pub struct Input {
+    path: PathBuf,
+    stream: Option<Stream>,
+    mate: Option<Box<Mate>>,
+}
+
+/// A stream input's bytes, and how many of them the count has read
+struct Stream {
+    state: Mutex<StreamState>,
+    consumed: Arc<AtomicU64>,
+}
+
+enum StreamState {
+    /// Not read yet: the reader to take the head from, or `None` to open
+    /// the path
+    Unread(Option<Box<dyn Read + Send>>),
+    /// Its first bytes, read ahead, and the rest
+    Head {
+        head: Arc<[u8]>,
+        rest: Box<dyn Read + Send>,
+    },
+    /// Handed to the count
+    Taken,
+}
+
+impl Input {
+    /// The input at `path`, read as a stream if it is not a regular file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let stream = is_stream(&path).then(|| Stream::new(None));
+        Self {
+            path,
+            stream,
+            mate: None,
+        }
+    }
+
+    /// A stream input read from `reader`, named `name` in messages
+    pub fn from_reader(name: impl Into<PathBuf>, reader: impl Read + Send + 'static) -> Self {
+        Self {
+            path: name.into(),
+            stream: Some(Stream::new(Some(Box::new(reader)))),
+            mate: None,
+        }
+    }
+
+    /// Count the input as the merge of its pairs with `mate`
+    pub fn with_mate(mut self, mate: Mate) -> Self {
+        self.mate = Some(Box::new(mate));
+        self
+    }
+
+    /// The R2 input this one is merged with, if any
+    pub fn mate(&self) -> Option<&Mate> {
+        self.mate.as_deref()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the input is a stream: it can be read once, front to back,
+    /// and has no size
+    pub fn is_stream(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Bytes of a regular file; `None` for a stream
+    pub fn file_size(&self) -> Result<Option<u64>> {
+        match self.stream {
+            Some(_) => Ok(None),
+            None => file_size(&self.path),
+        }
+    }
+
+    /// Bytes counted from a stream input so far, standing in for its size
+    pub fn streamed_bytes(&self) -> u64 {
+        self.stream
+            .as_ref()
+            .map_or(0, |stream| stream.consumed.load(Ordering::Relaxed))
+    }
+
+    /// The bytes of the input as stored. A regular file is opened anew each
+    /// time; of a stream, looks before the count (`consume` unset) get the
+    /// head, and the count the head and the rest.
+    fn open_raw(&self, consume: bool) -> Result<Box<dyn Read + Send>> {
+        let Some(ref stream) = self.stream else {
+            let file = RetryingFile::open(&self.path)
+                .with_context(|| format!("Failed to open file: {}", self.path.display()))?;
+            return Ok(Box::new(file));
+        };
+        let mut state = stream.state.lock().unwrap();
+        if let StreamState::Unread(ref mut source) = *state {
+            let mut source = match source.take() {
+                Some(source) => source,
+                None => Box::new(
+                    File::open(&self.path)
+                        .with_context(|| format!("Failed to open file: {}", self.path.display()))?,
+                ),
+            };
+            let mut head = Vec::new();
+            (&mut source)
+                .take(PIPE_HEAD_LEN)
+                .read_to_end(&mut head)
+                .with_context(|| format!("Failed to read file: {}", self.path.display()))?;
+            *state = StreamState::Head {
+                head: head.into(),
+                rest: source,
+            };
+        }
+        match std::mem::replace(&mut *state, StreamState::Taken) {
+            StreamState::Head { head, rest } if !consume => {
+                let peek = Cursor::new(Arc::clone(&head));
+                *state = StreamState::Head { head, rest };
+                Ok(Box::new(peek))
+            }
+            StreamState::Head { head, rest } => {
+                stream.consumed.store(head.len() as u64, Ordering::Relaxed);
+                let rest = CountingReader {
+                    inner: rest,
+                    consumed: Arc::clone(&stream.consumed),
+                    checksum: None,
+                };
+                Ok(Box::new(Cursor::new(head).chain(rest)))
+            }
+            _ => bail!(
+                "{} is a pipe and has already been read",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+impl Stream {
+    fn new(source: Option<Box<dyn Read + Send>>) -> Self {
+        Self {
+            state: Mutex::new(StreamState::Unread(source)),
+            consumed: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+/// Records of an input, parsed by needletail or, when malformed records are
+/// to be skipped, by the lenient line-based reader; with `--merge-pairs`, the
+/// merged reads of it and its mate
+pub struct Records<'a> {
+    parser: Parser<'a>,
+    /// Where each record is offered before it is emitted
+    read: ReadOptions<'a>,
+    /// Records left before the read stops early
+    remaining: Option<u64>,
+}
+
+enum Parser<'a> {
+    Strict(Box<dyn FastxReader>),
+    Lenient(LenientReader<'a>),
+    Paired(Box<PairedReader>),
+}
+
+impl Records<'_> {
+    /// Pass the next record's id, sequence and quality (FASTQ) to `emit`;
+    /// `false` at end of input
+    pub fn next_record(&mut self, emit: impl FnOnce(&[u8], &[u8], Option<&[u8]>)) -> Result<bool> {
+        if self.remaining == Some(0) {
+            return Ok(false);
+        }
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining -= 1;
+        }
+        let read = self.read;
+        let emit = |id: &[u8], seq: &[u8], qual: Option<&[u8]>| {
+            read.offer(id, seq, qual);
+            emit(id, seq, qual);
+        };
+        match self.parser {
+            Parser::Strict(ref mut reader) => match reader.next() {
+                Some(record) => {
+                    let record = record.context("Failed to read record")?;
+                    emit(record.id(), &record.seq(), record.qual());
+                    Ok(true)
+                }
+                None => Ok(false),
+            },
+            Parser::Lenient(ref mut reader) => reader.next_record(emit),
+            Parser::Paired(ref mut reader) => reader.next_record(emit),
+        }
+    }
+}
+
+/// Paths listed one per line in `list` (`-` for stdin); blank lines are ignored
+pub fn read_file_list(list: &Path) -> Result<Vec<PathBuf>> {
+    let reader: Box<dyn BufRead> = if list == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        let file = File::open(list)
+            .with_context(|| format!("Failed to open file list: {}", list.display()))?;
+        Box::new(BufReader::new(file))
+    };
+    let mut paths = Vec::new();
+    for line in reader.lines() {
+        let line = line.with_context(|| format!("Failed to read file list: {}", list.display()))?;
+        let path = line.trim_end_matches('\r');
+        if !path.trim().is_empty() {
+            paths.push(PathBuf::from(path));
+        }
+    }
+    Ok(paths)
+}
+
+/// Whether `input` holds FASTQ (its first decompressed byte is `@`)
+pub fn is_fastq(input: &Input) -> bool {
+    let mut first = [0u8];
+    peek_decoded(input)
+        .is_ok_and(|mut stream| stream.read_exact(&mut first).is_ok() && first[0] == b'@')
+}
+
+/// How an input is read, besides parsing its records
+#[derive(Clone, Copy, Default)]
+pub struct ReadOptions<'a> {
+    /// Skip malformed records, charging them to this budget, rather than
+    /// ending the read at the first
+    pub skip: Option<&'a SkipBudget>,
+    /// Hash the file's bytes as they are read
+    pub checksum: Option<&'a Arc<Checksum>>,
+    /// Write one read of each sequence out as it is read
+    pub dedup: Option<&'a DedupWriter>,
+    /// Write each read's assignment out as it is counted
+    pub assignments: Option<&'a AssignmentWriter<'a>>,
+    /// Add each read to these histograms as it is read
+    pub histograms: Option<&'a Histograms>,
+    /// Add each read's expected errors to its key as it is counted
+    pub expected_errors: Option<&'a ExpectedErrors>,
+    /// Tally each read's library artifacts as it is read
+    pub artifacts: Option<&'a Artifacts>,
+    /// Stop after this many records
+    pub limit: Option<u64>,
+}
+
+impl<'a> ReadOptions<'a> {
+    /// Pass a record to the dedup, histogram and artifact writers that are set
+    pub fn offer(&self, id: &[u8], seq: &[u8], qual: Option<&[u8]>) {
+        if let Some(dedup) = self.dedup {
+            dedup.offer(id, seq, qual);
+        }
+        if let Some(histograms) = self.histograms {
+            histograms.offer(seq, qual);
+        }
+        if let Some(artifacts) = self.artifacts {
+            artifacts.offer(seq);
+        }
+    }
+
+    /// One worker's side of the writers that take a read once it is keyed
+    pub fn key_hooks(&self) -> KeyHooks<'a> {
+        KeyHooks {
+            assignments: self
+                .assignments
+                .map(|writer| (writer, AssignmentCache::default())),
+            expected_errors: self
+                .expected_errors
+                .map(|expected_errors| (expected_errors, AHashMap::new())),
+        }
+    }
+}
+
+/// What a worker keeps for the writers that follow each read's key, so work
+/// done once per key is not repeated per read
+pub struct KeyHooks<'a> {
+    assignments: Option<(&'a AssignmentWriter<'a>, AssignmentCache)>,
+    /// The worker's expected errors per key, until `finish`
+    expected_errors: Option<(&'a ExpectedErrors, AHashMap<Vec<u8>, f64>)>,
+}
+
+impl KeyHooks<'_> {
+    /// Whether any writer needs the reads' keys
+    pub fn is_empty(&self) -> bool {
+        self.assignments.is_none() && self.expected_errors.is_none()
+    }
+
+    /// Pass a read's counting key (`None` if it was not counted)
+    pub fn offer(&mut self, id: &[u8], qual: Option<&[u8]>, key: Option<&[u8]>) {
+        if let Some((writer, ref mut cache)) = self.assignments {
+            writer.offer(cache, id, key);
+        }
+        if let (Some((expected_errors, sums)), Some(qual), Some(key)) =
+            (&mut self.expected_errors, qual, key)
+        {
+            let errors = expected_errors.of(qual);
+            match sums.get_mut(key) {
+                Some(sum) => *sum += errors,
+                None => {
+                    sums.insert(key.to_vec(), errors);
+                }
+            }
+        }
+    }
+
+    /// Hand the worker's tallies to the writers they were kept for
+    pub fn finish(self) {
+        if let Some((expected_errors, sums)) = self.expected_errors {
+            expected_errors.merge(sums);
+        }
+    }
+}
+
+/// Open a FASTA/FASTQ file for record-by-record reading
+pub fn open_records<'a>(
+    input: &Input,
+    read: ReadOptions<'a>,
+) -> Result<(Records<'a>, Arc<AtomicU64>)> {
+    if let Some(mate) = input.mate() {
+        // Progress and checksums follow R1
+        let (r1, consumed) = open_fastx(input, read.checksum)?;
+        let (r2, _) = open_fastx(&mate.input, None)?;
+        let reader = PairedReader::new(
+            r1,
+            r2,
+            mate.input.path(),
+            mate.options,
+            Arc::clone(&mate.stats),
+        );
+        return Ok((
+            Records {
+                parser: Parser::Paired(Box::new(reader)),
+                read,
+                remaining: read.limit,
+            },
+            consumed,
+        ));
+    }
+    let (parser, consumed) = match read.skip {
+        Some(budget) => {
+            let (stream, consumed) = open_decoded(input, read.checksum)?;
+            let reader = LenientReader::new(stream, budget)
+                .with_context(|| format!("Failed to open file: {}", input.path().display()))?;
+            (Parser::Lenient(reader), consumed)
+        }
+        None => {
+            let (reader, consumed) = open_fastx(input, read.checksum)?;
+            (Parser::Strict(reader), consumed)
+        }
+    };
+    Ok((
+        Records {
+            parser,
+            read,
+            remaining: read.limit,
+        },
+        consumed,
+    ))
+}
+
+/// Mean sequence length of the first records; `None` if none could be read
+pub fn mean_read_length(input: &Input) -> Option<f64> {
+    let mut reader = peek_fastx(input).ok()?;
+    let (mut reads, mut bases) = (0usize, 0usize);
+    for _ in 0..LENGTH_SAMPLE_RECORDS {
+        // Read errors are left for the counting pass to report
+        let Some(Ok(record)) = reader.next() else {
+            break;
+        };
+        reads += 1;
+        bases += record.num_bases();
+    }
+    (reads > 0).then(|| bases as f64 / reads as f64)
+}
+
+/// Open a FASTA/FASTQ file with needletail
+pub fn open_fastx(
+    input: &Input,
+    checksum: Option<&Arc<Checksum>>,
+) -> Result<(Box<dyn FastxReader>, Arc<AtomicU64>)> {
+    let (stream, consumed) = open_decoded(input, checksum)?;
+    Ok((fastx_reader(input.path(), stream)?, consumed))
+}
+
+/// Open the start of a FASTA/FASTQ file with needletail, to look at its
+/// first records before counting; of a pipe, only the read-ahead head
+pub fn peek_fastx(input: &Input) -> Result<Box<dyn FastxReader>> {
+    fastx_reader(input.path(), peek_decoded(input)?)
+}
+
+fn fastx_reader(path: &Path, stream: Box<dyn Read + Send>) -> Result<Box<dyn FastxReader>> {
+    // Surface decode errors here: needletail reports any failure on its first
+    // read as an empty file
+    let mut stream = BufReader::new(stream);
+    stream
+        .fill_buf()
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+    parse_fastx_reader(stream).with_context(|| format!("Failed to open file: {}", path.display()))
+}
+
+/// Whether `path` is a pipe, FIFO, character device or socket rather than a
+/// regular file: it can be read once, front to back, and has no size
+pub fn is_stream(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| !metadata.is_file() && !metadata.is_dir())
+}
+
+/// Bytes of a regular file; `None` for a stream, whose length is unknown
+/// until it has been read
+pub fn file_size(path: &Path) -> Result<Option<u64>> {
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+    Ok((metadata.is_file() || metadata.is_dir()).then_some(metadata.len()))
+}
+
+/// Compression of `input` as `open_decoded` detects it, from its leading
+/// bytes; `None` for plain text
+pub fn compression(input: &Input) -> Result<Option<&'static str>> {
+    let header = sniff(input)?;
+    Ok(if header.starts_with(&ZSTD_MAGIC) {
+        Some("zstd")
+    } else if bgzf::is_bgzf(&header) {
+        Some("BGZF")
+    } else if header.starts_with(&GZIP_MAGIC) {
+        Some("gzip")
+    } else if header.starts_with(&BZIP2_MAGIC) {
+        Some("bzip2")
+    } else if header.starts_with(&XZ_MAGIC) {
+        Some("xz")
+    } else {
+        None
+    })
+}
+
+/// Open a file as a decompressed byte stream, detecting compression from its
+/// leading bytes. BGZF (blocked gzip, as written by bgzip) is inflated
+/// block-parallel; other compressed formats are decoded on a separate thread
+/// so parsing does not wait on decompression.
+///
+/// The returned counter tracks bytes read from disk (compressed bytes for
+/// compressed inputs), for progress against the file size; `checksum`, if
+/// given, hashes those bytes.
+pub fn open_decoded(
+    input: &Input,
+    checksum: Option<&Arc<Checksum>>,
+) -> Result<(Box<dyn Read + Send>, Arc<AtomicU64>)> {
+    decoded(input, checksum, true)
+}
+
+/// Open the start of a file as a decompressed byte stream, as `open_decoded`
+/// does, to look at it before counting; of a pipe, only the read-ahead head
+pub fn peek_decoded(input: &Input) -> Result<Box<dyn Read + Send>> {
+    Ok(decoded(input, None, false)?.0)
+}
+
+fn decoded(
+    input: &Input,
+    checksum: Option<&Arc<Checksum>>,
+    consume: bool,
+) -> Result<(Box<dyn Read + Send>, Arc<AtomicU64>)> {
+    let path = input.path();
+    let header = sniff(input)?;
+    let consumed = Arc::new(AtomicU64::new(0));
+    let file = CountingReader {
+        inner: input.open_raw(consume)?,
+        consumed: Arc::clone(&consumed),
+        checksum: checksum.cloned(),
+    };
+
+    let stream: Box<dyn Read + Send> = if header.starts_with(&ZSTD_MAGIC) {
+        let decoder = zstd::Decoder::new(file)
+            .with_context(|| format!("Failed to start zstd decoder: {}", path.display()))?;
+        Box::new(ThreadedReader::new(decoder, "zstd"))
+    } else if bgzf::is_bgzf(&header) {
+        Box::new(BgzfReader::new(file))
+    } else if header.starts_with(&GZIP_MAGIC) {
+        let decoder = MultiGzDecoder::new(BufReader::new(file));
+        Box::new(ThreadedReader::new(decoder, "gzip"))
+    } else if header.starts_with(&BZIP2_MAGIC) {
+        Box::new(ThreadedReader::new(MultiBzDecoder::new(file), "bzip2"))
+    } else if header.starts_with(&XZ_MAGIC) {
+        let decoder = xz2::read::XzDecoder::new_multi_decoder(file);
+        Box::new(ThreadedReader::new(decoder, "xz"))
+    } else {
+        Box::new(file)
+    };
+    Ok((stream, consumed))
+}
+
+/// The leading bytes of `input`, by which its compression is detected
+fn sniff(input: &Input) -> Result<Vec<u8>> {
+    let mut header = Vec::new();
+    input
+        .open_raw(false)?
+        .take(SNIFF_LEN)
+        .read_to_end(&mut header)
+        .with_context(|| format!("Failed to read file: {}", input.path().display()))?;
+    Ok(header)
+}
+
+/// Reader that tallies, and optionally hashes, the bytes passing through it
+struct CountingReader<R> {
+    inner: R,
+    consumed: Arc<AtomicU64>,
+    checksum: Option<Arc<Checksum>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.consumed.fetch_add(n as u64, Ordering::Relaxed);
+        if let Some(ref checksum) = self.checksum {
+            checksum.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// Reader that runs `inner` on its own thread, prefetching decoded chunks
+/// through a bounded channel
+pub struct ThreadedReader {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl ThreadedReader {
+    /// Decode `inner` on a new thread; `format` names it in decode errors
+    pub fn new<R: Read + Send + 'static>(mut inner: R, format: &'static str) -> Self {
+        let (sender, chunks) = sync_channel(DECODE_QUEUE_DEPTH);
+        thread::spawn(move || {
+            loop {
+                let mut chunk = Vec::with_capacity(DECODE_CHUNK_SIZE);
+                let result = (&mut inner)
+                    .take(DECODE_CHUNK_SIZE as u64)
+                    .read_to_end(&mut chunk);
+                let message = match result {
+                    Ok(_) => Ok(chunk),
+                    Err(e) => {
+                        // Hand over what decoded cleanly, so the error surfaces
+                        // where the data ends rather than at the start
+                        if !chunk.is_empty() && sender.send(Ok(chunk)).is_err() {
+                            break;
+                        }
+                        Err(io::Error::new(
+                            e.kind(),
+                            format!("{} stream is truncated or corrupt ({})", format, e),
+                        ))
+                    }
+                };
+                let done = !matches!(message, Ok(ref chunk) if !chunk.is_empty());
+                // Stop at end of input, on a decode error, or once the parser hangs up
+                if sender.send(message).is_err() || done {
+                    break;
+                }
+            }
+        });
+        Self {
+            chunks,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for ThreadedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.buffer.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.buffer = chunk?;
+                    self.position = 0;
+                    if self.buffer.is_empty() {
+                        return Ok(0);
+                    }
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.buffer.len() - self.position);
+        buf[..n].copy_from_slice(&self.buffer[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::Command;
+
+    /// FASTQ records of 100 bp, more of them than the read-ahead head holds
+    fn fastq(records: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0..records {
+            let seq: Vec<u8> = (0..100).map(|j| b"ACGT"[(i + j) % 4]).collect();
+            writeln!(data, "@read{}", i).unwrap();
+            data.extend_from_slice(&seq);
+            data.extend_from_slice(b"\n+\n");
+            data.extend(std::iter::repeat_n(b'I', seq.len()));
+            data.push(b'\n');
+        }
+        data
+    }
+
+    #[test]
+    fn fifo_is_peeked_then_counted_once_with_spinner_progress() {
+        let dir = std::env::temp_dir().join(format!("seqtable-fifo-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fifo = dir.join("reads.fq");
+        let made = Command::new("mkfifo").arg(&fifo).status().unwrap();
+        assert!(made.success());
+
+        let data = fastq(30_000);
+        assert!(data.len() as u64 > PIPE_HEAD_LEN);
+        let writer = {
+            let (fifo, data) = (fifo.clone(), data.clone());
+            thread::spawn(move || File::create(fifo).unwrap().write_all(&data).unwrap())
+        };
+
+        let input = Input::new(&fifo);
+        assert!(input.is_stream());
+        // Looks before the count take the head and leave it for the count
+        assert!(is_fastq(&input));
+        assert_eq!(compression(&input).unwrap(), None);
+        assert_eq!(mean_read_length(&input), Some(100.0));
+
+        let size = input.file_size().unwrap();
+        assert_eq!(size, None);
+        let bar = crate::progress::byte_bar(size);
+        assert_eq!(bar.length(), None);
+
+        let (mut records, _) = open_records(&input, ReadOptions::default()).unwrap();
+        let mut count = 0;
+        while records.next_record(|_, _, _| {}).unwrap() {
+            count += 1;
+        }
+        writer.join().unwrap();
+        assert_eq!(count, 30_000);
+        assert_eq!(input.streamed_bytes(), data.len() as u64);
+        assert!(open_records(&input, ReadOptions::default()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn regular_files_reopen_and_have_a_size() {
+        let dir = std::env::temp_dir().join(format!("seqtable-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("reads.fq");
+        let data = fastq(10);
+        std::fs::write(&path, &data).unwrap();
+
+        let input = Input::new(&path);
+        assert!(!input.is_stream());
+        assert_eq!(input.file_size().unwrap(), Some(data.len() as u64));
+        for _ in 0..2 {
+            let (mut records, _) = open_records(&input, ReadOptions::default()).unwrap();
+            let mut count = 0;
+            while records.next_record(|_, _, _| {}).unwrap() {
+                count += 1;
+            }
+            assert_eq!(count, 10);
+        }
+        assert_eq!(input.streamed_bytes(), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
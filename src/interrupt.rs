@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Exit status for a run stopped by SIGINT (128 + signal number)
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Temporary files currently being written
+static IN_PROGRESS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Handle Ctrl-C by removing every output still being written, so an
+/// interrupted run never leaves a truncated table that looks complete
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        let paths = std::mem::take(&mut *IN_PROGRESS.lock().unwrap());
+        for path in &paths {
+            std::fs::remove_file(path).ok();
+        }
+        if paths.is_empty() {
+            eprintln!("\n⚠️  Interrupted");
+        } else {
+            eprintln!(
+                "\n⚠️  Interrupted; removed {} partial output file(s)",
+                paths.len()
+            );
+        }
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    })
+    .context("Failed to install Ctrl-C handler")
+}
+
+/// Output written to a hidden temporary file next to its destination and
+/// renamed into place by `finish`. Readers only ever see a complete file or
+/// none: the temporary file is removed if the guard is dropped unfinished (a
+/// write error) or the run is interrupted.
+pub struct PartialOutput {
+    temp_path: PathBuf,
+    final_path: PathBuf,
+    finished: bool,
+}
+
+impl PartialOutput {
+    pub fn new(path: &Path) -> Self {
+        // Same directory as the destination, so the rename cannot cross filesystems
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp_path = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+        IN_PROGRESS.lock().unwrap().push(temp_path.clone());
+        Self {
+            temp_path,
+            final_path: path.to_path_buf(),
+            finished: false,
+        }
+    }
+
+    /// Where to write the output until it is finished
+    pub fn path(&self) -> &Path {
+        &self.temp_path
+    }
+
+    /// Atomically replace the destination with the completed file
+    pub fn finish(mut self) -> Result<()> {
+        // Flush to disk first, so a crash cannot leave the renamed file empty
+        File::open(&self.temp_path)
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Failed to sync file: {}", self.temp_path.display()))?;
+        std::fs::rename(&self.temp_path, &self.final_path).with_context(|| {
+            format!(
+                "Failed to move output into place: {}",
+                self.final_path.display()
+            )
+        })?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for PartialOutput {
+    fn drop(&mut self) {
+        let mut in_progress = IN_PROGRESS.lock().unwrap();
+        if let Some(i) = in_progress.iter().position(|p| *p == self.temp_path) {
+            in_progress.swap_remove(i);
+        }
+        if !self.finished {
+            std::fs::remove_file(&self.temp_path).ok();
+        }
+    }
+}
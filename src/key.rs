@@ -0,0 +1,359 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// Part of each read counted in place of the whole sequence: slices of it,
+/// joined in order (`seq[0:8]+seq[20:40]`, or `prefix:N`/`suffix:N` alone)
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySpec {
+    slices: Vec<Slice>,
+    /// The spec as given, for metadata
+    text: String,
+}
+
+/// `seq[start:end]`, either bound counted from the end when negative
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Slice {
+    start: Bound,
+    /// `None` runs to the end of the read
+    end: Option<Bound>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    FromStart(usize),
+    FromEnd(usize),
+}
+
+impl KeySpec {
+    /// The bases of `seq` making up its key, or `None` if the read is too
+    /// short for any of the slices
+    pub fn extract<'s>(&self, seq: &'s [u8]) -> Option<Cow<'s, [u8]>> {
+        if let [slice] = self.slices[..] {
+            return slice
+                .range(seq.len())
+                .map(|(start, end)| Cow::Borrowed(&seq[start..end]));
+        }
+        let mut key = Vec::new();
+        for slice in &self.slices {
+            let (start, end) = slice.range(seq.len())?;
+            key.extend_from_slice(&seq[start..end]);
+        }
+        Some(Cow::Owned(key))
+    }
+}
+
+impl Slice {
+    /// Byte range of the slice in a read of `len` bases
+    fn range(&self, len: usize) -> Option<(usize, usize)> {
+        let start = self.start.offset(len)?;
+        let end = match self.end {
+            Some(end) => end.offset(len)?,
+            None => len,
+        };
+        (start < end).then_some((start, end))
+    }
+}
+
+impl Bound {
+    fn offset(&self, len: usize) -> Option<usize> {
+        match *self {
+            Bound::FromStart(n) => (n <= len).then_some(n),
+            Bound::FromEnd(n) => len.checked_sub(n),
+        }
+    }
+
+    /// Offset in a read of `len` bases, kept within it
+    fn clamped(&self, len: usize) -> usize {
+        match *self {
+            Bound::FromStart(n) => n.min(len),
+            Bound::FromEnd(n) => len.saturating_sub(n),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (from_end, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let n: usize = digits
+            .parse()
+            .map_err(|_| format!("slice bound must be an integer, got '{}'", text))?;
+        // As in Python, -0 is the start
+        Ok(if from_end && n > 0 {
+            Bound::FromEnd(n)
+        } else {
+            Bound::FromStart(n)
+        })
+    }
+}
+
+impl FromStr for Slice {
+    type Err = String;
+
+    fn from_str(term: &str) -> Result<Self, Self::Err> {
+        let shorthand = term.split_once(':').filter(|_| !term.starts_with("seq["));
+        if let Some((kind, length)) = shorthand {
+            let length: usize = length.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                format!("key length must be a positive integer, got '{}'", length)
+            })?;
+            return match kind {
+                "prefix" => Ok(Slice {
+                    start: Bound::FromStart(0),
+                    end: Some(Bound::FromStart(length)),
+                }),
+                "suffix" => Ok(Slice {
+                    start: Bound::FromEnd(length),
+                    end: None,
+                }),
+                _ => Err(format!(
+                    "unknown key '{}' (expected prefix or suffix)",
+                    kind
+                )),
+            };
+        }
+
+        let bounds = term
+            .strip_prefix("seq[")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .ok_or_else(|| {
+                format!(
+                    "expected seq[START:END], prefix:N or suffix:N, got '{}'",
+                    term
+                )
+            })?;
+        let (start, end) = bounds
+            .split_once(':')
+            .ok_or_else(|| format!("slice needs a ':' between its bounds, got '{}'", term))?;
+        let start = match start.trim() {
+            "" => Bound::FromStart(0),
+            start => Bound::parse(start)?,
+        };
+        let end = match end.trim() {
+            "" => None,
+            end => Some(Bound::parse(end)?),
+        };
+        let empty = match (start, end) {
+            (Bound::FromStart(start), Some(Bound::FromStart(end))) => start >= end,
+            (Bound::FromEnd(start), Some(Bound::FromEnd(end))) => start <= end,
+            _ => false,
+        };
+        if empty {
+            return Err(format!("slice '{}' is always empty", term));
+        }
+        Ok(Slice { start, end })
+    }
+}
+
+impl FromStr for KeySpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let slices = spec
+            .split('+')
+            .map(|term| term.trim().parse())
+            .collect::<Result<Vec<Slice>, _>>()?;
+        Ok(KeySpec {
+            slices,
+            text: spec.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for KeySpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Positions of each read replaced by N before it is keyed (`--mask`),
+/// `START:END` as in a `seq[START:END]` slice
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    slice: Slice,
+    text: String,
+}
+
+impl Mask {
+    /// `seq` with masked positions set to N; those past the end of a short
+    /// read are left out
+    pub fn apply<'s>(masks: &[Mask], seq: &'s [u8]) -> Cow<'s, [u8]> {
+        if masks.is_empty() {
+            return Cow::Borrowed(seq);
+        }
+        let mut masked = seq.to_vec();
+        for mask in masks {
+            let start = mask.slice.start.clamped(seq.len());
+            let end = mask
+                .slice
+                .end
+                .map_or(seq.len(), |end| end.clamped(seq.len()));
+            if start < end {
+                masked[start..end].fill(b'N');
+            }
+        }
+        Cow::Owned(masked)
+    }
+}
+
+impl FromStr for Mask {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if !text.contains(':') {
+            return Err(format!("expected START:END, got '{}'", text));
+        }
+        Ok(Mask {
+            slice: format!("seq[{}]", text)
+                .parse()
+                .map_err(|err: String| err.replace(&format!("seq[{}]", text), text))?,
+            text: text.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Windows of `length` bases every `step` bases along each read, counted in
+/// place of it (`--window L,S`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Windows {
+    length: usize,
+    step: usize,
+}
+
+impl Windows {
+    /// Pass each window of `seq` to `emit`, from its start; a read shorter
+    /// than one window has none, and bases past the last whole window are
+    /// not counted
+    pub fn for_each(&self, seq: &[u8], mut emit: impl FnMut(&[u8])) {
+        let Some(last) = seq.len().checked_sub(self.length) else {
+            return;
+        };
+        for start in (0..=last).step_by(self.step) {
+            emit(&seq[start..start + self.length]);
+        }
+    }
+}
+
+impl FromStr for Windows {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (length, step) = spec
+            .split_once(',')
+            .ok_or_else(|| format!("expected L,S (e.g. 50,25), got '{}'", spec))?;
+        let positive = |value: &str, name: &str| {
+            value
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("{} must be a positive integer, got '{}'", name, value))
+        };
+        Ok(Windows {
+            length: positive(length, "L")?,
+            step: positive(step, "S")?,
+        })
+    }
+}
+
+impl fmt::Display for Windows {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.length, self.step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(spec: &str, seq: &str) -> Option<String> {
+        let spec: KeySpec = spec.parse().unwrap();
+        spec.extract(seq.as_bytes())
+            .map(|key| String::from_utf8(key.into_owned()).unwrap())
+    }
+
+    fn windows(spec: &str, seq: &str) -> Vec<String> {
+        let windows: Windows = spec.parse().unwrap();
+        let mut found = Vec::new();
+        windows.for_each(seq.as_bytes(), |window| {
+            found.push(String::from_utf8(window.to_vec()).unwrap())
+        });
+        found
+    }
+
+    #[test]
+    fn slices_take_bases_from_either_end() {
+        assert_eq!(key("seq[2:5]", "ACGTACGT").as_deref(), Some("GTA"));
+        assert_eq!(key("seq[-3:]", "ACGTACGT").as_deref(), Some("CGT"));
+        assert_eq!(key("seq[:-6]", "ACGTACGT").as_deref(), Some("AC"));
+        assert_eq!(key("seq[-0:2]", "ACGTACGT").as_deref(), Some("AC"));
+        assert_eq!(key("prefix:3", "ACGTACGT").as_deref(), Some("ACG"));
+        assert_eq!(key("suffix:3", "ACGTACGT").as_deref(), Some("CGT"));
+        assert_eq!(
+            key("seq[0:2]+seq[-2:]", "ACGTACGG").as_deref(),
+            Some("ACGG")
+        );
+    }
+
+    #[test]
+    fn reads_too_short_for_a_slice_have_no_key() {
+        // Bounds past the end of the read, from either side
+        assert_eq!(key("seq[2:10]", "ACGTAC"), None);
+        assert_eq!(key("seq[7:]", "ACGTAC"), None);
+        assert_eq!(key("seq[-7:]", "ACGTAC"), None);
+        assert_eq!(key("prefix:7", "ACGTAC"), None);
+        assert_eq!(key("suffix:7", "ACGTAC"), None);
+        // Bounds that cross in a short read
+        assert_eq!(key("seq[3:-3]", "ACGTAC"), None);
+        // One short slice drops the whole joined key
+        assert_eq!(key("seq[0:2]+seq[4:8]", "ACGTAC"), None);
+        // A slice reaching exactly to the end still fits
+        assert_eq!(key("seq[2:6]", "ACGTAC").as_deref(), Some("GTAC"));
+    }
+
+    #[test]
+    fn malformed_or_empty_slices_are_refused() {
+        for spec in [
+            "seq[4:2]",
+            "seq[-2:-4]",
+            "seq[1]",
+            "seq[a:2]",
+            "prefix:0",
+            "infix:3",
+            "1:4",
+        ] {
+            assert!(spec.parse::<KeySpec>().is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn masks_past_the_end_of_a_short_read_are_left_out() {
+        let masks: Vec<Mask> = ["2:4", "8:12"].iter().map(|m| m.parse().unwrap()).collect();
+        assert_eq!(&*Mask::apply(&masks, b"ACGTACGT"), b"ACNNACGT");
+        assert_eq!(&*Mask::apply(&masks, b"ACGTACGTAC"), b"ACNNACGTNN");
+        assert_eq!(&*Mask::apply(&masks, b"AC"), b"AC");
+        assert!("4".parse::<Mask>().is_err());
+    }
+
+    #[test]
+    fn windows_cover_each_whole_stretch_of_a_read() {
+        assert_eq!(windows("4,2", "ACGTACGT"), ["ACGT", "GTAC", "ACGT"]);
+        // A step that does not divide the read leaves its tail out
+        assert_eq!(windows("4,3", "ACGTACGT"), ["ACGT", "TACG"]);
+        // A read of one window, and one shorter than a window
+        assert_eq!(windows("8,1", "ACGTACGT"), ["ACGTACGT"]);
+        assert!(windows("9,1", "ACGTACGT").is_empty());
+        // Steps past the window skip bases between windows
+        assert_eq!(windows("2,5", "ACGTACGT"), ["AC", "CG"]);
+        for spec in ["0,1", "4,0", "4", "a,1"] {
+            assert!(spec.parse::<Windows>().is_err(), "{}", spec);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::skip::SkipBudget;
+use anyhow::{Context, Result, bail};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+
+/// Read buffer for the decompressed stream
+const BUFFER_SIZE: usize = 1024 * 1024; // 1MB
+
+/// Line-based FASTA/FASTQ reader that skips malformed records, charging each
+/// to a `SkipBudget`, where needletail would stop at the first one
+pub struct LenientReader<'a> {
+    reader: BufReader<Box<dyn Read + Send>>,
+    budget: &'a SkipBudget,
+    fastq: bool,
+    /// Lines read ahead while looking for a record start, with line numbers
+    lookahead: VecDeque<(u64, Vec<u8>)>,
+    line_number: u64,
+    seq: Vec<u8>,
+}
+
+impl<'a> LenientReader<'a> {
+    pub fn new(stream: Box<dyn Read + Send>, budget: &'a SkipBudget) -> Result<Self> {
+        let mut reader = BufReader::with_capacity(BUFFER_SIZE, stream);
+        let fastq = match reader.fill_buf()?.first() {
+            Some(b'@') => true,
+            Some(b'>') => false,
+            Some(&byte) => bail!(
+                "Expected '@' or '>' at the start of the file but found '{}'",
+                byte as char
+            ),
+            None => bail!("File is empty"),
+        };
+        Ok(Self {
+            reader,
+            budget,
+            fastq,
+            lookahead: VecDeque::new(),
+            line_number: 0,
+            seq: Vec::new(),
+        })
+    }
+
+    /// Pass the next well-formed record's id, sequence and quality (FASTQ) to
+    /// `emit`; `false` at end of input
+    pub fn next_record(&mut self, emit: impl FnOnce(&[u8], &[u8], Option<&[u8]>)) -> Result<bool> {
+        if self.fastq {
+            self.next_fastq(emit)
+        } else {
+            self.next_fasta(emit)
+        }
+    }
+
+    fn next_fastq(&mut self, emit: impl FnOnce(&[u8], &[u8], Option<&[u8]>)) -> Result<bool> {
+        loop {
+            let Some((number, header)) = self.next_line()? else {
+                return Ok(false);
+            };
+            if header.is_empty() {
+                // Trailing blank lines
+                continue;
+            }
+            let location = format!("line {}", number);
+            let (seq, plus, qual) = (self.next_line()?, self.next_line()?, self.next_line()?);
+            match (seq, plus, qual) {
+                (Some((_, seq)), Some((_, plus)), Some((_, qual)))
+                    if header[0] == b'@' && plus.first() == Some(&b'+') =>
+                {
+                    if qual.len() != seq.len() {
+                        let reason = format!(
+                            "Sequence and quality lengths differ ({} vs {})",
+                            seq.len(),
+                            qual.len()
+                        );
+                        self.budget.skip(number, &location, &reason)?;
+                        continue;
+                    }
+                    emit(&header[1..], &seq, Some(&qual));
+                    return Ok(true);
+                }
+                (seq, plus, qual) => {
+                    self.budget
+                        .skip(number, &location, "not a four-line FASTQ record")?;
+                    // The record layout is broken: put back the lines after the
+                    // header and find the next record start among them
+                    for line in [qual, plus, seq].into_iter().flatten() {
+                        self.lookahead.push_front(line);
+                    }
+                    self.sync_fastq()?;
+                }
+            }
+        }
+    }
+
+    /// Drop lines up to the next record start. A quality line may also begin
+    /// with '@', but only a header is followed two lines later by '+'.
+    fn sync_fastq(&mut self) -> Result<()> {
+        loop {
+            let Some(at) = self.peek(0)?.map(|line| line.first() == Some(&b'@')) else {
+                return Ok(());
+            };
+            if at
+                && self
+                    .peek(2)?
+                    .is_some_and(|line| line.first() == Some(&b'+'))
+            {
+                return Ok(());
+            }
+            self.next_line()?;
+        }
+    }
+
+    fn next_fasta(&mut self, emit: impl FnOnce(&[u8], &[u8], Option<&[u8]>)) -> Result<bool> {
+        loop {
+            let Some((number, header)) = self.next_line()? else {
+                return Ok(false);
+            };
+            if header.is_empty() {
+                continue;
+            }
+            if header[0] != b'>' {
+                let location = format!("line {}", number);
+                self.budget
+                    .skip(number, &location, "sequence line outside a FASTA record")?;
+                self.skip_sequence_lines(None)?;
+                continue;
+            }
+            let mut seq = std::mem::take(&mut self.seq);
+            seq.clear();
+            self.skip_sequence_lines(Some(&mut seq))?;
+            emit(&header[1..], &seq, None);
+            self.seq = seq;
+            return Ok(true);
+        }
+    }
+
+    /// Consume lines up to the next header, appending them to `seq` if given
+    fn skip_sequence_lines(&mut self, mut seq: Option<&mut Vec<u8>>) -> Result<()> {
+        while self
+            .peek(0)?
+            .is_some_and(|line| line.first() != Some(&b'>'))
+        {
+            if let (Some((_, line)), Some(seq)) = (self.next_line()?, seq.as_deref_mut()) {
+                seq.extend_from_slice(&line);
+            }
+        }
+        Ok(())
+    }
+
+    fn next_line(&mut self) -> Result<Option<(u64, Vec<u8>)>> {
+        match self.lookahead.pop_front() {
+            Some(line) => Ok(Some(line)),
+            None => self.read_line(),
+        }
+    }
+
+    /// Line `n` ahead of the current position, without consuming it
+    fn peek(&mut self, n: usize) -> Result<Option<&[u8]>> {
+        while self.lookahead.len() <= n {
+            match self.read_line()? {
+                Some(line) => self.lookahead.push_back(line),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(&self.lookahead[n].1))
+    }
+
+    /// Next line from the stream, without its line ending
+    fn read_line(&mut self) -> Result<Option<(u64, Vec<u8>)>> {
+        let mut line = Vec::new();
+        let n = self
+            .reader
+            .read_until(b'\n', &mut line)
+            .context("Failed to read record")?;
+        if n == 0 {
+            return Ok(None);
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        self.line_number += 1;
+        Ok(Some((self.line_number, line)))
+    }
+}
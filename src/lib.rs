@@ -0,0 +1,3346 @@
+//! Count tables of FASTA/FASTQ sequences. The `seqtable` binary is a thin
+//! wrapper around [`main`]; the items exported here are for crates that
+//! count reads themselves or write seqtable's tables in formats of their own.
+//!
+//! [`count_file`] counts one input under [`CountOptions`] (the command line's
+//! options, plus any [`ReadTransform`]s of the caller's) on a rayon pool the
+//! caller builds, so an embedding program decides how the cores are shared;
+//! [`count_reader`] counts a stream (an upload, say) as it arrives.
+//!
+//! A format implements [`TableFormat`], opening an [`OutputWriter`] on a
+//! table's columns (Arrow fields), which is then given the rows a [`Batch`]
+//! at a time and finalized once all are in. Tables of [`Column`]s are then
+//! written in it by [`save_table`] or, a batch at a time, a [`TableWriter`]:
+//!
+//! ```
+//! use anyhow::Result;
+//! use seqtable::arrow::datatypes::Field;
+//! use seqtable::{Batch, Column, ColumnData, OutputWriter, TableFormat, TableOptions};
+//! use std::io::Write;
+//! use std::path::Path;
+//!
+//! /// One JSON array per row
+//! struct JsonLines;
+//!
+//! struct JsonLinesWriter {
+//!     file: std::fs::File,
+//!     columns: usize,
+//! }
+//!
+//! impl TableFormat for JsonLines {
+//!     fn open(
+//!         &self,
+//!         path: &Path,
+//!         fields: Vec<Field>,
+//!         _options: TableOptions,
+//!     ) -> Result<Box<dyn OutputWriter>> {
+//!         let file = std::fs::File::create(path)?;
+//!         Ok(Box::new(JsonLinesWriter { file, columns: fields.len() }))
+//!     }
+//! }
+//!
+//! impl OutputWriter for JsonLinesWriter {
+//!     fn write_batch(&mut self, batch: &dyn Batch) -> Result<()> {
+//!         for row in 0..batch.num_rows() {
+//!             let cells: Vec<_> = (0..self.columns).map(|c| batch.cell(row, c)).collect();
+//!             writeln!(self.file, "{:?}", cells)?;
+//!         }
+//!         Ok(())
+//!     }
+//!
+//!     fn finalize(self: Box<Self>) -> Result<()> {
+//!         Ok(self.file.sync_all()?)
+//!     }
+//! }
+//!
+//! # fn main() -> Result<()> {
+//! let path = std::env::temp_dir().join(format!("seqtable-doc-{}.jsonl", std::process::id()));
+//! let columns = [
+//!     Column::new("sequence", ColumnData::Text(vec!["ACGT".into(), "TTGA".into()])),
+//!     Column::new("count", ColumnData::Count(vec![12, 7])),
+//! ];
+//! seqtable::save_table(&columns, &path, &JsonLines, "none")?;
+//! assert_eq!(std::fs::read_to_string(&path)?, "[\"ACGT\", \"12\"]\n[\"TTGA\", \"7\"]\n");
+//! # std::fs::remove_file(&path)?;
+//! # Ok(())
+//! # }
+//! ```
+
+#![allow(clippy::collapsible_if)]
+
+use affinity::CpuLayout;
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use arena::{ArenaCounts, KeyBatch};
+use artifacts::Artifacts;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+use regex::Regex;
+use serde_json::json;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+mod affinity;
+mod amplicon;
+mod annotate;
+mod arena;
+mod artifacts;
+mod assign;
+mod avro;
+mod bases;
+mod benchmark;
+mod bgzf;
+mod biom;
+mod checksum;
+mod chimera;
+mod config;
+mod contaminants;
+mod counter;
+mod dedup;
+mod diff;
+mod diversity;
+mod duplicates;
+mod exit;
+mod expected;
+mod h5ad;
+mod histogram;
+mod illumina;
+mod input;
+mod interrupt;
+mod key;
+mod lenient;
+mod library;
+mod lookup;
+mod manifest;
+mod mapped;
+mod merge;
+mod minimizer;
+mod orc;
+mod orient;
+mod output;
+mod packed;
+mod pairs;
+mod progress;
+mod provenance;
+mod quality;
+mod retry;
+mod schema;
+mod serve;
+mod setop;
+mod similarity;
+mod sketch;
+mod skip;
+mod spill;
+mod state;
+mod stats;
+mod summary;
+mod table;
+mod template;
+mod transform;
+mod validate;
+mod watch;
+pub use arrow;
+pub use library::{CountOptions, Counts, count_file, count_reader};
+pub use output::{
+    Batch, Column, ColumnData, OutputFormat, OutputWriter, TableFormat, TableOptions, TableWriter,
+    save_table,
+};
+pub use transform::{Read, ReadTransform, Stage, Transforms};
+
+use amplicon::{Amplicons, Primer};
+use annotate::Reference;
+use assign::AssignmentWriter;
+use checksum::{Checksum, Digests, ExpectedMd5, ManifestEntry};
+use contaminants::{ContaminantAction, Contaminants};
+use counter::{CounterBackend, SharedCounter};
+use dedup::DedupWriter;
+use diff::DiffArgs;
+use diversity::Diversity;
+use duplicates::DuplicateInputs;
+use exit::{Classify, Failure};
+use expected::ExpectedSet;
+use histogram::{Histograms, Selection};
+use illumina::IlluminaField;
+use input::{Input, KeyHooks, ReadOptions};
+use key::{KeySpec, Mask, Windows};
+use lookup::LookupArgs;
+use merge::MergeArgs;
+use minimizer::{Minimizers, SeedPattern};
+use orient::Orientation;
+use output::{CountType, Filtered, SequenceRecord, SortOrder, StringType};
+use packed::SequenceCounter;
+use pairs::{Mate, MergeOptions};
+use progress::{BatchProgress, ProgressFormat};
+use provenance::Provenance;
+use quality::{ExpectedErrors, PhredOffset, QualityBins};
+use schema::ValidateTableArgs;
+use serve::ServeArgs;
+use setop::SetopArgs;
+use similarity::SimilarityArgs;
+use sketch::Signature;
+use skip::SkipBudget;
+use state::RunState;
+use summary::{FailedFile, FileSummary, RunSummary};
+use table::CountTable;
+use template::{Names, OutputTemplate};
+use validate::ValidateArgs;
+use watch::WatchArgs;
+
+/// Smallest chunk the memory limit shrinks adaptive chunking to
+const MIN_CHUNK_SIZE: usize = 1_000;
+
+/// Adaptive chunk size for inputs of unknown size (pipes), as for a large file
+const STREAM_CHUNK_SIZE: usize = 50_000;
+
+/// High-performance FASTA/FASTQ sequence counter with parallel processing
+#[derive(Parser, Debug, Clone)]
+#[command(name = "seqtable")]
+#[command(author = "Seungwon Lee")]
+#[command(version = "0.1.1")]
+#[command(about = "High performance FASTA/FASTQ sequence count table generator", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(group(clap::ArgGroup::new("filter").multiple(true)))]
+#[command(group(clap::ArgGroup::new("primer_pairs").args(["fwd_primer", "primers"])))]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Input file path(s) - FASTA/FASTQ, plain or gzip/bzip2/xz/zstd compressed,
+    /// or count tables (.parquet/.csv/.tsv) to filter, annotate or convert
+    #[arg(required_unless_present_any = ["file_list", "manifest"])]
+    input: Vec<PathBuf>,
+
+    /// Read further input paths from a file, one per line (`-` for stdin)
+    #[arg(long, value_name = "FILE")]
+    file_list: Option<PathBuf>,
+
+    /// CSV/TSV of further inputs with per-file options: an `input` column plus
+    /// any of sample, output, rpm, group-by-header, skip-errors, chunk-size,
+    /// threads
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+
+    /// Inputs are counts from another tool, one `sequence<TAB>count` line per
+    /// sequence (plain or compressed), rather than reads
+    #[arg(long)]
+    counts_in: bool,
+
+    /// What to do with an input listed twice, by path or as a copy of another
+    #[arg(long, value_name = "ACTION", default_value = "skip")]
+    duplicate_inputs: DuplicateInputs,
+
+    /// Sample name for the outputs and sketch, instead of the input file stem
+    /// (from `--sample-name` or the manifest)
+    #[arg(skip)]
+    sample_name: Option<String>,
+
+    /// Output file name (without extension), instead of sample name + suffix
+    #[arg(skip)]
+    output_name: Option<String>,
+
+    /// Output path from `--output`, instead of a name in the output directory
+    #[arg(skip)]
+    output_file: Option<PathBuf>,
+
+    /// Output directory (default: current directory)
+    #[arg(short, long, default_value = ".")]
+    output_dir: PathBuf,
+
+    /// Output filename suffix
+    #[arg(short = 's', long, default_value = "_counts")]
+    suffix: String,
+
+    /// Write the count table of the Nth input exactly here (repeat once per
+    /// input; the format is taken from the extension when recognized)
+    #[arg(long, value_name = "FILE")]
+    output: Vec<PathBuf>,
+
+    /// Sample name of the Nth input (repeat once per input; default: the file
+    /// stem), stored in Parquet metadata and used by merge, diff, and similarity
+    #[arg(long = "sample-name", value_name = "NAME")]
+    sample_names: Vec<String>,
+
+    /// Add a `sample` column holding the sample name to count tables
+    #[arg(long)]
+    sample_column: bool,
+
+    /// Count table columns to write, in this order (e.g. `sequence,count,rpm`)
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// Rename count table columns (e.g. `count=reads`; repeat or comma-separate)
+    #[arg(long, value_name = "OLD=NEW", value_delimiter = ',', value_parser = output::parse_rename)]
+    rename: Vec<(String, String)>,
+
+    /// Output file name; placeholders {stem} (input name without extensions),
+    /// {sample} (manifest sample, else stem), {suffix}, {date} (UTC), {ext}
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = "{sample}{suffix}.{ext}"
+    )]
+    output_template: OutputTemplate,
+
+    /// Output format(s); repeat or comma-separate to write several from one pass
+    #[arg(short = 'f', long, default_value = "parquet", value_delimiter = ',')]
+    format: Vec<OutputFormat>,
+
+    /// Chunk size for memory/speed tradeoff (0 = auto)
+    #[arg(short, long, default_value = "0")]
+    chunk_size: usize,
+
+    /// How worker threads combine counts (concurrent maps skip the final merge)
+    #[arg(long, default_value = "chunked")]
+    counter: CounterBackend,
+
+    /// Number of threads to use (0 = auto-detect, considering parallel jobs)
+    #[arg(short, long, default_value = "0")]
+    threads: usize,
+
+    /// Disable progress bar
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// How progress is reported on stderr: `bar` for progress bars and status
+    /// lines, `json` for NDJSON progress events in their place
+    #[arg(long, value_enum, default_value = "bar")]
+    progress: ProgressFormat,
+
+    /// Pin each worker thread to its own CPU, filling one NUMA node before
+    /// the next
+    #[arg(long)]
+    pin_threads: bool,
+
+    /// Spread memory across NUMA nodes, so the maps shared by all workers
+    /// (`--counter dashmap`/`shard`) are not served from a single node
+    #[arg(long)]
+    numa_interleave: bool,
+
+    /// Time counting the first records of the first input under several thread
+    /// counts, chunk sizes and counters, recommend the fastest, and exit
+    #[arg(long)]
+    benchmark: bool,
+
+    /// Resolve the inputs and print each one's format, chunk size and
+    /// planned outputs, then exit without counting or writing anything
+    #[arg(long, conflicts_with = "benchmark")]
+    dry_run: bool,
+
+    /// Print the resolved configuration (every option's effective value and
+    /// the inputs) as JSON, then exit; the `--summary` JSON includes it too
+    #[arg(long, conflicts_with_all = ["benchmark", "dry_run"])]
+    print_config: bool,
+
+    /// Skip malformed records instead of failing the file, up to N per file
+    /// (no limit if N is omitted or 0); the skipped count is reported
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "0")]
+    skip_errors: Option<u64>,
+
+    /// FASTQ quality encoding; detected from the first records unless given
+    #[arg(long, value_name = "OFFSET", default_value = "auto")]
+    phred_offset: PhredOffset,
+
+    /// Skip inputs completed by a previous run into the same output directory
+    /// (tracked in .seqtable-state.json)
+    #[arg(long, conflicts_with = "sketch")]
+    resume: bool,
+
+    /// Carry on with the remaining inputs when one fails, then exit non-zero
+    /// with the failures listed in failures.json in the output directory
+    #[arg(long)]
+    keep_going: bool,
+
+    #[arg(long, default_value = "snappy", help = output::COMPRESSION_HELP)]
+    compression: String,
+
+    /// Row order of count tables: most abundant first, or ascending byte order
+    /// of the sequence (or group) column, as `merge --presorted` reads them
+    #[arg(long, value_name = "ORDER", default_value = "count")]
+    sort: SortOrder,
+
+    /// Write TSV tables bgzip-compressed (.tsv.gz) with an index of the
+    /// sequence (or group) column beside them (.tsv.gz.idx), for `seqtable
+    /// lookup`; needs --sort sequence
+    #[arg(long)]
+    bgzip: bool,
+
+    /// Parquet type of the count column (u32 holds counts up to 4,294,967,295)
+    #[arg(long, value_name = "TYPE", default_value = "u64")]
+    count_type: CountType,
+
+    /// Parquet type of the sequence (or group) column
+    #[arg(long, value_name = "TYPE", default_value = "large_utf8")]
+    string_type: StringType,
+
+    /// Leave the input MD5 out of Parquet metadata, saving the time to hash each input
+    #[arg(long, conflicts_with = "checksum")]
+    no_checksum: bool,
+
+    /// Hash each input (MD5 and SHA-256) while counting and write a manifest of
+    /// inputs, checksums, and outputs (default: checksums.tsv in the output directory)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    checksum: Option<Option<PathBuf>>,
+
+    /// Check each input's MD5, computed while counting, against an md5sum
+    /// listing of file names; an input that differs or is not listed fails
+    #[arg(long, value_name = "FILE")]
+    verify_md5: Option<PathBuf>,
+
+    /// Retry a read failing with a transient error (EIO, a stale NFS handle,
+    /// a timeout) this many times, reopening the file where it stopped
+    #[arg(long, value_name = "N", default_value = "3")]
+    read_retries: u32,
+
+    /// Seconds before the first retry of a read, doubling with each one
+    #[arg(long, value_name = "SECS", default_value = "1")]
+    retry_delay: u64,
+
+    /// Also write one read of each distinct sequence, compressed by
+    /// extension (default: <table name>_dedup.fq.gz or .fa.gz beside each table)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    write_dedup: Option<Option<PathBuf>>,
+
+    /// Also write each read's name and the key it was counted under (and with
+    /// --annotate, its reference name) as TSV, compressed by extension
+    /// (default: <table name>_assignments.tsv.gz beside each table)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    write_assignments: Option<Option<PathBuf>>,
+
+    /// Also write a read length histogram (TSV) from the counting pass
+    /// (default: <table name>_length_hist.tsv beside each table)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    length_hist: Option<Option<PathBuf>>,
+
+    /// Also write a histogram of read GC content in whole percent (TSV)
+    /// (default: <table name>_gc_hist.tsv beside each table)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    gc_hist: Option<Option<PathBuf>>,
+
+    /// Also write bases per Phred quality score (TSV) for FASTQ inputs
+    /// (default: <table name>_quality_hist.tsv beside each table)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    quality_hist: Option<Option<PathBuf>>,
+
+    /// Also write the mean quality at each read position (TSV) for FASTQ inputs
+    /// (default: <table name>_cycle_quality.tsv beside each table)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    cycle_quality: Option<Option<PathBuf>>,
+
+    /// On failure, write the failure kind, exit status and error messages as
+    /// JSON to FILE
+    #[arg(long, value_name = "FILE", global = true)]
+    error_json: Option<PathBuf>,
+
+    /// Write a JSON run summary (reads, bytes read, throughput, peak memory,
+    /// and count map load factor per input) to FILE, or `-` for stdout
+    #[arg(long, value_name = "FILE")]
+    summary: Option<PathBuf>,
+
+    /// Calculate and include RPM (Reads Per Million) column
+    #[arg(long)]
+    rpm: bool,
+
+    /// Report each sample's alpha diversity (richness, Shannon entropy,
+    /// Simpson and inverse Simpson indices, evenness) and skew (Gini
+    /// coefficient, 90/10 percentile ratio, Lorenz curve AUC) over its
+    /// sequence counts, and add them to the --summary JSON
+    #[arg(long)]
+    diversity: bool,
+
+    /// Add an expected_errors column: per read, the sum of its bases' error
+    /// probabilities, averaged over the sequence's reads (FASTQ)
+    #[arg(long, conflicts_with_all = ["minimizers", "window"])]
+    expected_errors: bool,
+
+    /// Report the share of reads that are adapter dimers, shorter than
+    /// --artifact-min-length, or a single base repeated, and add the
+    /// breakdown to the --summary JSON
+    #[arg(long)]
+    artifacts: bool,
+
+    /// Reads shorter than N bases count as short artifacts
+    #[arg(long, value_name = "N", default_value_t = 20, requires = "artifacts")]
+    artifact_min_length: usize,
+
+    /// Leave sequences counted fewer than N times out of count tables
+    #[arg(long, value_name = "N", group = "filter")]
+    min_count: Option<u64>,
+
+    /// Keep only the N most abundant sequences in count tables
+    #[arg(long, value_name = "N", group = "filter")]
+    top: Option<usize>,
+
+    /// Leave sequences seen once out of count tables (same as --min-count 2)
+    #[arg(long, group = "filter")]
+    no_singletons: bool,
+
+    /// Keep only sequences seen once in count tables
+    #[arg(long, group = "filter", conflicts_with_all = ["no_singletons", "min_count"])]
+    singletons_only: bool,
+
+    /// End filtered count tables with an `__other__` row holding the reads removed
+    #[arg(long, requires = "filter")]
+    other_row: bool,
+
+    /// Add a chimera column flagging sequences made of the start of one more
+    /// abundant sequence and the end of another, as PCR template switching
+    /// joins amplicons
+    #[arg(long, conflicts_with_all = ["group_by_header", "minimizers", "window"])]
+    flag_chimeras: bool,
+
+    /// Leave the sequences --flag-chimeras would flag out of count tables
+    #[arg(
+        long,
+        group = "filter",
+        conflicts_with_all = ["flag_chimeras", "group_by_header", "minimizers", "window"]
+    )]
+    remove_chimeras: bool,
+
+    /// How many times as abundant as a chimera its parents must be
+    #[arg(long, value_name = "FOLD", default_value = "2")]
+    chimera_parent_fold: f64,
+
+    /// Count reads per group extracted from the record header instead of per sequence
+    /// (uses the first capture group if present, otherwise the whole match)
+    #[arg(long, value_name = "REGEX")]
+    group_by_header: Option<Regex>,
+
+    /// Also split counts by a tag extracted from the read name, such as a
+    /// sample barcode or UMI moved there upstream (the first capture group
+    /// if present, otherwise the whole match)
+    #[arg(long, value_name = "REGEX", conflicts_with = "group_by_header")]
+    group_by_read_name: Option<Regex>,
+
+    /// Count reads by part of their sequence: the first (`prefix:N`) or last
+    /// (`suffix:N`) N bases, or slices joined in order (`seq[0:8]+seq[20:40]`,
+    /// negative bounds count from the end); reads too short are not counted
+    #[arg(long, value_name = "SPEC", conflicts_with = "group_by_header")]
+    key: Option<KeySpec>,
+
+    /// Replace read positions START:END (0-based, END excluded, negative
+    /// bounds counting from the end) with N before keying, so variable bases
+    /// such as an embedded UMI do not split counts (repeat or comma-separate)
+    #[arg(
+        long,
+        value_name = "START:END",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        conflicts_with = "group_by_header"
+    )]
+    mask: Vec<Mask>,
+
+    /// Count the (k,w)-minimizers of each read instead of whole reads (e.g.
+    /// `15,10`), for long reads that rarely repeat exactly; K is at most 32
+    #[arg(
+        long,
+        value_name = "K,W",
+        conflicts_with_all = ["group_by_header", "key", "write_assignments"]
+    )]
+    minimizers: Option<Minimizers>,
+
+    /// Count every window of L bases, every S bases along each read (e.g.
+    /// `50,25`), instead of whole reads, for tiling coverage of long
+    /// amplicons; reads shorter than L are not counted
+    #[arg(
+        long,
+        value_name = "L,S",
+        conflicts_with_all = ["group_by_header", "minimizers", "write_assignments"]
+    )]
+    window: Option<Windows>,
+
+    /// Count amplicon inserts: find a primer pair (--fwd-primer and
+    /// --rev-primer, or one from --primers) in each read, on either strand,
+    /// and count the bases between them, oriented as the forward primer
+    /// reads; reads without both primers are not counted
+    #[arg(
+        long,
+        requires = "primer_pairs",
+        conflicts_with_all = ["group_by_header", "key", "minimizers"]
+    )]
+    amplicon: bool,
+
+    /// Forward primer for --amplicon (IUPAC codes allowed)
+    #[arg(long, value_name = "SEQ", requires_all = ["amplicon", "rev_primer"])]
+    fwd_primer: Option<Primer>,
+
+    /// Reverse primer for --amplicon, as ordered (5'→3' on the reverse strand)
+    #[arg(long, value_name = "SEQ", requires_all = ["amplicon", "fwd_primer"])]
+    rev_primer: Option<Primer>,
+
+    /// Primer panel for --amplicon: `NAME FORWARD REVERSE` per line; counts
+    /// get an amplicon column, and a read counts for the first pair it holds
+    #[arg(long, value_name = "FILE", requires = "amplicon")]
+    primers: Option<PathBuf>,
+
+    /// Mismatches allowed in each primer match
+    #[arg(long, value_name = "N", default_value = "2", requires = "amplicon")]
+    primer_max_mismatches: u32,
+
+    /// Reverse-complement reads that share more k-mers with the reverse
+    /// strand of the sequences in FASTA than with their forward strand, so
+    /// mixed-orientation libraries count each molecule under one sequence
+    #[arg(
+        long,
+        value_name = "FASTA",
+        conflicts_with_all = ["group_by_header", "amplicon"]
+    )]
+    orient: Option<PathBuf>,
+
+    /// Screen reads for contaminants before counting: FASTA files, or
+    /// `adapters` for the built-in Illumina adapter set (repeat or
+    /// comma-separate)
+    #[arg(long, value_name = "FASTA", value_delimiter = ',')]
+    contaminants: Vec<String>,
+
+    /// What happens to reads matching a contaminant
+    #[arg(
+        long,
+        value_name = "ACTION",
+        default_value = "remove",
+        requires = "contaminants"
+    )]
+    contaminant_action: ContaminantAction,
+
+    /// Inputs are paired-end mates, R1 then R2 of each pair: merge each
+    /// pair's overlapping mates into one read and count that; pairs that do
+    /// not overlap are not counted
+    #[arg(long, conflicts_with_all = ["skip_errors", "manifest"])]
+    merge_pairs: bool,
+
+    /// Shortest overlap accepted when merging mates
+    #[arg(
+        long,
+        value_name = "N",
+        default_value = "10",
+        requires = "merge_pairs",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    min_overlap: u64,
+
+    /// Mismatched bases allowed in the overlap of merged mates
+    #[arg(long, value_name = "N", default_value = "10", requires = "merge_pairs")]
+    max_overlap_diffs: u64,
+
+    /// Spaced seed for --minimizers, as long as K: k-mers are compared only
+    /// at `1` positions, so they match across mismatches at `0` positions
+    #[arg(long, value_name = "PATTERN", requires = "minimizers")]
+    seed_pattern: Option<SeedPattern>,
+
+    /// Also split counts by fields of Illumina read names, adding a column for each
+    /// (e.g. `lane` or `flowcell,lane`); reads with other headers are not counted
+    #[arg(long, value_name = "FIELDS", value_delimiter = ',')]
+    parse_illumina_headers: Vec<IlluminaField>,
+
+    /// Write separate tables per value of an Illumina read-name field
+    /// (`s1_counts_lane1.parquet`, sample `s1_lane1`)
+    #[arg(long, value_name = "FIELD")]
+    split_by: Option<IlluminaField>,
+
+    /// Also split counts by mean read quality, adding a quality_bin column
+    /// (e.g. `0-20,20-30,30+`; lower bounds inclusive); reads outside every
+    /// bin are not counted
+    #[arg(long, value_name = "BINS")]
+    quality_bins: Option<QualityBins>,
+
+    /// Reference FASTA to annotate sequences by exact match (adds name/matched columns)
+    #[arg(long, value_name = "FASTA")]
+    annotate: Option<PathBuf>,
+
+    /// Also label near matches within this edit distance of a reference (adds distance column)
+    #[arg(long, value_name = "N", default_value = "0", requires = "annotate")]
+    annotate_max_mismatches: u32,
+
+    /// Expected sequences (one per line, optionally `NAME SEQUENCE`) to report recovery for
+    #[arg(long, value_name = "FILE")]
+    expected: Option<PathBuf>,
+
+    /// Spike-in FASTA; adds normalized_count (counts per million spike-in reads)
+    #[arg(long, value_name = "FASTA")]
+    spike_in: Option<PathBuf>,
+
+    /// Write FracMinHash signatures of all inputs to a sourmash-compatible .sig file
+    #[arg(long, value_name = "FILE", conflicts_with = "group_by_header")]
+    sketch: Option<PathBuf>,
+
+    /// Sketch scaled factor (keep 1 in N k-mer hashes)
+    #[arg(long, default_value = "1000", requires = "sketch")]
+    scaled: u64,
+
+    /// Sketch k-mer size
+    #[arg(long, default_value = "31", requires = "sketch", value_parser = clap::value_parser!(u32).range(1..))]
+    sketch_k: u32,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Merge per-sample count tables into a sequence × sample matrix
+    Merge(MergeArgs),
+    /// Compare treatment against control samples (mean counts, log2 fold change)
+    Diff(DiffArgs),
+    /// Pairwise Jaccard, overlap, and Spearman similarity between samples
+    Similarity(SimilarityArgs),
+    /// Sequences shared by, in any of, or unique to the first of several tables
+    Setop(SetopArgs),
+    /// Check inputs for malformed records and corrupt compression without counting
+    Validate(ValidateArgs),
+    /// Check count tables' columns against the versioned count table schema
+    ValidateTable(ValidateTableArgs),
+    /// Print the rows of sequences in a --bgzip table through its index
+    Lookup(LookupArgs),
+    /// Monitor a directory and count new input files as they land
+    Watch(WatchArgs),
+    /// Serve counting jobs over HTTP: submit inputs, poll status, fetch tables
+    Serve(ServeArgs),
+}
+
+/// Auxiliary inputs loaded once and shared by every processed file
+struct SharedInputs {
+    reference: Option<Reference>,
+    /// `--minimizers`, with its spaced seed applied
+    minimizers: Option<Minimizers>,
+    /// `--amplicon` primer pairs
+    amplicon: Option<Amplicons>,
+    /// `--orient` reference
+    orientation: Option<Orientation>,
+    /// `--contaminants` screened for
+    contaminants: Option<Contaminants>,
+    expected: Option<ExpectedSet>,
+    spike_ins: Option<Reference>,
+    expected_md5: Option<ExpectedMd5>,
+    pools: ThreadPools,
+    /// NUMA nodes memory is spread over, for `--numa-interleave`
+    interleaved_nodes: Option<usize>,
+}
+
+/// Rayon pools by thread count, built as inputs first need them, so each input
+/// can run on its own number of threads without touching the global pool
+struct ThreadPools {
+    pools: Mutex<AHashMap<usize, Arc<rayon::ThreadPool>>>,
+    /// CPUs to pin workers to, for `--pin-threads`
+    pinning: Option<Arc<CpuLayout>>,
+}
+
+impl ThreadPools {
+    fn new(pinning: Option<CpuLayout>) -> Self {
+        Self {
+            pools: Mutex::new(AHashMap::new()),
+            pinning: pinning.map(Arc::new),
+        }
+    }
+
+    /// The pool for `--threads` value `requested` (0 = auto)
+    fn get(&self, requested: usize) -> Result<Arc<rayon::ThreadPool>> {
+        let threads = calculate_optimal_threads(requested);
+        let mut pools = self.pools.lock().unwrap();
+        if let Some(pool) = pools.get(&threads) {
+            return Ok(Arc::clone(pool));
+        }
+        let mut builder = rayon::ThreadPoolBuilder::new().num_threads(threads);
+        if let Some(ref layout) = self.pinning {
+            let layout = Arc::clone(layout);
+            builder = builder.start_handler(move |index| {
+                affinity::pin_current_thread(layout.cpu_for(index));
+            });
+        }
+        let pool = builder
+            .build()
+            .context("Failed to initialize thread pool")?;
+        let pool = Arc::new(pool);
+        pools.insert(threads, Arc::clone(&pool));
+        Ok(pool)
+    }
+}
+
+/// The `seqtable` command line: parse the arguments, run them, and exit with
+/// the status of any failure
+pub fn main() {
+    // Arguments clap cannot parse exit with `Failure::Usage`'s status, 2
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let error_json = args.error_json.clone();
+    if let Err(error) = try_main(args, &matches) {
+        eprintln!("Error: {:?}", error);
+        if let Some(path) = error_json {
+            if let Err(report_error) = exit::write_error_json(&path, &error) {
+                eprintln!("Error: {:?}", report_error);
+            }
+        }
+        std::process::exit(exit::code(&error));
+    }
+}
+
+fn try_main(mut args: Args, matches: &ArgMatches) -> Result<()> {
+    interrupt::install_handler()?;
+
+    match args.command {
+        Some(Command::Merge(ref merge_args)) => return merge::run(merge_args),
+        Some(Command::Diff(ref diff_args)) => return diff::run(diff_args),
+        Some(Command::Similarity(ref similarity_args)) => return similarity::run(similarity_args),
+        Some(Command::Setop(ref setop_args)) => return setop::run(setop_args),
+        Some(Command::Validate(ref validate_args)) => return validate::run(validate_args),
+        Some(Command::ValidateTable(ref table_args)) => return schema::run(table_args),
+        Some(Command::Lookup(ref lookup_args)) => return lookup::run(lookup_args),
+        Some(Command::Watch(ref watch_args)) => return watch::run(watch_args),
+        Some(Command::Serve(ref serve_args)) => return serve::run(serve_args),
+        None => {}
+    }
+    let run_start = Instant::now();
+    if args.progress == ProgressFormat::Json {
+        // The events take the place of the bars and status lines
+        args.quiet = true;
+        progress::enable_json_events();
+    }
+    if args.quiet {
+        progress::set_quiet();
+    }
+
+    if let Some(ref list) = args.file_list {
+        let listed = input::read_file_list(list).classify(Failure::Input)?;
+        args.input.extend(listed);
+        if args.input.is_empty() && args.manifest.is_none() {
+            return exit::usage(format!("No input files listed in {}", list.display()));
+        }
+    }
+
+    // Each input with the options it is counted with; manifest rows carry
+    // their own, the rest share the command line's
+    let entries = match args.manifest {
+        Some(ref path) => manifest::read(path).classify(Failure::Usage)?,
+        None => Vec::new(),
+    };
+    let paths = std::mem::take(&mut args.input);
+    let inputs = if args.merge_pairs {
+        pair_mates(paths, &args)?
+    } else {
+        paths.into_iter().map(Input::new).collect()
+    };
+    for (option, given) in [
+        ("--output", args.output.len()),
+        ("--sample-name", args.sample_names.len()),
+    ] {
+        if given > 0 && given != inputs.len() {
+            return exit::usage(format!(
+                "Got {} {} values for {} inputs; give one per input",
+                given,
+                option,
+                inputs.len()
+            ));
+        }
+    }
+    let mut jobs: Vec<(Input, Cow<Args>)> = Vec::with_capacity(inputs.len() + entries.len());
+    for (i, input) in inputs.into_iter().enumerate() {
+        let (output, sample) = (args.output.get(i), args.sample_names.get(i));
+        let job_args = if output.is_none() && sample.is_none() {
+            Cow::Borrowed(&args)
+        } else {
+            let mut job = match output {
+                Some(path) => with_output_file(&args, path).classify(Failure::Usage)?,
+                None => args.clone(),
+            };
+            job.sample_name = sample.cloned();
+            Cow::Owned(job)
+        };
+        jobs.push((input, job_args));
+    }
+    for entry in &entries {
+        let job_args = entry.apply(&args).classify(Failure::Usage)?;
+        jobs.push((Input::new(&entry.input), Cow::Owned(job_args)));
+    }
+    if let Some(option) = named_sidecars(&args).first() {
+        if jobs.len() > 1 {
+            return exit::usage(format!(
+                "{}=FILE names one file but there are {} inputs; \
+                 use {} for one beside each table",
+                option,
+                jobs.len(),
+                option
+            ));
+        }
+    }
+    if jobs.is_empty() {
+        return exit::usage("No input files given".to_string());
+    }
+    drop_duplicates(&mut jobs, &args)?;
+    for (input, job_args) in &jobs {
+        check_output_name(input.path(), job_args)?;
+    }
+    check_collisions(&jobs)?;
+
+    // Column choices are checked now rather than after counting the first input
+    for (_, job_args) in &jobs {
+        output::check_columns(job_args).classify(Failure::Usage)?;
+        lookup::check(job_args).classify(Failure::Usage)?;
+    }
+    let resolving: Vec<(&Path, &Args)> = jobs
+        .iter()
+        .map(|(input, job_args)| (input.path(), job_args.as_ref()))
+        .collect();
+    let config = config::resolved(&Args::command(), matches, &args, &resolving);
+    if args.print_config {
+        println!("{}", serde_json::to_string_pretty(&config)?);
+        return Ok(());
+    }
+    let shared = setup(&args)?;
+
+    if args.benchmark {
+        // Every trial reads the sample again
+        if jobs[0].0.is_stream() {
+            return exit::usage(format!(
+                "--benchmark needs a regular file, but {} is a pipe",
+                jobs[0].0.path().display()
+            ));
+        }
+        // Keyed as the run would key them, split field included; the offset
+        // only moves reads between bins, not the time it takes
+        let transforms = read_transforms(&args, &shared);
+        let fields = key_fields(&args);
+        let keys = RecordKey::new(&args, &shared, &transforms, &fields, Some(33));
+        return benchmark::run(&jobs[0].0, keys, args.threads);
+    }
+
+    // Print header (respect quiet flag)
+    if !args.quiet {
+        eprintln!("🧬 seqtable v0.1.1");
+        eprintln!("📁 Input files: {}", jobs.len());
+        if let Some(ref path) = args.manifest {
+            eprintln!(
+                "📋 Manifest: {} ({} entries)",
+                path.display(),
+                entries.len()
+            );
+        }
+        eprintln!(
+            "🧵 Threads per file: {}",
+            calculate_optimal_threads(args.threads)
+        );
+        if let Some(ref layout) = shared.pools.pinning {
+            eprintln!(
+                "📌 Pinned to {} CPUs on {} NUMA node(s)",
+                layout.cpus.len(),
+                layout.nodes
+            );
+        }
+        if let Some(nodes) = shared.interleaved_nodes {
+            eprintln!("🧩 Memory interleaved across {} NUMA node(s)", nodes);
+        }
+        eprintln!(
+            "📊 Output format: {}",
+            args.format
+                .iter()
+                .map(|f| format!("{:?}", f))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if args.rpm {
+            eprintln!("📈 RPM calculation: enabled");
+        }
+        if let Some(filters) = describe_filters(&args) {
+            eprintln!("🔽 Count filters: {}", filters);
+        }
+        if let Some(ref pattern) = args.group_by_header {
+            eprintln!("🏷️  Grouping by header pattern: {}", pattern);
+        }
+        if let Some(ref pattern) = args.group_by_read_name {
+            eprintln!("🏷️  Splitting by read-name tag: {}", pattern);
+        }
+        if let (Some(forward), Some(reverse)) = (&args.fwd_primer, &args.rev_primer) {
+            eprintln!(
+                "🧪 Amplicon inserts between {} and {} (up to {} mismatches)",
+                forward, reverse, args.primer_max_mismatches
+            );
+        }
+        if let (Some(path), Some(amplicons)) = (&args.primers, &shared.amplicon) {
+            eprintln!(
+                "🧪 Amplicon panel: {} primer pairs from {} (up to {} mismatches)",
+                amplicons.len(),
+                path.display(),
+                args.primer_max_mismatches
+            );
+        }
+        if !args.mask.is_empty() {
+            let masks: Vec<String> = args.mask.iter().map(Mask::to_string).collect();
+            eprintln!("🎭 Masking read positions: {}", masks.join(", "));
+        }
+        if let Some(ref path) = args.orient {
+            eprintln!("🧭 Orienting reads against {}", path.display());
+        }
+        if !args.contaminants.is_empty() {
+            eprintln!(
+                "🧫 Screening for contaminants: {} ({})",
+                args.contaminants.join(", "),
+                match args.contaminant_action {
+                    ContaminantAction::Remove => "removing matches",
+                    ContaminantAction::Flag => "flagging matches",
+                }
+            );
+        }
+        if !args.parse_illumina_headers.is_empty() {
+            eprintln!(
+                "🔬 Splitting by Illumina header fields: {}",
+                illumina_columns(&args.parse_illumina_headers).join(", ")
+            );
+        }
+        if let Some(field) = args.split_by {
+            eprintln!("✂️  Separate tables per {}", field.name());
+        }
+        if let Some(ref bins) = args.quality_bins {
+            eprintln!(
+                "📶 Splitting by mean read quality: {}",
+                bins.labels().join(", ")
+            );
+        }
+        if let Some(ref reference) = shared.reference {
+            eprintln!("🔖 Annotation reference: {} sequences", reference.len());
+            if args.annotate_max_mismatches > 0 {
+                eprintln!(
+                    "   Allowing up to {} mismatches",
+                    args.annotate_max_mismatches
+                );
+            }
+        }
+        if let Some(ref expected) = shared.expected {
+            eprintln!("🎯 Expected sequences: {}", expected.len());
+        }
+        if let Some(ref spike_ins) = shared.spike_ins {
+            eprintln!("⚖️  Spike-in normalization: {} sequences", spike_ins.len());
+        }
+        if let Some(ref sketch) = args.sketch {
+            eprintln!(
+                "✏️  Sketching: k={}, scaled={} → {}",
+                args.sketch_k,
+                args.scaled,
+                sketch.display()
+            );
+        }
+        if args.chunk_size == 0 {
+            eprintln!("🎯 Adaptive chunking: enabled");
+        }
+        eprintln!();
+    }
+
+    if args.dry_run {
+        return print_plan(&jobs, &args);
+    }
+
+    // Several inputs get a compact display: a bar per file plus an overall bar
+    let batch = (!args.quiet && jobs.len() > 1).then(|| BatchProgress::new(jobs.len()));
+
+    // Process each file, recording completions so an interrupted run can resume
+    let mut state = RunState::open(&args.output_dir, args.resume).classify(Failure::Input)?;
+    let mut signatures = Vec::new();
+    let mut checksums = Vec::new();
+    let mut run = RunSummary {
+        files: Vec::new(),
+        skipped: Vec::new(),
+        failed: Vec::new(),
+        config,
+    };
+    for (index, (input, job_args)) in jobs.iter().enumerate() {
+        let input_file = input.path();
+        progress::emit(
+            "file_start",
+            json!({
+                "file": input_file.display().to_string(),
+                "index": index,
+                "files": jobs.len(),
+            }),
+        );
+        let outputs = resume_outputs(&state, input, job_args);
+        if args.resume && state.is_complete(input_file, &outputs) {
+            let note = format!(
+                "⏭️  {}: completed in a previous run, skipped",
+                input_file.display()
+            );
+            match batch {
+                Some(ref batch) => batch.finish_file(&note),
+                None if !args.quiet => eprintln!("{}\n", note),
+                None => {}
+            }
+            // The earlier run's checksums, if it computed them
+            if let Some(digests) = state.digests(input_file) {
+                checksums.push(ManifestEntry::new(input, digests, outputs)?);
+            }
+            progress::emit(
+                "file_skipped",
+                json!({ "file": input_file.display().to_string() }),
+            );
+            run.skipped.push(input_file.to_path_buf());
+            continue;
+        }
+        let processed = process_file(input, job_args, &shared, batch.as_ref(), &mut signatures);
+        let (digests, file) = match processed {
+            Ok(processed) => processed,
+            Err(err) if args.keep_going => {
+                let error = format!("{:#}", err);
+                let note = format!("❌ {}: {}", input_file.display(), error);
+                match batch {
+                    Some(ref batch) => batch.finish_file(&note),
+                    None if !args.quiet => eprintln!("{}\n", note),
+                    None => {}
+                }
+                progress::emit(
+                    "file_failed",
+                    json!({ "file": input_file.display().to_string(), "error": error }),
+                );
+                run.failed.push(FailedFile {
+                    input: input_file.to_path_buf(),
+                    error,
+                });
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        state
+            .mark_complete(input_file, &file.outputs, digests.as_ref())
+            .classify(Failure::Output)?;
+        if let Some(digests) = digests {
+            checksums.push(ManifestEntry::new(input, digests, file.outputs.clone())?);
+        }
+        progress::emit(
+            "file_done",
+            json!({
+                "file": input_file.display().to_string(),
+                "records": file.total_reads,
+                "unique_sequences": file.unique_sequences,
+                "elapsed_secs": file.elapsed.as_secs_f64(),
+                "outputs": file.outputs.iter().map(|p| p.display().to_string()).collect::<Vec<_>>(),
+            }),
+        );
+        run.files.push(file);
+    }
+    if let Some(ref batch) = batch {
+        batch.finish();
+    }
+
+    if let Some(ref manifest) = args.checksum {
+        let path = match manifest {
+            Some(path) => path.clone(),
+            None => args.output_dir.join(checksum::MANIFEST_FILE),
+        };
+        // Only inputs hashed with SHA-256 belong in the manifest
+        checksums.retain(|entry| entry.digests.sha256.is_some());
+        checksum::write_manifest(&path, &checksums).classify(Failure::Output)?;
+        if !args.quiet {
+            eprintln!("🔐 Checksums: {}", path.display());
+        }
+    }
+
+    if let Some(ref sketch_path) = args.sketch {
+        sketch::save_signatures(&signatures, sketch_path).classify(Failure::Output)?;
+    }
+
+    if let Some(ref path) = args.summary {
+        summary::write(path, &run.to_json(run_start.elapsed())).classify(Failure::Output)?;
+        if !args.quiet {
+            eprintln!("📋 Summary: {}", path.display());
+        }
+    }
+
+    progress::emit(
+        "done",
+        json!({
+            "files": run.files.len(),
+            "skipped": run.skipped.len(),
+            "failed": run.failed.len(),
+            "records": run.total_reads(),
+            "elapsed_secs": run_start.elapsed().as_secs_f64(),
+        }),
+    );
+    if !args.quiet {
+        eprintln!("📈 Total: {}", run.describe(run_start.elapsed()));
+    }
+    if args.keep_going {
+        let failures = args.output_dir.join(summary::FAILURES_FILE);
+        if !run.failed.is_empty() {
+            summary::write(&failures, &run.failures_json()).classify(Failure::Output)?;
+            return Err(anyhow::anyhow!(
+                "{} of {} files failed (listed in {})",
+                run.failed.len(),
+                jobs.len(),
+                failures.display()
+            ))
+            .classify(Failure::PartialBatch);
+        }
+        // Drop failures left by an earlier run that this one has now counted
+        std::fs::remove_file(&failures).ok();
+    }
+    if !args.quiet {
+        eprintln!("\n✅ All files processed successfully!");
+    }
+    Ok(())
+}
+
+/// The R1 inputs of `--merge-pairs`, each carrying the R2 after it as its mate
+fn pair_mates(inputs: Vec<PathBuf>, args: &Args) -> Result<Vec<Input>> {
+    if !inputs.len().is_multiple_of(2) {
+        return exit::usage(format!(
+            "--merge-pairs takes R1 and R2 of each pair in turn, but got {} inputs",
+            inputs.len()
+        ));
+    }
+    let options = MergeOptions {
+        min_overlap: args.min_overlap as usize,
+        max_diffs: args.max_overlap_diffs as usize,
+    };
+    Ok(inputs
+        .chunks(2)
+        .map(|mates| {
+            let r2 = Input::new(&mates[1]);
+            Input::new(&mates[0]).with_mate(Mate::new(r2, options))
+        })
+        .collect())
+}
+
+/// Report inputs listed more than once, often a sample sheet mistake, and
+/// count each once unless `--duplicate-inputs` says otherwise
+fn drop_duplicates(jobs: &mut Vec<(Input, Cow<Args>)>, args: &Args) -> Result<()> {
+    let paths: Vec<&Path> = jobs.iter().map(|(input, _)| input.path()).collect();
+    let duplicates = duplicates::find(&paths).classify(Failure::Input)?;
+    let Some(duplicate) = duplicates.first() else {
+        return Ok(());
+    };
+    if args.duplicate_inputs == DuplicateInputs::Error {
+        return exit::usage(format!(
+            "Input listed twice: {}; remove it or pass --duplicate-inputs warn to count both",
+            duplicate.describe(&paths)
+        ));
+    }
+    if !args.quiet {
+        for duplicate in &duplicates {
+            eprintln!(
+                "⚠️  Input listed twice: {}; {}",
+                duplicate.describe(&paths),
+                match args.duplicate_inputs {
+                    DuplicateInputs::Skip => "counting it once",
+                    _ => "counting both",
+                }
+            );
+        }
+    }
+    if args.duplicate_inputs == DuplicateInputs::Skip {
+        let repeats: Vec<usize> = duplicates.iter().map(|duplicate| duplicate.index).collect();
+        let mut index = 0;
+        jobs.retain(|_| {
+            index += 1;
+            !repeats.contains(&(index - 1))
+        });
+    }
+    Ok(())
+}
+
+/// Refuse inputs whose outputs land on the same path (an `--output-template`
+/// without `{stem}`, say), before any of them is counted
+fn check_collisions(jobs: &[(Input, Cow<Args>)]) -> Result<()> {
+    let mut writers: AHashMap<PathBuf, &Path> = AHashMap::new();
+    for (input, job_args) in jobs {
+        let input_path = input.path();
+        for output in input_outputs(input, job_args) {
+            if let Some(other) = writers.insert(output.clone(), input_path) {
+                return exit::usage(format!(
+                    "{} and {} would both write {}; give each its own --output, \
+                     --sample-name or --output-template",
+                    other.display(),
+                    input_path.display(),
+                    output.display()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `--dry-run`: what each input would be read as and written to, from file
+/// sizes and leading bytes alone. Inputs that cannot be opened fail the run
+/// once all are listed.
+fn print_plan(jobs: &[(Input, Cow<Args>)], args: &Args) -> Result<()> {
+    let state = RunState::open(&args.output_dir, args.resume).classify(Failure::Input)?;
+    let mut unreadable = 0;
+    for (input, job_args) in jobs {
+        let input_path = input.path();
+        println!("📄 {}", input_path.display());
+        let detected = input
+            .file_size()
+            .and_then(|size| Ok((size, input::compression(input)?)));
+        let (file_size, compression) = match detected {
+            Ok(detected) => detected,
+            Err(err) => {
+                println!("   ❌ {:#}", err);
+                unreadable += 1;
+                continue;
+            }
+        };
+        let kind = if job_args.counts_in {
+            "sequence/count text"
+        } else if table::is_count_table(input_path) {
+            "count table"
+        } else if input::is_fastq(input) {
+            "FASTQ"
+        } else {
+            "FASTA"
+        };
+        println!(
+            "   📦 {}, {}, {}",
+            kind,
+            compression.unwrap_or("uncompressed"),
+            file_size.map_or("stream of unknown size".to_string(), summary::format_bytes)
+        );
+        println!(
+            "   🧵 Threads: {}",
+            calculate_optimal_threads(job_args.threads)
+        );
+        let chunk_size = calculate_chunk_size(file_size, job_args.chunk_size);
+        let reading = if is_counts_input(input_path, job_args) {
+            "counts as given, not recounted".to_string()
+        } else if mapped::is_mappable(input).unwrap_or(false) {
+            "memory-mapped, parsed in parallel ranges".to_string()
+        } else if chunk_size == 0 {
+            "sequential (small file)".to_string()
+        } else if job_args.chunk_size == 0 {
+            // The memory limit needs the read length, which means reading
+            format!(
+                "chunks of {} sequences (adaptive; smaller if memory is short)",
+                chunk_size
+            )
+        } else {
+            format!("chunks of {} sequences", chunk_size)
+        };
+        println!("   🎯 Reading: {}", reading);
+        for output in input_outputs(input, job_args) {
+            println!("   💾 → {}", output.display());
+        }
+        // Manifest rows may filter differently from the command line
+        let filters = describe_filters(job_args);
+        if filters != describe_filters(args) {
+            println!(
+                "   🔽 Count filters: {}",
+                filters.as_deref().unwrap_or("none")
+            );
+        }
+        if let Some(field) = job_args.split_by {
+            println!("   ✂️  Split into one table per {} found", field.name());
+        }
+        let outputs = resume_outputs(&state, input, job_args);
+        if args.resume && state.is_complete(input_path, &outputs) {
+            println!("   ⏭️  Completed in a previous run; would be skipped");
+        }
+    }
+    println!(
+        "\n🧪 Dry run: {} input(s) planned, nothing counted or written",
+        jobs.len()
+    );
+    if unreadable > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} inputs cannot be opened",
+            unreadable,
+            jobs.len()
+        ))
+        .classify(Failure::Input);
+    }
+    Ok(())
+}
+
+/// The count filters in effect, for the header; `None` without any
+fn describe_filters(args: &Args) -> Option<String> {
+    let mut filters = Vec::new();
+    if let Some(min_count) = args.min_count {
+        filters.push(format!("min count {}", min_count));
+    }
+    if args.no_singletons {
+        filters.push("no singletons".to_string());
+    }
+    if args.singletons_only {
+        filters.push("singletons only".to_string());
+    }
+    if let Some(top) = args.top {
+        filters.push(format!("top {}", top));
+    }
+    if args.remove_chimeras {
+        filters.push("chimeras removed".to_string());
+    }
+    if args.other_row {
+        filters.push("removed reads kept as __other__".to_string());
+    }
+    (!filters.is_empty()).then(|| filters.join(", "))
+}
+
+/// Create the output directory and load auxiliary inputs once for all files
+fn setup(args: &Args) -> Result<SharedInputs> {
+    if args.format.len() > 1 && !args.output_template.has_ext() {
+        return exit::usage(format!(
+            "--output-template '{}' needs {{ext}} to name outputs of several formats",
+            args.output_template
+        ));
+    }
+    // `s3://bucket/prefix` would otherwise become a local directory `s3:`
+    if let Some(scheme) = url_scheme(&args.output_dir) {
+        return exit::usage(format!(
+            "--output-dir {} is a URL ({}://), but tables are only written to local or \
+             mounted directories; write them locally and copy them up \
+             (aws s3 cp --recursive, gcloud storage cp -r, azcopy copy --recursive)",
+            args.output_dir.display(),
+            scheme
+        ));
+    }
+
+    retry::configure(args.read_retries, Duration::from_secs(args.retry_delay));
+
+    // Create output directory
+    if !args.dry_run {
+        std::fs::create_dir_all(&args.output_dir)
+            .context("Failed to create output directory")
+            .classify(Failure::Output)?;
+    }
+
+    // Worker threads inherit the memory policy, so it is set before any pool
+    // is built; a dry run counts nothing, and leaves both alone
+    let interleaved_nodes = (args.numa_interleave && !args.dry_run)
+        .then(affinity::interleave_memory)
+        .transpose()?;
+    let pinning = (args.pin_threads && !args.dry_run)
+        .then(CpuLayout::detect)
+        .transpose()?;
+    load_shared(args, ThreadPools::new(pinning), interleaved_nodes)
+}
+
+/// The scheme of a URL given for a path (`s3` of `s3://bucket/prefix`)
+fn url_scheme(path: &Path) -> Option<&str> {
+    let (scheme, _) = path.to_str()?.split_once("://")?;
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then_some(scheme)
+}
+
+/// The references and patterns of `args`, loaded once for every input
+fn load_shared(
+    args: &Args,
+    pools: ThreadPools,
+    interleaved_nodes: Option<usize>,
+) -> Result<SharedInputs> {
+    let minimizers = match (args.minimizers, &args.seed_pattern) {
+        (Some(minimizers), Some(pattern)) => {
+            Some(minimizers.with_seed(pattern).classify(Failure::Usage)?)
+        }
+        (minimizers, _) => minimizers,
+    };
+
+    let amplicon = match (&args.primers, &args.fwd_primer, &args.rev_primer) {
+        (Some(path), _, _) => {
+            Some(Amplicons::load(path, args.primer_max_mismatches).classify(Failure::Input)?)
+        }
+        (None, Some(forward), Some(reverse)) => {
+            Some(Amplicons::new(forward, reverse, args.primer_max_mismatches))
+        }
+        _ => None,
+    };
+
+    Ok(SharedInputs {
+        minimizers,
+        amplicon,
+        orientation: args
+            .orient
+            .as_deref()
+            .map(Orientation::load)
+            .transpose()
+            .classify(Failure::Input)?,
+        contaminants: (!args.contaminants.is_empty())
+            .then(|| Contaminants::load(&args.contaminants, args.contaminant_action))
+            .transpose()
+            .classify(Failure::Input)?,
+        reference: args
+            .annotate
+            .as_deref()
+            .map(|path| Reference::load(path, args.annotate_max_mismatches))
+            .transpose()
+            .classify(Failure::Input)?,
+        expected: args
+            .expected
+            .as_deref()
+            .map(ExpectedSet::load)
+            .transpose()
+            .classify(Failure::Input)?,
+        spike_ins: args
+            .spike_in
+            .as_deref()
+            .map(|path| Reference::load(path, 0))
+            .transpose()
+            .classify(Failure::Input)?,
+        expected_md5: args
+            .verify_md5
+            .as_deref()
+            .map(ExpectedMd5::load)
+            .transpose()
+            .classify(Failure::Input)?,
+        pools,
+        interleaved_nodes,
+    })
+}
+
+/// Calculate optimal thread count based on system resources and parallel jobs
+fn calculate_optimal_threads(requested: usize) -> usize {
+    if requested > 0 {
+        return requested;
+    }
+
+    // Check for RAYON_NUM_THREADS environment variable (set by user or parallel)
+    if let Ok(env_threads) = std::env::var("RAYON_NUM_THREADS") {
+        if let Ok(n) = env_threads.parse::<usize>() {
+            return n;
+        }
+    }
+
+    // Detect if running under GNU parallel or similar
+    let total_cores = num_cpus::get();
+
+    // Check for common parallel execution indicators
+    let parallel_jobs = std::env::var("PARALLEL_SEQ")
+        .ok()
+        .and_then(|_| {
+            // If PARALLEL_SEQ exists, we're in GNU parallel
+            // Try to get total jobs from PARALLEL environment
+            std::env::var("PARALLEL")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+        })
+        .unwrap_or(1);
+
+    if parallel_jobs > 1 {
+        // Running under parallel, divide threads
+        let threads_per_job = (total_cores / parallel_jobs).max(1);
+        return threads_per_job;
+    }
+
+    // Default: use all cores
+    total_cores
+}
+
+/// Calculate adaptive chunk size based on estimated file size
+fn calculate_chunk_size(file_size: Option<u64>, requested: usize) -> usize {
+    if requested > 0 {
+        return requested;
+    }
+    let Some(file_size) = file_size else {
+        return STREAM_CHUNK_SIZE;
+    };
+
+    // Estimate number of records (assuming ~100 bytes per record)
+    let estimated_records = (file_size / 100).max(100);
+
+    match estimated_records {
+        0..=10_000 => 0,                  // No chunking for tiny files
+        10_001..=100_000 => 10_000,       // Small files
+        100_001..=1_000_000 => 25_000,    // Medium files
+        1_000_001..=10_000_000 => 50_000, // Large files
+        _ => 100_000,                     // Very large files
+    }
+}
+
+/// Largest chunk for which the chunks in flight (about two per worker thread)
+/// hold no more than a quarter of `available` memory, given reads of
+/// `read_length` bases on average
+fn chunk_memory_limit(read_length: f64, available: u64) -> usize {
+    // Chunks keep each read's bases and an end offset
+    let record_bytes = read_length + 8.0;
+    let in_flight = 2 * rayon::current_num_threads();
+    let limit = (available / 4) as f64 / (in_flight as f64 * record_bytes);
+    (limit as usize).max(MIN_CHUNK_SIZE)
+}
+
+/// The run's options writing to `path` from `--output`, in the format its
+/// extension names
+fn with_output_file(args: &Args, path: &Path) -> Result<Args> {
+    let format = match OutputFormat::from_path(path) {
+        Some(format) => format,
+        None if args.format.len() == 1 => args.format[0].clone(),
+        None => anyhow::bail!(
+            "--output writes one table; name the format with the extension of {} or pass a single --format",
+            path.display()
+        ),
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    }
+    let mut args = args.clone();
+    args.format = vec![format];
+    args.output_file = Some(path.to_path_buf());
+    Ok(args)
+}
+
+/// Whether an input holds counts rather than reads: `--counts-in`, or a count
+/// table read back
+fn is_counts_input(input_path: &Path, args: &Args) -> bool {
+    args.counts_in || table::is_count_table(input_path)
+}
+
+/// Sample name of an input: the manifest's, else the file stem (without the
+/// suffix, for counts read back)
+fn base_name(input_path: &Path, args: &Args) -> String {
+    match args.sample_name {
+        Some(ref name) => name.clone(),
+        None if is_counts_input(input_path, args) => merge::sample_name(input_path, &args.suffix),
+        None if args.merge_pairs => pairs::sample_stem(&template::stem(input_path)),
+        None => template::stem(input_path),
+    }
+}
+
+/// Output path for `ext`, or for a companion report ending in `tail`
+fn output_path(input_path: &Path, args: &Args, ext: Option<&str>, tail: &str) -> PathBuf {
+    if let Some(ref file) = args.output_file {
+        return match ext {
+            Some(_) => file.clone(),
+            None => {
+                let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                file.with_file_name(format!("{}{}", stem, tail))
+            }
+        };
+    }
+    args.output_dir
+        .join(output_file_name(input_path, args, ext, tail))
+}
+
+/// File name of an output in the output directory, from the manifest's name
+/// or the template
+fn output_file_name(input_path: &Path, args: &Args, ext: Option<&str>, tail: &str) -> String {
+    match (&args.output_name, ext) {
+        (Some(name), Some(ext)) => format!("{}.{}", name, ext),
+        (Some(name), None) => format!("{}{}", name, tail),
+        (None, ext) => {
+            let stem = template::stem(input_path);
+            let names = Names {
+                stem: &stem,
+                sample: &base_name(input_path, args),
+                suffix: &args.suffix,
+            };
+            match ext {
+                Some(ext) => args.output_template.render(&names, ext),
+                None => args.output_template.render_companion(&names, tail),
+            }
+        }
+    }
+}
+
+/// Names rendered from `--sample-name`, `--suffix` or the manifest must be
+/// plain file names, so no output lands outside the output directory
+fn check_output_name(input_path: &Path, args: &Args) -> Result<()> {
+    if args.output_file.is_some() {
+        return Ok(());
+    }
+    let ext = args
+        .format
+        .first()
+        .map_or("parquet", |format| format.extension());
+    let name = output_file_name(input_path, args, Some(ext), "");
+    if name.contains(['/', '\\']) || name == ".." || name == "." {
+        return exit::usage(format!(
+            "Output name '{}' of {} is not a file name; drop '/', '\\' and '..' \
+             from --sample-name, --suffix and the manifest",
+            name,
+            input_path.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Count table path for each requested format
+fn table_paths(input_path: &Path, args: &Args) -> Vec<PathBuf> {
+    args.format
+        .iter()
+        .map(|format| {
+            let path = output_path(input_path, args, Some(format.extension()), "");
+            table_path(path, format, args)
+        })
+        .collect()
+}
+
+/// `path` of a table in `format`, with `.gz` appended for `--bgzip` TSV
+fn table_path(path: PathBuf, format: &OutputFormat, args: &Args) -> PathBuf {
+    let bgzip = args.bgzip && matches!(format, OutputFormat::Tsv);
+    if !bgzip || path.extension().is_some_and(|ext| ext == "gz") {
+        return path;
+    }
+    let mut name = path.into_os_string();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+fn expected_report_path(input_path: &Path, args: &Args) -> PathBuf {
+    output_path(input_path, args, None, "_expected.tsv")
+}
+
+/// Where `--write-dedup` puts the deduplicated reads of `input`
+fn dedup_path(input: &Input, args: &Args) -> Option<PathBuf> {
+    match args.write_dedup {
+        Some(Some(ref path)) => Some(path.clone()),
+        Some(None) => {
+            let tail = if input::is_fastq(input) {
+                "_dedup.fq.gz"
+            } else {
+                "_dedup.fa.gz"
+            };
+            Some(output_path(input.path(), args, None, tail))
+        }
+        None => None,
+    }
+}
+
+/// Per-input side outputs named with `=FILE`, which only suit a single input
+fn named_sidecars(args: &Args) -> Vec<&'static str> {
+    [
+        ("--write-dedup", &args.write_dedup),
+        ("--write-assignments", &args.write_assignments),
+        ("--length-hist", &args.length_hist),
+        ("--gc-hist", &args.gc_hist),
+        ("--quality-hist", &args.quality_hist),
+        ("--cycle-quality", &args.cycle_quality),
+    ]
+    .into_iter()
+    .filter(|(_, path)| matches!(path, Some(Some(_))))
+    .map(|(option, _)| option)
+    .collect()
+}
+
+/// Where a sidecar option given as `[=FILE]` writes for `input_path`: the
+/// named file, or `tail` added to the table name
+fn sidecar_path(
+    input_path: &Path,
+    args: &Args,
+    option: &Option<Option<PathBuf>>,
+    tail: &str,
+) -> Option<PathBuf> {
+    match option {
+        Some(Some(path)) => Some(path.clone()),
+        Some(None) => Some(output_path(input_path, args, None, tail)),
+        None => None,
+    }
+}
+
+/// Where `--write-assignments` puts the per-read assignments of `input_path`
+fn assignments_path(input_path: &Path, args: &Args) -> Option<PathBuf> {
+    sidecar_path(
+        input_path,
+        args,
+        &args.write_assignments,
+        "_assignments.tsv.gz",
+    )
+}
+
+/// Where the histogram options write for `input_path`
+struct HistogramPaths {
+    length: Option<PathBuf>,
+    gc: Option<PathBuf>,
+    quality: Option<PathBuf>,
+    cycles: Option<PathBuf>,
+}
+
+impl HistogramPaths {
+    fn new(input_path: &Path, args: &Args) -> Self {
+        Self {
+            length: sidecar_path(input_path, args, &args.length_hist, "_length_hist.tsv"),
+            gc: sidecar_path(input_path, args, &args.gc_hist, "_gc_hist.tsv"),
+            quality: sidecar_path(input_path, args, &args.quality_hist, "_quality_hist.tsv"),
+            cycles: sidecar_path(input_path, args, &args.cycle_quality, "_cycle_quality.tsv"),
+        }
+    }
+
+    /// The histograms to collect, with FASTQ scores read at `quality_offset`
+    fn selection(&self, quality_offset: u8) -> Option<Selection> {
+        let quality = self.quality.is_some() || self.cycles.is_some();
+        (self.length.is_some() || self.gc.is_some() || quality).then_some(Selection {
+            length: self.length.is_some(),
+            gc: self.gc.is_some(),
+            quality: quality.then_some(quality_offset),
+        })
+    }
+
+    fn into_paths(self) -> impl Iterator<Item = PathBuf> {
+        [self.length, self.gc, self.quality, self.cycles]
+            .into_iter()
+            .flatten()
+    }
+}
+
+/// Every file written for one input
+fn input_outputs(input: &Input, args: &Args) -> Vec<PathBuf> {
+    let input_path = input.path();
+    let mut outputs = table_paths(input_path, args);
+    if args.expected.is_some() {
+        outputs.push(expected_report_path(input_path, args));
+    }
+    outputs.extend(dedup_path(input, args));
+    outputs.extend(assignments_path(input_path, args));
+    outputs.extend(HistogramPaths::new(input_path, args).into_paths());
+    outputs
+}
+
+/// Outputs `input` must still have for a resumed run to skip it. Split tables
+/// depend on the values seen, so those the earlier run recorded are used.
+fn resume_outputs(state: &RunState, input: &Input, args: &Args) -> Vec<PathBuf> {
+    if args.split_by.is_none() {
+        return input_outputs(input, args);
+    }
+    let table = output_path(input.path(), args, None, "");
+    state.recorded_outputs(input.path(), table.parent().unwrap_or(Path::new("")))
+}
+
+/// Count one input and write its outputs, on a pool of the input's own
+/// thread count
+fn process_file(
+    input: &Input,
+    args: &Args,
+    shared: &SharedInputs,
+    batch: Option<&BatchProgress>,
+    signatures: &mut Vec<Signature>,
+) -> Result<(Option<Digests>, FileSummary)> {
+    let pool = shared.pools.get(args.threads)?;
+    pool.install(|| process_file_on_pool(input, args, shared, batch, signatures))
+}
+
+fn process_file_on_pool(
+    input: &Input,
+    args: &Args,
+    shared: &SharedInputs,
+    batch: Option<&BatchProgress>,
+    signatures: &mut Vec<Signature>,
+) -> Result<(Option<Digests>, FileSummary)> {
+    let input_path = input.path();
+    let start_time = Instant::now();
+
+    // Batch runs replace the per-step detail with one summary line per file
+    let verbose = !args.quiet && batch.is_none();
+
+    if verbose {
+        eprintln!("📄 Processing: {}", input_path.display());
+    }
+    if is_counts_input(input_path, args) {
+        return process_table(input, args, shared, batch, signatures);
+    }
+    // An unlisted input fails before the read, not after
+    if let Some(ref expected) = shared.expected_md5 {
+        expected.get(input_path).classify(Failure::Usage)?;
+    }
+
+    // Get file size for adaptive chunk size calculation; pipes have none
+    let file_size = input.file_size().classify(Failure::Input)?;
+    let mut chunk_size = calculate_chunk_size(file_size, args.chunk_size);
+    // Long reads on a small machine get smaller chunks than the file size alone
+    // suggests
+    let memory_limit = if args.chunk_size == 0 && chunk_size > 0 {
+        input::mean_read_length(input)
+            .zip(summary::available_memory())
+            .map(|(read_length, available)| chunk_memory_limit(read_length, available))
+            .filter(|&limit| limit < chunk_size)
+    } else {
+        None
+    };
+    if let Some(limit) = memory_limit {
+        chunk_size = limit;
+    }
+
+    if verbose && args.chunk_size == 0 {
+        eprintln!(
+            "   🎯 Adaptive chunk size: {}",
+            if chunk_size == 0 {
+                "disabled (small file)".to_string()
+            } else if memory_limit.is_some() {
+                format!("{} sequences (limited by available memory)", chunk_size)
+            } else {
+                format!("{} sequences", chunk_size)
+            }
+        );
+    }
+
+    // Count sequences
+    // Quality encoding, from a quick look at the first records
+    let quality = quality::resolve(input, args.phred_offset);
+    if quality.is_none() {
+        let option = if args.quality_bins.is_some() {
+            Some("--quality-bins")
+        } else if args.merge_pairs {
+            Some("--merge-pairs")
+        } else {
+            args.expected_errors.then_some("--expected-errors")
+        };
+        if let Some(option) = option {
+            return exit::usage(format!(
+                "{} needs read qualities, but {} is not FASTQ",
+                option,
+                input_path.display()
+            ));
+        }
+    }
+    if let Some(ref detection) = quality {
+        if verbose {
+            eprintln!("   🎚️  Quality encoding: {}", detection.encoding.name());
+        }
+        if let Some(warning) = quality::warning(detection) {
+            match batch {
+                Some(batch) => batch.println(&format!("⚠️  {}: {}", input_path.display(), warning)),
+                None if !args.quiet => eprintln!("   ⚠️  {}", warning),
+                None => {}
+            }
+        }
+    }
+
+    let file_bar = match batch {
+        Some(batch) => Some(batch.file_bar(input_path, file_size)),
+        None if progress::json_events() => Some(progress::event_bar(input_path, file_size)),
+        None => None,
+    };
+    let skip = args.skip_errors.map(SkipBudget::new);
+    let count_start = Instant::now();
+    // Inputs are hashed as they are read: for the manifest, and for the MD5
+    // recorded in Parquet and ORC footers and Avro headers
+    let footer_md5 = !args.no_checksum
+        && args.format.iter().any(|format| {
+            matches!(
+                format,
+                OutputFormat::Parquet | OutputFormat::Avro | OutputFormat::Orc
+            )
+        });
+    let checksum = (args.checksum.is_some() || footer_md5 || shared.expected_md5.is_some())
+        .then(|| Arc::new(Checksum::new(args.checksum.is_some())));
+    let dedup_path = dedup_path(input, args);
+    let dedup = dedup_path
+        .as_deref()
+        .map(DedupWriter::create)
+        .transpose()
+        .classify(Failure::Output)?;
+    let header_fields = key_fields(args);
+    let transforms = read_transforms(args, shared);
+    let keys = RecordKey::new(
+        args,
+        shared,
+        &transforms,
+        &header_fields,
+        quality
+            .as_ref()
+            .map(|detection| detection.encoding.offset()),
+    );
+    let assignments_path = assignments_path(input_path, args);
+    let assignments = match assignments_path {
+        Some(ref path) => {
+            let mut columns = illumina_columns(args.split_by.as_slice());
+            columns.extend(field_columns(args));
+            columns.push(if args.group_by_header.is_some() {
+                "group"
+            } else {
+                "sequence"
+            });
+            let writer = AssignmentWriter::create(
+                path,
+                &columns,
+                shared.reference.as_ref(),
+                args.annotate_max_mismatches > 0,
+            );
+            Some(writer.classify(Failure::Output)?)
+        }
+        None => None,
+    };
+    let histogram_paths = HistogramPaths::new(input_path, args);
+    // FASTA inputs have no qualities, so their quality tables stay empty
+    let quality_offset = quality.map_or(33, |detection| detection.encoding.offset());
+    let histograms = histogram_paths
+        .selection(quality_offset)
+        .map(Histograms::new);
+    let expected_errors = args
+        .expected_errors
+        .then(|| ExpectedErrors::new(quality_offset));
+    let artifacts = args
+        .artifacts
+        .then(|| Artifacts::new(args.artifact_min_length));
+    let read = ReadOptions {
+        skip: skip.as_ref(),
+        checksum: checksum.as_ref(),
+        dedup: dedup.as_ref(),
+        assignments: assignments.as_ref(),
+        histograms: histograms.as_ref(),
+        expected_errors: expected_errors.as_ref(),
+        artifacts: artifacts.as_ref(),
+        limit: None,
+    };
+    let (counts, total_reads) = count_sequences(
+        input,
+        chunk_size,
+        args.counter,
+        keys,
+        read,
+        verbose,
+        file_bar.as_ref(),
+    )
+    .classify(Failure::Input)?;
+    let map_load_factor =
+        (counts.capacity() > 0).then(|| counts.len() as f64 / counts.capacity() as f64);
+    let error_sums = expected_errors.map(ExpectedErrors::finish);
+    let digests = checksum
+        .map(|checksum| checksum.finish(input_path))
+        .transpose()
+        .classify(Failure::Input)?;
+    if let (Some(expected), Some(digests)) = (&shared.expected_md5, &digests) {
+        expected
+            .verify(input_path, digests)
+            .classify(Failure::Input)?;
+        if verbose {
+            eprintln!("   🔐 MD5 verified: {}", digests.md5);
+        }
+    }
+    if let Some(bar) = file_bar {
+        bar.finish_and_clear();
+    }
+    if let (Some(dedup), Some(path)) = (dedup, &dedup_path) {
+        let written = dedup.finish().classify(Failure::Output)?;
+        if verbose {
+            eprintln!("   🧬 Deduplicated reads: {} → {}", written, path.display());
+        }
+    }
+    if let (Some(assignments), Some(path)) = (assignments, &assignments_path) {
+        let written = assignments.finish().classify(Failure::Output)?;
+        if verbose {
+            eprintln!(
+                "   🔖 Read assignments: {} ({} unassigned) → {}",
+                written.reads,
+                written.unassigned,
+                path.display()
+            );
+        }
+    }
+    if let Some(histograms) = histograms {
+        if let Some(ref path) = histogram_paths.length {
+            histograms.save_length(path).classify(Failure::Output)?;
+            if verbose {
+                eprintln!("   📏 Length histogram → {}", path.display());
+            }
+        }
+        if let Some(ref path) = histogram_paths.gc {
+            histograms.save_gc(path).classify(Failure::Output)?;
+            if verbose {
+                eprintln!("   🧪 GC histogram → {}", path.display());
+            }
+        }
+        if let Some(ref path) = histogram_paths.quality {
+            histograms.save_quality(path).classify(Failure::Output)?;
+            if verbose {
+                eprintln!("   🎚️  Quality histogram → {}", path.display());
+            }
+        }
+        if let Some(ref path) = histogram_paths.cycles {
+            histograms.save_cycles(path).classify(Failure::Output)?;
+            if verbose {
+                eprintln!("   🔁 Per-cycle quality → {}", path.display());
+            }
+        }
+    }
+
+    if let Some(skip) = skip {
+        if skip.skipped() > 0 && !args.quiet {
+            let mut report = vec![format!(
+                "   ⚠️  Skipped {} malformed records in {}",
+                skip.skipped(),
+                input_path.display()
+            )];
+            report.extend(
+                skip.into_reported()
+                    .iter()
+                    .map(|skipped| format!("      {}: {}", skipped.location, skipped.reason)),
+            );
+            match batch {
+                Some(batch) => batch.println(&report.join("\n")),
+                None => eprintln!("{}", report.join("\n")),
+            }
+        }
+    }
+    let bytes_read = file_size.unwrap_or_else(|| input.streamed_bytes());
+    let throughput = progress::throughput(total_reads, bytes_read, count_start.elapsed());
+    if verbose {
+        eprintln!("   🚀 Throughput: {}", throughput);
+    }
+    if let Some(ref orientation) = shared.orientation {
+        let (flipped, unplaced) = orientation.take_counts();
+        if verbose {
+            eprintln!(
+                "   🔄 Oriented: {} reads reverse-complemented, {} on neither strand",
+                flipped, unplaced
+            );
+        }
+    }
+    let mut contaminant_reads = Vec::new();
+    let mut removed_reads = 0;
+    if let Some(ref contaminants) = shared.contaminants {
+        contaminant_reads = contaminants.take_counts();
+        let matched: u64 = contaminant_reads.iter().map(|(_, reads)| reads).sum();
+        if contaminants.action == ContaminantAction::Remove {
+            removed_reads = matched;
+        }
+        let share = |reads: u64| reads as f64 / total_reads.max(1) as f64 * 100.0;
+        let mut line = format!(
+            "{} {} reads ({:.2}%)",
+            match contaminants.action {
+                ContaminantAction::Remove => "removed",
+                ContaminantAction::Flag => "flagged",
+            },
+            matched,
+            share(matched)
+        );
+        let found: Vec<String> = contaminant_reads
+            .iter()
+            .filter(|(_, reads)| *reads > 0)
+            .map(|(name, reads)| format!("{} {:.2}%", name, share(*reads)))
+            .collect();
+        if !found.is_empty() {
+            line.push_str(&format!(": {}", found.join(", ")));
+        }
+        if verbose {
+            eprintln!("   🧫 Contaminants {}", line);
+        } else if let Some(batch) = batch {
+            batch.println(&format!(
+                "🧫 {}: contaminants {}",
+                input_path.display(),
+                line
+            ));
+        }
+    }
+    let artifacts = artifacts.map(|artifacts| artifacts.counts());
+    if let Some(ref artifacts) = artifacts {
+        let line = artifacts.describe(total_reads);
+        if verbose {
+            eprintln!("   🚧 Artifacts: {}", line);
+        } else if let Some(batch) = batch {
+            batch.println(&format!("🚧 {}: artifacts {}", input_path.display(), line));
+        }
+    }
+    if let Some(mate) = input.mate() {
+        let pairs = mate.stats.pairs.load(Ordering::Relaxed);
+        let merged = mate.stats.merged.load(Ordering::Relaxed);
+        if verbose {
+            eprintln!(
+                "   🔗 Merged with {}: {} of {} pairs ({:.2}%)",
+                mate.input.path().display(),
+                merged,
+                pairs,
+                merged as f64 / pairs.max(1) as f64 * 100.0
+            );
+        } else if let Some(batch) = batch {
+            batch.println(&format!(
+                "🔗 {}: merged {} of {} pairs",
+                input_path.display(),
+                merged,
+                pairs
+            ));
+        }
+    }
+
+    // Minimizer counts are per k-mer, so they do not add up to the reads
+    let one_key_per_read = args.minimizers.is_none() && args.window.is_none();
+    if one_key_per_read
+        && (args.group_by_header.is_some()
+            || !header_fields.is_empty()
+            || args.group_by_read_name.is_some()
+            || args.key.is_some()
+            || args.amplicon
+            || args.quality_bins.is_some())
+    {
+        let grouped_reads: u64 = counts.iter().map(|(_, count)| count).sum();
+        // Contaminants left out were reported above
+        let unmatched = total_reads - removed_reads - grouped_reads;
+        if unmatched > 0 {
+            let reason = if args.group_by_header.is_some() {
+                "did not match the header pattern".to_string()
+            } else {
+                let mut reasons = Vec::new();
+                if !header_fields.is_empty() {
+                    reasons.push("had no Illumina read name");
+                }
+                if args.group_by_read_name.is_some() {
+                    reasons.push("did not match the read-name pattern");
+                }
+                if args.key.is_some() {
+                    reasons.push("were too short for the key");
+                }
+                if args.amplicon {
+                    reasons.push("lacked a primer");
+                }
+                if args.quality_bins.is_some() {
+                    reasons.push("fell in no quality bin");
+                }
+                reasons.join(" or ")
+            };
+            // Warnings still surface in batch runs, above the bars
+            if let Some(batch) = batch {
+                batch.println(&format!(
+                    "⚠️  {}: {} reads {}",
+                    input_path.display(),
+                    unmatched,
+                    reason
+                ));
+            } else if verbose {
+                eprintln!("   ⚠️  {} reads {}", unmatched, reason);
+            }
+        }
+    }
+
+    // Each value of the split field is written as a sample of its own
+    let parts = match args.split_by {
+        None => vec![Part {
+            label: None,
+            counts,
+            total_reads,
+            other_reads: 0,
+            error_sums,
+        }],
+        Some(field) => {
+            let mut error_sums = error_sums.map(illumina::partition);
+            illumina::partition_counts(&counts)
+                .into_iter()
+                .map(|(value, counts)| Part {
+                    error_sums: error_sums
+                        .as_mut()
+                        .map(|parts| parts.remove(&value).unwrap_or_default()),
+                    label: Some((field, value)),
+                    total_reads: counts.iter().map(|(_, count)| count).sum(),
+                    counts,
+                    other_reads: 0,
+                })
+                .collect()
+        }
+    };
+    if let Some(field) = args.split_by {
+        // Without a single Illumina read name there is nothing to split by,
+        // and no table would be written
+        if parts.is_empty() && total_reads > removed_reads {
+            return Err(anyhow::anyhow!(
+                "No read in {} has an Illumina read name to take the {} from; drop --split-by",
+                input_path.display(),
+                field.name()
+            ))
+            .classify(Failure::Input);
+        }
+        if verbose {
+            eprintln!("   ✂️  Split into {} by {}", parts.len(), field.name());
+        }
+    }
+
+    // Provenance shared by every part's tables
+    let input = Provenance {
+        sample: base_name(input_path, args),
+        input: std::fs::canonicalize(input_path)
+            .unwrap_or_else(|_| input_path.to_path_buf())
+            .display()
+            .to_string(),
+        input_md5: digests.as_ref().map(|d| d.md5.clone()),
+        input_sha256: digests.as_ref().and_then(|d| d.sha256.clone()),
+        quality_encoding: quality.map(|detection| detection.encoding),
+        total_reads,
+        key: args.key.as_ref().map(KeySpec::to_string),
+        mask: (!args.mask.is_empty()).then(|| {
+            args.mask
+                .iter()
+                .map(Mask::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        }),
+        minimizers: args.minimizers.map(|minimizers| minimizers.to_string()),
+        window: args.window.map(|window| window.to_string()),
+        seed_pattern: args.seed_pattern.as_ref().map(SeedPattern::to_string),
+        amplicon: shared.amplicon.as_ref().map(Amplicons::to_string),
+        orient: args.orient.as_ref().map(|path| path.display().to_string()),
+        contaminants: (!args.contaminants.is_empty()).then(|| args.contaminants.join(",")),
+        header_fields: Vec::new(),
+        filtered: None,
+        sort: args.sort,
+    };
+    let mut tables = Vec::new();
+    let mut diversity = Vec::new();
+    for part in &parts {
+        let written = write_part(input_path, args, shared, part, &input, verbose, signatures);
+        let (written, part_diversity) = written.classify(Failure::Output)?;
+        tables.extend(written);
+        diversity.extend(part_diversity);
+    }
+    let unique_sequences: usize = parts.iter().map(|part| part.counts.len()).sum();
+
+    let outputs = tables
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let duration = start_time.elapsed();
+    if let Some(batch) = batch {
+        batch.finish_file(&format!(
+            "✓ {}: {} unique sequences, {} total reads → {} ({:.2}s, {})",
+            input_path.display(),
+            unique_sequences,
+            total_reads,
+            outputs,
+            duration.as_secs_f64(),
+            throughput
+        ));
+    } else if verbose {
+        eprintln!(
+            "   ✓ {} unique sequences, {} total reads → {}",
+            unique_sequences, total_reads, outputs
+        );
+        eprintln!("   ⏱️  Processing time: {:.2}s", duration.as_secs_f64());
+        let mut resources = Vec::new();
+        if let Some(rss) = summary::peak_rss() {
+            resources.push(format!("peak memory {}", summary::format_bytes(rss)));
+        }
+        if let Some(load) = map_load_factor {
+            resources.push(format!("map load factor {:.2}", load));
+        }
+        if !resources.is_empty() {
+            eprintln!("   🧠 Resources: {}", resources.join(", "));
+        }
+        eprintln!();
+    }
+
+    let mut written = tables;
+    if args.expected.is_some() {
+        written.extend(
+            parts
+                .iter()
+                .map(|part| part.path(expected_report_path(input_path, args))),
+        );
+    }
+    written.extend(dedup_path);
+    written.extend(assignments_path);
+    written.extend(histogram_paths.into_paths());
+    let file = FileSummary {
+        input: input_path.to_path_buf(),
+        outputs: written,
+        total_reads,
+        unique_sequences,
+        bytes_read,
+        elapsed: duration,
+        map_load_factor,
+        peak_rss: summary::peak_rss(),
+        contaminant_reads,
+        diversity,
+        artifacts,
+    };
+    Ok((digests, file))
+}
+
+/// Write counts given as an input (a count table, or `--counts-in` text)
+/// again: they go through the filters, normalization, annotation and formats
+/// as counted reads would
+fn process_table(
+    input: &Input,
+    args: &Args,
+    shared: &SharedInputs,
+    batch: Option<&BatchProgress>,
+    signatures: &mut Vec<Signature>,
+) -> Result<(Option<Digests>, FileSummary)> {
+    let input_path = input.path();
+    let start_time = Instant::now();
+    let verbose = !args.quiet && batch.is_none();
+    if let Some(option) = read_options(args).first() {
+        return exit::usage(format!(
+            "{} needs reads, but {} holds counts",
+            option,
+            input_path.display()
+        ));
+    }
+    for output in table_paths(input_path, args) {
+        if same_file(&output, input_path) {
+            return exit::usage(format!(
+                "Rewriting {} would replace it; pick another --output-dir or --suffix",
+                input_path.display()
+            ));
+        }
+    }
+    let file_size = std::fs::metadata(input_path)
+        .with_context(|| format!("Failed to open file: {}", input_path.display()))
+        .classify(Failure::Input)?
+        .len();
+
+    let table = if args.counts_in {
+        CountTable::read_text(input)
+    } else {
+        CountTable::read(input_path)
+    };
+    let table = table.classify(Failure::Input)?;
+    let mut counts = table.counts;
+    let total_reads: u64 = counts.values().sum();
+    let other_reads = counts.remove(output::OTHER_KEY).unwrap_or(0);
+    if verbose {
+        eprintln!(
+            "   📋 Counts: {} sequences, {} reads{}",
+            counts.len(),
+            total_reads,
+            if other_reads > 0 {
+                format!(" ({} in {})", other_reads, output::OTHER_KEY)
+            } else {
+                String::new()
+            }
+        );
+    }
+
+    let input = Provenance {
+        sample: base_name(input_path, args),
+        input: std::fs::canonicalize(input_path)
+            .unwrap_or_else(|_| input_path.to_path_buf())
+            .display()
+            .to_string(),
+        input_md5: None,
+        input_sha256: None,
+        quality_encoding: None,
+        total_reads,
+        key: None,
+        mask: None,
+        minimizers: None,
+        window: None,
+        seed_pattern: None,
+        amplicon: None,
+        orient: None,
+        contaminants: None,
+        header_fields: Vec::new(),
+        filtered: None,
+        sort: args.sort,
+    };
+    let part = Part {
+        label: None,
+        counts: counts
+            .iter()
+            .map(|(key, &count)| (key.as_str(), count))
+            .collect(),
+        total_reads,
+        other_reads,
+        error_sums: None,
+    };
+    let (tables, diversity) =
+        write_part(input_path, args, shared, &part, &input, verbose, signatures)
+            .classify(Failure::Output)?;
+    let unique_sequences = part.counts.len();
+
+    let outputs = tables
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let duration = start_time.elapsed();
+    if let Some(batch) = batch {
+        batch.finish_file(&format!(
+            "✓ {}: {} unique sequences, {} total reads → {} ({:.2}s)",
+            input_path.display(),
+            unique_sequences,
+            total_reads,
+            outputs,
+            duration.as_secs_f64()
+        ));
+    } else if verbose {
+        eprintln!(
+            "   ✓ {} unique sequences, {} total reads → {}",
+            unique_sequences, total_reads, outputs
+        );
+        eprintln!("   ⏱️  Processing time: {:.2}s", duration.as_secs_f64());
+        eprintln!();
+    }
+
+    let mut written = tables;
+    if args.expected.is_some() {
+        written.push(expected_report_path(input_path, args));
+    }
+    let file = FileSummary {
+        input: input_path.to_path_buf(),
+        outputs: written,
+        total_reads,
+        unique_sequences,
+        bytes_read: file_size,
+        elapsed: duration,
+        map_load_factor: None,
+        peak_rss: summary::peak_rss(),
+        contaminant_reads: Vec::new(),
+        diversity: diversity.into_iter().collect(),
+        artifacts: None,
+    };
+    Ok((None, file))
+}
+
+/// The options given that only apply while reading records
+fn read_options(args: &Args) -> Vec<&'static str> {
+    [
+        ("--skip-errors", args.skip_errors.is_some()),
+        ("--write-dedup", args.write_dedup.is_some()),
+        ("--write-assignments", args.write_assignments.is_some()),
+        ("--length-hist", args.length_hist.is_some()),
+        ("--gc-hist", args.gc_hist.is_some()),
+        ("--quality-hist", args.quality_hist.is_some()),
+        ("--cycle-quality", args.cycle_quality.is_some()),
+        ("--group-by-header", args.group_by_header.is_some()),
+        ("--group-by-read-name", args.group_by_read_name.is_some()),
+        ("--key", args.key.is_some()),
+        ("--mask", !args.mask.is_empty()),
+        ("--minimizers", args.minimizers.is_some()),
+        ("--window", args.window.is_some()),
+        ("--amplicon", args.amplicon),
+        ("--orient", args.orient.is_some()),
+        ("--contaminants", !args.contaminants.is_empty()),
+        ("--merge-pairs", args.merge_pairs),
+        (
+            "--parse-illumina-headers",
+            !args.parse_illumina_headers.is_empty(),
+        ),
+        ("--split-by", args.split_by.is_some()),
+        ("--quality-bins", args.quality_bins.is_some()),
+        ("--expected-errors", args.expected_errors),
+        ("--artifacts", args.artifacts),
+        ("--verify-md5", args.verify_md5.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(option, given)| given.then_some(option))
+    .collect()
+}
+
+/// Whether two paths name the same existing file
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Counts written as one sample: a whole input, or one value of `--split-by`
+struct Part {
+    /// Split field and its value
+    label: Option<(IlluminaField, String)>,
+    counts: ArenaCounts,
+    total_reads: u64,
+    /// Reads a count table read back had already folded into `__other__`
+    other_reads: u64,
+    /// Summed expected errors of each key's reads, with `--expected-errors`
+    error_sums: Option<AHashMap<String, f64>>,
+}
+
+impl Part {
+    /// `path` with the split value added before its extension
+    /// (`s1_counts.parquet` → `s1_counts_lane1.parquet`)
+    fn path(&self, path: PathBuf) -> PathBuf {
+        let Some(tag) = self.tag() else {
+            return path;
+        };
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(ext) => format!("{}_{}.{}", stem, tag, ext.to_string_lossy()),
+            None => format!("{}_{}", stem, tag),
+        };
+        path.with_file_name(name)
+    }
+
+    /// Split field and value as a name tag, e.g. `lane1`
+    fn tag(&self) -> Option<String> {
+        self.label
+            .as_ref()
+            .map(|(field, value)| format!("{}{}", field.name(), value))
+    }
+}
+
+/// Report on and save one part's counts, returning the tables written
+fn write_part(
+    input_path: &Path,
+    args: &Args,
+    shared: &SharedInputs,
+    part: &Part,
+    input: &Provenance,
+    verbose: bool,
+    signatures: &mut Vec<Signature>,
+) -> Result<(Vec<PathBuf>, Option<Diversity>)> {
+    let sample = match part.tag() {
+        Some(tag) => format!("{}_{}", input.sample, tag),
+        None => input.sample.clone(),
+    };
+    if verbose && part.label.is_some() {
+        eprintln!("   ✂️  {}: {} reads", sample, part.total_reads);
+    }
+    let mut written = Vec::new();
+
+    // Reports see per-field counts summed back together
+    let header_fields = field_columns(args).len();
+    let collapsed;
+    let sequence_counts = if header_fields == 0 {
+        &part.counts
+    } else {
+        collapsed = illumina::collapse(&part.counts, header_fields);
+        &collapsed
+    };
+
+    let diversity = args.diversity.then(|| {
+        let diversity =
+            Diversity::from_counts(&sample, sequence_counts.iter().map(|(_, count)| count));
+        // Against a known library, skew counts the members that got no reads
+        let members = match (&shared.expected, &shared.reference) {
+            (Some(expected), _) => Some(expected.entry_counts(sequence_counts)),
+            (None, Some(reference)) => Some(reference.sequence_counts(sequence_counts)),
+            (None, None) => None,
+        };
+        let diversity = match members {
+            Some(members) => diversity.with_library_skew(members),
+            None => diversity,
+        };
+        if verbose {
+            eprintln!("   🌳 Diversity: {}", diversity.describe());
+            eprintln!("   📐 Skew: {}", diversity.describe_skew());
+        }
+        diversity
+    });
+
+    // Report recovery of expected sequences
+    if let Some(ref expected) = shared.expected {
+        let report_path = part.path(expected_report_path(input_path, args));
+        let recovery = expected.write_report(sequence_counts, &report_path)?;
+
+        if verbose {
+            eprintln!(
+                "   🎯 Expected recovered: {}/{} ({:.2}%), {:.2}% of reads → {}",
+                recovery.detected,
+                recovery.total,
+                recovery.rate(),
+                recovery.reads as f64 / part.total_reads.max(1) as f64 * 100.0,
+                report_path.display()
+            );
+        }
+    }
+
+    // Sketch from the counted sequences, so no second pass over the reads
+    if args.sketch.is_some() {
+        let signature = Signature::from_counts(
+            sequence_counts,
+            args.sketch_k as usize,
+            args.scaled,
+            &sample,
+            &input_path.display().to_string(),
+        );
+        if verbose {
+            eprintln!("   ✏️  Sketch: {} hashes", signature.len());
+        }
+        signatures.push(signature);
+    }
+
+    // Per-sample factor scaling counts to "per million spike-in reads"
+    let spike_factor = match shared.spike_ins {
+        Some(ref spike_ins) => {
+            let spike_reads = spike_ins.matched_reads(sequence_counts);
+            if spike_reads == 0 {
+                anyhow::bail!(
+                    "No spike-in reads detected in {}{}; cannot normalize",
+                    input_path.display(),
+                    part.tag()
+                        .map(|tag| format!(" ({})", tag))
+                        .unwrap_or_default()
+                );
+            }
+            if verbose {
+                eprintln!(
+                    "   ⚖️  Spike-in reads: {} ({:.2}% of reads)",
+                    spike_reads,
+                    spike_reads as f64 / part.total_reads.max(1) as f64 * 100.0
+                );
+            }
+            Some(1_000_000.0 / spike_reads as f64)
+        }
+        None => None,
+    };
+
+    // Rows point into the count map; records are made from them only as
+    // each batch is written
+    let mut rows = count_rows(&part.counts);
+
+    // Drop rare sequences, keeping count of what was removed
+    let mut filtered = filter_rows(&mut rows, args);
+    if let Some(filtered) = filtered {
+        if verbose {
+            eprintln!(
+                "   🧹 Filtered out: {} sequences ({} reads)",
+                filtered.sequences, filtered.reads
+            );
+        }
+    }
+    // Chimeras are looked for among the rows the filters kept
+    if args.flag_chimeras || args.remove_chimeras {
+        let keys: Vec<(&str, &str, u64)> = rows
+            .iter()
+            .map(|row| {
+                let (fields, sequence) = illumina::split_fields(row.key, header_fields);
+                (fields, sequence, row.count)
+            })
+            .collect();
+        let chimeras = chimera::find(&keys, args.chimera_parent_fold);
+        let sequences = chimeras.iter().filter(|&&chimera| chimera).count();
+        let reads: u64 = rows
+            .iter()
+            .zip(&chimeras)
+            .filter(|(_, chimera)| **chimera)
+            .map(|(row, _)| row.count)
+            .sum();
+        if verbose {
+            eprintln!(
+                "   🔀 Chimeras{}: {} of {} sequences ({:.2}% of reads)",
+                if args.remove_chimeras { " removed" } else { "" },
+                sequences,
+                rows.len(),
+                reads as f64 / part.total_reads.max(1) as f64 * 100.0
+            );
+        }
+        if args.remove_chimeras {
+            let mut chimeras = chimeras.into_iter();
+            rows.retain(|_| !chimeras.next().unwrap());
+            let removed = filtered.get_or_insert(Filtered {
+                sequences: 0,
+                reads: 0,
+            });
+            removed.sequences += sequences as u64;
+            removed.reads += reads;
+        } else {
+            for (row, chimera) in rows.iter_mut().zip(chimeras) {
+                row.chimera = chimera;
+            }
+        }
+    }
+    // Reads an earlier filter left out stay left out, and rejoin `__other__`
+    if part.other_reads > 0 {
+        let earlier = filtered.get_or_insert(Filtered {
+            sequences: 0,
+            reads: 0,
+        });
+        earlier.reads += part.other_reads;
+    }
+
+    // Save in each requested format
+    let provenance = Provenance {
+        sample,
+        total_reads: part.total_reads,
+        header_fields: part
+            .label
+            .iter()
+            .map(|(field, value)| (field.name(), value.clone()))
+            .chain(
+                args.parse_illumina_headers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| {
+                        let keys = rows.iter().map(|row| row.key);
+                        (field.name(), illumina::observed(keys, header_fields, i))
+                    }),
+            )
+            .collect(),
+        filtered,
+        ..input.clone()
+    };
+    let other_key = "\t".repeat(header_fields) + output::OTHER_KEY;
+    let mut other_errors = None;
+    if let Some(filtered) = filtered.filter(|_| args.other_row) {
+        // The filtered reads' expected errors are what the kept rows leave
+        other_errors = part.error_sums.as_ref().map(|sums| {
+            let kept: f64 = rows
+                .iter()
+                .map(|row| sums.get(row.key).copied().unwrap_or_default())
+                .sum();
+            (sums.values().sum::<f64>() - kept).max(0.0) / filtered.reads.max(1) as f64
+        });
+        rows.push(Row {
+            key: &other_key,
+            count: filtered.reads,
+            chimera: false,
+            other: true,
+        });
+    }
+    // Filters and `--top` go by count, so rows are reordered only now; rows
+    // of one sequence with different header fields end up side by side
+    if args.sort == SortOrder::Sequence {
+        rows.par_sort_unstable_by(|a, b| {
+            let (a_fields, a_sequence) = illumina::split_fields(a.key, header_fields);
+            let (b_fields, b_sequence) = illumina::split_fields(b.key, header_fields);
+            (a_sequence, a_fields).cmp(&(b_sequence, b_fields))
+        });
+    }
+
+    // Annotate against reference
+    let annotations = shared.reference.as_ref().map(|reference| {
+        let annotations: Vec<_> = rows
+            .par_iter()
+            .map(|row| {
+                let (_, sequence) = illumina::split_fields(row.key, header_fields);
+                (!row.other)
+                    .then(|| reference.assign(sequence.as_bytes()))
+                    .flatten()
+            })
+            .collect();
+        if verbose {
+            let sequences = rows.iter().filter(|row| !row.other).count();
+            let (matched, matched_reads) = rows
+                .iter()
+                .zip(&annotations)
+                .filter(|(_, found)| found.is_some())
+                .fold((0, 0), |(n, reads), (row, _)| (n + 1, reads + row.count));
+            eprintln!(
+                "   🔖 Annotated: {}/{} unique sequences ({:.2}% of reads)",
+                matched,
+                sequences,
+                matched_reads as f64 / part.total_reads.max(1) as f64 * 100.0
+            );
+        }
+        annotations
+    });
+
+    let table = TableRows {
+        rows,
+        header_fields,
+        total_reads: part.total_reads,
+        include_rpm: args.rpm,
+        spike_factor,
+        error_sums: part.error_sums.as_ref(),
+        other_errors,
+        chimeras: args.flag_chimeras,
+        annotations,
+    };
+    for format in &args.format {
+        let output_path = part.path(output_path(input_path, args, Some(format.extension()), ""));
+        let output_path = table_path(output_path, format, args);
+        output::save_output(&table, &output_path, format, &provenance, args, verbose)?;
+        written.push(output_path);
+    }
+
+    Ok((written, diversity))
+}
+
+/// How records map to counting keys
+#[derive(Clone, Copy)]
+struct RecordKey<'a> {
+    /// Header group pattern, counted in place of the sequence
+    group: Option<&'a Regex>,
+    /// Illumina read-name fields prefixed to the key
+    illumina: &'a [IlluminaField],
+    /// Read-name tag pattern keyed after the Illumina fields
+    read_name: Option<&'a Regex>,
+    /// What reads go through before they are keyed: contaminant screening,
+    /// masks, orientation, and the `--key` part or amplicon insert
+    transforms: &'a Transforms<'a>,
+    /// Count each minimizer of the transformed read in place of all of it
+    minimizers: Option<&'a Minimizers>,
+    /// Count each window of the transformed read in place of all of it
+    windows: Option<&'a Windows>,
+    /// Tally reads for the orientation and contaminant reports; off when
+    /// reads are keyed again for side outputs
+    tally: bool,
+    /// Mean-quality bins keyed after the Illumina fields, with the input's
+    /// Phred offset
+    quality_bins: Option<(&'a QualityBins, u8)>,
+}
+
+impl<'a> RecordKey<'a> {
+    /// Keys of a run's options, by `illumina` fields (see [`key_fields`]);
+    /// `quality_offset` is the input's, for `--quality-bins`
+    fn new(
+        args: &'a Args,
+        shared: &'a SharedInputs,
+        transforms: &'a Transforms<'a>,
+        illumina: &'a [IlluminaField],
+        quality_offset: Option<u8>,
+    ) -> Self {
+        Self {
+            group: args.group_by_header.as_ref(),
+            illumina,
+            read_name: args.group_by_read_name.as_ref(),
+            transforms,
+            minimizers: shared.minimizers.as_ref(),
+            windows: args.window.as_ref(),
+            tally: true,
+            quality_bins: args.quality_bins.as_ref().zip(quality_offset),
+        }
+    }
+
+    /// Whether keys come from the sequence alone (all of it, its `--key`
+    /// part or amplicon insert, or its minimizers)
+    fn is_sequence(&self) -> bool {
+        self.group.is_none()
+            && self.illumina.is_empty()
+            && self.read_name.is_none()
+            && self.quality_bins.is_none()
+            && !self.transforms.adds_fields()
+    }
+
+    /// Pass the counted parts of `seq` to `emit`, as for `parts`, once it
+    /// has been through the transforms
+    fn sequences(&self, seq: &[u8], emit: impl FnMut(&[u8])) {
+        if let Some(read) = self.transforms.apply(seq, self.tally) {
+            self.parts(&read.seq, emit);
+        }
+    }
+
+    /// Pass `seq` to `emit` whole, or each of its minimizers or `--window`
+    /// windows
+    fn parts(&self, seq: &[u8], mut emit: impl FnMut(&[u8])) {
+        if let Some(minimizers) = self.minimizers {
+            return minimizers.for_each(seq, emit);
+        }
+        match self.windows {
+            Some(windows) => windows.for_each(seq, emit),
+            None => emit(seq),
+        }
+    }
+}
+
+/// Illumina fields a run keys by: the split field leads, ahead of those kept
+/// as columns
+fn key_fields(args: &Args) -> Vec<IlluminaField> {
+    args.split_by
+        .into_iter()
+        .chain(args.parse_illumina_headers.iter().copied())
+        .collect()
+}
+
+/// The transforms a run's options put reads through
+fn read_transforms<'a>(args: &'a Args, shared: &'a SharedInputs) -> Transforms<'a> {
+    let mut steps: Vec<&dyn ReadTransform> = Vec::new();
+    if let Some(ref contaminants) = shared.contaminants {
+        steps.push(contaminants);
+    }
+    if !args.mask.is_empty() {
+        steps.push(&args.mask);
+    }
+    if let Some(ref orientation) = shared.orientation {
+        steps.push(orientation);
+    }
+    if let Some(ref spec) = args.key {
+        steps.push(spec);
+    }
+    if let Some(ref amplicons) = shared.amplicon {
+        steps.push(amplicons);
+    }
+    Transforms::new(steps)
+}
+
+/// Pass the counting keys of a record to `emit`: the transformed sequence
+/// (whole, or one per minimizer or window), or the header group when a
+/// pattern is given, after any Illumina fields, read-name tag, quality bin,
+/// amplicon name and contaminant (tab-separated). None if a transform leaves
+/// the read out, the header does not match or is not an Illumina read name,
+/// or its mean quality is in no bin.
+fn record_keys(
+    id: &[u8],
+    seq: &[u8],
+    qual: Option<&[u8]>,
+    keys: RecordKey,
+    mut emit: impl FnMut(&[u8]),
+) {
+    let Some(read) = keys.transforms.apply(seq, keys.tally) else {
+        return;
+    };
+    let mut fields = match keys.illumina {
+        [] => None,
+        illumina => match illumina::fields_key(id, illumina) {
+            Some(fields) => Some(fields),
+            None => return,
+        },
+    };
+    if let Some(pattern) = keys.read_name {
+        let Some(tag) = header_key(id, pattern) else {
+            return;
+        };
+        fields = Some(match fields {
+            Some(fields) => format!("{}\t{}", fields, tag),
+            None => tag,
+        });
+    }
+    if let Some((bins, offset)) = keys.quality_bins {
+        let Some(bin) = qual.and_then(|qual| bins.bin(qual, offset)) else {
+            return;
+        };
+        fields = Some(match fields {
+            Some(fields) => format!("{}\t{}", fields, bin),
+            None => bin.to_string(),
+        });
+    }
+    for name in [read.amplicon, read.contaminant].into_iter().flatten() {
+        fields = Some(match fields {
+            Some(fields) => format!("{}\t{}", fields, name),
+            None => name.to_string(),
+        });
+    }
+    // Plain sequence keys are passed on as they are, without copying
+    let mut key = Vec::new();
+    let mut emit = |part: &[u8]| match fields {
+        Some(ref fields) => {
+            key.clear();
+            key.extend_from_slice(fields.as_bytes());
+            key.push(b'\t');
+            key.extend_from_slice(part);
+            emit(&key);
+        }
+        None => emit(part),
+    };
+    match keys.group {
+        Some(pattern) => {
+            if let Some(group) = header_key(id, pattern) {
+                emit(group.as_bytes());
+            }
+        }
+        None => keys.parts(&read.seq, emit),
+    }
+}
+
+/// [`record_keys`], also passing each read's key (`None` if it was not
+/// counted) to the `hooks` that follow it
+fn hooked_record_keys(
+    id: &[u8],
+    seq: &[u8],
+    qual: Option<&[u8]>,
+    keys: RecordKey,
+    hooks: &mut KeyHooks,
+    mut emit: impl FnMut(&[u8]),
+) {
+    if hooks.is_empty() {
+        return record_keys(id, seq, qual, keys, emit);
+    }
+    let mut counted = false;
+    record_keys(id, seq, qual, keys, |key| {
+        hooks.offer(id, qual, Some(key));
+        counted = true;
+        emit(key);
+    });
+    if !counted {
+        hooks.offer(id, qual, None);
+    }
+}
+
+/// Column names of Illumina header fields
+fn illumina_columns(fields: &[IlluminaField]) -> Vec<&'static str> {
+    fields.iter().map(IlluminaField::name).collect()
+}
+
+/// Columns of the fields the run keys counts by ahead of the sequence:
+/// Illumina header fields, then the read-name tag, quality bin, amplicon and
+/// contaminant
+fn field_columns(args: &Args) -> Vec<&'static str> {
+    let mut columns = illumina_columns(&args.parse_illumina_headers);
+    if args.group_by_read_name.is_some() {
+        columns.push("tag");
+    }
+    if args.quality_bins.is_some() {
+        columns.push("quality_bin");
+    }
+    if args.primers.is_some() {
+        columns.push("amplicon");
+    }
+    if !args.contaminants.is_empty() && args.contaminant_action == ContaminantAction::Flag {
+        columns.push("contaminant");
+    }
+    columns
+}
+
+/// Header group: capture group 1 if the pattern has one, else the whole match
+fn header_key(id: &[u8], pattern: &Regex) -> Option<String> {
+    let header = String::from_utf8_lossy(id);
+    let captures = pattern.captures(&header)?;
+    let group = captures.get(1).or_else(|| captures.get(0))?;
+    Some(group.as_str().to_string())
+}
+
+#[allow(clippy::collapsible_if)]
+fn count_sequences(
+    input: &Input,
+    chunk_size: usize,
+    counter: CounterBackend,
+    keys: RecordKey,
+    read: ReadOptions,
+    show_progress: bool,
+    batch_bar: Option<&ProgressBar>,
+) -> Result<(ArenaCounts, u64)> {
+    // Uncompressed local files are parsed in parallel straight from a memory
+    // map, and hashed from it alongside
+    let file_path = input.path();
+    if let Some(map) = mapped::map_plain_text(input)? {
+        let data = match read.limit {
+            Some(records) => mapped::record_prefix(&map, records),
+            None => &map,
+        };
+        return std::thread::scope(|scope| {
+            if let Some(checksum) = read.checksum {
+                scope.spawn(|| checksum.update(data));
+            }
+            mapped::count_sequences(data, counter, keys, read, show_progress, batch_bar)
+        })
+        .with_context(|| format!("Failed to parse file: {}", file_path.display()));
+    }
+
+    // Small file optimization: no chunking
+    if chunk_size == 0 {
+        return count_sequences_sequential(input, keys, read, show_progress);
+    }
+
+    let (mut records, consumed) = input::open_records(input, read)?;
+
+    // Progress follows bytes read from disk, so compressed inputs report
+    // their true position rather than a guess at the record count
+    let file_size = input.file_size()?;
+
+    let progress = if show_progress {
+        Some(progress::byte_bar(file_size))
+    } else {
+        batch_bar.cloned()
+    };
+
+    // Read records in chunks. Concurrent backends count each chunk on the
+    // pool as soon as it fills; the chunked backend keeps them for later.
+    let shared = SharedCounter::new(counter);
+    let mut chunks = Vec::new();
+    let mut n_chunks = 0usize;
+    let mut total_records = 0u64;
+
+    rayon::in_place_scope(|scope| -> Result<()> {
+        let mut current_chunk = KeyBatch::with_capacity(chunk_size);
+        let mut flush = |chunk: KeyBatch| {
+            n_chunks += 1;
+            match shared.as_ref() {
+                Some(counter) => scope.spawn(move |_| counter.add(chunk)),
+                None => chunks.push(chunk),
+            }
+        };
+
+        let mut hooks = read.key_hooks();
+        while records.next_record(|id, seq, qual| {
+            hooked_record_keys(id, seq, qual, keys, &mut hooks, |key| {
+                current_chunk.push(key)
+            });
+        })? {
+            total_records += 1;
+
+            // Update progress bar
+            if let Some(ref pb) = progress {
+                if total_records.is_multiple_of(10000) {
+                    pb.set_position(consumed.load(Ordering::Relaxed));
+                    progress::set_read_rate(pb, total_records);
+                }
+            }
+
+            if current_chunk.len() >= chunk_size {
+                flush(std::mem::replace(
+                    &mut current_chunk,
+                    KeyBatch::with_capacity(chunk_size),
+                ));
+            }
+        }
+
+        hooks.finish();
+        if !current_chunk.is_empty() {
+            flush(current_chunk);
+        }
+        Ok(())
+    })?;
+
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+
+    if show_progress {
+        eprintln!("   📊 Total records: {}", total_records);
+        eprint!("   🔄 Parallel processing ({} chunks)...", n_chunks);
+        std::io::Write::flush(&mut std::io::stderr()).ok();
+    }
+
+    let final_counts = match shared {
+        Some(counter) => counter.into_counts(),
+        None => {
+            // Parallel counting
+            let results: Vec<SequenceCounter> = chunks
+                .par_iter()
+                .map(|chunk| {
+                    let mut local_counts = SequenceCounter::new();
+                    for seq in chunk.iter() {
+                        local_counts.add(seq);
+                    }
+                    local_counts
+                })
+                .collect();
+
+            // Parallel merge
+            let counts = results
+                .into_par_iter()
+                .reduce(SequenceCounter::new, SequenceCounter::merge);
+            if show_progress {
+                print_packed(&counts);
+            }
+            counts.into_counts()
+        }
+    };
+
+    if show_progress {
+        eprintln!(" Done!");
+    }
+
+    Ok((final_counts, total_records))
+}
+
+/// Note when every read was counted with fixed-length packed keys
+fn print_packed(counts: &SequenceCounter) {
+    if let Some(len) = counts.packed_len() {
+        eprint!(" (fixed-length {} bp fast path)", len);
+    }
+}
+
+/// Fast path for small files - no chunking, single-threaded
+fn count_sequences_sequential(
+    input: &Input,
+    keys: RecordKey,
+    read: ReadOptions,
+    show_progress: bool,
+) -> Result<(ArenaCounts, u64)> {
+    let (mut records, _) = input::open_records(input, read)?;
+
+    if show_progress {
+        eprintln!("   📊 Processing (sequential mode for small file)...");
+    }
+
+    let mut counts = ArenaCounts::new();
+    let mut total_records = 0u64;
+
+    let mut hooks = read.key_hooks();
+    while records.next_record(|id, seq, qual| {
+        hooked_record_keys(id, seq, qual, keys, &mut hooks, |key| counts.add(key));
+    })? {
+        total_records += 1;
+    }
+    hooks.finish();
+
+    if show_progress {
+        eprintln!("   📊 Total records: {}", total_records);
+    }
+
+    Ok((counts, total_records))
+}
+
+/// A count table row before it is written: its key in the count map
+struct Row<'a> {
+    key: &'a str,
+    count: u64,
+    /// Whether `--flag-chimeras` found the sequence to be a chimera
+    chimera: bool,
+    /// The `__other__` row, totalling reads filtered out of the table so
+    /// its counts still sum to the reads counted
+    other: bool,
+}
+
+/// Rows of every counted key, sorted by count
+fn count_rows(counts: &ArenaCounts) -> Vec<Row<'_>> {
+    let mut rows: Vec<Row> = counts
+        .iter()
+        .map(|(key, count)| Row {
+            key,
+            count,
+            chimera: false,
+            other: false,
+        })
+        .collect();
+
+    // Sort by count (descending)
+    rows.par_sort_unstable_by_key(|r| std::cmp::Reverse(r.count));
+    rows
+}
+
+/// Apply the count filters, then `--top`, to count-sorted rows; `None` if
+/// no filter was given
+fn filter_rows(rows: &mut Vec<Row>, args: &Args) -> Option<Filtered> {
+    let min_count = match (args.min_count, args.no_singletons) {
+        (Some(min_count), true) => Some(min_count.max(2)),
+        (None, true) => Some(2),
+        (min_count, false) => min_count,
+    };
+    if min_count.is_none() && args.top.is_none() && !args.singletons_only {
+        return None;
+    }
+
+    let before: u64 = rows.iter().map(|r| r.count).sum();
+    let n_before = rows.len();
+    if let Some(min_count) = min_count {
+        rows.retain(|r| r.count >= min_count);
+    }
+    if args.singletons_only {
+        rows.retain(|r| r.count == 1);
+    }
+    if let Some(top) = args.top {
+        rows.truncate(top);
+    }
+    Some(Filtered {
+        sequences: (n_before - rows.len()) as u64,
+        reads: before - rows.iter().map(|r| r.count).sum::<u64>(),
+    })
+}
+
+/// A part's rows in table order, with what their records are made from
+struct TableRows<'a> {
+    rows: Vec<Row<'a>>,
+    /// Tab-separated parts of each key (Illumina fields) moved out of the
+    /// sequence
+    header_fields: usize,
+    total_reads: u64,
+    include_rpm: bool,
+    spike_factor: Option<f64>,
+    /// Summed expected errors of each key's reads, with `--expected-errors`
+    error_sums: Option<&'a AHashMap<String, f64>>,
+    /// Mean expected errors of the `__other__` row's reads
+    other_errors: Option<f64>,
+    /// Write the chimera flags (`--flag-chimeras`)
+    chimeras: bool,
+    /// Reference match of each row, when annotating
+    annotations: Option<Vec<Option<(&'a str, u32)>>>,
+}
+
+impl output::Rows for TableRows<'_> {
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn records(&self, range: std::ops::Range<usize>) -> Vec<SequenceRecord<'_>> {
+        range
+            .into_par_iter()
+            .map(|i| {
+                let row = &self.rows[i];
+                let count = row.count;
+                let (fields, sequence) = illumina::split_key(row.key, self.header_fields);
+                let expected_errors = if row.other {
+                    self.other_errors
+                } else {
+                    self.error_sums
+                        .map(|sums| sums.get(row.key).copied().unwrap_or_default() / count as f64)
+                };
+                SequenceRecord {
+                    sequence,
+                    header_fields: fields,
+                    count,
+                    rpm: self
+                        .include_rpm
+                        .then(|| (count as f64 / self.total_reads as f64) * 1_000_000.0),
+                    normalized_count: self.spike_factor.map(|factor| count as f64 * factor),
+                    expected_errors,
+                    chimera: self.chimeras.then_some(row.chimera),
+                    annotation: self.annotations.as_ref().map(|annotations| {
+                        let found = annotations[i];
+                        output::Annotation {
+                            name: found.map(|(name, _)| name.to_string()),
+                            distance: found.map(|(_, distance)| distance),
+                        }
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_store_urls_are_told_from_paths() {
+        assert_eq!(url_scheme(Path::new("s3://bucket/prefix/")), Some("s3"));
+        assert_eq!(url_scheme(Path::new("gs://bucket")), Some("gs"));
+        assert_eq!(url_scheme(Path::new("az://container/runs")), Some("az"));
+        assert_eq!(url_scheme(Path::new("results/run1")), None);
+        assert_eq!(url_scheme(Path::new("/data/s3:/x")), None);
+        assert_eq!(url_scheme(Path::new("./s3://x")), None);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::arena::ArenaCounts;
+use crate::input::{Input, ReadOptions};
+use crate::transform::ReadTransform;
+use crate::{Args, RecordKey, SharedInputs, ThreadPools, quality};
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// How [`count_file`] counts: options as the `seqtable` command line takes
+/// them, with the references they name loaded once, and any read transforms
+/// of the caller's own
+pub struct CountOptions {
+    args: Args,
+    shared: SharedInputs,
+    transforms: Vec<Box<dyn ReadTransform>>,
+}
+
+impl CountOptions {
+    /// Options given as on the command line (`["--key", "seq[0:12]"]`).
+    /// References (`--orient`, `--contaminants`, ...) are loaded now; options
+    /// about outputs, threads and inputs are accepted and left unused.
+    pub fn from_args<I, T>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        // Inputs are given to `count_file` instead; one stands in for them
+        let command = [OsString::from("seqtable"), OsString::from("-")];
+        let args =
+            Args::try_parse_from(command.into_iter().chain(args.into_iter().map(Into::into)))
+                .context("Invalid counting options")?;
+        let shared = crate::load_shared(&args, ThreadPools::new(None), None)?;
+        Ok(Self {
+            args,
+            shared,
+            transforms: Vec::new(),
+        })
+    }
+
+    /// Put reads through `transform` as well, after the options' own
+    /// transforms of its stage
+    pub fn with_transform(mut self, transform: impl ReadTransform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+}
+
+/// The keys counted in an input, and how many records it had
+pub struct Counts {
+    counts: ArenaCounts,
+    total_reads: u64,
+}
+
+impl Counts {
+    pub fn get(&self, key: &str) -> Option<u64> {
+        self.counts.get(key)
+    }
+
+    /// Each key and its count, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.counts.iter()
+    }
+
+    /// Distinct keys
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Records read, counted or not
+    pub fn total_reads(&self) -> u64 {
+        self.total_reads
+    }
+}
+
+/// Count the reads of `path` as the command line would, before its count
+/// filters, normalization and outputs, on the caller's `pool`. The pool alone
+/// sets the threads used, so inputs can be counted side by side on pools
+/// that split the cores between them.
+pub fn count_file(path: &Path, options: &CountOptions, pool: &rayon::ThreadPool) -> Result<Counts> {
+    let input = Input::new(path);
+    pool.install(|| count_input(&input, options))
+}
+
+/// Count the reads of `reader` as [`count_file`] counts a file, as they
+/// arrive: a request body or other stream, plain or compressed, with no
+/// temporary file. `name` stands for the input in messages. Only blocking
+/// readers are taken; an async stream is counted through a bridge to one
+/// (`tokio_util::io::SyncIoBridge`) on a blocking task.
+pub fn count_reader(
+    name: impl Into<PathBuf>,
+    reader: impl Read + Send + 'static,
+    options: &CountOptions,
+    pool: &rayon::ThreadPool,
+) -> Result<Counts> {
+    let input = Input::from_reader(name, reader);
+    pool.install(|| count_input(&input, options))
+}
+
+fn count_input(input: &Input, options: &CountOptions) -> Result<Counts> {
+    let (args, shared) = (&options.args, &options.shared);
+    let quality = quality::resolve(input, args.phred_offset);
+    let transforms = options
+        .transforms
+        .iter()
+        .fold(crate::read_transforms(args, shared), |chain, step| {
+            chain.with(step.as_ref())
+        });
+    let fields = crate::key_fields(args);
+    let keys = RecordKey::new(
+        args,
+        shared,
+        &transforms,
+        &fields,
+        quality.map(|detection| detection.encoding.offset()),
+    );
+    let chunk_size = crate::calculate_chunk_size(input.file_size()?, args.chunk_size);
+    let (counts, total_reads) = crate::count_sequences(
+        input,
+        chunk_size,
+        args.counter,
+        keys,
+        ReadOptions::default(),
+        false,
+        None,
+    )
+    .with_context(|| format!("Failed to count {}", input.path().display()))?;
+    Ok(Counts {
+        counts,
+        total_reads,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::{Read, Stage};
+
+    /// Leaves out reads starting with T
+    struct NoLeadingT;
+
+    impl ReadTransform for NoLeadingT {
+        fn stage(&self) -> Stage {
+            Stage::Filter
+        }
+
+        fn apply<'s>(&'s self, read: &mut Read<'s>, _tally: bool) -> bool {
+            read.seq.first() != Some(&b'T')
+        }
+    }
+
+    fn pool(threads: usize) -> rayon::ThreadPool {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn counts_are_the_same_on_any_pool_and_take_extra_transforms() {
+        let dir = std::env::temp_dir().join(format!("seqtable-library-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("reads.fa");
+        let reads = ["ACGTAC", "ACGTAC", "ACGTAC", "TTTTTT", "TTTTTT", "ACGTAA"];
+        let fasta: String = reads
+            .iter()
+            .enumerate()
+            .map(|(i, seq)| format!(">r{}\n{}\n", i, seq))
+            .collect();
+        std::fs::write(&path, fasta).unwrap();
+
+        let options = CountOptions::from_args(["--key", "seq[0:4]"]).unwrap();
+        for threads in [1, 3] {
+            let counts = count_file(&path, &options, &pool(threads)).unwrap();
+            assert_eq!(counts.total_reads(), 6);
+            assert_eq!(counts.len(), 2);
+            assert_eq!(counts.get("ACGT"), Some(4));
+            assert_eq!(counts.get("TTTT"), Some(2));
+        }
+
+        let options = options.with_transform(NoLeadingT);
+        let counts = count_file(&path, &options, &pool(2)).unwrap();
+        assert_eq!(counts.get("ACGT"), Some(4));
+        assert_eq!(counts.get("TTTT"), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn streams_are_counted_as_files_are() {
+        let fastq: String = ["ACGTAC", "ACGTAC", "GGGTTT"]
+            .iter()
+            .map(|seq| format!("@r\n{}\n+\nIIIIII\n", seq))
+            .collect();
+        let mut gzipped = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        std::io::Write::write_all(&mut gzipped, fastq.as_bytes()).unwrap();
+        let upload = std::io::Cursor::new(gzipped.finish().unwrap());
+
+        let options = CountOptions::from_args(["--key", "seq[0:3]"]).unwrap();
+        let counts = count_reader("upload.fq.gz", upload, &options, &pool(2)).unwrap();
+        assert_eq!(counts.total_reads(), 3);
+        assert_eq!(counts.get("ACG"), Some(2));
+        assert_eq!(counts.get("GGG"), Some(1));
+    }
+
+    #[test]
+    fn bad_options_are_refused() {
+        assert!(CountOptions::from_args(["--key", "seq[4:2"]).is_err());
+    }
+}
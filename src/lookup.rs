@@ -0,0 +1,399 @@
+use crate::Args;
+use crate::bgzf::{self, BgzfWriter};
+use crate::exit::{Classify, Failure};
+use crate::interrupt::PartialOutput;
+use crate::output::{Batch, OutputFormat, OutputWriter, SortOrder};
+use anyhow::{Context, Result, bail};
+use arrow::datatypes::Field;
+use clap::Args as ClapArgs;
+use flate2::read::MultiGzDecoder;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Second column of an index, after the key column's name
+const OFFSET_COLUMN: &str = "virtual_offset";
+
+/// Print the rows of sequences in a table written with `--bgzip`, reading
+/// only the blocks its index points to
+#[derive(ClapArgs, Debug, Clone)]
+pub struct LookupArgs {
+    /// Count table written with --bgzip (.tsv.gz)
+    pub table: PathBuf,
+
+    /// Sequences (or groups) to print the rows of
+    #[arg(required = true)]
+    pub keys: Vec<String>,
+
+    /// Index of the table [default: <TABLE>.idx]
+    #[arg(long, value_name = "FILE")]
+    pub index: Option<PathBuf>,
+
+    /// Disable the report of keys not in the table
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+/// Index of a `--bgzip` table, beside it with `.idx` appended
+pub fn index_path(table: &Path) -> PathBuf {
+    let mut name = table.as_os_str().to_owned();
+    name.push(".idx");
+    PathBuf::from(name)
+}
+
+/// `--bgzip` writes sequence-sorted TSV tables with their key column, which
+/// is what the index covers and lookups search
+pub fn check(args: &Args) -> Result<()> {
+    if !args.bgzip {
+        return Ok(());
+    }
+    if !args
+        .format
+        .iter()
+        .any(|format| matches!(format, OutputFormat::Tsv))
+    {
+        bail!("--bgzip applies to TSV tables; add -f tsv");
+    }
+    if args.sort != SortOrder::Sequence {
+        bail!("--bgzip indexes tables by their key column; add --sort sequence");
+    }
+    let key = if args.group_by_header.is_some() {
+        "group"
+    } else {
+        "sequence"
+    };
+    if !args.columns.is_empty() && !args.columns.iter().any(|column| column == key) {
+        bail!("--bgzip indexes the {} column; keep it in --columns", key);
+    }
+    Ok(())
+}
+
+/// TSV table in BGZF blocks, with an index of the first key starting in
+/// each block
+pub struct IndexedWriter {
+    partial: PartialOutput,
+    writer: BgzfWriter<BufWriter<File>>,
+    index_partial: PartialOutput,
+    index: csv::Writer<BufWriter<File>>,
+    columns: usize,
+    /// Position of the key column
+    key: usize,
+    /// Block the last indexed row starts in
+    indexed_block: Option<u64>,
+}
+
+impl IndexedWriter {
+    pub fn open(path: &Path, fields: &[Field], key: usize) -> Result<Self> {
+        let partial = PartialOutput::new(path);
+        let file = File::create(partial.path())
+            .with_context(|| format!("Failed to create file: {}", path.display()))?;
+        let mut writer = BgzfWriter::new(BufWriter::new(file));
+        let mut header = tsv_writer(Vec::new());
+        header.write_record(fields.iter().map(|field| field.name()))?;
+        writer.write_all(&header.into_inner().map_err(|err| err.into_error())?)?;
+
+        let index_path = index_path(path);
+        let index_partial = PartialOutput::new(&index_path);
+        let index_file = File::create(index_partial.path())
+            .with_context(|| format!("Failed to create file: {}", index_path.display()))?;
+        let mut index = tsv_writer(BufWriter::new(index_file));
+        index.write_record([fields[key].name().as_str(), OFFSET_COLUMN])?;
+
+        Ok(Self {
+            partial,
+            writer,
+            index_partial,
+            index,
+            columns: fields.len(),
+            key,
+            indexed_block: None,
+        })
+    }
+}
+
+impl OutputWriter for IndexedWriter {
+    fn write_batch(&mut self, batch: &dyn Batch) -> Result<()> {
+        // Rows are formatted as the TSV writer does, noting where each
+        // starts so it can be indexed by its virtual offset
+        let mut text = tsv_writer(Vec::new());
+        let mut starts = Vec::with_capacity(batch.num_rows() + 1);
+        for row in 0..batch.num_rows() {
+            text.flush()?;
+            starts.push(text.get_ref().len());
+            let cells: Vec<Cow<str>> = (0..self.columns)
+                .map(|column| batch.cell(row, column))
+                .collect();
+            text.write_record(cells.iter().map(|cell| cell.as_bytes()))?;
+        }
+        let text = text.into_inner().map_err(|err| err.into_error())?;
+        starts.push(text.len());
+
+        for (row, span) in starts.windows(2).enumerate() {
+            let offset = self.writer.virtual_offset();
+            if self.indexed_block != Some(offset >> 16) {
+                self.index
+                    .write_record([batch.cell(row, self.key).as_ref(), &offset.to_string()])?;
+                self.indexed_block = Some(offset >> 16);
+            }
+            self.writer.write_all(&text[span[0]..span[1]])?;
+        }
+        Ok(())
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<()> {
+        self.writer.finish()?;
+        self.index.flush()?;
+        self.partial.finish()?;
+        self.index_partial.finish()
+    }
+}
+
+fn tsv_writer<W: Write>(writer: W) -> csv::Writer<W> {
+    csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(writer)
+}
+
+pub fn run(args: &LookupArgs) -> Result<()> {
+    let index_path = args
+        .index
+        .clone()
+        .unwrap_or_else(|| index_path(&args.table));
+    let (key_column, entries) = read_index(&index_path).classify(Failure::Input)?;
+
+    let mut header = [0; 18];
+    let mut file = File::open(&args.table)
+        .with_context(|| format!("Failed to open table: {}", args.table.display()))
+        .classify(Failure::Input)?;
+    let is_bgzf = file.read(&mut header).is_ok_and(|_| bgzf::is_bgzf(&header));
+    if !is_bgzf {
+        return Err(anyhow::anyhow!(
+            "{} is not BGZF; write it with --bgzip",
+            args.table.display()
+        ))
+        .classify(Failure::Input);
+    }
+
+    let header_row = records_from(&args.table, 0)
+        .classify(Failure::Input)?
+        .next();
+    let columns = match header_row {
+        Some(record) => record
+            .context("Failed to read the table header")
+            .classify(Failure::Input)?,
+        None => {
+            return Err(anyhow::anyhow!("{} is empty", args.table.display()))
+                .classify(Failure::Input);
+        }
+    };
+    let Some(key) = columns.iter().position(|column| column == key_column) else {
+        return Err(anyhow::anyhow!(
+            "{} has no {} column, which {} indexes",
+            args.table.display(),
+            key_column,
+            index_path.display()
+        ))
+        .classify(Failure::Input);
+    };
+
+    let mut out = tsv_writer(std::io::stdout().lock());
+    out.write_record(&columns)?;
+    let mut missing = Vec::new();
+    for query in &args.keys {
+        let rows = rows_of(&args.table, &entries, key, query)?;
+        for record in &rows {
+            out.write_record(record)?;
+        }
+        if rows.is_empty() {
+            missing.push(query);
+        }
+    }
+    out.flush()?;
+
+    if !args.quiet {
+        for query in missing {
+            eprintln!("⚠️  Not in the table: {}", query);
+        }
+    }
+    Ok(())
+}
+
+/// Rows of a table whose `key`th column is `query`, read from the block its
+/// index `entries` point to
+fn rows_of(
+    table: &Path,
+    entries: &[(String, u64)],
+    key: usize,
+    query: &str,
+) -> Result<Vec<csv::StringRecord>> {
+    // Rows of a key may start in the block before the first one indexed
+    // under it
+    let start = entries
+        .partition_point(|(first, _)| first.as_str() < query)
+        .saturating_sub(1);
+    let mut rows = Vec::new();
+    if let Some(&(_, offset)) = entries.get(start) {
+        for record in records_from(table, offset)? {
+            let record =
+                record.with_context(|| format!("Failed to read a row of {}", table.display()))?;
+            let row_key = record.get(key).unwrap_or_default();
+            if row_key > query {
+                break;
+            }
+            if row_key == query {
+                rows.push(record);
+            }
+        }
+    }
+    Ok(rows)
+}
+
+/// The key column an index covers, and its (first key, virtual offset)
+/// entries in key order
+fn read_index(path: &Path) -> Result<(String, Vec<(String, u64)>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_path(path)
+        .with_context(|| format!("Failed to open index: {}", path.display()))?;
+    let headers = reader.headers()?.clone();
+    if headers.len() != 2 || &headers[1] != OFFSET_COLUMN {
+        bail!("{} is not a seqtable --bgzip index", path.display());
+    }
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record.with_context(|| format!("Failed to read index: {}", path.display()))?;
+        let offset = record[1]
+            .parse()
+            .with_context(|| format!("Bad offset in {}: '{}'", path.display(), &record[1]))?;
+        entries.push((record[0].to_string(), offset));
+    }
+    Ok((headers[0].to_string(), entries))
+}
+
+/// TSV records of a BGZF table from `virtual_offset` on
+fn records_from(
+    path: &Path,
+    virtual_offset: u64,
+) -> Result<csv::StringRecordsIntoIter<MultiGzDecoder<BufReader<File>>>> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open table: {}", path.display()))?;
+    file.seek(SeekFrom::Start(virtual_offset >> 16))?;
+    let mut decoder = MultiGzDecoder::new(BufReader::new(file));
+    let within = virtual_offset & 0xffff;
+    let skipped = std::io::copy(&mut (&mut decoder).take(within), &mut std::io::sink())?;
+    if skipped != within {
+        bail!("{} ends before offset {}", path.display(), virtual_offset);
+    }
+    Ok(csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .from_reader(decoder)
+        .into_records())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::ArrayRef;
+    use arrow::datatypes::DataType;
+
+    /// Rows of (tag, sequence, count), sorted by sequence
+    struct Rows(Vec<[String; 3]>);
+
+    impl Batch for Rows {
+        fn num_rows(&self) -> usize {
+            self.0.len()
+        }
+
+        fn array(&self, _column: usize) -> Result<ArrayRef> {
+            unreachable!("indexed tables are written by cell")
+        }
+
+        fn cell(&self, row: usize, column: usize) -> Cow<'_, str> {
+            Cow::Borrowed(&self.0[row][column])
+        }
+    }
+
+    /// Each sequence in three rows, one per tag, so some sequences have rows
+    /// on both sides of a block boundary
+    fn rows(sequences: usize) -> Vec<[String; 3]> {
+        (0..sequences)
+            .flat_map(|i| {
+                ["a", "b", "c"].map(|tag| [tag.to_string(), format!("SEQ{:08}", i), i.to_string()])
+            })
+            .collect()
+    }
+
+    fn write_table(name: &str, rows: &[[String; 3]]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "seqtable-lookup-{}-{}.tsv.gz",
+            name,
+            std::process::id()
+        ));
+        let fields = [
+            Field::new("tag", DataType::Utf8, false),
+            Field::new("sequence", DataType::Utf8, false),
+            Field::new("count", DataType::UInt64, false),
+        ];
+        let mut writer = Box::new(IndexedWriter::open(&path, &fields, 1).unwrap());
+        // Several batches, as tables are written
+        for batch in rows.chunks(1000) {
+            writer.write_batch(&Rows(batch.to_vec())).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    #[test]
+    fn finds_every_row_of_keys_across_blocks() {
+        let rows = rows(5000);
+        let path = write_table("blocks", &rows);
+        let (column, entries) = read_index(&index_path(&path)).unwrap();
+        assert_eq!(column, "sequence");
+        assert!(entries.len() > 3, "the table spans several blocks");
+
+        // Keys starting a block, those just before them, and every 97th
+        let sequences: Vec<&String> = rows.iter().map(|row| &row[1]).step_by(3).collect();
+        let queries = entries
+            .iter()
+            .filter_map(|(first, _)| sequences.iter().position(|s| *s == first))
+            .flat_map(|i| [i.saturating_sub(1), i])
+            .chain((0..sequences.len()).step_by(97))
+            .map(|i| sequences[i]);
+        for sequence in queries {
+            let found = rows_of(&path, &entries, 1, sequence).unwrap();
+            let expected: Vec<&[String; 3]> =
+                rows.iter().filter(|row| &row[1] == sequence).collect();
+            assert_eq!(found.len(), expected.len(), "rows of {}", sequence);
+            for (record, row) in found.iter().zip(expected) {
+                assert_eq!(record, &row[..]);
+            }
+        }
+        for absent in ["A", "SEQ00000001x", "SEQ99999999"] {
+            assert!(rows_of(&path, &entries, 1, absent).unwrap().is_empty());
+        }
+        std::fs::remove_file(index_path(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn index_entries_point_at_their_rows() {
+        let rows = rows(5000);
+        let path = write_table("offsets", &rows);
+        let (_, entries) = read_index(&index_path(&path)).unwrap();
+        let blocks: Vec<u64> = entries.iter().map(|(_, offset)| offset >> 16).collect();
+        assert!(blocks.windows(2).all(|pair| pair[0] < pair[1]));
+
+        for (first, offset) in &entries {
+            let record = records_from(&path, *offset)
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
+            assert_eq!(&record[1], first.as_str());
+        }
+        std::fs::remove_file(index_path(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
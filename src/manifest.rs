@@ -0,0 +1,117 @@
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// Manifest columns besides `input`; empty cells keep the command-line value
+const COLUMNS: [&str; 7] = [
+    "sample",
+    "output",
+    "rpm",
+    "group-by-header",
+    "skip-errors",
+    "chunk-size",
+    "threads",
+];
+
+/// One manifest row: an input and the options that differ for it
+pub struct Entry {
+    pub input: PathBuf,
+    /// (column, value) for every non-empty cell other than `input`
+    overrides: Vec<(String, String)>,
+}
+
+/// Read a CSV/TSV manifest (by extension, like a sample sheet) with an `input`
+/// column. Relative input paths are resolved against the manifest's directory.
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let delimiter = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => b',',
+        _ => b'\t',
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        // Trailing empty cells may be left off
+        .flexible(true)
+        .from_path(path)
+        .with_context(|| format!("Failed to open manifest: {}", path.display()))?;
+
+    // Column names as the long options they override, so `skip_errors` also works
+    let headers: Vec<String> = reader
+        .headers()
+        .with_context(|| format!("Failed to read manifest: {}", path.display()))?
+        .iter()
+        .map(|name| name.trim().to_ascii_lowercase().replace('_', "-"))
+        .collect();
+    let Some(input_column) = headers.iter().position(|name| name == "input") else {
+        bail!("Manifest has no 'input' column: {}", path.display());
+    };
+    if let Some(unknown) = headers
+        .iter()
+        .find(|name| *name != "input" && !COLUMNS.contains(&name.as_str()))
+    {
+        bail!(
+            "Unknown manifest column '{}' (expected input, {})",
+            unknown,
+            COLUMNS.join(", ")
+        );
+    }
+
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let mut entries = Vec::new();
+    for row in reader.records() {
+        let row = row.with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+        let input = row.get(input_column).unwrap_or_default().trim();
+        if input.is_empty() {
+            bail!(
+                "Manifest row {} has no input path",
+                row.position().map_or(0, |p| p.line())
+            );
+        }
+        let overrides = headers
+            .iter()
+            .zip(row.iter())
+            .filter(|(name, value)| *name != "input" && !value.trim().is_empty())
+            .map(|(name, value)| (name.clone(), value.trim().to_string()))
+            .collect();
+        entries.push(Entry {
+            input: base_dir.join(input),
+            overrides,
+        });
+    }
+    Ok(entries)
+}
+
+impl Entry {
+    /// The run's options with this row's overrides applied
+    pub fn apply(&self, args: &crate::Args) -> Result<crate::Args> {
+        let mut args = args.clone();
+        for (column, value) in &self.overrides {
+            let invalid = || {
+                format!(
+                    "Invalid {} '{}' for {}",
+                    column,
+                    value,
+                    self.input.display()
+                )
+            };
+            match column.as_str() {
+                "sample" => args.sample_name = Some(value.clone()),
+                "output" => args.output_name = Some(value.clone()),
+                "rpm" => {
+                    args.rpm = match value.to_ascii_lowercase().as_str() {
+                        "true" | "yes" | "1" => true,
+                        "false" | "no" | "0" => false,
+                        _ => bail!("{} (expected true or false)", invalid()),
+                    }
+                }
+                "group-by-header" => {
+                    args.group_by_header = Some(Regex::new(value).with_context(invalid)?)
+                }
+                "skip-errors" => args.skip_errors = Some(value.parse().with_context(invalid)?),
+                "chunk-size" => args.chunk_size = value.parse().with_context(invalid)?,
+                "threads" => args.threads = value.parse().with_context(invalid)?,
+                _ => unreachable!("columns are checked when the manifest is read"),
+            }
+        }
+        Ok(args)
+    }
+}
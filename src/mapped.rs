@@ -0,0 +1,397 @@
+use crate::RecordKey;
+use crate::arena::{ArenaCounts, KeyBatch};
+use crate::counter::{CounterBackend, SharedCounter};
+use crate::input::{Input, ReadOptions};
+use crate::packed::SequenceCounter;
+use crate::retry;
+use crate::skip::SkipBudget;
+use anyhow::{Context, Result, bail};
+use indicatif::ProgressBar;
+use memchr::memchr;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Smallest byte range handed to one worker
+const MIN_RANGE_SIZE: usize = 1024 * 1024; // 1MB
+
+/// Byte ranges per rayon thread, so uneven ranges still balance out
+const RANGES_PER_THREAD: usize = 4;
+
+/// Keys buffered per worker before inserting into a shared counter
+const KEY_BATCH_SIZE: usize = 4096;
+
+/// Whether `input` is counted from a memory map: a regular, uncompressed
+/// FASTA/FASTQ file (first byte `>` or `@`), not a mate read in step with
+/// another, and not on a network mount when read errors are to be retried.
+/// The rest go through the streaming reader.
+pub fn is_mappable(input: &Input) -> Result<bool> {
+    let path = input.path();
+    if retry::enabled() && retry::on_network_fs(path) {
+        return Ok(false);
+    }
+    // Merged mates are read in step, record by record, and streams have no
+    // file to map
+    if input.mate().is_some() || input.is_stream() {
+        return Ok(false);
+    }
+    // Checked before opening: opening a pipe only to close it would drop what
+    // was written to it
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+    if !metadata.is_file() || metadata.len() == 0 {
+        return Ok(false);
+    }
+    let mut first = [0];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut first))
+        .with_context(|| format!("Failed to open file: {}", path.display()))?;
+    Ok(matches!(first[0], b'>' | b'@'))
+}
+
+/// Memory-map `input` if `is_mappable` says it is counted that way
+pub fn map_plain_text(input: &Input) -> Result<Option<Mmap>> {
+    if !is_mappable(input)? {
+        return Ok(None);
+    }
+    let path = input.path();
+    let file =
+        File::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+
+    // SAFETY: the map is read-only and only lives for the counting pass; as with
+    // any reader, truncating the input mid-run is not supported
+    let map = unsafe { Mmap::map(&file) }
+        .with_context(|| format!("Failed to memory-map file: {}", path.display()))?;
+    // The file may have been replaced since it was checked
+    Ok(matches!(map.first(), Some(b'>' | b'@')).then_some(map))
+}
+
+/// Count records of a mapped FASTA/FASTQ by splitting it into byte ranges,
+/// resynchronizing each range on a record boundary, and parsing them in parallel.
+/// With a concurrent `counter`, workers insert into one shared map instead.
+/// `bar`, if given, advances by each range's length as it completes. With a
+/// `read.skip` budget, malformed FASTQ records are skipped instead of failing;
+/// every record goes through `read.offer`, and its key through the range's
+/// `read.key_hooks`. (`read.checksum` is left to the caller.)
+pub fn count_sequences(
+    data: &[u8],
+    counter: CounterBackend,
+    keys: RecordKey,
+    read: ReadOptions,
+    show_progress: bool,
+    bar: Option<&ProgressBar>,
+) -> Result<(ArenaCounts, u64)> {
+    let fastq = data[0] == b'@';
+    let n_ranges =
+        (data.len() / MIN_RANGE_SIZE).clamp(1, rayon::current_num_threads() * RANGES_PER_THREAD);
+    let range_size = data.len().div_ceil(n_ranges);
+
+    if show_progress {
+        eprint!("   📊 Processing (memory-mapped, {} ranges)...", n_ranges);
+        std::io::Write::flush(&mut std::io::stderr()).ok();
+    }
+
+    let shared = SharedCounter::new(counter);
+    let reads_done = AtomicU64::new(0);
+    let (counts, total_records) = (0..n_ranges)
+        .into_par_iter()
+        .map(|i| {
+            let start = i * range_size;
+            let end = ((i + 1) * range_size).min(data.len());
+            let mut counts = SequenceCounter::new();
+            let mut batch = KeyBatch::default();
+            let mut hooks = read.key_hooks();
+            let mut total = 0u64;
+            let mut count = |id: &[u8], seq: &[u8], qual: Option<&[u8]>| {
+                total += 1;
+                read.offer(id, seq, qual);
+                if keys.is_sequence() && shared.is_none() && hooks.is_empty() {
+                    // Sequences go straight to the (possibly packed) local counter
+                    return keys.sequences(seq, |seq| counts.add(seq));
+                }
+                crate::hooked_record_keys(id, seq, qual, keys, &mut hooks, |key| {
+                    match shared.as_ref() {
+                        Some(counter) => {
+                            batch.push(key);
+                            if batch.len() >= KEY_BATCH_SIZE {
+                                counter.add(std::mem::take(&mut batch));
+                            }
+                        }
+                        None => counts.add(key),
+                    }
+                });
+            };
+            if fastq {
+                parse_fastq(data, start, end, read.skip, &mut count)?;
+            } else {
+                parse_fasta(data, start, end, &mut count);
+            }
+            hooks.finish();
+            if let Some(counter) = shared.as_ref() {
+                counter.add(batch);
+            }
+            if let Some(bar) = bar {
+                let reads = reads_done.fetch_add(total, Ordering::Relaxed) + total;
+                bar.inc((end - start) as u64);
+                crate::progress::set_read_rate(bar, reads);
+            }
+            Ok::<_, anyhow::Error>((counts, total))
+        })
+        .try_reduce(
+            || (SequenceCounter::new(), 0),
+            |(acc, acc_total), (counts, total)| Ok((acc.merge(counts), acc_total + total)),
+        )?;
+
+    if show_progress {
+        crate::print_packed(&counts);
+    }
+    let counts = match shared {
+        Some(counter) => counter.into_counts(),
+        None => counts.into_counts(),
+    };
+
+    if show_progress {
+        eprintln!(" Done!");
+        eprintln!("   📊 Total records: {}", total_records);
+    }
+
+    Ok((counts, total_records))
+}
+
+/// The start of a mapped FASTA/FASTQ holding its first `records` records
+/// (four lines each for FASTQ, as `count_sequences` expects)
+pub fn record_prefix(data: &[u8], records: u64) -> &[u8] {
+    let mut pos = 0;
+    if data[0] == b'@' {
+        for _ in 0..records.saturating_mul(4) {
+            if pos >= data.len() {
+                break;
+            }
+            pos = line(data, pos).1;
+        }
+    } else {
+        // A record ends where the header after it starts
+        let mut headers = 0;
+        while pos < data.len() {
+            if data[pos] == b'>' {
+                if headers == records {
+                    break;
+                }
+                headers += 1;
+            }
+            pos = line(data, pos).1;
+        }
+    }
+    &data[..pos]
+}
+
+/// Line starting at `pos`, without its line ending, and the start of the next line
+fn line(data: &[u8], pos: usize) -> (&[u8], usize) {
+    let end = memchr(b'\n', &data[pos..]).map_or(data.len(), |i| pos + i);
+    let text = &data[pos..end];
+    let text = text.strip_suffix(b"\r").unwrap_or(text);
+    (text, (end + 1).min(data.len()))
+}
+
+/// Start of the first line at or after `pos`
+fn next_line_start(data: &[u8], pos: usize) -> usize {
+    if pos == 0 || data[pos - 1] == b'\n' {
+        return pos;
+    }
+    memchr(b'\n', &data[pos..]).map_or(data.len(), |i| pos + i + 1)
+}
+
+/// Parse FASTA records that start within `start..end`; multi-line sequences
+/// are joined
+fn parse_fasta(
+    data: &[u8],
+    start: usize,
+    end: usize,
+    mut emit: impl FnMut(&[u8], &[u8], Option<&[u8]>),
+) {
+    // Headers are the only lines starting with '>'
+    let mut pos = next_line_start(data, start);
+    while pos < data.len() && data[pos] != b'>' {
+        pos = line(data, pos).1;
+    }
+
+    let mut seq = Vec::new();
+    while pos < end && pos < data.len() {
+        let (header, mut next) = line(data, pos);
+        seq.clear();
+        while next < data.len() && data[next] != b'>' {
+            let (text, after) = line(data, next);
+            seq.extend_from_slice(text);
+            next = after;
+        }
+        emit(&header[1..], &seq, None);
+        pos = next;
+    }
+}
+
+/// Parse four-line FASTQ records that start within `start..end`. Malformed
+/// records fail the parse unless a `skip` budget allows passing over them.
+fn parse_fastq(
+    data: &[u8],
+    start: usize,
+    end: usize,
+    skip: Option<&SkipBudget>,
+    mut emit: impl FnMut(&[u8], &[u8], Option<&[u8]>),
+) -> Result<()> {
+    let mut pos = sync_fastq(data, next_line_start(data, start));
+
+    while pos < end && pos < data.len() {
+        let (header, seq_start) = line(data, pos);
+        if header.is_empty() {
+            // Trailing blank lines
+            pos = seq_start;
+            continue;
+        }
+        let (seq, plus_start) = line(data, seq_start);
+        let (plus, qual_start) = line(data, plus_start);
+        let (qual, next) = line(data, qual_start);
+
+        if header[0] != b'@' || plus.first() != Some(&b'+') {
+            let Some(budget) = skip else {
+                bail!("Invalid FASTQ record at byte offset {}", pos);
+            };
+            let location = format!("byte offset {}", pos);
+            budget.skip(pos as u64, &location, "not a four-line FASTQ record")?;
+            // The record layout is broken, so find the next record start
+            pos = sync_fastq(data, seq_start);
+            continue;
+        }
+        if qual.len() != seq.len() {
+            let reason = format!(
+                "Sequence and quality lengths differ ({} vs {})",
+                seq.len(),
+                qual.len()
+            );
+            let Some(budget) = skip else {
+                bail!("{} in FASTQ record at byte offset {}", reason, pos);
+            };
+            budget.skip(pos as u64, &format!("byte offset {}", pos), &reason)?;
+            pos = next;
+            continue;
+        }
+        emit(&header[1..], seq, Some(qual));
+        pos = next;
+    }
+    Ok(())
+}
+
+/// First record start at or after the line start `pos`. A quality line may
+/// also begin with '@', but only a header is followed two lines later by '+'.
+fn sync_fastq(data: &[u8], mut pos: usize) -> usize {
+    while pos < data.len() {
+        if data[pos] == b'@' {
+            let plus_start = line(data, line(data, pos).1).1;
+            if data.get(plus_start) == Some(&b'+') {
+                return pos;
+            }
+        }
+        pos = line(data, pos).1;
+    }
+    data.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Record = (String, String);
+
+    fn record(id: &[u8], seq: &[u8]) -> Record {
+        let text = |bytes: &[u8]| String::from_utf8(bytes.to_vec()).unwrap();
+        (text(id), text(seq))
+    }
+
+    /// Records of `data` parsed range by range, as the workers would
+    fn by_ranges(data: &[u8], range_size: usize) -> Vec<Record> {
+        let mut records = Vec::new();
+        for start in (0..data.len()).step_by(range_size) {
+            let end = (start + range_size).min(data.len());
+            let mut emit = |id: &[u8], seq: &[u8], _: Option<&[u8]>| records.push(record(id, seq));
+            if data[0] == b'@' {
+                parse_fastq(data, start, end, None, &mut emit).unwrap();
+            } else {
+                parse_fasta(data, start, end, &mut emit);
+            }
+        }
+        records
+    }
+
+    /// Every range size splits the input into the same records, once each
+    fn assert_ranges_agree(data: &[u8], expected: &[Record]) {
+        for range_size in 1..=data.len() {
+            assert_eq!(
+                by_ranges(data, range_size),
+                expected,
+                "ranges of {}",
+                range_size
+            );
+        }
+    }
+
+    fn owned(records: &[(&str, &str)]) -> Vec<Record> {
+        records
+            .iter()
+            .map(|(id, seq)| (id.to_string(), seq.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn fastq_ranges_resync_past_quality_lines_starting_with_at() {
+        let data = b"@r1\nACGT\n+\n@@II\n@r2\nGG\n+r2\n@I\n@r3\nTTA\n+\nIII\n";
+        assert_ranges_agree(data, &owned(&[("r1", "ACGT"), ("r2", "GG"), ("r3", "TTA")]));
+
+        // From the quality line, the next header is the first record start
+        let qual = 11;
+        assert_eq!(&data[qual..qual + 4], b"@@II");
+        assert_eq!(sync_fastq(data, qual), 16);
+        assert_eq!(sync_fastq(data, 0), 0);
+    }
+
+    #[test]
+    fn crlf_line_endings_are_left_out_of_records() {
+        let fastq = b"@r1\r\nACGT\r\n+\r\nIIII\r\n@r2\r\nCC\r\n+\r\nII\r\n";
+        assert_ranges_agree(fastq, &owned(&[("r1", "ACGT"), ("r2", "CC")]));
+
+        let fasta = b">r1 one\r\nACG\r\nTTA\r\n>r2\r\nGG\r\n";
+        assert_ranges_agree(fasta, &owned(&[("r1 one", "ACGTTA"), ("r2", "GG")]));
+    }
+
+    #[test]
+    fn multi_line_fasta_records_are_joined_across_range_boundaries() {
+        let data = b">a\nACGT\nACGT\nAC\n>b\nTT\n>c\nGGGG\nCCCC\n";
+        assert_ranges_agree(
+            data,
+            &owned(&[("a", "ACGTACGTAC"), ("b", "TT"), ("c", "GGGGCCCC")]),
+        );
+    }
+
+    #[test]
+    fn line_starts_are_found_from_anywhere_in_a_line() {
+        let data = b"ab\ncd\nef";
+        assert_eq!(next_line_start(data, 0), 0);
+        assert_eq!(next_line_start(data, 1), 3);
+        assert_eq!(next_line_start(data, 3), 3);
+        assert_eq!(next_line_start(data, 4), 6);
+        assert_eq!(next_line_start(data, 7), data.len());
+    }
+
+    #[test]
+    fn record_prefixes_end_after_whole_records() {
+        let fastq = b"@r1\nAC\n+\nII\n@r2\nGG\n+\nII\n@r3\nTT\n+\nII\n";
+        assert_eq!(record_prefix(fastq, 0), b"");
+        assert_eq!(record_prefix(fastq, 2), &fastq[..24]);
+        assert_eq!(record_prefix(fastq, 10), fastq);
+
+        let fasta = b">a\nAC\nGT\n>b\nTT\n>c\nGG\n";
+        assert_eq!(record_prefix(fasta, 1), b">a\nAC\nGT\n");
+        assert_eq!(record_prefix(fasta, 2), b">a\nAC\nGT\n>b\nTT\n");
+        assert_eq!(record_prefix(fasta, 3), fasta);
+    }
+}
@@ -0,0 +1,945 @@
+use crate::exit::{self, Classify, Failure};
+use crate::output::{self, Column, ColumnData, MatrixFormat, OutputFormat, SortOrder, TableWriter};
+use crate::spill::{self, ExternalSorter, Spill};
+use crate::table::{CountTable, TableRows};
+use crate::template;
+use ahash::AHashMap;
+use anyhow::{Context, Result, bail};
+use clap::{Args as ClapArgs, ValueEnum};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Merged rows per batch the streamed merge writes
+const MERGE_BATCH_ROWS: usize = 64 * 1024;
+
+/// Combine per-sample count tables into a sequence × sample matrix
+#[derive(ClapArgs, Debug, Clone)]
+pub struct MergeArgs {
+    /// Count tables written by seqtable (.parquet/.csv/.tsv)
+    #[arg(required = true)]
+    pub input: Vec<PathBuf>,
+
+    /// Output file path
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Output format (default: inferred from the output extension, else parquet)
+    #[arg(short = 'f', long)]
+    pub format: Option<MatrixFormat>,
+
+    /// Suffix stripped from input file stems to derive sample names
+    #[arg(short = 's', long, default_value = "_counts")]
+    pub suffix: String,
+
+    /// Sample sheet (CSV/TSV) whose first column names samples; the remaining
+    /// columns become per-sample metadata (h5ad `obs`, BIOM sample metadata)
+    #[arg(long)]
+    pub sample_sheet: Option<PathBuf>,
+
+    /// Normalization written alongside raw counts
+    #[arg(long, default_value = "none")]
+    pub normalize: Normalization,
+
+    #[arg(long, default_value = "snappy", help = crate::output::COMPRESSION_HELP)]
+    pub compression: String,
+
+    /// Row order of the matrix: by total count (descending), or by sequence,
+    /// which skips sorting the merged rows
+    #[arg(long, default_value = "count")]
+    pub sort: SortOrder,
+
+    /// Every table is sorted by sequence, CSV/TSV included (which cannot
+    /// record it), so none is sorted again before the merge; a row out of
+    /// order is an error
+    #[arg(long)]
+    pub presorted: bool,
+
+    /// Disable progress output
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Normalization {
+    /// DESeq2-style median-of-ratios size factors
+    MedianOfRatios,
+    /// Counts per million
+    Cpm,
+    /// Raw counts only
+    None,
+}
+
+/// Merged counts, stored column-major (one vector per sample)
+pub struct CountMatrix {
+    pub key_column: String,
+    pub samples: Vec<String>,
+    pub sequences: Vec<String>,
+    pub counts: Vec<Vec<u64>>,
+    pub normalized: Option<Vec<Vec<f64>>>,
+    /// Sample sheet columns, one value per sample (empty when not in the sheet)
+    pub sample_metadata: Vec<(String, Vec<String>)>,
+}
+
+pub fn run(args: &MergeArgs) -> Result<()> {
+    let start_time = Instant::now();
+    let format = args
+        .format
+        .clone()
+        .or_else(|| MatrixFormat::from_path(&args.output))
+        .unwrap_or(MatrixFormat::Parquet);
+
+    if !args.quiet {
+        eprintln!("🧬 seqtable v0.1.1 merge");
+        eprintln!("📁 Input tables: {}", args.input.len());
+        eprintln!("📊 Output format: {:?}", format);
+        if args.normalize != Normalization::None {
+            eprintln!("⚖️  Normalization: {:?}", args.normalize);
+        }
+        eprintln!();
+    }
+
+    // Parquet, CSV, TSV, Avro and ORC are written a batch at a time; the other formats,
+    // and median-of-ratios, need the whole matrix at once
+    let table_format = match format {
+        MatrixFormat::Parquet => Some(OutputFormat::Parquet),
+        MatrixFormat::Csv => Some(OutputFormat::Csv),
+        MatrixFormat::Tsv => Some(OutputFormat::Tsv),
+        MatrixFormat::Avro => Some(OutputFormat::Avro),
+        MatrixFormat::Orc => Some(OutputFormat::Orc),
+        _ => None,
+    };
+    match table_format {
+        Some(ref table_format) if args.normalize != Normalization::MedianOfRatios => {
+            return merge_streaming(args, table_format, start_time);
+        }
+        _ if args.presorted => {
+            return exit::usage(
+                "--presorted applies to the streamed merge: Parquet, CSV, TSV, Avro or ORC output \
+                 without median-of-ratios"
+                    .to_string(),
+            );
+        }
+        _ => {}
+    }
+
+    let mut matrix = load_matrix(&args.input, &args.suffix, args.quiet)?;
+    if args.sort == SortOrder::Sequence {
+        sort_by_sequence(&mut matrix);
+    }
+
+    if let Some(ref path) = args.sample_sheet {
+        matrix.sample_metadata =
+            read_sample_sheet(path, &matrix.samples).classify(Failure::Input)?;
+        if !args.quiet {
+            eprintln!(
+                "   📋 Sample sheet: {} metadata columns",
+                matrix.sample_metadata.len()
+            );
+        }
+    }
+
+    if let Some(size_factors) = size_factors(&matrix, args.normalize).classify(Failure::Input)? {
+        if !args.quiet {
+            eprintln!();
+            for (sample, factor) in matrix.samples.iter().zip(&size_factors) {
+                eprintln!("   ⚖️  Size factor {}: {:.4}", sample, factor);
+            }
+        }
+        matrix.normalized = Some(
+            matrix
+                .counts
+                .iter()
+                .zip(&size_factors)
+                .map(|(column, factor)| column.iter().map(|&c| c as f64 / factor).collect())
+                .collect(),
+        );
+    }
+
+    if !args.quiet {
+        eprint!("\n   💾 Saving to {}...", format.extension().to_uppercase());
+        std::io::Write::flush(&mut std::io::stderr()).ok();
+    }
+
+    output::save_matrix(&matrix, &args.output, &format, &args.compression)
+        .classify(Failure::Output)?;
+
+    if !args.quiet {
+        eprintln!(" Done!");
+        eprintln!(
+            "   ✓ {} sequences × {} samples → {}",
+            matrix.sequences.len(),
+            matrix.samples.len(),
+            args.output.display()
+        );
+        eprintln!(
+            "   ⏱️  Processing time: {:.2}s",
+            start_time.elapsed().as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
+
+/// Read count tables and outer-join them into a matrix, one sample per input
+pub fn load_matrix(paths: &[PathBuf], suffix: &str, quiet: bool) -> Result<CountMatrix> {
+    let mut tables = Vec::with_capacity(paths.len());
+    let mut samples = Vec::with_capacity(paths.len());
+    for path in paths {
+        let table = CountTable::read(path).classify(Failure::Input)?;
+        // Names recorded by seqtable win over the file name
+        let sample = match table.sample {
+            Some(ref sample) => sample.clone(),
+            None => sample_name(path, suffix),
+        };
+        if samples.contains(&sample) {
+            return exit::usage(format!(
+                "Duplicate sample name '{}' ({})",
+                sample,
+                path.display()
+            ));
+        }
+        if !quiet {
+            eprintln!(
+                "   📄 {}: {} sequences, {} reads",
+                sample,
+                table.counts.len(),
+                table.total()
+            );
+        }
+        samples.push(sample);
+        tables.push(table);
+    }
+
+    Ok(build_matrix(tables, samples))
+}
+
+/// Merge by streaming: tables sorted by sequence are read as they are, the
+/// rest go through an external sort, and a k-way merge over both sums each
+/// key's counts. Memory holds a batch per table and a bounded sort buffer,
+/// never a whole table.
+fn merge_streaming(
+    args: &MergeArgs,
+    table_format: &OutputFormat,
+    start_time: Instant,
+) -> Result<()> {
+    let mut unsorted = ExternalSorter::new(&args.output);
+    let mut sorted_tables = Vec::new();
+    let mut samples = Vec::with_capacity(args.input.len());
+    let mut key_column = None;
+    for path in &args.input {
+        let rows = TableRows::open(path).classify(Failure::Input)?;
+        let sort = rows.sort.clone();
+        if args.presorted {
+            if let Some(sort) = sort.as_deref().filter(|&sort| sort != "sequence") {
+                return Err(anyhow::anyhow!(
+                    "{} is sorted by {}; --presorted needs tables written with --sort sequence",
+                    path.display(),
+                    sort
+                ))
+                .classify(Failure::Input);
+            }
+        }
+        let sample = match rows.sample {
+            Some(ref sample) => sample.clone(),
+            None => sample_name(path, &args.suffix),
+        };
+        if samples.contains(&sample) {
+            return exit::usage(format!(
+                "Duplicate sample name '{}' ({})",
+                sample,
+                path.display()
+            ));
+        }
+        key_column.get_or_insert_with(|| rows.key_column.clone());
+        let sample_idx = samples.len() as u32;
+
+        if args.presorted || sort.as_deref() == Some("sequence") {
+            if !args.quiet {
+                eprintln!("   📄 {}: sorted by sequence, streamed", sample);
+            }
+            sorted_tables.push(SortedRows {
+                path,
+                rows,
+                sample: sample_idx,
+                last: None,
+                row: 0,
+            });
+        } else {
+            let (mut n_rows, mut total) = (0usize, 0u64);
+            for row in rows {
+                let (key, count) = row
+                    .with_context(|| format!("Failed to read count table: {}", path.display()))
+                    .classify(Failure::Input)?;
+                n_rows += 1;
+                total += count;
+                unsorted
+                    .push(Entry {
+                        key,
+                        sample: sample_idx,
+                        count,
+                    })
+                    .classify(Failure::Output)?;
+            }
+            if !args.quiet {
+                eprintln!("   📄 {}: {} sequences, {} reads", sample, n_rows, total);
+            }
+        }
+        samples.push(sample);
+    }
+    let key_column = key_column.unwrap_or_else(|| "sequence".to_string());
+
+    // In sequence order rows go out as they are merged, so CPM needs each
+    // sample's total first; in count order the totals are known by then
+    let per_million = match (args.sort, args.normalize) {
+        (SortOrder::Sequence, Normalization::Cpm) => {
+            let mut factors = Vec::with_capacity(args.input.len());
+            for path in &args.input {
+                let mut total = 0u64;
+                for row in TableRows::open(path).classify(Failure::Input)? {
+                    total += row.classify(Failure::Input)?.1;
+                }
+                factors.push(per_million(total));
+            }
+            Some(factors)
+        }
+        _ => None,
+    };
+
+    let mut sources: Vec<Box<dyn Iterator<Item = Result<Entry>> + '_>> = sorted_tables
+        .into_iter()
+        .map(|table| Box::new(table) as Box<dyn Iterator<Item = Result<Entry>>>)
+        .collect();
+    sources.push(Box::new(unsorted.finish().classify(Failure::Output)?));
+
+    let mut sink = MatrixSink {
+        writer: TableWriter::new(&args.output, table_format.clone(), &args.compression),
+        batch: MatrixBatch::new(samples.len()),
+        by_count: (args.sort == SortOrder::Count).then(|| ExternalSorter::new(&args.output)),
+        key_column: &key_column,
+        samples: &samples,
+        per_million,
+        totals: vec![0; samples.len()],
+        rows: 0,
+    };
+    let mut heap = BinaryHeap::with_capacity(sources.len());
+    // Errors of the sorted tables are tagged as input ones; the rest come
+    // from reading back the sort's own runs
+    for (source, entries) in sources.iter_mut().enumerate() {
+        if let Some(entry) = entries.next().transpose().classify(Failure::Output)? {
+            heap.push(Reverse((entry, source)));
+        }
+    }
+    // Entries come off the heap in key order; equal keys from every table
+    // (and repeats within one) add up into the current row
+    let mut current: Option<String> = None;
+    let mut row = vec![0u64; samples.len()];
+    while let Some(Reverse((entry, source))) = heap.pop() {
+        let Entry { key, sample, count } = entry;
+        if current.as_ref() != Some(&key) {
+            if let Some(done) = current.replace(key) {
+                sink.push(done, &row).classify(Failure::Output)?;
+                row.fill(0);
+            }
+        }
+        row[sample as usize] += count;
+        if let Some(entry) = sources[source]
+            .next()
+            .transpose()
+            .classify(Failure::Output)?
+        {
+            heap.push(Reverse((entry, source)));
+        }
+    }
+    if let Some(done) = current {
+        sink.push(done, &row).classify(Failure::Output)?;
+    }
+    let rows_written = sink.finish(args.normalize).classify(Failure::Output)?;
+
+    if !args.quiet {
+        eprintln!(
+            "\n   ✓ {} sequences × {} samples → {}",
+            rows_written,
+            samples.len(),
+            args.output.display()
+        );
+        eprintln!(
+            "   ⏱️  Processing time: {:.2}s",
+            start_time.elapsed().as_secs_f64()
+        );
+    }
+    Ok(())
+}
+
+fn per_million(total: u64) -> f64 {
+    total.max(1) as f64 / 1_000_000.0
+}
+
+/// One table row on its way into the merge, ordered by key
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Entry {
+    key: String,
+    sample: u32,
+    count: u64,
+}
+
+impl Spill for Entry {
+    fn size(&self) -> usize {
+        self.key.len() + std::mem::size_of::<Self>()
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        spill::write_str(writer, &self.key)?;
+        writer.write_all(&self.sample.to_le_bytes())?;
+        writer.write_all(&self.count.to_le_bytes())
+    }
+
+    fn read_from(reader: &mut impl BufRead) -> io::Result<Option<Self>> {
+        let Some(key) = spill::read_str(reader)? else {
+            return Ok(None);
+        };
+        Ok(Some(Entry {
+            key,
+            sample: spill::read_u32(reader)?,
+            count: spill::read_u64(reader)?,
+        }))
+    }
+}
+
+/// A merged row, ordered as `build_matrix` orders rows: by total count
+/// (descending), then key
+struct MatrixRow {
+    total: u64,
+    key: String,
+    counts: Vec<u64>,
+}
+
+impl Ord for MatrixRow {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .total
+            .cmp(&self.total)
+            .then_with(|| self.key.cmp(&other.key))
+    }
+}
+
+impl PartialOrd for MatrixRow {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MatrixRow {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MatrixRow {}
+
+impl Spill for MatrixRow {
+    fn size(&self) -> usize {
+        self.key.len() + self.counts.len() * 8 + std::mem::size_of::<Self>()
+    }
+
+    /// Counts are written sparsely, as most rows are absent from most samples
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        spill::write_str(writer, &self.key)?;
+        let nonzero = self.counts.iter().filter(|&&count| count > 0).count();
+        writer.write_all(&(self.counts.len() as u32).to_le_bytes())?;
+        writer.write_all(&(nonzero as u32).to_le_bytes())?;
+        for (idx, &count) in self.counts.iter().enumerate().filter(|(_, c)| **c > 0) {
+            writer.write_all(&(idx as u32).to_le_bytes())?;
+            writer.write_all(&count.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn read_from(reader: &mut impl BufRead) -> io::Result<Option<Self>> {
+        let Some(key) = spill::read_str(reader)? else {
+            return Ok(None);
+        };
+        let mut counts = vec![0; spill::read_u32(reader)? as usize];
+        for _ in 0..spill::read_u32(reader)? {
+            let idx = spill::read_u32(reader)? as usize;
+            counts[idx] = spill::read_u64(reader)?;
+        }
+        Ok(Some(MatrixRow {
+            total: counts.iter().sum(),
+            key,
+            counts,
+        }))
+    }
+}
+
+/// Where merged rows go: straight out in key order, or through an external
+/// sort into count order
+struct MatrixSink<'a> {
+    writer: TableWriter,
+    batch: MatrixBatch,
+    by_count: Option<ExternalSorter<MatrixRow>>,
+    key_column: &'a str,
+    samples: &'a [String],
+    per_million: Option<Vec<f64>>,
+    totals: Vec<u64>,
+    rows: usize,
+}
+
+impl MatrixSink<'_> {
+    fn push(&mut self, key: String, counts: &[u64]) -> Result<()> {
+        self.rows += 1;
+        for (total, &count) in self.totals.iter_mut().zip(counts) {
+            *total += count;
+        }
+        match self.by_count {
+            Some(ref mut sorter) => sorter.push(MatrixRow {
+                total: counts.iter().sum(),
+                key,
+                counts: counts.to_vec(),
+            }),
+            None => self.write_row(key, counts),
+        }
+    }
+
+    fn write_row(&mut self, key: String, counts: &[u64]) -> Result<()> {
+        self.batch.push(key, counts);
+        if self.batch.len() == MERGE_BATCH_ROWS {
+            let columns =
+                self.batch
+                    .take(self.key_column, self.samples, self.per_million.as_deref());
+            self.writer.write(&columns)?;
+        }
+        Ok(())
+    }
+
+    /// Write the remaining rows; returns how many rows the matrix has
+    fn finish(mut self, normalize: Normalization) -> Result<usize> {
+        if let Some(sorter) = self.by_count.take() {
+            if normalize == Normalization::Cpm {
+                self.per_million = Some(self.totals.iter().map(|&t| per_million(t)).collect());
+            }
+            for row in sorter.finish()? {
+                let row = row?;
+                self.write_row(row.key, &row.counts)?;
+            }
+        }
+        let columns = self
+            .batch
+            .take(self.key_column, self.samples, self.per_million.as_deref());
+        self.writer.write(&columns)?;
+        self.writer.finish()?;
+        Ok(self.rows)
+    }
+}
+
+/// One table sorted by sequence, checked to be in order as it is read
+struct SortedRows<'a> {
+    path: &'a Path,
+    rows: TableRows,
+    sample: u32,
+    last: Option<String>,
+    row: usize,
+}
+
+impl Iterator for SortedRows<'_> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self
+            .rows
+            .next()?
+            .with_context(|| format!("Failed to read count table: {}", self.path.display()));
+        let entry = row.and_then(|(key, count)| {
+            self.row += 1;
+            if let Some(ref last) = self.last {
+                if key < *last {
+                    bail!(
+                        "{} is not sorted by sequence ('{}' follows '{}' on row {}); \
+                         write it with --sort sequence",
+                        self.path.display(),
+                        key,
+                        last,
+                        self.row
+                    );
+                }
+            }
+            self.last = Some(key.clone());
+            Ok(Entry {
+                key,
+                sample: self.sample,
+                count,
+            })
+        });
+        Some(entry.classify(Failure::Input))
+    }
+}
+
+/// Merged rows waiting to be written
+struct MatrixBatch {
+    sequences: Vec<String>,
+    counts: Vec<Vec<u64>>,
+}
+
+impl MatrixBatch {
+    fn new(n_samples: usize) -> Self {
+        Self {
+            sequences: Vec::new(),
+            counts: vec![Vec::new(); n_samples],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.sequences.len()
+    }
+
+    fn push(&mut self, sequence: String, row: &[u64]) {
+        self.sequences.push(sequence);
+        for (column, &count) in self.counts.iter_mut().zip(row) {
+            column.push(count);
+        }
+    }
+
+    /// The batch's columns as `save_matrix` lays them out, leaving it empty
+    fn take(
+        &mut self,
+        key_column: &str,
+        samples: &[String],
+        per_million: Option<&[f64]>,
+    ) -> Vec<Column> {
+        let mut columns = vec![Column::new(
+            key_column,
+            ColumnData::Text(std::mem::take(&mut self.sequences)),
+        )];
+        let counts: Vec<Vec<u64>> = self.counts.iter_mut().map(std::mem::take).collect();
+        let normalized: Vec<Column> = per_million
+            .map(|factors| {
+                samples
+                    .iter()
+                    .zip(&counts)
+                    .zip(factors)
+                    .map(|((sample, column), factor)| {
+                        let values = column.iter().map(|&c| c as f64 / factor).collect();
+                        Column::new(
+                            format!("{}_normalized", sample),
+                            ColumnData::Float(values, 2),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        for (sample, column) in samples.iter().zip(counts) {
+            columns.push(Column::new(sample, ColumnData::Count(column)));
+        }
+        columns.extend(normalized);
+        columns
+    }
+}
+
+/// Sample name from the file name, without known extensions and the output
+/// suffix, so `a.1_counts.tsv` and `a.2_counts.tsv` stay apart
+pub fn sample_name(path: &Path, suffix: &str) -> String {
+    let stem = template::stem(path);
+    stem.strip_suffix(suffix)
+        .filter(|s| !s.is_empty())
+        .unwrap_or(&stem)
+        .to_string()
+}
+
+/// Outer-join tables on their key, rows sorted by total count (descending)
+fn build_matrix(tables: Vec<CountTable>, samples: Vec<String>) -> CountMatrix {
+    let key_column = tables
+        .first()
+        .map(|t| t.key_column.clone())
+        .unwrap_or_else(|| "sequence".to_string());
+
+    let n_samples = tables.len();
+    let mut rows: AHashMap<String, Vec<u64>> = AHashMap::new();
+    for (sample_idx, table) in tables.into_iter().enumerate() {
+        for (sequence, count) in table.counts {
+            rows.entry(sequence).or_insert_with(|| vec![0; n_samples])[sample_idx] += count;
+        }
+    }
+
+    let mut rows: Vec<(String, Vec<u64>)> = rows.into_iter().collect();
+    rows.sort_unstable_by(|a, b| {
+        let total_a: u64 = a.1.iter().sum();
+        let total_b: u64 = b.1.iter().sum();
+        total_b.cmp(&total_a).then_with(|| a.0.cmp(&b.0))
+    });
+
+    let mut sequences = Vec::with_capacity(rows.len());
+    let mut counts = vec![Vec::with_capacity(rows.len()); n_samples];
+    for (sequence, row) in rows {
+        sequences.push(sequence);
+        for (column, count) in counts.iter_mut().zip(row) {
+            column.push(count);
+        }
+    }
+
+    CountMatrix {
+        key_column,
+        samples,
+        sequences,
+        counts,
+        normalized: None,
+        sample_metadata: Vec::new(),
+    }
+}
+
+/// Reorder the matrix rows by key
+fn sort_by_sequence(matrix: &mut CountMatrix) {
+    let mut order: Vec<usize> = (0..matrix.sequences.len()).collect();
+    order.sort_unstable_by(|&a, &b| matrix.sequences[a].cmp(&matrix.sequences[b]));
+    matrix.sequences = order
+        .iter()
+        .map(|&row| std::mem::take(&mut matrix.sequences[row]))
+        .collect();
+    for column in &mut matrix.counts {
+        *column = order.iter().map(|&row| column[row]).collect();
+    }
+}
+
+/// Metadata columns from a sample sheet, aligned to `samples`. The sheet's
+/// first column holds sample names; samples absent from it get empty values.
+fn read_sample_sheet(path: &Path, samples: &[String]) -> Result<Vec<(String, Vec<String>)>> {
+    let delimiter = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => b',',
+        _ => b'\t',
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_path(path)
+        .with_context(|| format!("Failed to open sample sheet: {}", path.display()))?;
+
+    let headers = reader.headers()?.clone();
+    let mut columns: Vec<(String, Vec<String>)> = headers
+        .iter()
+        .skip(1)
+        .map(|name| (name.to_string(), vec![String::new(); samples.len()]))
+        .collect();
+
+    for row in reader.records() {
+        let row =
+            row.with_context(|| format!("Failed to read sample sheet: {}", path.display()))?;
+        let Some(idx) = samples.iter().position(|s| s == &row[0]) else {
+            continue;
+        };
+        for (column, value) in columns.iter_mut().zip(row.iter().skip(1)) {
+            column.1[idx] = value.to_string();
+        }
+    }
+
+    Ok(columns)
+}
+
+/// Per-sample divisors for the chosen normalization (`None` for raw counts)
+fn size_factors(matrix: &CountMatrix, method: Normalization) -> Result<Option<Vec<f64>>> {
+    match method {
+        Normalization::None => Ok(None),
+        Normalization::Cpm => Ok(Some(
+            matrix
+                .counts
+                .iter()
+                .map(|column| column.iter().sum::<u64>().max(1) as f64 / 1_000_000.0)
+                .collect(),
+        )),
+        Normalization::MedianOfRatios => median_of_ratios(matrix).map(Some),
+    }
+}
+
+/// DESeq2 size factors: per sample, the median ratio of its counts to each
+/// sequence's geometric mean, over sequences observed in every sample
+fn median_of_ratios(matrix: &CountMatrix) -> Result<Vec<f64>> {
+    let n_rows = matrix.sequences.len();
+    let log_means: Vec<Option<f64>> = (0..n_rows)
+        .map(|row| {
+            let mut sum = 0.0;
+            for column in &matrix.counts {
+                if column[row] == 0 {
+                    return None;
+                }
+                sum += (column[row] as f64).ln();
+            }
+            Some(sum / matrix.counts.len() as f64)
+        })
+        .collect();
+
+    matrix
+        .counts
+        .iter()
+        .zip(&matrix.samples)
+        .map(|(column, sample)| {
+            let mut log_ratios: Vec<f64> = log_means
+                .iter()
+                .zip(column)
+                .filter_map(|(mean, &count)| mean.map(|m| (count as f64).ln() - m))
+                .collect();
+            let median = median(&mut log_ratios).with_context(|| {
+                format!(
+                    "No sequences shared by all samples; cannot compute size factor for {}",
+                    sample
+                )
+            })?;
+            Ok(median.exp())
+        })
+        .collect()
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(counts: Vec<Vec<u64>>) -> CountMatrix {
+        CountMatrix {
+            key_column: "sequence".to_string(),
+            samples: (0..counts.len()).map(|i| format!("s{}", i)).collect(),
+            sequences: (0..counts[0].len()).map(|i| format!("seq{}", i)).collect(),
+            counts,
+            normalized: None,
+            sample_metadata: Vec::new(),
+        }
+    }
+
+    fn merge_args(dir: &Path, input: Vec<PathBuf>, sort: SortOrder, presorted: bool) -> MergeArgs {
+        MergeArgs {
+            input,
+            output: dir.join("matrix.tsv"),
+            format: None,
+            suffix: "_counts".to_string(),
+            sample_sheet: None,
+            normalize: Normalization::None,
+            compression: "snappy".to_string(),
+            sort,
+            presorted,
+            quiet: true,
+        }
+    }
+
+    #[test]
+    fn size_factors_are_the_median_ratio_to_the_geometric_mean() {
+        // The second sample is the first at twice the depth
+        let factors = median_of_ratios(&matrix(vec![vec![10, 20, 40], vec![20, 40, 80]])).unwrap();
+        let expected = [1.0 / 2f64.sqrt(), 2f64.sqrt()];
+        for (factor, expected) in factors.iter().zip(expected) {
+            assert!(
+                (factor - expected).abs() < 1e-12,
+                "{} vs {}",
+                factor,
+                expected
+            );
+        }
+
+        // Rows with a zero are left out (ratios 1 and 2, then 1 and 1/2), and
+        // a sample sharing none is refused
+        let factors = median_of_ratios(&matrix(vec![vec![5, 0, 8], vec![5, 3, 2]])).unwrap();
+        assert!((factors[0] - 2f64.sqrt()).abs() < 1e-12, "{:?}", factors);
+        assert!(
+            (factors[1] - 1.0 / 2f64.sqrt()).abs() < 1e-12,
+            "{:?}",
+            factors
+        );
+        assert!(median_of_ratios(&matrix(vec![vec![1, 0], vec![0, 1]])).is_err());
+
+        assert_eq!(per_million(2_000_000), 2.0);
+        assert_eq!(per_million(0), per_million(1));
+    }
+
+    #[test]
+    fn matrix_rows_spill_sparsely_and_read_back_whole() {
+        let row = MatrixRow {
+            total: 12,
+            key: "ACGT".to_string(),
+            counts: vec![0, 7, 0, 0, 5, 0],
+        };
+        let mut spilled = Vec::new();
+        row.write_to(&mut spilled).unwrap();
+        let mut reader = io::Cursor::new(&spilled);
+        let back = MatrixRow::read_from(&mut reader).unwrap().unwrap();
+        assert_eq!(
+            (back.total, &back.key[..], &back.counts[..]),
+            (12, "ACGT", &row.counts[..])
+        );
+        assert!(MatrixRow::read_from(&mut reader).unwrap().is_none());
+
+        // Only the two nonzero counts are written, with their sample index
+        let mut dense = Vec::new();
+        let dense_row = MatrixRow {
+            counts: vec![1; 6],
+            ..back
+        };
+        dense_row.write_to(&mut dense).unwrap();
+        assert_eq!(dense.len() - spilled.len(), 4 * 12);
+    }
+
+    #[test]
+    fn streamed_merges_sum_keys_across_sorted_and_unsorted_tables() {
+        let dir = std::env::temp_dir().join(format!("seqtable-merge-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a_counts.tsv");
+        let b = dir.join("b_counts.tsv");
+        std::fs::write(&a, "sequence\tcount\nAAA\t3\nCCC\t1\nGGG\t2\n").unwrap();
+        std::fs::write(&b, "sequence\tcount\nTTT\t9\nAAA\t4\nCCC\t1\n").unwrap();
+
+        let read_matrix = |args: &MergeArgs| {
+            std::fs::read_to_string(&args.output)
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        let args = merge_args(&dir, vec![a.clone(), b.clone()], SortOrder::Count, false);
+        merge_streaming(&args, &OutputFormat::Tsv, Instant::now()).unwrap();
+        assert_eq!(
+            read_matrix(&args),
+            [
+                "sequence\ta\tb",
+                "TTT\t0\t9",
+                "AAA\t3\t4",
+                "CCC\t1\t1",
+                "GGG\t2\t0"
+            ]
+        );
+
+        let args = merge_args(&dir, vec![a.clone(), b.clone()], SortOrder::Sequence, false);
+        merge_streaming(&args, &OutputFormat::Tsv, Instant::now()).unwrap();
+        assert_eq!(
+            read_matrix(&args),
+            [
+                "sequence\ta\tb",
+                "AAA\t3\t4",
+                "CCC\t1\t1",
+                "GGG\t2\t0",
+                "TTT\t0\t9"
+            ]
+        );
+
+        // Claimed sorted, `b` is caught out where TTT comes before AAA
+        let args = merge_args(&dir, vec![a, b], SortOrder::Sequence, true);
+        let error = merge_streaming(&args, &OutputFormat::Tsv, Instant::now()).unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(
+            message.contains("'AAA' follows 'TTT' on row 2"),
+            "{}",
+            message
+        );
+        assert_eq!(exit::failure_of(&error), Some(Failure::Input));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+use anyhow::{Result, bail};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+/// Longest k-mer held in a 64-bit 2-bit encoding
+const MAX_K: usize = 32;
+
+/// (k,w)-minimizers: in every window of `w` consecutive k-mers, the canonical
+/// k-mer with the smallest hash
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Minimizers {
+    k: usize,
+    w: usize,
+    /// 2-bit slots of the k-mer that a spaced seed keeps; the rest are ignored
+    seed: Option<u64>,
+}
+
+/// Spaced seed mask over a k-mer: `1` positions are compared, `0` positions
+/// may differ (e.g. `1101101`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedPattern(String);
+
+/// A k-mer position in the current window
+struct Candidate {
+    hash: u64,
+    pos: usize,
+    code: u64,
+}
+
+impl Minimizers {
+    /// Compare only the `1` positions of `pattern`, which must span the k-mer
+    pub fn with_seed(self, pattern: &SeedPattern) -> Result<Self> {
+        if pattern.0.len() != self.k {
+            bail!(
+                "--seed-pattern {} spans {} bases but --minimizers K is {}",
+                pattern,
+                pattern.0.len(),
+                self.k
+            );
+        }
+        let seed = pattern.0.bytes().fold(0u64, |seed, care| {
+            (seed << 2) | if care == b'1' { 3 } else { 0 }
+        });
+        Ok(Self {
+            seed: Some(seed),
+            ..self
+        })
+    }
+
+    /// Pass each minimizer of `seq` to `emit` as its canonical k-mer, once
+    /// per position. K-mers with bases other than ACGT are skipped, and a
+    /// stretch of fewer than `w` k-mers (a short read) contributes its smallest.
+    /// Under a spaced seed, ignored positions are written as `N`.
+    pub fn for_each(&self, seq: &[u8], mut emit: impl FnMut(&[u8])) {
+        let k = self.k;
+        let mask = if k == MAX_K {
+            u64::MAX
+        } else {
+            (1 << (2 * k)) - 1
+        };
+        let shift = 2 * (k - 1);
+        let care = self.seed.unwrap_or(mask);
+        let mut kmer = [0u8; MAX_K];
+        let mut emit_code = |code: u64| {
+            for (i, base) in kmer[..k].iter_mut().enumerate() {
+                let slot = 2 * (k - 1 - i);
+                *base = if care >> slot & 3 == 0 {
+                    b'N'
+                } else {
+                    b"ACGT"[(code >> slot & 3) as usize]
+                };
+            }
+            emit(&kmer[..k]);
+        };
+
+        let (mut forward, mut reverse) = (0u64, 0u64);
+        let mut run = 0usize;
+        let mut window: VecDeque<Candidate> = VecDeque::with_capacity(self.w);
+        let mut last_emitted = None;
+        for (i, &base) in seq.iter().enumerate() {
+            let code = match base.to_ascii_uppercase() {
+                b'A' => 0,
+                b'C' => 1,
+                b'G' => 2,
+                b'T' => 3,
+                _ => {
+                    flush_short(&window, run, k, self.w, last_emitted, &mut emit_code);
+                    window.clear();
+                    run = 0;
+                    continue;
+                }
+            };
+            forward = ((forward << 2) | code) & mask;
+            reverse = (reverse >> 2) | ((3 - code) << shift);
+            run += 1;
+            if run < k {
+                continue;
+            }
+
+            let pos = i + 1 - k;
+            // The seed applies to both strands read 5' to 3'
+            let canonical = (forward & care).min(reverse & care);
+            let hash = hash64(canonical, mask);
+            while window.back().is_some_and(|last| last.hash > hash) {
+                window.pop_back();
+            }
+            window.push_back(Candidate {
+                hash,
+                pos,
+                code: canonical,
+            });
+            if run - k + 1 < self.w {
+                continue;
+            }
+            while window
+                .front()
+                .is_some_and(|first| first.pos + self.w <= pos)
+            {
+                window.pop_front();
+            }
+            let first = &window[0];
+            if last_emitted != Some(first.pos) {
+                last_emitted = Some(first.pos);
+                emit_code(first.code);
+            }
+        }
+        flush_short(&window, run, k, self.w, last_emitted, &mut emit_code);
+    }
+}
+
+/// Emit the smallest k-mer of a stretch too short to fill a window
+fn flush_short(
+    window: &VecDeque<Candidate>,
+    run: usize,
+    k: usize,
+    w: usize,
+    last_emitted: Option<usize>,
+    emit: &mut impl FnMut(u64),
+) {
+    if run < k || run - k + 1 >= w {
+        return;
+    }
+    if let Some(first) = window.front() {
+        if last_emitted != Some(first.pos) {
+            emit(first.code);
+        }
+    }
+}
+
+/// Invertible integer hash (as in minimap2), so minimizers are not biased
+/// toward poly-A k-mers as lexicographic order would be
+fn hash64(key: u64, mask: u64) -> u64 {
+    let mut key = (!key).wrapping_add(key << 21) & mask;
+    key ^= key >> 24;
+    key = key.wrapping_add(key << 3).wrapping_add(key << 8) & mask;
+    key ^= key >> 14;
+    key = key.wrapping_add(key << 2).wrapping_add(key << 4) & mask;
+    key ^= key >> 28;
+    key.wrapping_add(key << 31) & mask
+}
+
+impl FromStr for Minimizers {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (k, w) = spec
+            .split_once(',')
+            .ok_or_else(|| format!("expected K,W (e.g. 15,10), got '{}'", spec))?;
+        let k: usize = k
+            .trim()
+            .parse()
+            .ok()
+            .filter(|k| (1..=MAX_K).contains(k))
+            .ok_or_else(|| format!("K must be an integer from 1 to {}, got '{}'", MAX_K, k))?;
+        let w: usize = w
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&w| w > 0)
+            .ok_or_else(|| format!("W must be a positive integer, got '{}'", w))?;
+        Ok(Minimizers { k, w, seed: None })
+    }
+}
+
+impl FromStr for SeedPattern {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        if pattern.is_empty()
+            || pattern.len() > MAX_K
+            || !pattern.bytes().all(|b| b == b'0' || b == b'1')
+        {
+            return Err(format!(
+                "expected up to {} 0s and 1s (e.g. 1101101), got '{}'",
+                MAX_K, pattern
+            ));
+        }
+        if !pattern.contains('1') {
+            return Err(format!("seed pattern '{}' keeps no positions", pattern));
+        }
+        Ok(SeedPattern(pattern.to_string()))
+    }
+}
+
+impl fmt::Display for SeedPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for Minimizers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.k, self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bases;
+
+    fn minimizers(spec: &str, seq: &[u8]) -> Vec<String> {
+        let minimizers: Minimizers = spec.parse().unwrap();
+        let mut found = Vec::new();
+        minimizers.for_each(seq, |kmer| {
+            found.push(String::from_utf8(kmer.to_vec()).unwrap())
+        });
+        found
+    }
+
+    fn canonical(kmer: &[u8]) -> Vec<u8> {
+        let revcomp = bases::reverse_complement(kmer);
+        kmer.to_vec().min(revcomp)
+    }
+
+    fn code(kmer: &[u8]) -> u64 {
+        kmer.iter()
+            .fold(0, |code, &base| (code << 2) | u64::from(bases::code(base)))
+    }
+
+    /// Each window's (leftmost) smallest canonical k-mer, once per position
+    fn brute_force(k: usize, w: usize, seq: &[u8]) -> Vec<String> {
+        let mask = if k == MAX_K {
+            u64::MAX
+        } else {
+            (1 << (2 * k)) - 1
+        };
+        let kmers: Vec<Vec<u8>> = seq.windows(k).map(canonical).collect();
+        let mut found = Vec::new();
+        let mut last = None;
+        for start in 0..=kmers.len().saturating_sub(w) {
+            let window = &kmers[start..(start + w).min(kmers.len())];
+            let (offset, kmer) = window
+                .iter()
+                .enumerate()
+                .min_by_key(|(offset, kmer)| (hash64(code(kmer), mask), *offset))
+                .unwrap();
+            if last != Some(start + offset) {
+                last = Some(start + offset);
+                found.push(String::from_utf8(kmer.clone()).unwrap());
+            }
+        }
+        found
+    }
+
+    fn random_sequence(len: usize) -> Vec<u8> {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                b"ACGT"[(state % 4) as usize]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn minimizers_match_a_window_by_window_search() {
+        let seq = random_sequence(400);
+        for (k, w) in [(5, 1), (11, 5), (15, 10), (32, 4)] {
+            let found = minimizers(&format!("{},{}", k, w), &seq);
+            assert_eq!(found, brute_force(k, w, &seq), "{},{}", k, w);
+        }
+    }
+
+    #[test]
+    fn both_strands_give_the_same_canonical_minimizers() {
+        let seq = random_sequence(300);
+        let revcomp = bases::reverse_complement(&seq);
+        let set = |mut kmers: Vec<String>| {
+            kmers.sort();
+            kmers.dedup();
+            kmers
+        };
+        assert_eq!(
+            set(minimizers("11,5", &seq)),
+            set(minimizers("11,5", &revcomp))
+        );
+        // A k-mer and its reverse complement are one key
+        assert_eq!(minimizers("4,1", b"AAAC"), minimizers("4,1", b"GTTT"));
+    }
+
+    #[test]
+    fn ns_split_reads_and_short_stretches_give_their_smallest() {
+        let seq = random_sequence(60);
+        let mut masked = seq.clone();
+        masked[30] = b'N';
+        let mut expected = brute_force(11, 5, &seq[..30]);
+        expected.extend(brute_force(11, 5, &seq[31..]));
+        assert_eq!(minimizers("11,5", &masked), expected);
+
+        // Shorter than one window of k-mers: one minimizer; shorter than k: none
+        assert_eq!(minimizers("5,10", &seq[..8]), brute_force(5, 10, &seq[..8]));
+        assert_eq!(minimizers("5,10", &seq[..8]).len(), 1);
+        assert!(minimizers("5,10", &seq[..4]).is_empty());
+        assert_eq!(minimizers("3,1", b"acgt"), minimizers("3,1", b"ACGT"));
+    }
+
+    #[test]
+    fn spaced_seeds_write_ignored_positions_as_n() {
+        let minimizers = Minimizers::from_str("4,1")
+            .unwrap()
+            .with_seed(&"1011".parse().unwrap())
+            .unwrap();
+        let mut found = Vec::new();
+        minimizers.for_each(b"ACGT", |kmer| found.push(kmer.to_vec()));
+        assert_eq!(found, [b"ANGT".to_vec()]);
+        assert!(
+            Minimizers::from_str("5,1")
+                .unwrap()
+                .with_seed(&"1011".parse().unwrap())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn specs_outside_the_supported_range_are_refused() {
+        for spec in ["0,5", "33,5", "15,0", "15", "a,b"] {
+            assert!(spec.parse::<Minimizers>().is_err(), "{}", spec);
+        }
+        assert!("".parse::<SeedPattern>().is_err());
+        assert!("0000".parse::<SeedPattern>().is_err());
+        assert!("10201".parse::<SeedPattern>().is_err());
+    }
+}
@@ -0,0 +1,526 @@
+use crate::interrupt::PartialOutput;
+use crate::output::{Batch, OutputWriter, TableOptions};
+use anyhow::{Context, Result, bail};
+use arrow::array::{Array, ArrayRef, AsArray};
+use arrow::datatypes::{DataType, Field, Float64Type, UInt32Type, UInt64Type};
+use flate2::write::DeflateEncoder;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"ORC";
+
+/// Encoded rows held before they are written out as a stripe
+const STRIPE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Rows between checks of the bytes held against `STRIPE_BYTES`
+const STRIPE_CHECK_ROWS: u64 = 1024;
+
+/// Bytes compressed as one chunk; readers size their buffers by it
+const COMPRESSION_BLOCK: usize = 256 * 1024;
+
+/// ORC_135, the last writer fix a reader checks for before trusting the
+/// file's statistics
+const WRITER_VERSION: u64 = 6;
+
+/// ORC file: stripes of column streams, then a footer with the schema,
+/// stripe list and metadata, and the postscript saying how to read it
+pub struct OrcWriter {
+    partial: PartialOutput,
+    writer: BufWriter<File>,
+    names: Vec<String>,
+    columns: Vec<ColumnBuffer>,
+    compression: Compression,
+    metadata: Vec<(String, String)>,
+    stripes: Vec<Stripe>,
+    /// Rows buffered for the next stripe, and in the stripes written
+    rows: u64,
+    total_rows: u64,
+    /// Bytes of the file so far
+    offset: u64,
+}
+
+/// ORC compression kinds, from the Parquet `--compression` name
+#[derive(Clone, Copy)]
+enum Compression {
+    None,
+    Zlib,
+    Snappy,
+    Zstd,
+}
+
+impl Compression {
+    /// `gzip`, `snappy` and `zstd` have ORC counterparts; the other codecs
+    /// leave the file uncompressed
+    fn from_compression(compression: &str) -> Self {
+        match compression.to_lowercase().as_str() {
+            "gzip" => Compression::Zlib,
+            "snappy" => Compression::Snappy,
+            "zstd" => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    fn kind(self) -> u64 {
+        match self {
+            Compression::None => 0,
+            Compression::Zlib => 1,
+            Compression::Snappy => 2,
+            Compression::Zstd => 5,
+        }
+    }
+
+    /// `data` as chunks behind 3-byte headers of their length and whether
+    /// they were left as they were (when compressing did not shrink them)
+    fn compress(self, data: Vec<u8>) -> Result<Vec<u8>> {
+        if let Compression::None = self {
+            return Ok(data);
+        }
+        let mut snappy = snap::raw::Encoder::new();
+        let mut out = Vec::with_capacity(data.len() / 2);
+        for chunk in data.chunks(COMPRESSION_BLOCK) {
+            let compressed = match self {
+                Compression::Zlib => {
+                    let mut encoder =
+                        DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                    encoder.write_all(chunk)?;
+                    encoder.finish()?
+                }
+                Compression::Snappy => snappy.compress_vec(chunk)?,
+                Compression::Zstd => zstd::bulk::compress(chunk, 0)?,
+                Compression::None => unreachable!(),
+            };
+            let (header, body) = if compressed.len() < chunk.len() {
+                (compressed.len() << 1, compressed.as_slice())
+            } else {
+                (chunk.len() << 1 | 1, chunk)
+            };
+            out.extend_from_slice(&(header as u32).to_le_bytes()[..3]);
+            out.extend_from_slice(body);
+        }
+        Ok(out)
+    }
+}
+
+/// ORC types of count table columns
+#[derive(Clone, Copy)]
+enum Kind {
+    Boolean,
+    Long,
+    Double,
+    String,
+}
+
+impl Kind {
+    fn of(field: &Field) -> Result<Self> {
+        Ok(match field.data_type() {
+            DataType::Utf8 | DataType::LargeUtf8 => Kind::String,
+            DataType::UInt32 | DataType::UInt64 => Kind::Long,
+            DataType::Float64 => Kind::Double,
+            DataType::Boolean => Kind::Boolean,
+            other => bail!(
+                "Column '{}' is {}, which ORC output lacks",
+                field.name(),
+                other
+            ),
+        })
+    }
+
+    fn id(self) -> u64 {
+        match self {
+            Kind::Boolean => 0,
+            Kind::Long => 4,
+            Kind::Double => 6,
+            Kind::String => 7,
+        }
+    }
+}
+
+/// ORC stream kinds
+const PRESENT: u64 = 0;
+const DATA: u64 = 1;
+const LENGTH: u64 = 2;
+
+/// One column's values for the next stripe
+struct ColumnBuffer {
+    kind: Kind,
+    /// Whether each row has a value
+    present: Vec<bool>,
+    /// Longs, or the lengths of strings
+    ints: Vec<i64>,
+    /// Doubles as little-endian bytes, or strings end to end
+    bytes: Vec<u8>,
+    bools: Vec<bool>,
+    /// Values and whether any were null, over the whole file
+    values: u64,
+    has_null: bool,
+}
+
+impl ColumnBuffer {
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            present: Vec::new(),
+            ints: Vec::new(),
+            bytes: Vec::new(),
+            bools: Vec::new(),
+            values: 0,
+            has_null: false,
+        }
+    }
+
+    fn push(&mut self, array: &ArrayRef, row: usize) -> Result<()> {
+        if array.is_null(row) {
+            self.present.push(false);
+            self.has_null = true;
+            return Ok(());
+        }
+        self.present.push(true);
+        self.values += 1;
+        match array.data_type() {
+            DataType::Utf8 => self.push_str(array.as_string::<i32>().value(row)),
+            DataType::LargeUtf8 => self.push_str(array.as_string::<i64>().value(row)),
+            DataType::UInt32 => self
+                .ints
+                .push(array.as_primitive::<UInt32Type>().value(row).into()),
+            DataType::UInt64 => {
+                let value = array.as_primitive::<UInt64Type>().value(row);
+                self.ints
+                    .push(i64::try_from(value).context("A count exceeds ORC's long range")?);
+            }
+            DataType::Float64 => {
+                let value = array.as_primitive::<Float64Type>().value(row);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            DataType::Boolean => self.bools.push(array.as_boolean().value(row)),
+            other => bail!("ORC output lacks {} columns", other),
+        }
+        Ok(())
+    }
+
+    fn push_str(&mut self, value: &str) {
+        self.ints.push(value.len() as i64);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn buffered_bytes(&self) -> usize {
+        self.present.len() + self.ints.len() * 8 + self.bytes.len() + self.bools.len()
+    }
+
+    /// The buffered rows as (stream kind, bytes), emptying the buffer. The
+    /// present stream is left out of stripes without nulls.
+    fn take_streams(&mut self) -> Vec<(u64, Vec<u8>)> {
+        let mut streams = Vec::new();
+        if self.present.contains(&false) {
+            streams.push((PRESENT, boolean_rle(&self.present)));
+        }
+        self.present.clear();
+        let ints = std::mem::take(&mut self.ints);
+        let bytes = std::mem::take(&mut self.bytes);
+        match self.kind {
+            Kind::Boolean => streams.push((DATA, boolean_rle(&std::mem::take(&mut self.bools)))),
+            Kind::Long => streams.push((DATA, int_rle(&ints, true))),
+            Kind::Double => streams.push((DATA, bytes)),
+            Kind::String => {
+                streams.push((DATA, bytes));
+                streams.push((LENGTH, int_rle(&ints, false)));
+            }
+        }
+        streams
+    }
+}
+
+/// Where a written stripe lies, for the footer
+struct Stripe {
+    offset: u64,
+    data_length: u64,
+    footer_length: u64,
+    rows: u64,
+}
+
+impl OrcWriter {
+    pub fn open(path: &Path, fields: Vec<Field>, options: TableOptions) -> Result<Self> {
+        let columns = fields
+            .iter()
+            .map(|field| Kind::of(field).map(ColumnBuffer::new))
+            .collect::<Result<Vec<_>>>()?;
+
+        let partial = PartialOutput::new(path);
+        let file = File::create(partial.path())
+            .with_context(|| format!("Failed to create file: {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(MAGIC)?;
+
+        Ok(Self {
+            partial,
+            writer,
+            names: fields.iter().map(|field| field.name().clone()).collect(),
+            columns,
+            compression: Compression::from_compression(options.compression),
+            // Footer metadata of Parquet tables goes into the ORC footer
+            metadata: options
+                .metadata
+                .into_iter()
+                .map(|kv| (kv.key, kv.value.unwrap_or_default()))
+                .collect(),
+            stripes: Vec::new(),
+            rows: 0,
+            total_rows: 0,
+            offset: MAGIC.len() as u64,
+        })
+    }
+
+    fn write_stripe(&mut self) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let mut stripe_footer = Message::default();
+        let mut data_length = 0;
+        for (index, column) in self.columns.iter_mut().enumerate() {
+            for (kind, data) in column.take_streams() {
+                let data = self.compression.compress(data)?;
+                self.writer.write_all(&data)?;
+                data_length += data.len() as u64;
+                let mut stream = Message::default();
+                stream
+                    .uint(1, kind)
+                    .uint(2, index as u64 + 1)
+                    .uint(3, data.len() as u64);
+                stripe_footer.message(1, &stream);
+            }
+        }
+        // The root struct and every column are written DIRECT
+        for _ in 0..=self.columns.len() {
+            let mut encoding = Message::default();
+            encoding.uint(1, 0);
+            stripe_footer.message(2, &encoding);
+        }
+        let stripe_footer = self.compression.compress(stripe_footer.0)?;
+        self.writer.write_all(&stripe_footer)?;
+
+        self.stripes.push(Stripe {
+            offset: self.offset,
+            data_length,
+            footer_length: stripe_footer.len() as u64,
+            rows: self.rows,
+        });
+        self.offset += data_length + stripe_footer.len() as u64;
+        self.total_rows += self.rows;
+        self.rows = 0;
+        Ok(())
+    }
+
+    fn footer(&self) -> Message {
+        let mut footer = Message::default();
+        footer
+            .uint(1, MAGIC.len() as u64)
+            .uint(2, self.offset - MAGIC.len() as u64);
+        for stripe in &self.stripes {
+            let mut info = Message::default();
+            info.uint(1, stripe.offset)
+                .uint(2, 0)
+                .uint(3, stripe.data_length)
+                .uint(4, stripe.footer_length)
+                .uint(5, stripe.rows);
+            footer.message(3, &info);
+        }
+
+        // Type 0 is the row struct, its fields types 1..
+        let mut root = Message::default();
+        root.uint(1, 12).packed(2, 1..=self.columns.len() as u64);
+        for name in &self.names {
+            root.bytes(3, name.as_bytes());
+        }
+        footer.message(4, &root);
+        for column in &self.columns {
+            let mut column_type = Message::default();
+            column_type.uint(1, column.kind.id());
+            footer.message(4, &column_type);
+        }
+
+        for (key, value) in &self.metadata {
+            let mut item = Message::default();
+            item.bytes(1, key.as_bytes()).bytes(2, value.as_bytes());
+            footer.message(5, &item);
+        }
+        footer.uint(6, self.total_rows);
+
+        let mut statistics = Message::default();
+        statistics.uint(1, self.total_rows);
+        footer.message(7, &statistics);
+        for column in &self.columns {
+            let mut statistics = Message::default();
+            statistics
+                .uint(1, column.values)
+                .uint(10, column.has_null.into());
+            footer.message(7, &statistics);
+        }
+        // No row indexes
+        footer.uint(8, 0);
+        footer
+    }
+}
+
+impl OutputWriter for OrcWriter {
+    fn write_batch(&mut self, batch: &dyn Batch) -> Result<()> {
+        let arrays = (0..self.columns.len())
+            .map(|column| batch.array(column))
+            .collect::<Result<Vec<ArrayRef>>>()?;
+        for row in 0..batch.num_rows() {
+            for (column, array) in self.columns.iter_mut().zip(&arrays) {
+                column.push(array, row)?;
+            }
+            self.rows += 1;
+            if self.rows.is_multiple_of(STRIPE_CHECK_ROWS) {
+                let buffered: usize = self.columns.iter().map(ColumnBuffer::buffered_bytes).sum();
+                if buffered >= STRIPE_BYTES {
+                    self.write_stripe()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<()> {
+        self.write_stripe()?;
+        let footer = self.compression.compress(self.footer().0)?;
+        self.writer.write_all(&footer)?;
+
+        // The postscript is never compressed; its length is the last byte
+        let mut postscript = Message::default();
+        postscript
+            .uint(1, footer.len() as u64)
+            .uint(2, self.compression.kind())
+            .uint(3, COMPRESSION_BLOCK as u64)
+            .packed(4, [0, 12].into_iter())
+            .uint(5, 0)
+            .uint(6, WRITER_VERSION)
+            .bytes(8000, MAGIC);
+        self.writer.write_all(&postscript.0)?;
+        self.writer.write_all(&[postscript.0.len() as u8])?;
+        self.writer.flush()?;
+        self.partial.finish()
+    }
+}
+
+/// Protocol Buffers message of the ORC file tail, field by field
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn uint(&mut self, field: u64, value: u64) -> &mut Self {
+        write_varint(&mut self.0, field << 3);
+        write_varint(&mut self.0, value);
+        self
+    }
+
+    fn bytes(&mut self, field: u64, value: &[u8]) -> &mut Self {
+        write_varint(&mut self.0, field << 3 | 2);
+        write_varint(&mut self.0, value.len() as u64);
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    fn message(&mut self, field: u64, message: &Message) -> &mut Self {
+        self.bytes(field, &message.0)
+    }
+
+    fn packed(&mut self, field: u64, values: impl Iterator<Item = u64>) -> &mut Self {
+        let mut packed = Vec::new();
+        for value in values {
+            write_varint(&mut packed, value);
+        }
+        self.bytes(field, &packed)
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Integer run-length encoding (version 1), as runs of up to 128 literals;
+/// signed values are zig-zag encoded
+fn int_rle(values: &[i64], signed: bool) -> Vec<u8> {
+    let mut out = Vec::new();
+    for literals in values.chunks(128) {
+        out.push(-(literals.len() as i16) as u8);
+        for &value in literals {
+            let value = if signed {
+                ((value << 1) ^ (value >> 63)) as u64
+            } else {
+                value as u64
+            };
+            write_varint(&mut out, value);
+        }
+    }
+    out
+}
+
+/// Byte run-length encoding, as runs of up to 128 literals
+fn byte_rle(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() + bytes.len() / 128 + 1);
+    for literals in bytes.chunks(128) {
+        out.push(-(literals.len() as i16) as u8);
+        out.extend_from_slice(literals);
+    }
+    out
+}
+
+/// Bits packed high to low into bytes, then byte run-length encoded
+fn boolean_rle(bits: &[bool]) -> Vec<u8> {
+    let bytes: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| {
+            byte.iter()
+                .enumerate()
+                .fold(0, |packed, (i, &bit)| packed | u8::from(bit) << (7 - i))
+        })
+        .collect();
+    byte_rle(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (length, original) of each chunk header, and the chunk bodies
+    fn chunks(mut data: &[u8]) -> Vec<(usize, bool, &[u8])> {
+        let mut chunks = Vec::new();
+        while !data.is_empty() {
+            let header = u32::from_le_bytes([data[0], data[1], data[2], 0]) as usize;
+            let (body, rest) = data[3..].split_at(header >> 1);
+            chunks.push((header >> 1, header & 1 == 1, body));
+            data = rest;
+        }
+        chunks
+    }
+
+    #[test]
+    fn snappy_chunks_decompress_to_the_data() {
+        let data: Vec<u8> = b"ACGT".repeat(COMPRESSION_BLOCK / 2);
+        let compressed = Compression::Snappy.compress(data.clone()).unwrap();
+        let chunks = chunks(&compressed);
+        assert_eq!(chunks.len(), 2);
+        let mut decoder = snap::raw::Decoder::new();
+        let mut round_trip = Vec::new();
+        for (length, original, body) in chunks {
+            assert!(!original);
+            assert_eq!(body.len(), length);
+            round_trip.extend(decoder.decompress_vec(body).unwrap());
+        }
+        assert_eq!(round_trip, data);
+        assert_eq!(Compression::from_compression("snappy").kind(), 2);
+    }
+
+    #[test]
+    fn chunks_compressing_would_grow_are_kept_original() {
+        let data = b"AC".to_vec();
+        let compressed = Compression::Snappy.compress(data.clone()).unwrap();
+        // Header of a 2-byte original chunk: (2 << 1) | 1
+        assert_eq!(compressed, [5, 0, 0, b'A', b'C']);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::bases::reverse_complement;
+use ahash::AHashSet;
+use anyhow::{Context, Result};
+use needletail::parse_fastx_file;
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Length of the k-mers reads are placed on the reference by
+pub const ORIENT_K: usize = 15;
+
+/// Orients reads to a reference (`--orient`): a read sharing more k-mers
+/// with the reference's reverse strand than its forward one is counted as
+/// its reverse complement
+pub struct Orientation {
+    /// Packed k-mers of the reference sequences as given
+    forward: AHashSet<u64>,
+    /// Packed k-mers of their reverse complements
+    reverse: AHashSet<u64>,
+    /// Reads reverse-complemented, and reads sharing no k-mer either way,
+    /// since `take_counts`
+    flipped: AtomicU64,
+    unplaced: AtomicU64,
+}
+
+/// 2-bit code of a base in any case, or `None` for N and other codes
+fn code(base: u8) -> Option<u64> {
+    match base {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' | b'U' | b'u' => Some(3),
+        _ => None,
+    }
+}
+
+/// Packed `k`-mers of `seq` (`k` up to 32), skipping those with bases
+/// other than ACGT
+pub fn kmers(seq: &[u8], k: usize) -> impl Iterator<Item = u64> + '_ {
+    let mask = u64::MAX >> (64 - 2 * k);
+    let (mut kmer, mut valid) = (0u64, 0usize);
+    seq.iter().filter_map(move |&base| match code(base) {
+        Some(code) => {
+            kmer = (kmer << 2 | code) & mask;
+            valid += 1;
+            (valid >= k).then_some(kmer)
+        }
+        None => {
+            valid = 0;
+            None
+        }
+    })
+}
+
+impl Orientation {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = parse_fastx_file(path).context(format!(
+            "Failed to open orientation reference: {}",
+            path.display()
+        ))?;
+
+        let (mut forward, mut reverse) = (AHashSet::new(), AHashSet::new());
+        while let Some(record) = reader.next() {
+            let record = record.context("Failed to read orientation reference record")?;
+            let seq = record.seq();
+            forward.extend(kmers(&seq, ORIENT_K));
+            reverse.extend(kmers(&reverse_complement(&seq), ORIENT_K));
+        }
+        if forward.is_empty() {
+            anyhow::bail!(
+                "{} has no {}-mers of ACGT to orient reads by",
+                path.display(),
+                ORIENT_K
+            );
+        }
+
+        Ok(Self {
+            forward,
+            reverse,
+            flipped: AtomicU64::new(0),
+            unplaced: AtomicU64::new(0),
+        })
+    }
+
+    /// `seq` as it lies on the reference: reverse-complemented if more of its
+    /// k-mers are on the reverse strand, else as it is (ties included). With
+    /// `tally`, the read counts toward `take_counts`.
+    pub fn orient<'s>(&self, seq: &'s [u8], tally: bool) -> Cow<'s, [u8]> {
+        let (mut forward, mut reverse) = (0usize, 0usize);
+        for kmer in kmers(seq, ORIENT_K) {
+            forward += usize::from(self.forward.contains(&kmer));
+            reverse += usize::from(self.reverse.contains(&kmer));
+        }
+        if reverse > forward {
+            if tally {
+                self.flipped.fetch_add(1, Ordering::Relaxed);
+            }
+            return Cow::Owned(reverse_complement(seq));
+        }
+        if tally && forward == 0 {
+            self.unplaced.fetch_add(1, Ordering::Relaxed);
+        }
+        Cow::Borrowed(seq)
+    }
+
+    /// Reads reverse-complemented and reads left unplaced since the last
+    /// call, which starts the tallies over for the next input
+    pub fn take_counts(&self) -> (u64, u64) {
+        (
+            self.flipped.swap(0, Ordering::Relaxed),
+            self.unplaced.swap(0, Ordering::Relaxed),
+        )
+    }
+}
@@ -1,24 +1,45 @@
 use crate::Args;
-use anyhow::{Context, Result};
-use arrow::array::{Float64Array, LargeStringArray, UInt64Array};
-use arrow::datatypes::{DataType, Field, Schema};
+use crate::avro::AvroWriter;
+use crate::interrupt::PartialOutput;
+use crate::lookup::IndexedWriter;
+use crate::merge::CountMatrix;
+use crate::orc::OrcWriter;
+use crate::provenance::{self, Provenance};
+use anyhow::{Context, Result, bail};
+use arrow::array::{
+    ArrayRef, BooleanArray, Float64Array, LargeStringArray, StringArray, UInt32Array, UInt64Array,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
 use arrow::record_batch::RecordBatch;
 use clap::ValueEnum;
 use parquet::arrow::ArrowWriter;
+use parquet::file::metadata::KeyValue;
 use parquet::file::properties::WriterProperties;
+use rayon::prelude::*;
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
 use std::path::Path;
 use std::sync::Arc;
 
 // Increased buffer size for better I/O performance
 const WRITE_BUFFER_SIZE: usize = 512 * 1024; // 512KB
 
+/// Rows per Arrow batch written to Parquet (the writer's default row group
+/// size), bounding the arrays held in memory while a table is written
+const PARQUET_BATCH_ROWS: usize = 1024 * 1024;
+
 #[derive(Debug, Clone, ValueEnum)]
 pub enum OutputFormat {
     Parquet,
     Csv,
     Tsv,
+    /// Avro object container file, with the Parquet footer metadata in its
+    /// header
+    Avro,
+    /// ORC file, with the Parquet footer metadata in its footer
+    Orc,
 }
 
 impl OutputFormat {
@@ -27,79 +48,761 @@ impl OutputFormat {
             OutputFormat::Parquet => "parquet",
             OutputFormat::Csv => "csv",
             OutputFormat::Tsv => "tsv",
+            OutputFormat::Avro => "avro",
+            OutputFormat::Orc => "orc",
+        }
+    }
+
+    /// Infer the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "parquet" => Some(OutputFormat::Parquet),
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            "avro" => Some(OutputFormat::Avro),
+            "orc" => Some(OutputFormat::Orc),
+            _ => None,
+        }
+    }
+}
+
+/// Arrow type of the count column in Parquet count tables
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CountType {
+    U32,
+    U64,
+}
+
+/// Row order of count tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// Most abundant first
+    Count,
+    /// Ascending byte order of the key column, each key's rows together
+    Sequence,
+}
+
+impl SortOrder {
+    pub fn name(self) -> &'static str {
+        match self {
+            SortOrder::Count => "count",
+            SortOrder::Sequence => "sequence",
         }
     }
 }
 
-pub struct SequenceRecord {
-    pub sequence: String,
+/// Arrow type of the key column in Parquet count tables
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum StringType {
+    Utf8,
+    /// 64-bit offsets, needed only past 2 GiB of sequence text per table
+    #[value(name = "large_utf8", alias = "large-utf8")]
+    LargeUtf8,
+}
+
+/// Formats for merged sequence × sample matrices
+#[derive(Debug, Clone, ValueEnum)]
+pub enum MatrixFormat {
+    Parquet,
+    Csv,
+    Tsv,
+    Avro,
+    Orc,
+    /// MatrixMarket coordinate format with sequences.tsv/samples.tsv sidecars
+    Mtx,
+    /// AnnData (samples × sequences) for Scanpy
+    H5ad,
+    /// BIOM v2.1 (HDF5) feature table for QIIME 2
+    Biom,
+}
+
+impl MatrixFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            MatrixFormat::Parquet => "parquet",
+            MatrixFormat::Csv => "csv",
+            MatrixFormat::Tsv => "tsv",
+            MatrixFormat::Avro => "avro",
+            MatrixFormat::Orc => "orc",
+            MatrixFormat::Mtx => "mtx",
+            MatrixFormat::H5ad => "h5ad",
+            MatrixFormat::Biom => "biom",
+        }
+    }
+
+    /// Infer the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "mtx" => Some(MatrixFormat::Mtx),
+            "h5ad" => Some(MatrixFormat::H5ad),
+            "biom" => Some(MatrixFormat::Biom),
+            _ => OutputFormat::from_path(path).map(|format| match format {
+                OutputFormat::Parquet => MatrixFormat::Parquet,
+                OutputFormat::Csv => MatrixFormat::Csv,
+                OutputFormat::Tsv => MatrixFormat::Tsv,
+                OutputFormat::Avro => MatrixFormat::Avro,
+                OutputFormat::Orc => MatrixFormat::Orc,
+            }),
+        }
+    }
+}
+
+/// One table row, borrowing its sequence and fields from the count map
+pub struct SequenceRecord<'a> {
+    pub sequence: &'a str,
+    /// Illumina read-name field values, one per `--parse-illumina-headers`
+    /// field, then the quality bin with `--quality-bins`
+    pub header_fields: Vec<&'a str>,
     pub count: u64,
     pub rpm: Option<f64>,
+    pub normalized_count: Option<f64>,
+    /// Mean expected errors of the sequence's reads
+    pub expected_errors: Option<f64>,
+    /// Whether `--flag-chimeras` found the sequence to be a chimera
+    pub chimera: Option<bool>,
+    pub annotation: Option<Annotation>,
+}
+
+/// Rows of a count table, made into records a batch at a time so the whole
+/// table is never held as records either
+pub trait Rows: Sync {
+    fn len(&self) -> usize;
+
+    /// Records of the rows in `range`, in table order
+    fn records(&self, range: Range<usize>) -> Vec<SequenceRecord<'_>>;
+}
+
+/// `--compression` help, shared by every command writing tables
+pub const COMPRESSION_HELP: &str = "Compression type for Parquet (none, snappy, gzip, brotli, zstd); \
+     Avro and ORC take snappy, gzip and zstd, and are left uncompressed by the others";
+
+/// Key of the row totalling reads filtered out of a count table
+pub const OTHER_KEY: &str = "__other__";
+
+/// Sequences and reads left out of a count table by its filters
+#[derive(Clone, Copy)]
+pub struct Filtered {
+    pub sequences: u64,
+    pub reads: u64,
 }
 
-pub fn save_output(records: &[SequenceRecord], output_path: &Path, args: &Args) -> Result<()> {
-    if !args.quiet {
-        print!(
-            "   💾 Saving to {}...",
-            args.format.extension().to_uppercase()
+/// Reference match for a sequence (present on every record when annotating)
+pub struct Annotation {
+    pub name: Option<String>,
+    pub distance: Option<u32>,
+}
+
+/// Output columns that depend on run options rather than on the records
+struct Layout<'a> {
+    /// Illumina header field and quality bin columns, before the key
+    header_columns: Vec<&'static str>,
+    key_column: &'a str,
+    edit_distance: bool,
+    provenance: &'a Provenance,
+    /// Repeat the sample name in a `sample` column
+    sample_column: bool,
+    /// `--columns`, empty for all
+    columns: &'a [String],
+    rename: &'a [(String, String)],
+    count_type: CountType,
+    string_type: StringType,
+}
+
+impl Layout<'_> {
+    /// Columns to write, as (index into `names`, output name). Selected
+    /// columns absent from this table (e.g. `rpm` when it has no rows) are left out.
+    fn selected(&self, names: &[&str]) -> Vec<(usize, String)> {
+        let indices: Vec<usize> = if self.columns.is_empty() {
+            (0..names.len()).collect()
+        } else {
+            self.columns
+                .iter()
+                .filter_map(|column| names.iter().position(|name| name == column))
+                .collect()
+        };
+        indices
+            .into_iter()
+            .map(|i| (i, renamed(names[i], self.rename)))
+            .collect()
+    }
+}
+
+fn renamed(name: &str, rename: &[(String, String)]) -> String {
+    rename
+        .iter()
+        .find(|(old, _)| old == name)
+        .map_or(name, |(_, new)| new.as_str())
+        .to_string()
+}
+
+/// Parse a `--rename` pair, `OLD=NEW`
+pub fn parse_rename(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+            Ok((old.to_string(), new.to_string()))
+        }
+        _ => Err(format!("expected OLD=NEW, got '{}'", value)),
+    }
+}
+
+/// Count table columns the run's options produce, in output order
+fn table_columns(args: &Args) -> Vec<&str> {
+    let mut columns = crate::field_columns(args);
+    columns.push(if args.group_by_header.is_some() {
+        "group"
+    } else {
+        "sequence"
+    });
+    columns.push("count");
+    if args.rpm {
+        columns.push("rpm");
+    }
+    if args.spike_in.is_some() {
+        columns.push("normalized_count");
+    }
+    if args.expected_errors {
+        columns.push("expected_errors");
+    }
+    if args.flag_chimeras {
+        columns.push("chimera");
+    }
+    if args.annotate.is_some() {
+        columns.extend(["name", "matched"]);
+        if args.annotate_max_mismatches > 0 {
+            columns.push("distance");
+        }
+    }
+    if args.sample_column {
+        columns.push("sample");
+    }
+    columns
+}
+
+/// Check that `--columns` and `--rename` name columns the tables will have,
+/// that no column is renamed twice or to another column of the schema, and
+/// that no two output columns end up with the same name
+pub fn check_columns(args: &Args) -> Result<()> {
+    let available = table_columns(args);
+    for (index, (old, new)) in args.rename.iter().enumerate() {
+        if args.rename[..index]
+            .iter()
+            .any(|(earlier, _)| earlier == old)
+        {
+            bail!("Column '{}' is renamed more than once", old);
+        }
+        // Readers and validate-table know schema columns by name
+        if new != old && column_types(new).is_some() {
+            bail!(
+                "Cannot rename '{}' to '{}', which names another count table column",
+                old,
+                new
+            );
+        }
+    }
+    let unknown = args
+        .columns
+        .iter()
+        .chain(args.rename.iter().map(|(old, _)| old))
+        .find(|column| !available.contains(&column.as_str()));
+    if let Some(column) = unknown {
+        bail!(
+            "Unknown column '{}' (the count tables have: {})",
+            column,
+            available.join(", ")
         );
-        std::io::Write::flush(&mut std::io::stdout()).ok();
     }
 
-    match args.format {
-        OutputFormat::Parquet => save_parquet(records, output_path, &args.compression)?,
-        OutputFormat::Csv => save_csv(records, output_path, b',')?,
-        OutputFormat::Tsv => save_csv(records, output_path, b'\t')?,
+    let selected: Vec<&str> = if args.columns.is_empty() {
+        available
+    } else {
+        args.columns.iter().map(String::as_str).collect()
+    };
+    let mut names = std::collections::HashSet::new();
+    for column in selected {
+        let name = renamed(column, &args.rename);
+        if !names.insert(name.clone()) {
+            bail!("Column '{}' would be written twice", name);
+        }
+    }
+    Ok(())
+}
+
+pub fn save_output(
+    rows: &dyn Rows,
+    output_path: &Path,
+    format: &OutputFormat,
+    provenance: &Provenance,
+    args: &Args,
+    verbose: bool,
+) -> Result<()> {
+    if verbose {
+        eprint!("   💾 Saving to {}...", format.extension().to_uppercase());
+        std::io::Write::flush(&mut std::io::stderr()).ok();
+    }
+
+    let layout = Layout {
+        header_columns: crate::field_columns(args),
+        // Grouped counts are keyed by header group rather than by sequence
+        key_column: if args.group_by_header.is_some() {
+            "group"
+        } else {
+            "sequence"
+        },
+        edit_distance: args.annotate_max_mismatches > 0,
+        provenance,
+        sample_column: args.sample_column,
+        columns: &args.columns,
+        rename: &args.rename,
+        count_type: args.count_type,
+        string_type: args.string_type,
+    };
+
+    // Keep the selected columns, under their output names
+    let first = rows.records(0..rows.len().min(1));
+    let all = count_columns(first.first(), &layout);
+    let names: Vec<&str> = all.iter().map(|(_, field)| field.name().as_str()).collect();
+    let selected = layout.selected(&names);
+    let columns: Vec<CountColumn> = selected.iter().map(|(i, _)| all[*i].0).collect();
+    let fields: Vec<Field> = selected
+        .iter()
+        .map(|(i, name)| all[*i].1.clone().with_name(name))
+        .collect();
+
+    // Rows go out a batch at a time, so the whole table is never held as
+    // Arrow arrays at once
+    let mut metadata = provenance.metadata();
+    let renames: Vec<String> = selected
+        .iter()
+        .filter(|(i, name)| names[*i] != name)
+        .map(|(i, name)| format!("{}={}", names[*i], name))
+        .collect();
+    if !renames.is_empty() {
+        metadata.push(KeyValue::new(
+            provenance::RENAME_KEY.to_string(),
+            renames.join(","),
+        ));
+    }
+    let options = TableOptions {
+        compression: &args.compression,
+        metadata,
+    };
+    // `--bgzip` TSV is indexed by the key column, which `lookup::check` keeps
+    let key = columns.iter().position(|c| matches!(c, CountColumn::Key));
+    let mut writer: Box<dyn OutputWriter> = match key {
+        Some(key) if args.bgzip && matches!(format, OutputFormat::Tsv) => {
+            Box::new(IndexedWriter::open(output_path, &fields, key)?)
+        }
+        _ => format.open(output_path, fields, options)?,
+    };
+    for start in (0..rows.len()).step_by(PARQUET_BATCH_ROWS) {
+        let records = rows.records(start..rows.len().min(start + PARQUET_BATCH_ROWS));
+        writer.write_batch(&CountRows {
+            records: &records,
+            layout: &layout,
+            columns: &columns,
+        })?;
     }
+    writer.finalize()?;
 
-    if !args.quiet {
-        println!(" Done!");
+    if verbose {
+        eprintln!(" Done!");
     }
     Ok(())
 }
 
-fn save_parquet(records: &[SequenceRecord], output_path: &Path, compression: &str) -> Result<()> {
-    // Define schema
-    let mut fields = vec![
-        Field::new("sequence", DataType::LargeUtf8, false),
-        Field::new("count", DataType::UInt64, false),
-    ];
+/// A table format, opening an `OutputWriter` for each table written in it.
+/// `OutputFormat` holds the built-in formats; other crates add formats of
+/// their own by implementing this and `OutputWriter`.
+pub trait TableFormat: Send + Sync {
+    /// Open a table of `fields` at `path`
+    fn open(
+        &self,
+        path: &Path,
+        fields: Vec<Field>,
+        options: TableOptions,
+    ) -> Result<Box<dyn OutputWriter>>;
+}
+
+/// One table in a format: opened on the table's columns by
+/// `TableFormat::open`, then given the rows a batch at a time, and finalized
+/// once all are in. A new built-in format is a new implementation and an arm
+/// in `OutputFormat::open`.
+pub trait OutputWriter: Send {
+    /// Write the rows of `batch`, which has the columns the table was opened on
+    fn write_batch(&mut self, batch: &dyn Batch) -> Result<()>;
+
+    /// Flush the table and move it into place
+    fn finalize(self: Box<Self>) -> Result<()>;
+}
+
+/// Rows handed to an `OutputWriter`, readable by column as Arrow arrays (for
+/// columnar formats) or by cell as text (for text formats)
+pub trait Batch: Sync {
+    fn num_rows(&self) -> usize;
+
+    /// Values of the `column`th column
+    fn array(&self, column: usize) -> Result<ArrayRef>;
+
+    /// Text of the `column`th column in row `row`
+    fn cell(&self, row: usize, column: usize) -> Cow<'_, str>;
+}
+
+/// What formats may need to open a table besides its columns
+pub struct TableOptions<'a> {
+    /// Parquet compression codec (`--compression`), which Avro and ORC map to their own
+    pub compression: &'a str,
+    /// Parquet footer key-value metadata
+    pub metadata: Vec<KeyValue>,
+}
+
+impl TableFormat for OutputFormat {
+    /// Tables are written to a temporary path until they are finalized
+    fn open(
+        &self,
+        path: &Path,
+        fields: Vec<Field>,
+        options: TableOptions,
+    ) -> Result<Box<dyn OutputWriter>> {
+        Ok(match self {
+            OutputFormat::Parquet => Box::new(ParquetWriter::open(path, fields, options)?),
+            OutputFormat::Csv => Box::new(DelimitedWriter::open(path, &fields, b',')?),
+            OutputFormat::Tsv => Box::new(DelimitedWriter::open(path, &fields, b'\t')?),
+            OutputFormat::Avro => Box::new(AvroWriter::open(path, fields, options)?),
+            OutputFormat::Orc => Box::new(OrcWriter::open(path, fields, options)?),
+        })
+    }
+}
+
+struct ParquetWriter {
+    partial: PartialOutput,
+    writer: ArrowWriter<File>,
+    schema: SchemaRef,
+}
+
+impl ParquetWriter {
+    fn open(path: &Path, fields: Vec<Field>, options: TableOptions) -> Result<Self> {
+        let schema = Arc::new(Schema::new(fields));
+        let (partial, writer) = create_parquet(
+            path,
+            Arc::clone(&schema),
+            options.compression,
+            options.metadata,
+        )?;
+        Ok(Self {
+            partial,
+            writer,
+            schema,
+        })
+    }
+}
 
-    if records.first().and_then(|r| r.rpm).is_some() {
-        fields.push(Field::new("rpm", DataType::Float64, false));
+impl OutputWriter for ParquetWriter {
+    fn write_batch(&mut self, batch: &dyn Batch) -> Result<()> {
+        // The batch's columns are built in parallel
+        let arrays = (0..self.schema.fields().len())
+            .into_par_iter()
+            .map(|column| batch.array(column))
+            .collect::<Result<Vec<_>>>()?;
+        let batch = RecordBatch::try_new(Arc::clone(&self.schema), arrays)
+            .context("Failed to create RecordBatch")?;
+        self.writer.write(&batch).context("Failed to write data")
     }
 
-    let schema = Arc::new(Schema::new(fields));
+    fn finalize(self: Box<Self>) -> Result<()> {
+        self.writer.close().context("Failed to close file")?;
+        self.partial.finish()
+    }
+}
 
-    // Pre-allocate with capacity
-    let capacity = records.len();
-    let mut sequences = Vec::with_capacity(capacity);
-    let mut counts = Vec::with_capacity(capacity);
+/// CSV or TSV, with a header row of the column names
+struct DelimitedWriter {
+    partial: PartialOutput,
+    writer: csv::Writer<BufWriter<File>>,
+    columns: usize,
+}
+
+impl DelimitedWriter {
+    fn open(path: &Path, fields: &[Field], delimiter: u8) -> Result<Self> {
+        let partial = PartialOutput::new(path);
+        let file = File::create(partial.path())
+            .with_context(|| format!("Failed to create file: {}", path.display()))?;
 
-    for record in records {
-        sequences.push(record.sequence.as_str());
-        counts.push(record.count);
+        // Use larger buffer for better I/O performance
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .buffer_capacity(WRITE_BUFFER_SIZE)
+            .from_writer(BufWriter::with_capacity(WRITE_BUFFER_SIZE, file));
+        writer.write_record(fields.iter().map(|field| field.name()))?;
+        Ok(Self {
+            partial,
+            writer,
+            columns: fields.len(),
+        })
     }
+}
 
-    let seq_array = LargeStringArray::from(sequences);
-    let count_array = UInt64Array::from(counts);
+impl OutputWriter for DelimitedWriter {
+    fn write_batch(&mut self, batch: &dyn Batch) -> Result<()> {
+        for row in 0..batch.num_rows() {
+            let cells: Vec<Cow<str>> = (0..self.columns)
+                .map(|column| batch.cell(row, column))
+                .collect();
+            self.writer
+                .write_record(cells.iter().map(|cell| cell.as_bytes()))?;
+        }
+        Ok(())
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        self.partial.finish()
+    }
+}
 
-    // Build arrays
-    let mut arrays: Vec<Arc<dyn arrow::array::Array>> =
-        vec![Arc::new(seq_array), Arc::new(count_array)];
+/// A count table column, by where its values come from in the records
+#[derive(Clone, Copy)]
+enum CountColumn {
+    /// The Nth of the header fields, ahead of the key
+    Field(usize),
+    Key,
+    Count,
+    Rpm,
+    NormalizedCount,
+    ExpectedErrors,
+    Chimera,
+    Name,
+    Matched,
+    Distance,
+    Sample,
+}
 
-    // Add RPM if present
-    if records.first().and_then(|r| r.rpm).is_some() {
-        let rpm_values: Vec<f64> = records.iter().map(|r| r.rpm.unwrap()).collect();
-        arrays.push(Arc::new(Float64Array::from(rpm_values)));
+/// Types each count table column may have. Columns are written as the first,
+/// except the key and `count`, which `--string-type` and `--count-type` pick
+/// among theirs; `validate-table` accepts any of them.
+const COLUMN_TYPES: &[(&str, &[DataType])] = &[
+    ("sequence", &[DataType::Utf8, DataType::LargeUtf8]),
+    ("group", &[DataType::Utf8, DataType::LargeUtf8]),
+    ("count", &[DataType::UInt32, DataType::UInt64]),
+    ("rpm", &[DataType::Float64]),
+    ("normalized_count", &[DataType::Float64]),
+    ("expected_errors", &[DataType::Float64]),
+    ("chimera", &[DataType::Boolean]),
+    ("name", &[DataType::Utf8]),
+    ("matched", &[DataType::Boolean]),
+    ("distance", &[DataType::UInt32]),
+    ("sample", &[DataType::Utf8]),
+    ("instrument", &[DataType::Utf8]),
+    ("run", &[DataType::Utf8]),
+    ("flowcell", &[DataType::Utf8]),
+    ("lane", &[DataType::Utf8]),
+    ("tile", &[DataType::Utf8]),
+    ("tag", &[DataType::Utf8]),
+    ("quality_bin", &[DataType::Utf8]),
+    ("amplicon", &[DataType::Utf8]),
+    ("contaminant", &[DataType::Utf8]),
+];
+
+/// Types a count table column may have, or `None` for a column outside
+/// the schema
+pub fn column_types(name: &str) -> Option<&'static [DataType]> {
+    COLUMN_TYPES
+        .iter()
+        .find(|(column, _)| *column == name)
+        .map(|(_, types)| *types)
+}
+
+/// Arrow field of a count table column, with the `choice`th of its types
+fn column_field(name: &str, choice: usize, nullable: bool) -> Field {
+    let types = column_types(name).expect("count table columns are in COLUMN_TYPES");
+    Field::new(name, types[choice].clone(), nullable)
+}
+
+/// Every column of a count table with its Arrow field, in output order;
+/// optional columns are there when the first record has their values
+fn count_columns(first: Option<&SequenceRecord>, layout: &Layout) -> Vec<(CountColumn, Field)> {
+    let mut columns: Vec<(CountColumn, Field)> = layout
+        .header_columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (CountColumn::Field(i), column_field(name, 0, false)))
+        .collect();
+    let key_type = match layout.string_type {
+        StringType::Utf8 => 0,
+        StringType::LargeUtf8 => 1,
+    };
+    let count_type = match layout.count_type {
+        CountType::U32 => 0,
+        CountType::U64 => 1,
+    };
+    columns.push((
+        CountColumn::Key,
+        column_field(layout.key_column, key_type, false),
+    ));
+    columns.push((CountColumn::Count, column_field("count", count_type, false)));
+
+    if first.and_then(|r| r.rpm).is_some() {
+        columns.push((CountColumn::Rpm, column_field("rpm", 0, false)));
+    }
+    if first.and_then(|r| r.normalized_count).is_some() {
+        columns.push((
+            CountColumn::NormalizedCount,
+            column_field("normalized_count", 0, false),
+        ));
+    }
+    if first.and_then(|r| r.expected_errors).is_some() {
+        columns.push((
+            CountColumn::ExpectedErrors,
+            column_field("expected_errors", 0, false),
+        ));
+    }
+    if first.and_then(|r| r.chimera).is_some() {
+        columns.push((CountColumn::Chimera, column_field("chimera", 0, false)));
+    }
+    if first.is_some_and(|r| r.annotation.is_some()) {
+        columns.push((CountColumn::Name, column_field("name", 0, true)));
+        columns.push((CountColumn::Matched, column_field("matched", 0, false)));
+        if layout.edit_distance {
+            columns.push((CountColumn::Distance, column_field("distance", 0, true)));
+        }
+    }
+    if layout.sample_column {
+        columns.push((CountColumn::Sample, column_field("sample", 0, false)));
+    }
+    columns
+}
+
+/// A batch of count table rows, with the selected columns
+struct CountRows<'r, 'a> {
+    records: &'r [SequenceRecord<'a>],
+    layout: &'r Layout<'r>,
+    columns: &'r [CountColumn],
+}
+
+impl CountRows<'_, '_> {
+    fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        self.records.iter().map(|r| r.annotation.as_ref().unwrap())
+    }
+}
+
+impl Batch for CountRows<'_, '_> {
+    fn num_rows(&self) -> usize {
+        self.records.len()
+    }
+
+    fn array(&self, column: usize) -> Result<ArrayRef> {
+        let records = self.records;
+        Ok(match self.columns[column] {
+            CountColumn::Field(i) => {
+                let values: StringArray =
+                    records.iter().map(|r| Some(r.header_fields[i])).collect();
+                Arc::new(values)
+            }
+            CountColumn::Key => {
+                let sequences: Vec<&str> = records.iter().map(|r| r.sequence).collect();
+                match self.layout.string_type {
+                    StringType::Utf8 => {
+                        let bytes: usize = sequences.iter().map(|s| s.len()).sum();
+                        if bytes > i32::MAX as usize {
+                            bail!(
+                                "Sequences exceed 2 GiB, too much for utf8; use --string-type large_utf8"
+                            );
+                        }
+                        Arc::new(StringArray::from(sequences))
+                    }
+                    StringType::LargeUtf8 => Arc::new(LargeStringArray::from(sequences)),
+                }
+            }
+            CountColumn::Count => match self.layout.count_type {
+                CountType::U32 => {
+                    let counts = records
+                        .iter()
+                        .map(|r| u32::try_from(r.count))
+                        .collect::<Result<Vec<u32>, _>>()
+                        .context("A count exceeds the u32 range; use --count-type u64")?;
+                    Arc::new(UInt32Array::from(counts))
+                }
+                CountType::U64 => Arc::new(UInt64Array::from_iter_values(
+                    records.iter().map(|r| r.count),
+                )),
+            },
+            CountColumn::Rpm => Arc::new(Float64Array::from_iter_values(
+                records.iter().map(|r| r.rpm.unwrap()),
+            )),
+            CountColumn::NormalizedCount => Arc::new(Float64Array::from_iter_values(
+                records.iter().map(|r| r.normalized_count.unwrap()),
+            )),
+            CountColumn::ExpectedErrors => Arc::new(Float64Array::from_iter_values(
+                records.iter().map(|r| r.expected_errors.unwrap()),
+            )),
+            CountColumn::Chimera => {
+                let chimeras: BooleanArray = records.iter().map(|r| r.chimera).collect();
+                Arc::new(chimeras)
+            }
+            CountColumn::Name => {
+                let names: StringArray = self.annotations().map(|a| a.name.as_deref()).collect();
+                Arc::new(names)
+            }
+            CountColumn::Matched => {
+                let matched: BooleanArray =
+                    self.annotations().map(|a| Some(a.name.is_some())).collect();
+                Arc::new(matched)
+            }
+            CountColumn::Distance => {
+                let distances: UInt32Array = self.annotations().map(|a| a.distance).collect();
+                Arc::new(distances)
+            }
+            CountColumn::Sample => {
+                let sample = self.layout.provenance.sample.as_str();
+                Arc::new(StringArray::from(vec![sample; records.len()]))
+            }
+        })
     }
 
-    // Create RecordBatch
-    let batch =
-        RecordBatch::try_new(schema.clone(), arrays).context("Failed to create RecordBatch")?;
+    // Strings are borrowed from the records, only numbers are formatted
+    fn cell(&self, row: usize, column: usize) -> Cow<'_, str> {
+        let record = &self.records[row];
+        let flag = |value: bool| Cow::Borrowed(if value { "true" } else { "false" });
+        match self.columns[column] {
+            CountColumn::Field(i) => Cow::Borrowed(record.header_fields[i]),
+            CountColumn::Key => Cow::Borrowed(record.sequence),
+            CountColumn::Count => Cow::Owned(record.count.to_string()),
+            CountColumn::Rpm => Cow::Owned(format!("{:.2}", record.rpm.unwrap())),
+            CountColumn::NormalizedCount => {
+                Cow::Owned(format!("{:.2}", record.normalized_count.unwrap()))
+            }
+            CountColumn::ExpectedErrors => {
+                Cow::Owned(format!("{:.4}", record.expected_errors.unwrap()))
+            }
+            CountColumn::Chimera => flag(record.chimera.unwrap()),
+            CountColumn::Name => {
+                let annotation = record.annotation.as_ref().unwrap();
+                Cow::Borrowed(annotation.name.as_deref().unwrap_or_default())
+            }
+            CountColumn::Matched => flag(record.annotation.as_ref().unwrap().name.is_some()),
+            CountColumn::Distance => Cow::Owned(
+                record
+                    .annotation
+                    .as_ref()
+                    .unwrap()
+                    .distance
+                    .map(|d| d.to_string())
+                    .unwrap_or_default(),
+            ),
+            CountColumn::Sample => Cow::Borrowed(&self.layout.provenance.sample),
+        }
+    }
+}
 
+/// Open a Parquet file, written to a temporary path until `finish`ed
+fn create_parquet(
+    output_path: &Path,
+    schema: SchemaRef,
+    compression: &str,
+    metadata: Vec<KeyValue>,
+) -> Result<(PartialOutput, ArrowWriter<File>)> {
     // Configure Parquet writer
-    let file = File::create(output_path)
+    let partial = PartialOutput::new(output_path);
+    let file = File::create(partial.path())
         .with_context(|| format!("Failed to create file: {}", output_path.display()))?;
 
     let compression = match compression.to_lowercase().as_str() {
@@ -113,50 +816,257 @@ fn save_parquet(records: &[SequenceRecord], output_path: &Path, compression: &st
 
     let props = WriterProperties::builder()
         .set_compression(compression)
+        .set_key_value_metadata(Some(metadata))
         .build();
 
-    let mut writer =
+    let writer =
         ArrowWriter::try_new(file, schema, Some(props)).context("Failed to create ArrowWriter")?;
+    Ok((partial, writer))
+}
 
-    writer.write(&batch).context("Failed to write data")?;
-    writer.close().context("Failed to close file")?;
+/// A named output column for tables that are not per-sequence count records
+pub struct Column {
+    pub name: String,
+    pub data: ColumnData,
+}
 
-    Ok(())
+pub enum ColumnData {
+    Text(Vec<String>),
+    Count(Vec<u64>),
+    /// Floating-point values with the number of decimals used in CSV/TSV
+    Float(Vec<f64>, usize),
+    /// Floating-point values written in scientific notation (e.g. p-values)
+    Scientific(Vec<f64>),
 }
 
-fn save_csv(records: &[SequenceRecord], output_path: &Path, delimiter: u8) -> Result<()> {
-    let file = File::create(output_path)
-        .with_context(|| format!("Failed to create file: {}", output_path.display()))?;
+impl Column {
+    pub fn new(name: impl Into<String>, data: ColumnData) -> Self {
+        Self {
+            name: name.into(),
+            data,
+        }
+    }
 
-    // Use larger buffer for better I/O performance
-    let writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, file);
+    fn to_arrow(&self) -> (Field, Arc<dyn arrow::array::Array>) {
+        match &self.data {
+            ColumnData::Text(values) => (
+                Field::new(&self.name, DataType::LargeUtf8, false),
+                Arc::new(LargeStringArray::from_iter_values(values.iter())),
+            ),
+            ColumnData::Count(values) => (
+                Field::new(&self.name, DataType::UInt64, false),
+                Arc::new(UInt64Array::from(values.clone())),
+            ),
+            ColumnData::Float(values, _) | ColumnData::Scientific(values) => (
+                Field::new(&self.name, DataType::Float64, false),
+                Arc::new(Float64Array::from(values.clone())),
+            ),
+        }
+    }
 
-    let mut csv_writer = csv::WriterBuilder::new()
-        .delimiter(delimiter)
-        .buffer_capacity(WRITE_BUFFER_SIZE)
-        .from_writer(writer);
+    fn format_value(&self, row: usize) -> String {
+        match &self.data {
+            ColumnData::Text(values) => values[row].clone(),
+            ColumnData::Count(values) => values[row].to_string(),
+            ColumnData::Float(values, decimals) => format!("{:.*}", decimals, values[row]),
+            ColumnData::Scientific(values) => format!("{:.4e}", values[row]),
+        }
+    }
 
-    // Write header
-    let has_rpm = records.first().and_then(|r| r.rpm).is_some();
-    if has_rpm {
-        csv_writer.write_record(["sequence", "count", "rpm"])?;
-    } else {
-        csv_writer.write_record(["sequence", "count"])?;
+    fn len(&self) -> usize {
+        match &self.data {
+            ColumnData::Text(values) => values.len(),
+            ColumnData::Count(values) => values.len(),
+            ColumnData::Float(values, _) | ColumnData::Scientific(values) => values.len(),
+        }
     }
+}
 
-    // Write data
-    for record in records {
-        if let Some(rpm) = record.rpm {
-            csv_writer.write_record([
-                record.sequence.as_str(),
-                &record.count.to_string(),
-                &format!("{:.2}", rpm),
-            ])?;
-        } else {
-            csv_writer.write_record([record.sequence.as_str(), &record.count.to_string()])?;
+/// Write equal-length columns as a table in the given format
+pub fn save_table(
+    columns: &[Column],
+    output_path: &Path,
+    format: &dyn TableFormat,
+    compression: &str,
+) -> Result<()> {
+    let fields = columns.iter().map(|c| c.to_arrow().0).collect();
+    let options = TableOptions {
+        compression,
+        metadata: provenance::common_metadata(),
+    };
+    let mut writer = format.open(output_path, fields, options)?;
+    writer.write_batch(&ColumnBatch(columns))?;
+    writer.finalize()
+}
+
+/// `Column`s as a batch
+struct ColumnBatch<'a>(&'a [Column]);
+
+impl Batch for ColumnBatch<'_> {
+    fn num_rows(&self) -> usize {
+        self.0.first().map_or(0, Column::len)
+    }
+
+    fn array(&self, column: usize) -> Result<ArrayRef> {
+        Ok(self.0[column].to_arrow().1)
+    }
+
+    fn cell(&self, row: usize, column: usize) -> Cow<'_, str> {
+        Cow::Owned(self.0[column].format_value(row))
+    }
+}
+
+/// A table written a batch of equal-length columns at a time, for tables
+/// too large to build whole; every batch has the same columns
+pub struct TableWriter {
+    output_path: std::path::PathBuf,
+    format: Box<dyn TableFormat>,
+    compression: String,
+    writer: Option<Box<dyn OutputWriter>>,
+}
+
+impl TableWriter {
+    /// Nothing is created until the first batch, which fixes the columns
+    pub fn new(output_path: &Path, format: impl TableFormat + 'static, compression: &str) -> Self {
+        Self {
+            output_path: output_path.to_path_buf(),
+            format: Box::new(format),
+            compression: compression.to_string(),
+            writer: None,
+        }
+    }
+
+    pub fn write(&mut self, columns: &[Column]) -> Result<()> {
+        let writer = match self.writer {
+            Some(ref mut writer) => writer,
+            None => {
+                let fields = columns.iter().map(|c| c.to_arrow().0).collect();
+                let options = TableOptions {
+                    compression: &self.compression,
+                    metadata: provenance::common_metadata(),
+                };
+                self.writer
+                    .insert(self.format.open(&self.output_path, fields, options)?)
+            }
+        };
+        writer.write_batch(&ColumnBatch(columns))
+    }
+
+    /// Close the table and move it into place; a table never written to is
+    /// left uncreated
+    pub fn finish(self) -> Result<()> {
+        match self.writer {
+            Some(writer) => writer.finalize(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Write a merged matrix: key, one count column per sample, then
+/// `<sample>_normalized` columns when normalization was requested
+pub fn save_matrix(
+    matrix: &CountMatrix,
+    output_path: &Path,
+    format: &MatrixFormat,
+    compression: &str,
+) -> Result<()> {
+    let format = match format {
+        MatrixFormat::Parquet => OutputFormat::Parquet,
+        MatrixFormat::Csv => OutputFormat::Csv,
+        MatrixFormat::Tsv => OutputFormat::Tsv,
+        MatrixFormat::Avro => OutputFormat::Avro,
+        MatrixFormat::Orc => OutputFormat::Orc,
+        MatrixFormat::Mtx => return save_matrix_mtx(matrix, output_path),
+        MatrixFormat::H5ad => return crate::h5ad::save_h5ad(matrix, output_path),
+        MatrixFormat::Biom => return crate::biom::save_biom(matrix, output_path),
+    };
+
+    let mut columns = vec![Column::new(
+        &matrix.key_column,
+        ColumnData::Text(matrix.sequences.clone()),
+    )];
+
+    for (sample, counts) in matrix.samples.iter().zip(&matrix.counts) {
+        columns.push(Column::new(sample, ColumnData::Count(counts.clone())));
+    }
+
+    if let Some(ref normalized) = matrix.normalized {
+        for (sample, values) in matrix.samples.iter().zip(normalized) {
+            columns.push(Column::new(
+                format!("{}_normalized", sample),
+                ColumnData::Float(values.clone(), 2),
+            ));
+        }
+    }
+
+    save_table(&columns, output_path, &format, compression)
+}
+
+/// Write the sparse matrix (sequences as rows, samples as columns) in
+/// MatrixMarket coordinate format, with one-name-per-line `sequences.tsv` and
+/// `samples.tsv` next to it. Normalized values go to `<stem>_normalized.mtx`.
+fn save_matrix_mtx(matrix: &CountMatrix, output_path: &Path) -> Result<()> {
+    let n_rows = matrix.sequences.len();
+    let n_cols = matrix.samples.len();
+
+    let nonzero = |column: &Vec<u64>| column.iter().filter(|&&c| c > 0).count();
+    let nnz: usize = matrix.counts.iter().map(nonzero).sum();
+
+    let partial = PartialOutput::new(output_path);
+    let mut writer = create_buffered(partial.path())?;
+    writeln!(writer, "%%MatrixMarket matrix coordinate integer general")?;
+    writeln!(writer, "%metadata generated by seqtable v0.1.1")?;
+    writeln!(writer, "{} {} {}", n_rows, n_cols, nnz)?;
+    for (col, column) in matrix.counts.iter().enumerate() {
+        for (row, &count) in column.iter().enumerate().filter(|(_, c)| **c > 0) {
+            writeln!(writer, "{} {} {}", row + 1, col + 1, count)?;
+        }
+    }
+    writer.flush()?;
+    partial.finish()?;
+
+    if let Some(ref normalized) = matrix.normalized {
+        let stem = output_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("matrix");
+        let normalized_path = output_path.with_file_name(format!("{}_normalized.mtx", stem));
+
+        let partial = PartialOutput::new(&normalized_path);
+        let mut writer = create_buffered(partial.path())?;
+        writeln!(writer, "%%MatrixMarket matrix coordinate real general")?;
+        writeln!(writer, "%metadata generated by seqtable v0.1.1")?;
+        writeln!(writer, "{} {} {}", n_rows, n_cols, nnz)?;
+        for (col, (column, counts)) in normalized.iter().zip(&matrix.counts).enumerate() {
+            for (row, value) in column.iter().enumerate() {
+                if counts[row] > 0 {
+                    writeln!(writer, "{} {} {}", row + 1, col + 1, value)?;
+                }
+            }
         }
+        writer.flush()?;
+        partial.finish()?;
     }
 
-    csv_writer.flush()?;
+    let sidecar = |name: &str, lines: &[String]| -> Result<()> {
+        let path = output_path.with_file_name(name);
+        let partial = PartialOutput::new(&path);
+        let mut writer = create_buffered(partial.path())?;
+        for line in lines {
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()?;
+        partial.finish()?;
+        Ok(())
+    };
+    sidecar("sequences.tsv", &matrix.sequences)?;
+    sidecar("samples.tsv", &matrix.samples)?;
+
     Ok(())
 }
+
+fn create_buffered(path: &Path) -> Result<BufWriter<File>> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create file: {}", path.display()))?;
+    Ok(BufWriter::with_capacity(WRITE_BUFFER_SIZE, file))
+}
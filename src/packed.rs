@@ -0,0 +1,241 @@
+use crate::arena::ArenaCounts;
+use crate::bases;
+use ahash::AHashMap;
+
+/// Longest sequence that fits a packed key (2 bits per base in a u128)
+const MAX_PACKED_LEN: usize = 64;
+
+/// Sequence counts that start out keyed by 2-bit packed integers, which hash
+/// and compare far faster than strings. The first sequence fixes the length;
+/// reads of it with an `N` (or another non-ACGT base) are counted under
+/// string keys beside the packed ones, which they can never equal, while a
+/// read of another length switches the counter permanently to string keys.
+pub struct SequenceCounter {
+    packed: Option<PackedCounts>,
+    strings: ArenaCounts,
+}
+
+struct PackedCounts {
+    len: usize,
+    counts: AHashMap<u128, u64>,
+}
+
+impl SequenceCounter {
+    pub fn new() -> Self {
+        Self {
+            packed: None,
+            strings: ArenaCounts::new(),
+        }
+    }
+
+    pub fn add(&mut self, seq: &[u8]) {
+        if self.packed.is_none() && self.strings.is_empty() && seq.len() <= MAX_PACKED_LEN {
+            self.packed = Some(PackedCounts {
+                len: seq.len(),
+                counts: AHashMap::new(),
+            });
+        }
+
+        if let Some(ref mut packed) = self.packed {
+            if seq.len() == packed.len {
+                match pack(seq) {
+                    Some(key) => *packed.counts.entry(key).or_insert(0) += 1,
+                    None => self.strings.add(seq),
+                }
+                return;
+            }
+            self.unpack();
+        }
+        self.strings.add(seq);
+    }
+
+    /// Combine two counters, staying packed only if both are packed at the same length
+    pub fn merge(mut self, mut other: Self) -> Self {
+        // An empty side has nothing to contribute either way
+        if other.is_empty() {
+            return self;
+        }
+        if self.is_empty() {
+            return other;
+        }
+        if let (Some(a), Some(b)) = (&mut self.packed, &mut other.packed) {
+            if a.len == b.len {
+                for (key, count) in b.counts.drain() {
+                    *a.counts.entry(key).or_insert(0) += count;
+                }
+                self.strings = std::mem::take(&mut self.strings).merge(other.strings);
+                return self;
+            }
+        }
+
+        self.unpack();
+        other.unpack();
+        self.strings = std::mem::take(&mut self.strings).merge(other.strings);
+        self
+    }
+
+    /// Read length when every sequence had it, so ACGT reads were counted on
+    /// the packed fast path
+    pub fn packed_len(&self) -> Option<usize> {
+        self.packed.as_ref().map(|p| p.len)
+    }
+
+    pub fn into_counts(self) -> ArenaCounts {
+        let mut counts = self.strings;
+        if let Some(packed) = self.packed {
+            let mut seq = [0u8; MAX_PACKED_LEN];
+            for (key, count) in packed.counts {
+                counts.add_count(unpack(key, &mut seq[..packed.len]), count);
+            }
+        }
+        counts
+    }
+
+    fn is_empty(&self) -> bool {
+        self.strings.is_empty() && self.packed.as_ref().is_none_or(|p| p.counts.is_empty())
+    }
+
+    /// Move packed counts to string keys
+    fn unpack(&mut self) {
+        if let Some(packed) = self.packed.take() {
+            let mut seq = [0u8; MAX_PACKED_LEN];
+            for (key, count) in packed.counts {
+                self.strings
+                    .add_count(unpack(key, &mut seq[..packed.len]), count);
+            }
+        }
+    }
+}
+
+impl Default for SequenceCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Validated first, so the packing itself is arithmetic on the ASCII bits
+/// rather than a per-base `match`
+fn pack(seq: &[u8]) -> Option<u128> {
+    bases::is_acgt(seq).then(|| {
+        seq.iter().fold(0u128, |key, &base| {
+            (key << 2) | u128::from(bases::code(base))
+        })
+    })
+}
+
+/// Write the bases of `key` into `seq`, which sets the length
+fn unpack(key: u128, seq: &mut [u8]) -> &[u8] {
+    let len = seq.len();
+    for (i, base) in seq.iter_mut().enumerate() {
+        let shift = 2 * (len - 1 - i);
+        *base = b"ACGT"[((key >> shift) & 0b11) as usize];
+    }
+    seq
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(counter: SequenceCounter) -> Vec<(String, u64)> {
+        let counts = counter.into_counts();
+        let mut rows: Vec<_> = counts.iter().map(|(key, n)| (key.to_string(), n)).collect();
+        rows.sort();
+        rows
+    }
+
+    fn read(len: usize, offset: usize) -> Vec<u8> {
+        (0..len).map(|i| b"ACGT"[(i * 7 + offset) % 4]).collect()
+    }
+
+    #[test]
+    fn sequences_round_trip_through_packed_keys_at_every_width() {
+        for len in [1, 63, 64] {
+            let (a, b) = (read(len, 0), read(len, 1));
+            assert_eq!(unpack(pack(&a).unwrap(), &mut vec![0; len]), &a[..]);
+
+            let mut counter = SequenceCounter::new();
+            for seq in [&a, &b, &a] {
+                counter.add(seq);
+            }
+            assert_eq!(counter.packed_len(), Some(len));
+            let mut expected = vec![
+                (String::from_utf8(a).unwrap(), 2),
+                (String::from_utf8(b).unwrap(), 1),
+            ];
+            expected.sort();
+            assert_eq!(sorted(counter), expected);
+        }
+
+        // One base past a u128 is counted by string from the start
+        let long = read(65, 0);
+        let mut counter = SequenceCounter::new();
+        counter.add(&long);
+        counter.add(&long);
+        assert_eq!(counter.packed_len(), None);
+        assert_eq!(sorted(counter), [(String::from_utf8(long).unwrap(), 2)]);
+    }
+
+    #[test]
+    fn reads_with_n_or_lowercase_are_counted_beside_packed_ones() {
+        let mut counter = SequenceCounter::new();
+        for seq in [&b"ACGT"[..], b"ACNT", b"acgt", b"ACGT", b"ACNT"] {
+            counter.add(seq);
+        }
+        assert_eq!(counter.packed_len(), Some(4));
+        assert_eq!(
+            sorted(counter),
+            [
+                ("ACGT".to_string(), 2),
+                ("ACNT".to_string(), 2),
+                ("acgt".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_read_of_another_length_falls_back_to_string_keys() {
+        let mut counter = SequenceCounter::new();
+        for seq in [&b"ACGT"[..], b"ACGT", b"ACGTA", b"ACGT"] {
+            counter.add(seq);
+        }
+        assert_eq!(counter.packed_len(), None);
+        assert_eq!(
+            sorted(counter),
+            [("ACGT".to_string(), 3), ("ACGTA".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn merging_stays_packed_only_at_one_length() {
+        let side = |seqs: &[&[u8]]| {
+            let mut counter = SequenceCounter::new();
+            for seq in seqs {
+                counter.add(seq);
+            }
+            counter
+        };
+
+        let merged = side(&[b"AAAA", b"CCNC"]).merge(side(&[b"AAAA", b"GGGG"]));
+        assert_eq!(merged.packed_len(), Some(4));
+        assert_eq!(
+            sorted(merged),
+            [
+                ("AAAA".to_string(), 2),
+                ("CCNC".to_string(), 1),
+                ("GGGG".to_string(), 1),
+            ]
+        );
+
+        let merged = side(&[b"AAAA"]).merge(side(&[b"AAAAAA", b"AAAA"]));
+        assert_eq!(merged.packed_len(), None);
+        assert_eq!(
+            sorted(merged),
+            [("AAAA".to_string(), 2), ("AAAAAA".to_string(), 1)]
+        );
+
+        let merged = SequenceCounter::new().merge(side(&[b"TTT"]));
+        assert_eq!(merged.packed_len(), Some(3));
+        assert_eq!(sorted(merged), [("TTT".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,346 @@
+use crate::bases::reverse_complement;
+use crate::input::Input;
+use anyhow::{Context, Result, bail};
+use needletail::FastxReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Matched bases outweigh mismatched ones this many times over when
+/// choosing among overlaps
+const MISMATCH_PENALTY: i64 = 4;
+
+/// Overlapping bases per mismatch allowed, however many `max_diffs` allows,
+/// so short overlaps cannot match by chance
+const BASES_PER_DIFF: usize = 10;
+
+/// The R2 input an R1 input is merged with for `--merge-pairs`, how, and
+/// what came of it
+pub struct Mate {
+    pub input: Input,
+    pub options: MergeOptions,
+    pub stats: Arc<MergeStats>,
+}
+
+/// How mates are overlapped
+#[derive(Debug, Clone, Copy)]
+pub struct MergeOptions {
+    /// Shortest overlap accepted
+    pub min_overlap: usize,
+    /// Mismatched bases allowed in the overlap (and at most one per
+    /// `BASES_PER_DIFF` overlapping bases)
+    pub max_diffs: usize,
+}
+
+/// Pairs read, and of those, pairs merged
+#[derive(Default)]
+pub struct MergeStats {
+    pub pairs: AtomicU64,
+    pub merged: AtomicU64,
+}
+
+impl Mate {
+    /// Merge the R1 input this is set on with `r2`
+    pub fn new(r2: Input, options: MergeOptions) -> Self {
+        Self {
+            input: r2,
+            options,
+            stats: Arc::new(MergeStats::default()),
+        }
+    }
+}
+
+/// `stem` without its R1 tag (`s1_R1` → `s1`, `s1_S1_L001_R1_001` →
+/// `s1_S1_L001_001`, `s1_1` → `s1`), naming the pair's sample
+pub fn sample_stem(stem: &str) -> String {
+    for tag in ["_R1_", ".R1."] {
+        if let Some((head, tail)) = stem.rsplit_once(tag) {
+            return format!("{}{}{}", head, &tag[..1], tail);
+        }
+    }
+    for tag in ["_R1", ".R1", "_1", ".1"] {
+        if let Some(head) = stem.strip_suffix(tag) {
+            return head.to_string();
+        }
+    }
+    stem.to_string()
+}
+
+/// Read name without its comment or `/1`, `/2` mate suffix
+fn pair_name(id: &[u8]) -> &[u8] {
+    let name_end = id
+        .iter()
+        .position(|b| b.is_ascii_whitespace())
+        .unwrap_or(id.len());
+    let name = &id[..name_end];
+    name.strip_suffix(b"/1")
+        .or_else(|| name.strip_suffix(b"/2"))
+        .unwrap_or(name)
+}
+
+/// Reads of two mate files merged pair by pair; pairs that do not overlap
+/// are passed over, and counted in the stats
+pub struct PairedReader {
+    r1: Box<dyn FastxReader>,
+    r2: Box<dyn FastxReader>,
+    r2_path: PathBuf,
+    options: MergeOptions,
+    stats: Arc<MergeStats>,
+    id: Vec<u8>,
+    seq: Vec<u8>,
+    qual: Vec<u8>,
+}
+
+impl PairedReader {
+    pub fn new(
+        r1: Box<dyn FastxReader>,
+        r2: Box<dyn FastxReader>,
+        r2_path: &Path,
+        options: MergeOptions,
+        stats: Arc<MergeStats>,
+    ) -> Self {
+        Self {
+            r1,
+            r2,
+            r2_path: r2_path.to_path_buf(),
+            options,
+            stats,
+            id: Vec::new(),
+            seq: Vec::new(),
+            qual: Vec::new(),
+        }
+    }
+
+    /// Pass the next merged read's id (R1's), sequence and qualities to
+    /// `emit`; `false` once both files end
+    pub fn next_record(&mut self, emit: impl FnOnce(&[u8], &[u8], Option<&[u8]>)) -> Result<bool> {
+        loop {
+            let (first, second) = match (self.r1.next(), self.r2.next()) {
+                (None, None) => return Ok(false),
+                (Some(first), Some(second)) => (
+                    first.context("Failed to read record")?,
+                    second.with_context(|| {
+                        format!("Failed to read record of {}", self.r2_path.display())
+                    })?,
+                ),
+                (Some(_), None) => bail!(
+                    "{} has fewer reads than its R1 file; mates are out of step",
+                    self.r2_path.display()
+                ),
+                (None, Some(_)) => bail!(
+                    "{} has more reads than its R1 file; mates are out of step",
+                    self.r2_path.display()
+                ),
+            };
+            if pair_name(first.id()) != pair_name(second.id()) {
+                bail!(
+                    "Mates out of step: R1 read {} is paired with {} in {}",
+                    String::from_utf8_lossy(first.id()),
+                    String::from_utf8_lossy(second.id()),
+                    self.r2_path.display()
+                );
+            }
+            let (Some(qual1), Some(qual2)) = (first.qual(), second.qual()) else {
+                bail!("--merge-pairs needs FASTQ mates with qualities");
+            };
+            self.stats.pairs.fetch_add(1, Ordering::Relaxed);
+            let merged = merge(
+                (&first.seq(), qual1),
+                (&second.seq(), qual2),
+                self.options,
+                &mut self.seq,
+                &mut self.qual,
+            );
+            if merged {
+                self.stats.merged.fetch_add(1, Ordering::Relaxed);
+                self.id.clear();
+                self.id.extend_from_slice(first.id());
+                emit(&self.id, &self.seq, Some(&self.qual));
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Align R1 with R2's reverse complement and write the merged read to `seq`
+/// and `qual`. R2's reverse complement may start along R1 (the mates overlap
+/// end to start) or before R1 starts (the fragment is shorter than the
+/// reads, which read through into the adapters: a dovetail); either way the
+/// merged read runs from R1's start to the end of R2's reverse complement,
+/// so read-through past the fragment is trimmed. Of the alignments
+/// overlapping long enough and within the mismatches allowed, the best
+/// scoring (matches less a penalty per mismatch) wins. Where the mates agree
+/// the higher quality is kept; where they disagree, the better-scored base,
+/// with the lower of the two scores. `false`, leaving `seq` and `qual`
+/// unspecified, if no alignment qualifies.
+fn merge(
+    (seq1, qual1): (&[u8], &[u8]),
+    (seq2, qual2): (&[u8], &[u8]),
+    options: MergeOptions,
+    seq: &mut Vec<u8>,
+    qual: &mut Vec<u8>,
+) -> bool {
+    let rc_seq = reverse_complement(seq2);
+    let rc_qual: Vec<u8> = qual2.iter().rev().copied().collect();
+
+    // `shift` is where R2's reverse complement starts along R1, negative for
+    // a dovetail; the overlap is `start..end` along R1
+    let (len1, len2) = (seq1.len() as isize, rc_seq.len() as isize);
+    let min_overlap = options.min_overlap as isize;
+    let mut best: Option<(i64, isize)> = None;
+    for shift in (min_overlap - len2..=len1 - min_overlap).rev() {
+        let (start, end) = (shift.max(0), len1.min(shift + len2));
+        let overlap = (end - start).max(0) as usize;
+        if overlap < options.min_overlap {
+            continue;
+        }
+        let tail = &seq1[start as usize..end as usize];
+        let head = &rc_seq[(start - shift) as usize..(end - shift) as usize];
+        let max_diffs = options.max_diffs.min(overlap / BASES_PER_DIFF);
+        let mut diffs = 0;
+        for (a, b) in tail.iter().zip(head) {
+            if !a.eq_ignore_ascii_case(b) {
+                diffs += 1;
+                if diffs > max_diffs {
+                    break;
+                }
+            }
+        }
+        if diffs > max_diffs {
+            continue;
+        }
+        let score = (overlap - diffs) as i64 - MISMATCH_PENALTY * diffs as i64;
+        if best.is_none_or(|(best_score, _)| score > best_score) {
+            best = Some((score, shift));
+        }
+    }
+    let Some((_, shift)) = best else {
+        return false;
+    };
+
+    let (start, end) = (shift.max(0) as usize, len1.min(shift + len2) as usize);
+    let offset = (start as isize - shift) as usize;
+    seq.clear();
+    qual.clear();
+    seq.extend_from_slice(&seq1[..start]);
+    qual.extend_from_slice(&qual1[..start]);
+    for i in 0..end - start {
+        let (b1, q1) = (seq1[start + i], qual1[start + i]);
+        let (b2, q2) = (rc_seq[offset + i], rc_qual[offset + i]);
+        if b1.eq_ignore_ascii_case(&b2) {
+            seq.push(b1);
+            qual.push(q1.max(q2));
+        } else {
+            seq.push(if q1 >= q2 { b1 } else { b2 });
+            qual.push(q1.min(q2));
+        }
+    }
+    seq.extend_from_slice(&rc_seq[offset + end - start..]);
+    qual.extend_from_slice(&rc_qual[offset + end - start..]);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bases::complement;
+
+    const OPTIONS: MergeOptions = MergeOptions {
+        min_overlap: 10,
+        max_diffs: 10,
+    };
+
+    /// A fragment with no repeats long enough to align by chance
+    const FRAGMENT: &[u8] = b"ACGGTCATTGCAAGCTTGACCTAGGATCCATGAACGTTAGCCTGAAAGTC";
+
+    /// Merge of R1 `seq1` and R2 `seq2`, with every base scored `quality`
+    fn merged(seq1: &[u8], seq2: &[u8], quality: u8) -> Option<Vec<u8>> {
+        let (qual1, qual2) = (vec![quality; seq1.len()], vec![quality; seq2.len()]);
+        let (mut seq, mut qual) = (Vec::new(), Vec::new());
+        merge((seq1, &qual1), (seq2, &qual2), OPTIONS, &mut seq, &mut qual).then_some(seq)
+    }
+
+    #[test]
+    fn overlapping_mates_cover_the_fragment() {
+        let r1 = &FRAGMENT[..30];
+        let r2 = reverse_complement(&FRAGMENT[15..]);
+        assert_eq!(merged(r1, &r2, b'I').as_deref(), Some(FRAGMENT));
+    }
+
+    #[test]
+    fn dovetailed_mates_are_trimmed_to_the_fragment() {
+        // Reads longer than the fragment run on into their adapters
+        let fragment = &FRAGMENT[..24];
+        let r1 = [fragment, b"AGATCGGAAGAGC"].concat();
+        let r2 = [reverse_complement(fragment).as_slice(), b"AGATCGGAAGAGC"].concat();
+        assert_eq!(merged(&r1, &r2, b'I').as_deref(), Some(fragment));
+    }
+
+    #[test]
+    fn staggered_mates_keep_the_fragment_only() {
+        // R1 reads past the end of the fragment R2 starts at
+        let fragment = &FRAGMENT[..40];
+        let r1 = [fragment, b"AGATCGG"].concat();
+        let r2 = reverse_complement(&fragment[5..]);
+        assert_eq!(merged(&r1, &r2, b'I').as_deref(), Some(fragment));
+    }
+
+    #[test]
+    fn mismatches_take_the_better_scored_base() {
+        let r1 = FRAGMENT[..30].to_vec();
+        let mut r2 = reverse_complement(&FRAGMENT[15..]);
+        // Position 20 of the fragment, read wrongly by R2
+        let at = r2.len() - 1 - (20 - 15);
+        r2[at] = complement(b'T');
+        let qual1 = vec![b'I'; r1.len()];
+        let mut qual2 = vec![b'I'; r2.len()];
+        qual2[at] = b'#';
+        let (mut seq, mut qual) = (Vec::new(), Vec::new());
+        assert!(merge(
+            (&r1, &qual1),
+            (&r2, &qual2),
+            OPTIONS,
+            &mut seq,
+            &mut qual
+        ));
+        assert_eq!(seq, FRAGMENT);
+        assert_eq!(qual[20], b'#');
+    }
+
+    #[test]
+    fn an_input_with_a_mate_reads_merged_pairs() {
+        let dir = std::env::temp_dir().join(format!("seqtable-mates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (r1, r2) = (dir.join("s_R1.fq"), dir.join("s_R2.fq"));
+        let r2_seq = reverse_complement(&FRAGMENT[15..]);
+        let fastq = |seq: &[u8]| {
+            let seq = String::from_utf8_lossy(seq);
+            format!("@p1\n{}\n+\n{}\n", seq, "I".repeat(seq.len()))
+        };
+        std::fs::write(&r1, fastq(&FRAGMENT[..30])).unwrap();
+        std::fs::write(&r2, fastq(&r2_seq)).unwrap();
+
+        let input = Input::new(&r1).with_mate(Mate::new(Input::new(&r2), OPTIONS));
+        // Mates are read in step, so never from one memory map
+        assert!(!crate::mapped::is_mappable(&input).unwrap());
+        let (mut records, _) = crate::input::open_records(&input, Default::default()).unwrap();
+        let mut seqs = Vec::new();
+        while records
+            .next_record(|_, seq, _| seqs.push(seq.to_vec()))
+            .unwrap()
+        {}
+        assert_eq!(seqs, [FRAGMENT]);
+        let stats = &input.mate().unwrap().stats;
+        assert_eq!(stats.pairs.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.merged.load(Ordering::Relaxed), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unrelated_mates_do_not_merge() {
+        let r1 = &FRAGMENT[..25];
+        let r2 = &FRAGMENT[25..];
+        assert_eq!(merged(r1, r2, b'I'), None);
+    }
+}
@@ -0,0 +1,210 @@
+use clap::ValueEnum;
+use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
+use serde_json::{Value, json};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Byte bar layout shared by single- and multi-file runs; `{msg}` carries the
+/// read rate, which indicatif cannot derive from a byte position
+const BYTES_TEMPLATE: &str = "[{bar:40.cyan/blue}] {decimal_bytes}/{decimal_total_bytes} ({percent}%) \
+                              {decimal_bytes_per_sec}, {msg}, ETA {eta}";
+
+/// Layout for inputs of unknown size (pipes): bytes read so far, no bar
+const STREAM_TEMPLATE: &str = "{decimal_bytes} read, {decimal_bytes_per_sec}, {msg}, {elapsed}";
+
+/// Least time between two progress events of a JSON-reporting run
+const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Set once a run reports progress as JSON events
+static JSON_EVENTS: AtomicBool = AtomicBool::new(false);
+
+/// Set once a run's status lines are turned off (`--quiet`)
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// When the last progress event went out
+static LAST_EVENT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// How a run reports its progress on stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressFormat {
+    /// Progress bars and human-readable status lines
+    Bar,
+    /// One JSON object per line (NDJSON) instead, for GUIs and workflow engines
+    Json,
+}
+
+/// Report progress as JSON events from here on
+pub fn enable_json_events() {
+    JSON_EVENTS.store(true, Ordering::Relaxed);
+}
+
+pub fn json_events() -> bool {
+    JSON_EVENTS.load(Ordering::Relaxed)
+}
+
+/// Leave out warnings from here on, unless they go out as JSON events
+pub fn set_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+}
+
+/// A warning about `path` raised away from the status lines (a read being
+/// retried, say): a `warning` event, or a line unless the run is quiet
+pub fn warning(path: &Path, message: &str) {
+    if json_events() {
+        emit(
+            "warning",
+            json!({ "file": path.display().to_string(), "message": message }),
+        );
+    } else if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("⚠️  {}: {}", path.display(), message);
+    }
+}
+
+/// Write one NDJSON event to stderr, if the run reports JSON events
+pub fn emit(event: &str, fields: Value) {
+    if !json_events() {
+        return;
+    }
+    // `event` leads the line, ahead of the (alphabetized) fields
+    let fields = fields.to_string();
+    match fields.strip_prefix('{') {
+        Some("}") | None => eprintln!("{{\"event\":{}}}", Value::from(event)),
+        Some(rest) => eprintln!("{{\"event\":{},{}", Value::from(event), rest),
+    }
+}
+
+/// Hidden byte bar whose position `set_read_rate` turns into progress
+/// events for `path`
+pub fn event_bar(path: &Path, total_bytes: Option<u64>) -> ProgressBar {
+    let bar = ProgressBar::hidden();
+    if let Some(total_bytes) = total_bytes {
+        bar.set_length(total_bytes);
+    }
+    bar.set_prefix(path.display().to_string());
+    bar
+}
+
+/// Progress bar over the bytes of one input file; a spinner if its size is
+/// unknown
+pub fn byte_bar(total_bytes: Option<u64>) -> ProgressBar {
+    let bar = new_bar(total_bytes);
+    bar.set_style(byte_style("   {spinner:.green} ", total_bytes));
+    set_read_rate(&bar, 0);
+    bar
+}
+
+fn new_bar(total_bytes: Option<u64>) -> ProgressBar {
+    match total_bytes {
+        Some(total_bytes) => ProgressBar::new(total_bytes),
+        None => ProgressBar::new_spinner(),
+    }
+}
+
+fn byte_style(lead: &str, total_bytes: Option<u64>) -> ProgressStyle {
+    let template = if total_bytes.is_some() {
+        BYTES_TEMPLATE
+    } else {
+        STREAM_TEMPLATE
+    };
+    ProgressStyle::default_bar()
+        .template(&format!("{}{}", lead, template))
+        .unwrap()
+        .progress_chars("#>-")
+}
+
+/// Show the read rate so far on a byte bar
+pub fn set_read_rate(bar: &ProgressBar, reads: u64) {
+    let secs = bar.elapsed().as_secs_f64();
+    let rate = if secs > 0.0 { reads as f64 / secs } else { 0.0 };
+    bar.set_message(format!("{:.0} reads/s", rate));
+    if json_events() {
+        progress_event(bar, reads);
+    }
+}
+
+/// Emit a progress event for an event bar, at most one per `EVENT_INTERVAL`
+fn progress_event(bar: &ProgressBar, reads: u64) {
+    {
+        let mut last = LAST_EVENT.lock().unwrap();
+        if last.is_some_and(|time| time.elapsed() < EVENT_INTERVAL) {
+            return;
+        }
+        *last = Some(Instant::now());
+    }
+    let total = bar.length();
+    let bytes = bar.position();
+    emit(
+        "progress",
+        json!({
+            "file": bar.prefix(),
+            "records": reads,
+            "bytes": bytes,
+            "total_bytes": total,
+            "percent": total.map(|total| if total > 0 { bytes as f64 * 100.0 / total as f64 } else { 0.0 }),
+            "reads_per_sec": reads as f64 / bar.elapsed().as_secs_f64().max(f64::EPSILON),
+        }),
+    );
+}
+
+/// Reads and input megabytes (10^6 bytes, as read from disk) per second
+pub fn throughput(reads: u64, bytes: u64, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    format!(
+        "{:.0} reads/s, {:.1} MB/s",
+        reads as f64 / secs,
+        bytes as f64 / 1e6 / secs
+    )
+}
+
+/// Progress display for multi-file runs: an overall bar counting finished
+/// files, one bar per in-flight file, and a one-line summary per file printed
+/// above the bars in place of the per-file detail
+pub struct BatchProgress {
+    multi: MultiProgress,
+    overall: ProgressBar,
+}
+
+impl BatchProgress {
+    pub fn new(n_files: usize) -> Self {
+        let multi = MultiProgress::new();
+        let overall = multi.add(ProgressBar::new(n_files as u64));
+        overall.set_style(
+            ProgressStyle::default_bar()
+                .template("   📁 [{bar:40.green/white}] {pos}/{len} files ({elapsed})")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        Self { multi, overall }
+    }
+
+    /// Byte-progress bar for one input, shown above the overall bar
+    pub fn file_bar(&self, path: &Path, total_bytes: Option<u64>) -> ProgressBar {
+        let bar = self
+            .multi
+            .insert_before(&self.overall, new_bar(total_bytes))
+            // A file that fails drops its bar rather than finishing it
+            .with_finish(ProgressFinish::AndClear);
+        bar.set_style(byte_style("   {spinner:.green} {prefix:20!} ", total_bytes));
+        set_read_rate(&bar, 0);
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        bar.set_prefix(name.to_string_lossy().into_owned());
+        bar
+    }
+
+    /// Print a line above the bars without disturbing them
+    pub fn println(&self, line: &str) {
+        self.multi.suspend(|| eprintln!("{}", line));
+    }
+
+    /// Print a file's summary line and advance the overall bar
+    pub fn finish_file(&self, line: &str) {
+        self.println(line);
+        self.overall.inc(1);
+    }
+
+    pub fn finish(&self) {
+        self.overall.finish_and_clear();
+    }
+}
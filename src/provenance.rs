@@ -0,0 +1,164 @@
+use crate::output::{Filtered, SortOrder};
+use crate::quality::Encoding;
+use crate::schema::SCHEMA_VERSION;
+use crate::template;
+use parquet::file::metadata::KeyValue;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Parquet key-value metadata key holding the sample name
+pub const SAMPLE_KEY: &str = "seqtable.sample";
+
+/// Parquet key-value metadata key holding the row order (`SortOrder::name`)
+pub const SORT_KEY: &str = "seqtable.sort";
+
+/// Parquet key-value metadata key holding the count table `SCHEMA_VERSION`
+pub const SCHEMA_KEY: &str = "seqtable.schema_version";
+
+/// Parquet key-value metadata key holding the `--rename` pairs applied to
+/// the table's columns, as comma-separated `OLD=NEW`
+pub const RENAME_KEY: &str = "seqtable.renamed";
+
+/// Where a count table came from, written into its Parquet footer
+#[derive(Clone)]
+pub struct Provenance {
+    pub sample: String,
+    pub input: String,
+    /// Hex digests of the input file as stored on disk (compressed bytes), when computed
+    pub input_md5: Option<String>,
+    pub input_sha256: Option<String>,
+    /// FASTQ quality encoding, absent for FASTA
+    pub quality_encoding: Option<Encoding>,
+    pub total_reads: u64,
+    /// `--key` spec the sequences were cut down by
+    pub key: Option<String>,
+    /// `--mask` ranges set to N before keying (comma-separated)
+    pub mask: Option<String>,
+    /// `--minimizers` K,W counted in place of sequences
+    pub minimizers: Option<String>,
+    /// `--window` L,S counted in place of sequences
+    pub window: Option<String>,
+    /// `--seed-pattern` the minimizers were compared under
+    pub seed_pattern: Option<String>,
+    /// `--amplicon` primers (`FWD,REV`) whose inserts were counted
+    pub amplicon: Option<String>,
+    /// `--orient` reference reads were oriented to
+    pub orient: Option<String>,
+    /// `--contaminants` sources reads were screened against (comma-separated)
+    pub contaminants: Option<String>,
+    /// Illumina header fields split by, with their observed values (comma-separated)
+    pub header_fields: Vec<(&'static str, String)>,
+    /// What the count filters left out of the table
+    pub filtered: Option<Filtered>,
+    pub sort: SortOrder,
+}
+
+impl Provenance {
+    /// Footer entries of a count table, after those of every seqtable table
+    pub fn metadata(&self) -> Vec<KeyValue> {
+        let mut metadata = common_metadata();
+        metadata.push(entry(SCHEMA_KEY, &SCHEMA_VERSION.to_string()));
+        metadata.push(entry(SAMPLE_KEY, &self.sample));
+        metadata.push(entry("seqtable.input", &self.input));
+        if let Some(ref md5) = self.input_md5 {
+            metadata.push(entry("seqtable.input_md5", md5));
+        }
+        if let Some(ref sha256) = self.input_sha256 {
+            metadata.push(entry("seqtable.input_sha256", sha256));
+        }
+        if let Some(encoding) = self.quality_encoding {
+            metadata.push(entry(
+                "seqtable.phred_offset",
+                &encoding.offset().to_string(),
+            ));
+        }
+        metadata.push(entry("seqtable.total_reads", &self.total_reads.to_string()));
+        if let Some(ref key) = self.key {
+            metadata.push(entry("seqtable.key", key));
+        }
+        if let Some(ref mask) = self.mask {
+            metadata.push(entry("seqtable.mask", mask));
+        }
+        if let Some(ref minimizers) = self.minimizers {
+            metadata.push(entry("seqtable.minimizers", minimizers));
+        }
+        if let Some(ref window) = self.window {
+            metadata.push(entry("seqtable.window", window));
+        }
+        if let Some(ref pattern) = self.seed_pattern {
+            metadata.push(entry("seqtable.seed_pattern", pattern));
+        }
+        if let Some(ref amplicon) = self.amplicon {
+            metadata.push(entry("seqtable.amplicon", amplicon));
+        }
+        if let Some(ref orient) = self.orient {
+            metadata.push(entry("seqtable.orient", orient));
+        }
+        if let Some(ref contaminants) = self.contaminants {
+            metadata.push(entry("seqtable.contaminants", contaminants));
+        }
+        if let Some(filtered) = self.filtered {
+            metadata.push(entry(
+                "seqtable.filtered_sequences",
+                &filtered.sequences.to_string(),
+            ));
+            metadata.push(entry(
+                "seqtable.filtered_reads",
+                &filtered.reads.to_string(),
+            ));
+        }
+        metadata.push(entry(SORT_KEY, self.sort.name()));
+        for (field, values) in &self.header_fields {
+            metadata.push(entry(&format!("seqtable.illumina.{}", field), values));
+        }
+        metadata
+    }
+}
+
+/// Version, command line, and creation time, written into every Parquet table
+pub fn common_metadata() -> Vec<KeyValue> {
+    vec![
+        entry("seqtable.version", env!("CARGO_PKG_VERSION")),
+        entry("seqtable.command", &command_line()),
+        entry("seqtable.created", &timestamp()),
+    ]
+}
+
+fn entry(key: &str, value: &str) -> KeyValue {
+    KeyValue::new(key.to_string(), value.to_string())
+}
+
+/// The invocation, with arguments quoted where a shell would need it
+fn command_line() -> String {
+    std::env::args()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"-_./=:,+@%".contains(&b));
+            if plain {
+                arg
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Current UTC time as RFC 3339 (e.g. 2024-05-01T12:00:00Z)
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (year, month, day) = template::civil_from_days((secs / 86_400) as i64);
+    let time = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
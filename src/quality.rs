@@ -0,0 +1,265 @@
+use crate::input::{self, Input};
+use ahash::AHashMap;
+use clap::ValueEnum;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Records whose quality strings are scanned to detect the encoding
+const DETECT_RECORDS: usize = 10_000;
+
+/// Lowest quality character of Phred+64 data (Solexa scores go down to -5)
+const PHRED64_MIN: u8 = b';';
+
+/// Highest quality character Illumina writes in Phred+33 (Q42)
+const PHRED33_MAX: u8 = b'K';
+
+/// Quality encoding to assume, or `auto` to detect it from the file
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum PhredOffset {
+    #[default]
+    Auto,
+    #[value(name = "33")]
+    Phred33,
+    #[value(name = "64")]
+    Phred64,
+}
+
+/// ASCII offset of FASTQ quality scores
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// Sanger and Illumina 1.8+
+    Phred33,
+    /// Illumina 1.3 to 1.7 (and Solexa)
+    Phred64,
+}
+
+impl Encoding {
+    pub fn offset(&self) -> u8 {
+        match self {
+            Encoding::Phred33 => 33,
+            Encoding::Phred64 => 64,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Phred33 => "Phred+33",
+            Encoding::Phred64 => "Phred+64",
+        }
+    }
+}
+
+/// The quality encoding of a FASTQ input
+#[derive(Clone, Copy)]
+pub struct Detection {
+    pub encoding: Encoding,
+    /// The scanned qualities fit both encodings, so Phred+33 was assumed
+    pub ambiguous: bool,
+    /// Given with `--phred-offset` but the qualities say otherwise
+    pub conflicts: bool,
+}
+
+/// The encoding of `path` as set by `offset`, checking it against the
+/// qualities of the first records. `None` for FASTA (or unreadable) inputs.
+pub fn resolve(input: &Input, offset: PhredOffset) -> Option<Detection> {
+    let (min, max) = quality_range(input)?;
+    let detected = if min < PHRED64_MIN {
+        // Only Phred+33 goes below Phred+64's lowest score
+        Some(Encoding::Phred33)
+    } else if max > PHRED33_MAX {
+        Some(Encoding::Phred64)
+    } else {
+        None
+    };
+    let detection = match offset {
+        PhredOffset::Auto => Detection {
+            encoding: detected.unwrap_or(Encoding::Phred33),
+            ambiguous: detected.is_none(),
+            conflicts: false,
+        },
+        PhredOffset::Phred33 | PhredOffset::Phred64 => {
+            let encoding = if offset == PhredOffset::Phred33 {
+                Encoding::Phred33
+            } else {
+                Encoding::Phred64
+            };
+            Detection {
+                encoding,
+                ambiguous: false,
+                conflicts: detected.is_some_and(|detected| detected != encoding),
+            }
+        }
+    };
+    Some(detection)
+}
+
+/// Lowest and highest quality characters among the first records
+fn quality_range(input: &Input) -> Option<(u8, u8)> {
+    let mut reader = input::peek_fastx(input).ok()?;
+    let (mut min, mut max) = (u8::MAX, u8::MIN);
+    for _ in 0..DETECT_RECORDS {
+        // Read errors are left for the counting pass to report
+        let Some(Ok(record)) = reader.next() else {
+            break;
+        };
+        let qual = record.qual()?;
+        for &byte in qual {
+            min = min.min(byte);
+            max = max.max(byte);
+        }
+    }
+    (min <= max).then_some((min, max))
+}
+
+/// Warning for an encoding that had to be assumed or contradicts the qualities
+pub fn warning(detection: &Detection) -> Option<String> {
+    if detection.ambiguous {
+        Some(
+            "Quality encoding is ambiguous (scores fit Phred+33 and Phred+64); \
+             assuming Phred+33, set --phred-offset to override"
+                .to_string(),
+        )
+    } else if detection.conflicts {
+        Some(format!(
+            "Qualities do not look like {} as given by --phred-offset",
+            detection.encoding.name()
+        ))
+    } else {
+        None
+    }
+}
+
+/// Mean read quality ranges (`--quality-bins 0-20,20-30,30+`), each from its
+/// lower bound up to but not including the next
+#[derive(Debug, Clone)]
+pub struct QualityBins {
+    bins: Vec<Bin>,
+}
+
+#[derive(Debug, Clone)]
+struct Bin {
+    low: f64,
+    /// `None` for an open-ended last bin (`30+`)
+    high: Option<f64>,
+    /// As written, naming the bin in tables
+    label: String,
+}
+
+impl QualityBins {
+    /// Label of the bin holding the mean Phred score of `qual`; `None` for
+    /// reads between or outside the bins
+    pub fn bin(&self, qual: &[u8], offset: u8) -> Option<&str> {
+        if qual.is_empty() {
+            return None;
+        }
+        let sum: u64 = qual
+            .iter()
+            .map(|&byte| byte.saturating_sub(offset) as u64)
+            .sum();
+        let mean = sum as f64 / qual.len() as f64;
+        self.bins
+            .iter()
+            .find(|bin| mean >= bin.low && bin.high.is_none_or(|high| mean < high))
+            .map(|bin| bin.label.as_str())
+    }
+
+    pub fn labels(&self) -> Vec<&str> {
+        self.bins.iter().map(|bin| bin.label.as_str()).collect()
+    }
+}
+
+impl FromStr for QualityBins {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut bins: Vec<Bin> = Vec::new();
+        for term in spec.split(',').map(str::trim) {
+            let score = |value: &str| {
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|score| score.is_finite() && *score >= 0.0)
+                    .ok_or_else(|| format!("'{}' is not a Phred score in bin '{}'", value, term))
+            };
+            let bin = if let Some(low) = term.strip_suffix('+') {
+                Bin {
+                    low: score(low)?,
+                    high: None,
+                    label: term.to_string(),
+                }
+            } else if let Some((low, high)) = term.split_once('-') {
+                let (low, high) = (score(low)?, score(high)?);
+                if low >= high {
+                    return Err(format!("bin '{}' is empty", term));
+                }
+                Bin {
+                    low,
+                    high: Some(high),
+                    label: term.to_string(),
+                }
+            } else {
+                return Err(format!("expected LOW-HIGH or LOW+, got '{}'", term));
+            };
+            if let Some(last) = bins.last() {
+                match last.high {
+                    None => return Err(format!("'{}' must be the last bin", last.label)),
+                    Some(high) if bin.low < high => {
+                        return Err(format!(
+                            "bins must ascend without overlapping, but '{}' starts inside '{}'",
+                            term, last.label
+                        ));
+                    }
+                    Some(_) => {}
+                }
+            }
+            bins.push(bin);
+        }
+        Ok(Self { bins })
+    }
+}
+
+/// Expected errors of the reads counted under each key: per read, the sum of
+/// its bases' error probabilities (`10^(-Q/10)`), as `--maxee` in usearch and
+/// DADA2 filter on. Each worker sums its reads under the keys it counted them
+/// by (see `KeyHooks`), and hands its sums over once at the end.
+pub struct ExpectedErrors {
+    /// Error probability per quality character
+    probability: Vec<f64>,
+    sums: Mutex<AHashMap<String, f64>>,
+}
+
+impl ExpectedErrors {
+    /// Tally reads whose scores are read at `offset`
+    pub fn new(offset: u8) -> Self {
+        let probability = (0..=u8::MAX)
+            .map(|byte| 10f64.powf(-f64::from(byte.saturating_sub(offset)) / 10.0))
+            .collect();
+        Self {
+            probability,
+            sums: Mutex::new(AHashMap::new()),
+        }
+    }
+
+    /// Expected errors of a read with qualities `qual`
+    pub fn of(&self, qual: &[u8]) -> f64 {
+        qual.iter()
+            .map(|&byte| self.probability[byte as usize])
+            .sum()
+    }
+
+    /// Add one worker's sums per key
+    pub fn merge(&self, worker: AHashMap<Vec<u8>, f64>) {
+        let mut sums = self.sums.lock().unwrap();
+        for (key, errors) in worker {
+            *sums
+                .entry(String::from_utf8_lossy(&key).into_owned())
+                .or_insert(0.0) += errors;
+        }
+    }
+
+    /// Summed expected errors per key
+    pub fn finish(self) -> AHashMap<String, f64> {
+        self.sums.into_inner().unwrap()
+    }
+}
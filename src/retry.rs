@@ -0,0 +1,159 @@
+use crate::progress;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Filesystem magic numbers (`statfs` f_type) of network and FUSE mounts
+#[cfg(target_os = "linux")]
+const NETWORK_FS_MAGIC: [u64; 7] = [
+    0x6969,     // NFS
+    0x517b,     // SMB
+    0xff534d42, // CIFS
+    0xfe534d42, // SMB2
+    0x65735546, // FUSE (sshfs, s3fs, gcsfuse, ...)
+    0x00c36400, // Ceph
+    0x5346414f, // AFS
+];
+
+/// Times a failed read is retried; none until `configure` is called
+static RETRIES: AtomicU32 = AtomicU32::new(0);
+
+/// Wait before the first retry, in milliseconds; it doubles with each one
+static DELAY_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Retry reads that fail with a transient error `retries` times, waiting
+/// `delay`, then twice as long, and so on between attempts
+pub fn configure(retries: u32, delay: Duration) {
+    RETRIES.store(retries, Ordering::Relaxed);
+    DELAY_MS.store(delay.as_millis() as u64, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    RETRIES.load(Ordering::Relaxed) > 0
+}
+
+/// Whether `err` is one a network filesystem may clear up by itself: a
+/// timeout, an I/O error from a dropped connection, or a stale NFS handle.
+/// Interrupted reads are repeated at once instead, without a retry.
+fn is_transient(err: &io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    if matches!(err.raw_os_error(), Some(libc::EIO | libc::ESTALE)) {
+        return true;
+    }
+    err.kind() == io::ErrorKind::TimedOut
+}
+
+/// Run `attempt` until it succeeds, fails for good, or the retries run out
+fn with_retries<T>(path: &Path, mut attempt: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let mut delay = Duration::from_millis(DELAY_MS.load(Ordering::Relaxed));
+    let mut retried = 0;
+    loop {
+        match attempt() {
+            Ok(value) => return Ok(value),
+            Err(err) if retried < retries && is_transient(&err) => {
+                retried += 1;
+                progress::warning(
+                    path,
+                    &format!(
+                        "{}; retrying in {:.1}s ({}/{})",
+                        err,
+                        delay.as_secs_f64(),
+                        retried,
+                        retries
+                    ),
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(err) if retried > 0 => {
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("{} (after {} retries)", err, retried),
+                ));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// A file read from start to end that, when a read fails with a transient
+/// error, is reopened and read again from where it stopped
+pub struct RetryingFile {
+    path: PathBuf,
+    file: File,
+    position: u64,
+}
+
+impl RetryingFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::open_at(path, 0)
+    }
+
+    /// Open `path` to read from byte `offset` on
+    pub fn open_at(path: &Path, offset: u64) -> io::Result<Self> {
+        let file = with_retries(path, || {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            Ok(file)
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            position: offset,
+        })
+    }
+}
+
+impl Read for RetryingFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Self {
+            path,
+            file,
+            position,
+        } = self;
+        let mut retrying = false;
+        let n = with_retries(path, || {
+            // A handle that failed may stay broken (a stale NFS handle), so
+            // a retry reads through a new one
+            if retrying {
+                let mut reopened = File::open(&*path)?;
+                reopened.seek(SeekFrom::Start(*position))?;
+                *file = reopened;
+            }
+            retrying = true;
+            loop {
+                match file.read(buf) {
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    read => return read,
+                }
+            }
+        })?;
+        *position += n as u64;
+        Ok(n)
+    }
+}
+
+/// Whether `path` is on a network or FUSE mount, where a read error on a
+/// memory-mapped page kills the process rather than failing the read
+pub fn on_network_fs(path: &Path) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+            return false;
+        };
+        let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return false;
+        }
+        NETWORK_FS_MAGIC.contains(&(stat.f_type as u64 & 0xffff_ffff))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+        false
+    }
+}
@@ -0,0 +1,358 @@
+use crate::exit::{Classify, Failure};
+use crate::output::column_types;
+use crate::provenance::{RENAME_KEY, SCHEMA_KEY};
+use crate::validate::write_report;
+use anyhow::{Result, anyhow};
+use arrow::datatypes::DataType;
+use clap::Args as ClapArgs;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Version of the count table layout, recorded as `seqtable.schema_version`
+/// in Parquet footers. Bump it when a column changes name or type, so
+/// tables from older and newer versions can be told apart.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Check Parquet count tables against the count table schema
+#[derive(ClapArgs, Debug, Clone)]
+pub struct ValidateTableArgs {
+    /// Count tables written by seqtable (.parquet)
+    #[arg(required = true)]
+    pub input: Vec<PathBuf>,
+
+    /// Write a JSON report of all problems found (`-` for stdout)
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Disable progress output
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+/// Outcome of checking one table
+struct TableReport {
+    path: PathBuf,
+    /// `seqtable.schema_version` of the footer; absent in tables written
+    /// before versioning
+    schema_version: Option<String>,
+    rows: i64,
+    /// What makes the table fail the check
+    problems: Vec<String>,
+    /// What the check let through but a reader may trip on
+    warnings: Vec<String>,
+}
+
+impl TableReport {
+    fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "path": self.path.display().to_string(),
+            "valid": self.is_valid(),
+            "schema_version": self.schema_version,
+            "rows": self.rows,
+            "problems": self.problems,
+            "warnings": self.warnings,
+        })
+    }
+}
+
+pub fn run(args: &ValidateTableArgs) -> Result<()> {
+    let start_time = Instant::now();
+    let verbose = !args.quiet;
+
+    if verbose {
+        eprintln!("🧬 seqtable v0.1.1 validate-table");
+        eprintln!("📋 Schema version: {}", SCHEMA_VERSION);
+        eprintln!("📁 Tables: {}", args.input.len());
+        eprintln!();
+    }
+
+    let mut reports = Vec::with_capacity(args.input.len());
+    for path in &args.input {
+        if verbose {
+            eprintln!("📄 Checking: {}", path.display());
+        }
+        let report = check_table(path);
+        if verbose {
+            print_summary(&report);
+        }
+        reports.push(report);
+    }
+
+    let invalid = reports.iter().filter(|r| !r.is_valid()).count();
+    if let Some(ref report_path) = args.report {
+        let report = json!({
+            "valid": invalid == 0,
+            "schema_version": SCHEMA_VERSION,
+            "files": reports.iter().map(TableReport::to_json).collect::<Vec<_>>(),
+        });
+        if report_path == Path::new("-") {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &report)?;
+            writeln!(stdout)?;
+        } else {
+            write_report(report_path, &report)?;
+            if verbose {
+                eprintln!("📋 Report: {}", report_path.display());
+            }
+        }
+    }
+
+    if verbose {
+        eprintln!("⏱️  Total time: {:.2}s", start_time.elapsed().as_secs_f64());
+    }
+    if invalid > 0 {
+        return Err(anyhow!(
+            "{} of {} tables failed validation",
+            invalid,
+            reports.len()
+        ))
+        .classify(Failure::Input);
+    }
+    Ok(())
+}
+
+/// Check the footer and columns of `path`, without reading its rows
+fn check_table(path: &Path) -> TableReport {
+    let mut report = TableReport {
+        path: path.to_path_buf(),
+        schema_version: None,
+        rows: 0,
+        problems: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => {
+            report.problems.push(format!("Failed to open: {}", err));
+            return report;
+        }
+    };
+    let builder = match ParquetRecordBatchReaderBuilder::try_new(file) {
+        Ok(builder) => builder,
+        Err(err) => {
+            report
+                .problems
+                .push(format!("Not a readable Parquet file: {}", err));
+            return report;
+        }
+    };
+
+    let file_metadata = builder.metadata().file_metadata();
+    report.rows = file_metadata.num_rows();
+    let footer = |key: &str| {
+        file_metadata.key_value_metadata().and_then(|metadata| {
+            metadata
+                .iter()
+                .find(|kv| kv.key == key)
+                .and_then(|kv| kv.value.clone())
+        })
+    };
+    report.schema_version = footer(SCHEMA_KEY);
+    // Columns given other names with `--rename` are checked by their own
+    let renames: Vec<(String, String)> = footer(RENAME_KEY)
+        .iter()
+        .flat_map(|renames| renames.split(','))
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(old, new)| (old.to_string(), new.to_string()))
+        .collect();
+    match report.schema_version.as_deref().map(str::parse::<u32>) {
+        None => report.warnings.push(format!(
+            "No {} in the footer: written before schema versioning, or not by seqtable",
+            SCHEMA_KEY
+        )),
+        Some(Err(_)) => report.problems.push(format!(
+            "Unreadable {}: '{}'",
+            SCHEMA_KEY,
+            report.schema_version.as_deref().unwrap_or_default()
+        )),
+        Some(Ok(version)) if version > SCHEMA_VERSION => report.problems.push(format!(
+            "Schema version {} is newer than this seqtable's ({})",
+            version, SCHEMA_VERSION
+        )),
+        Some(Ok(_)) => {}
+    }
+
+    let schema = builder.schema();
+    let names: Vec<&str> = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let name = field.name().as_str();
+            renames
+                .iter()
+                .find(|(_, new)| new == name)
+                .map_or(name, |(old, _)| old.as_str())
+        })
+        .collect();
+    if !names.contains(&"count") {
+        report.problems.push("Missing `count` column".to_string());
+    }
+    match names
+        .iter()
+        .filter(|&&name| name == "sequence" || name == "group")
+        .count()
+    {
+        0 => report
+            .problems
+            .push("Missing key column (`sequence` or `group`)".to_string()),
+        1 => {}
+        _ => report
+            .problems
+            .push("Both `sequence` and `group` key columns".to_string()),
+    }
+    for (index, field) in schema.fields().iter().enumerate() {
+        let name = field.name();
+        if names[..index].contains(&names[index]) {
+            report
+                .problems
+                .push(format!("Column `{}` appears more than once", name));
+            continue;
+        }
+        match column_types(names[index]) {
+            Some(types) if !types.contains(field.data_type()) => {
+                report.problems.push(format!(
+                    "Column `{}` is {}, expected {}",
+                    name,
+                    field.data_type(),
+                    types
+                        .iter()
+                        .map(DataType::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" or ")
+                ));
+            }
+            Some(_) => {}
+            None => report
+                .warnings
+                .push(format!("Column `{}` is not part of the schema", name)),
+        }
+    }
+    report
+}
+
+fn print_summary(report: &TableReport) {
+    if let Some(ref version) = report.schema_version {
+        eprintln!("   📋 Schema version: {}", version);
+    }
+    for warning in &report.warnings {
+        eprintln!("   ⚠️  {}", warning);
+    }
+    if report.is_valid() {
+        eprintln!("   ✓ {} rows, columns match the schema", report.rows);
+        return;
+    }
+    for problem in &report.problems {
+        eprintln!("   ❌ {}", problem);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{ArrayRef, StringArray, UInt64Array};
+    use arrow::record_batch::RecordBatch;
+    use clap::Parser;
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::metadata::KeyValue;
+    use parquet::file::properties::WriterProperties;
+    use std::sync::Arc;
+
+    fn check_renames(renames: &str) -> Result<()> {
+        let args =
+            crate::Args::try_parse_from(["seqtable", "reads.fq", "--rpm", "--rename", renames])?;
+        crate::output::check_columns(&args)
+    }
+
+    #[test]
+    fn renames_to_schema_columns_or_twice_over_are_refused() {
+        assert!(check_renames("count=reads,rpm=cpm").is_ok());
+        for renames in [
+            "count=sequence",
+            "rpm=normalized_count",
+            "sequence=group",
+            "count=sample",
+        ] {
+            let error = check_renames(renames).unwrap_err().to_string();
+            assert!(
+                error.contains("names another count table column"),
+                "{}: {}",
+                renames,
+                error
+            );
+        }
+        let error = check_renames("count=reads,count=total")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("renamed more than once"), "{}", error);
+        // Two columns given one new name
+        let error = check_renames("count=value,rpm=value")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("written twice"), "{}", error);
+    }
+
+    /// A Parquet table of `sequence` and the counts under `count_name`
+    fn write_table(path: &Path, count_name: &str, footer: Vec<KeyValue>) {
+        let batch = RecordBatch::try_from_iter([
+            (
+                "sequence",
+                Arc::new(StringArray::from(vec!["ACGT", "GGCC"])) as ArrayRef,
+            ),
+            (
+                count_name,
+                Arc::new(UInt64Array::from(vec![5, 3])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        let properties = WriterProperties::builder()
+            .set_key_value_metadata(Some(footer))
+            .build();
+        let mut writer = ArrowWriter::try_new(
+            File::create(path).unwrap(),
+            batch.schema(),
+            Some(properties),
+        )
+        .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn renamed_columns_are_checked_under_their_original_names() {
+        let dir = std::env::temp_dir().join(format!("seqtable-schema-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let version = KeyValue::new(SCHEMA_KEY.to_string(), SCHEMA_VERSION.to_string());
+
+        let path = dir.join("renamed.parquet");
+        let renamed = KeyValue::new(RENAME_KEY.to_string(), "count=reads".to_string());
+        write_table(&path, "reads", vec![version.clone(), renamed]);
+        let report = check_table(&path);
+        assert!(report.is_valid(), "{:?}", report.problems);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+
+        // Without the footer, `reads` is an unknown column and `count` is missing
+        let path = dir.join("unrecorded.parquet");
+        write_table(&path, "reads", vec![version.clone()]);
+        let report = check_table(&path);
+        assert_eq!(report.problems, ["Missing `count` column"]);
+        assert_eq!(
+            report.warnings,
+            ["Column `reads` is not part of the schema"]
+        );
+
+        let path = dir.join("newer.parquet");
+        let newer = KeyValue::new(SCHEMA_KEY.to_string(), (SCHEMA_VERSION + 1).to_string());
+        write_table(&path, "count", vec![newer]);
+        assert!(!check_table(&path).is_valid());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
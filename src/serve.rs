@@ -0,0 +1,1106 @@
+use anyhow::{Context, Result, bail};
+use arrow::ipc::writer::StreamWriter;
+use clap::{Args as ClapArgs, Parser};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::{Map, Value, json};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Take, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Largest request body accepted; job descriptions are a few hundred bytes
+const MAX_BODY: usize = 1024 * 1024;
+
+/// Largest request line plus headers accepted
+const MAX_HEAD: usize = 64 * 1024;
+
+/// How long a client may stall reading or writing before it is dropped
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Requests answered at once, each on a thread of its own; clients past this
+/// are answered `503` at once
+const MAX_CONNECTIONS: usize = 64;
+
+/// How long turning a client away may wait on it
+const BUSY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Files each job leaves in its directory besides the tables
+const LOG_FILE: &str = "log.txt";
+const SUMMARY_FILE: &str = "summary.json";
+const ERROR_FILE: &str = "error.json";
+
+/// Options the server sets for every job itself, or that would write outside
+/// the job's directory
+const RESERVED_OPTIONS: [&str; 17] = [
+    "-o",
+    "--output-dir",
+    "--output",
+    "--output-template",
+    "-f",
+    "--format",
+    "-q",
+    "--quiet",
+    "--progress",
+    "--summary",
+    "--error-json",
+    "--file-list",
+    "--manifest",
+    "--dry-run",
+    "--print-config",
+    "--benchmark",
+    "--sketch",
+];
+
+/// Sidecar options allowed beside each table, but not with `=FILE`
+const SIDECAR_OPTIONS: [&str; 7] = [
+    "--checksum",
+    "--write-dedup",
+    "--write-assignments",
+    "--length-hist",
+    "--gc-hist",
+    "--quality-hist",
+    "--cycle-quality",
+];
+
+/// Serve counting jobs over HTTP: submit, poll, and fetch the tables
+#[derive(ClapArgs, Debug, Clone)]
+pub struct ServeArgs {
+    /// Address to listen on: `:8080` is 127.0.0.1 only; name the host to
+    /// accept other machines (`0.0.0.0:8080`)
+    #[arg(long, value_name = "ADDR")]
+    pub http: String,
+
+    /// Directory submitted inputs must lie under; relative inputs are taken
+    /// from it
+    #[arg(long, value_name = "DIR")]
+    pub data_dir: PathBuf,
+
+    /// Directory holding one subdirectory of outputs, log and summary per job
+    #[arg(long, value_name = "DIR", default_value = "seqtable-jobs")]
+    pub jobs_dir: PathBuf,
+
+    /// Jobs counted at the same time; the rest wait in order
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u64).range(1..))]
+    pub workers: u64,
+
+    /// Disable the job log on stderr
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobState {
+    fn name(self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+        }
+    }
+}
+
+/// One submitted input and its counting options
+struct Job {
+    id: u64,
+    input: PathBuf,
+    args: Vec<String>,
+    dir: PathBuf,
+    state: JobState,
+    started: Option<Instant>,
+    elapsed_secs: Option<f64>,
+    exit_code: Option<i32>,
+    /// Tables and reports the run wrote, from its summary
+    outputs: Vec<PathBuf>,
+    /// The run's `--error-json` report when it failed
+    error: Option<Value>,
+}
+
+impl Job {
+    /// Taken up by a worker
+    fn start(&mut self) {
+        self.state = JobState::Running;
+        self.started = Some(Instant::now());
+    }
+
+    /// Counted, or failed to be
+    fn finish(&mut self, outcome: Result<JobOutcome>) {
+        self.elapsed_secs = self.started.map(|t| t.elapsed().as_secs_f64());
+        match outcome {
+            Ok(JobOutcome::Done(outputs)) => {
+                self.state = JobState::Done;
+                self.exit_code = Some(0);
+                self.outputs = outputs;
+            }
+            Ok(JobOutcome::Failed(code, error)) => {
+                self.state = JobState::Failed;
+                self.exit_code = code;
+                self.error = Some(error);
+            }
+            Err(err) => {
+                self.state = JobState::Failed;
+                self.error = Some(json!({ "message": format!("{:#}", err) }));
+            }
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "input": self.input.display().to_string(),
+            "args": self.args,
+            "state": self.state.name(),
+            "elapsed_secs": self.elapsed_secs.or_else(|| self.started.map(|t| t.elapsed().as_secs_f64())),
+            "exit_code": self.exit_code,
+            "outputs": self.outputs.iter().filter_map(|p| p.file_name()).map(|n| n.to_string_lossy()).collect::<Vec<_>>(),
+            "error": self.error,
+            "url": format!("/jobs/{}", self.id),
+        })
+    }
+}
+
+struct Server {
+    jobs: Mutex<Vec<Job>>,
+    queue: Sender<usize>,
+    jobs_dir: PathBuf,
+    /// `--data-dir`, canonical
+    data_dir: PathBuf,
+    /// Id of the first job, past any left in the directory by earlier servers
+    first_id: u64,
+    /// Requests being answered
+    connections: AtomicUsize,
+    quiet: bool,
+}
+
+/// A request being answered, counted against `MAX_CONNECTIONS` until dropped
+struct Connection(Arc<Server>);
+
+impl Connection {
+    fn open(server: &Arc<Server>) -> Option<Self> {
+        if server.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            server.connections.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(Self(server.clone()))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn run(args: &ServeArgs) -> Result<()> {
+    let addr = listen_addr(&args.http);
+    let data_dir = args
+        .data_dir
+        .canonicalize()
+        .with_context(|| format!("Failed to open data directory: {}", args.data_dir.display()))?;
+    let listener =
+        TcpListener::bind(&addr).with_context(|| format!("Failed to listen on {}", addr))?;
+    std::fs::create_dir_all(&args.jobs_dir).with_context(|| {
+        format!(
+            "Failed to create jobs directory: {}",
+            args.jobs_dir.display()
+        )
+    })?;
+
+    let (queue, pending) = channel();
+    let server = Arc::new(Server {
+        jobs: Mutex::new(Vec::new()),
+        queue,
+        jobs_dir: args.jobs_dir.clone(),
+        data_dir,
+        first_id: last_job_id(&args.jobs_dir) + 1,
+        connections: AtomicUsize::new(0),
+        quiet: args.quiet,
+    });
+    let pending = Arc::new(Mutex::new(pending));
+    for _ in 0..args.workers {
+        let (server, pending) = (server.clone(), pending.clone());
+        std::thread::spawn(move || work(&server, &pending));
+    }
+
+    if !args.quiet {
+        eprintln!("🧬 seqtable v0.1.1 serve");
+        eprintln!("🌐 Listening on http://{}", addr);
+        eprintln!("📂 Jobs directory: {}", args.jobs_dir.display());
+        eprintln!("📥 Inputs from: {}", server.data_dir.display());
+        eprintln!("🧵 Workers: {}", args.workers);
+        eprintln!();
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("⚠️  Connection failed: {}", err);
+                continue;
+            }
+        };
+        let Some(connection) = Connection::open(&server) else {
+            turn_away(stream);
+            continue;
+        };
+        std::thread::spawn(move || {
+            let server = &connection.0;
+            if let Err(err) = handle(stream, server) {
+                if !server.quiet {
+                    eprintln!("⚠️  Request failed: {:#}", err);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// `--http` as a socket address; a bare `:PORT` listens on loopback only
+fn listen_addr(http: &str) -> String {
+    match http.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{}", port),
+        None => http.to_string(),
+    }
+}
+
+/// Answer `503` to a client over `MAX_CONNECTIONS`, without a thread
+fn turn_away(mut stream: TcpStream) {
+    stream.set_write_timeout(Some(BUSY_TIMEOUT)).ok();
+    Response::error(503, "Too many requests at once; try again shortly")
+        .write_to(&mut stream)
+        .ok();
+}
+
+/// Highest numbered job directory already present, or 0
+fn last_job_id(jobs_dir: &Path) -> u64 {
+    std::fs::read_dir(jobs_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Count queued jobs one at a time, each in a child seqtable process so a
+/// failing input cannot take the server down
+fn work(server: &Server, pending: &Mutex<Receiver<usize>>) {
+    loop {
+        let Ok(idx) = pending.lock().unwrap().recv() else {
+            return;
+        };
+        let (id, input, args, dir) = {
+            let mut jobs = server.jobs.lock().unwrap();
+            let job = &mut jobs[idx];
+            job.start();
+            (job.id, job.input.clone(), job.args.clone(), job.dir.clone())
+        };
+        let outcome = run_job(&input, &args, &dir);
+
+        let mut jobs = server.jobs.lock().unwrap();
+        let job = &mut jobs[idx];
+        job.finish(outcome);
+        if !server.quiet {
+            match job.state {
+                JobState::Done => eprintln!(
+                    "✓ Job {}: {} ({:.2}s)",
+                    id,
+                    input.display(),
+                    job.elapsed_secs.unwrap_or_default()
+                ),
+                _ => eprintln!(
+                    "❌ Job {}: {} (exit {}, log in {})",
+                    id,
+                    input.display(),
+                    job.exit_code.map_or("none".to_string(), |c| c.to_string()),
+                    dir.join(LOG_FILE).display()
+                ),
+            }
+        }
+    }
+}
+
+enum JobOutcome {
+    Done(Vec<PathBuf>),
+    /// Exit status (none if killed by a signal) and the error report
+    Failed(Option<i32>, Value),
+}
+
+fn run_job(input: &Path, args: &[String], dir: &Path) -> Result<JobOutcome> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create job directory: {}", dir.display()))?;
+    let log_path = dir.join(LOG_FILE);
+    let log = File::create(&log_path)
+        .with_context(|| format!("Failed to create file: {}", log_path.display()))?;
+    let exe = std::env::current_exe().context("Failed to locate the seqtable executable")?;
+    let status = Command::new(exe)
+        .arg(input)
+        .args(args)
+        .arg("--output-dir")
+        .arg(dir)
+        .args(["--format", "parquet,csv", "--quiet"])
+        .arg("--summary")
+        .arg(dir.join(SUMMARY_FILE))
+        .arg("--error-json")
+        .arg(dir.join(ERROR_FILE))
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .status()
+        .context("Failed to start the counting process")?;
+
+    if !status.success() {
+        let error = read_json(&dir.join(ERROR_FILE)).unwrap_or_else(
+            |_| json!({ "message": format!("counting stopped; see {}", LOG_FILE) }),
+        );
+        return Ok(JobOutcome::Failed(status.code(), error));
+    }
+    let summary = read_json(&dir.join(SUMMARY_FILE))?;
+    let outputs = summary["files"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|file| file["outputs"].as_array())
+        .flatten()
+        .filter_map(|path| path.as_str().map(PathBuf::from))
+        .collect();
+    Ok(JobOutcome::Done(outputs))
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: &Value) -> Self {
+        let mut body = serde_json::to_vec_pretty(value).unwrap_or_default();
+        body.push(b'\n');
+        Self {
+            status,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, &json!({ "error": message.into() }))
+    }
+
+    fn write_to(&self, stream: &mut impl Write) -> std::io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        )?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Answer one request; every response closes the connection
+fn handle(stream: TcpStream, server: &Server) -> Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = answer(&mut reader, server);
+    let mut stream = stream;
+    response.write_to(&mut stream)?;
+    Ok(())
+}
+
+/// The response to the request `reader` holds
+fn answer(reader: &mut impl BufRead, server: &Server) -> Response {
+    match read_request(reader) {
+        Ok(request) => route(&request, server),
+        Err(err) if err.to_string().contains("headers too large") => {
+            Response::error(431, err.to_string())
+        }
+        Err(err) if err.to_string().contains("too large") => Response::error(413, err.to_string()),
+        Err(err) => Response::error(400, format!("{:#}", err)),
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<Request> {
+    let mut head = reader.take(MAX_HEAD as u64);
+    let mut line = String::new();
+    read_head_line(&mut head, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        bail!("Malformed request line");
+    };
+    let method = method.to_string();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = percent_decode(path, false)?;
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key, true)?, percent_decode(value, true)?))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if read_head_line(&mut head, &mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().context("Invalid Content-Length")?;
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        bail!("Request body too large ({} bytes)", content_length);
+    }
+    let mut body = vec![0; content_length];
+    head.into_inner().read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        query,
+        body,
+    })
+}
+
+/// One line of the request head, failing once the head passes `MAX_HEAD`
+fn read_head_line(head: &mut Take<impl BufRead>, line: &mut String) -> Result<usize> {
+    let read = head.read_line(line)?;
+    if head.limit() == 0 && !line.ends_with('\n') {
+        bail!("Request headers too large (over {} bytes)", MAX_HEAD);
+    }
+    Ok(read)
+}
+
+/// `%XX` escapes of a request path or query part, and `+` for a space in
+/// queries
+fn percent_decode(text: &str, query: bool) -> Result<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.bytes();
+    while let Some(byte) = rest.next() {
+        match byte {
+            b'%' => {
+                let digit = |byte: Option<u8>| char::from(byte?).to_digit(16);
+                let (Some(high), Some(low)) = (digit(rest.next()), digit(rest.next())) else {
+                    bail!("Malformed %-escape in '{}'", text);
+                };
+                bytes.push((high * 16 + low) as u8);
+            }
+            b'+' if query => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).with_context(|| format!("'{}' is not UTF-8 once decoded", text))
+}
+
+fn route(request: &Request, server: &Server) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let job = |id: &str| -> Option<usize> {
+        let id: u64 = id.parse().ok()?;
+        let idx = id.checked_sub(server.first_id)? as usize;
+        (idx < server.jobs.lock().unwrap().len()).then_some(idx)
+    };
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["jobs"]) => submit(request, server),
+        ("GET", ["jobs"]) => {
+            let jobs = server.jobs.lock().unwrap();
+            Response::json(
+                200,
+                &json!(jobs.iter().map(Job::to_json).collect::<Vec<_>>()),
+            )
+        }
+        ("GET", ["jobs", id]) => match job(id) {
+            Some(idx) => Response::json(200, &server.jobs.lock().unwrap()[idx].to_json()),
+            None => Response::error(404, format!("No job {}", id)),
+        },
+        ("GET", ["jobs", id, "results"]) => match job(id) {
+            Some(idx) => results(request, server, idx),
+            None => Response::error(404, format!("No job {}", id)),
+        },
+        ("GET", ["jobs", id, "files", name]) => match job(id) {
+            Some(idx) => output_file(server, idx, name),
+            None => Response::error(404, format!("No job {}", id)),
+        },
+        (_, ["jobs"] | ["jobs", _] | ["jobs", _, "results"] | ["jobs", _, "files", _]) => {
+            Response::error(405, format!("{} is not allowed here", request.method))
+        }
+        _ => Response::error(404, format!("No such endpoint: {}", request.path)),
+    }
+}
+
+/// `POST /jobs` with `{"input": PATH, "args": [OPTIONS...]}`
+fn submit(request: &Request, server: &Server) -> Response {
+    let body: Value = match serde_json::from_slice(&request.body) {
+        Ok(body) => body,
+        Err(err) => return Response::error(400, format!("Invalid JSON: {}", err)),
+    };
+    let Some(input) = body["input"].as_str() else {
+        return Response::error(400, "Missing \"input\": the path of a file to count");
+    };
+    let args: Vec<String> = match body.get("args") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(args)) => {
+            match args.iter().map(|a| a.as_str().map(String::from)).collect() {
+                Some(args) => args,
+                None => return Response::error(400, "\"args\" must be an array of strings"),
+            }
+        }
+        Some(_) => return Response::error(400, "\"args\" must be an array of strings"),
+    };
+    if let Some(option) = args.iter().find_map(|arg| reserved(arg)) {
+        return Response::error(400, format!("{} is set by the server", option));
+    }
+    let input = match resolve_input(&server.data_dir, input) {
+        Ok(input) => input,
+        Err(response) => return response,
+    };
+    // Options are checked as the job will parse them, so mistakes fail now
+    let command = [OsString::from("seqtable"), input.clone().into_os_string()];
+    let mut parsed = match crate::Args::try_parse_from(
+        command.into_iter().chain(args.iter().map(OsString::from)),
+    ) {
+        Ok(parsed) => parsed,
+        Err(err) => return Response::error(400, err.to_string().trim().to_string()),
+    };
+    // Anything not taken as an option's value would be counted as an input
+    // the data directory was never checked for
+    if parsed.input.len() != 1 || parsed.command.is_some() {
+        return Response::error(
+            400,
+            "\"args\" takes options only; name the file to count in \"input\"",
+        );
+    }
+    parsed.sample_name = parsed.sample_names.first().cloned();
+    if let Err(err) = crate::check_output_name(&input, &parsed) {
+        return Response::error(400, err.to_string());
+    }
+
+    let mut jobs = server.jobs.lock().unwrap();
+    let idx = jobs.len();
+    let id = server.first_id + idx as u64;
+    jobs.push(Job {
+        id,
+        input: input.clone(),
+        args,
+        dir: server.jobs_dir.join(id.to_string()),
+        state: JobState::Queued,
+        started: None,
+        elapsed_secs: None,
+        exit_code: None,
+        outputs: Vec::new(),
+        error: None,
+    });
+    if server.queue.send(idx).is_err() {
+        return Response::error(500, "No workers are running");
+    }
+    if !server.quiet {
+        eprintln!("📥 Job {}: {}", id, input.display());
+    }
+    Response::json(202, &jobs[idx].to_json())
+}
+
+/// The file `input` names under the data directory, relative to it unless
+/// absolute; links are followed before it is checked
+fn resolve_input(data_dir: &Path, input: &str) -> Result<PathBuf, Response> {
+    let missing = || Response::error(400, format!("No such input file: {}", input));
+    let path = data_dir.join(input).canonicalize().map_err(|_| missing())?;
+    if !path.starts_with(data_dir) {
+        return Err(Response::error(
+            403,
+            format!("{} is outside the server's data directory", input),
+        ));
+    }
+    if !path.is_file() {
+        return Err(missing());
+    }
+    Ok(path)
+}
+
+/// The option `arg` names if the server reserves it
+fn reserved(arg: &str) -> Option<&str> {
+    let name = arg.split_once('=').map_or(arg, |(name, _)| name);
+    if RESERVED_OPTIONS.contains(&name)
+        || (arg.contains('=') && SIDECAR_OPTIONS.contains(&name))
+        // Short options take their value attached, too (`-ocounts`)
+        || ["-o", "-f", "-q"].iter().any(|short| !arg.starts_with("--") && arg.starts_with(short))
+    {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// `GET /jobs/{id}/results?format=json|csv|parquet|arrow`: the job's count table
+fn results(request: &Request, server: &Server, idx: usize) -> Response {
+    let jobs = server.jobs.lock().unwrap();
+    let job = &jobs[idx];
+    if job.state != JobState::Done {
+        let message = match job.state {
+            JobState::Failed => format!("Job {0} failed; see /jobs/{0}", job.id),
+            state => format!("Job {} is {}", job.id, state.name()),
+        };
+        return Response::error(409, message);
+    }
+    let format = request.param("format").unwrap_or("json");
+    let extension = match format {
+        "json" | "csv" => "csv",
+        "parquet" | "arrow" => "parquet",
+        _ => {
+            return Response::error(
+                400,
+                format!("Unknown format '{}' (json, csv, parquet, arrow)", format),
+            );
+        }
+    };
+    let Some(table) = job
+        .outputs
+        .iter()
+        .find(|path| path.extension().is_some_and(|ext| ext == extension))
+    else {
+        return Response::error(404, format!("Job {} wrote no {} table", job.id, extension));
+    };
+    let body = match format {
+        "json" => csv_to_json(table).map(|rows| Response::json(200, &rows)),
+        "csv" => std::fs::read(table)
+            .map_err(Into::into)
+            .map(|body| Response {
+                status: 200,
+                content_type: "text/csv",
+                body,
+            }),
+        "arrow" => parquet_to_arrow(table).map(|body| Response {
+            status: 200,
+            content_type: "application/vnd.apache.arrow.stream",
+            body,
+        }),
+        _ => std::fs::read(table)
+            .map_err(Into::into)
+            .map(|body| Response {
+                status: 200,
+                content_type: "application/vnd.apache.parquet",
+                body,
+            }),
+    };
+    body.unwrap_or_else(|err: anyhow::Error| Response::error(500, format!("{:#}", err)))
+}
+
+/// `GET /jobs/{id}/files/{name}`: any output the job listed, e.g. one table
+/// per lane under `--split-by`
+fn output_file(server: &Server, idx: usize, name: &str) -> Response {
+    let jobs = server.jobs.lock().unwrap();
+    let job = &jobs[idx];
+    let Some(path) = job
+        .outputs
+        .iter()
+        .find(|path| path.file_name().is_some_and(|n| n == name))
+    else {
+        return Response::error(404, format!("Job {} has no output {}", job.id, name));
+    };
+    match std::fs::read(path) {
+        Ok(body) => Response {
+            status: 200,
+            content_type: "application/octet-stream",
+            body,
+        },
+        Err(err) => Response::error(500, format!("Failed to read {}: {}", name, err)),
+    }
+}
+
+/// A Parquet table as an Arrow IPC stream, which pyarrow
+/// (`pyarrow.ipc.open_stream`) and R (`arrow::read_ipc_stream`) read straight
+/// into memory
+fn parquet_to_arrow(path: &Path) -> Result<Vec<u8>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    let mut writer = StreamWriter::try_new(Vec::new(), builder.schema())?;
+    for batch in builder.build()? {
+        writer.write(&batch?)?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// A CSV table as an array of row objects, numbers and booleans typed
+fn csv_to_json(path: &Path) -> Result<Value> {
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let headers = reader.headers()?.clone();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let row: Map<String, Value> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(name, value)| (name.to_string(), typed(value)))
+            .collect();
+        rows.push(Value::Object(row));
+    }
+    Ok(Value::Array(rows))
+}
+
+fn typed(value: &str) -> Value {
+    if value.is_empty() {
+        Value::Null
+    } else if let Ok(number) = value.parse::<u64>() {
+        json!(number)
+    } else if let Some(number) = value.parse::<f64>().ok().filter(|n| n.is_finite()) {
+        json!(number)
+    } else if let Ok(flag) = value.parse::<bool>() {
+        json!(flag)
+    } else {
+        json!(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{Column, ColumnData, OutputFormat, save_table};
+    use arrow::ipc::reader::StreamReader;
+    use arrow::util::display::array_value_to_string;
+
+    /// A temporary directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("seqtable-serve-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn server(data_dir: &Path) -> (Server, Receiver<usize>) {
+        let (queue, pending) = channel();
+        let server = Server {
+            jobs: Mutex::new(Vec::new()),
+            queue,
+            jobs_dir: data_dir.join("jobs"),
+            data_dir: data_dir.canonicalize().unwrap(),
+            first_id: 1,
+            connections: AtomicUsize::new(0),
+            quiet: true,
+        };
+        (server, pending)
+    }
+
+    fn send(server: &Server, method: &str, target: &str, body: &str) -> Response {
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            target,
+            body.len(),
+            body
+        );
+        answer(&mut request.as_bytes(), server)
+    }
+
+    fn body(response: &Response) -> Value {
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    #[test]
+    fn requests_are_parsed_with_queries_and_bodies() {
+        let head = "GET /jobs/%31/results?format=csv&note=a%20b+c HTTP/1.1\r\nHost: x\r\n\r\n";
+        let request = read_request(&mut head.as_bytes()).unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/jobs/1/results");
+        assert_eq!(request.param("format"), Some("csv"));
+        assert_eq!(request.param("note"), Some("a b c"));
+        assert_eq!(request.param("other"), None);
+        assert!(request.body.is_empty());
+
+        let post = "POST /jobs HTTP/1.1\r\ncontent-length: 8\r\n\r\n{\"a\": 1}trailing";
+        let request = read_request(&mut post.as_bytes()).unwrap();
+        assert_eq!(request.body, b"{\"a\": 1}");
+
+        for malformed in [
+            "GARBAGE\r\n\r\n",
+            "GET /jobs/%G1 HTTP/1.1\r\n\r\n",
+            "POST /jobs HTTP/1.1\r\nContent-Length: lots\r\n\r\n",
+        ] {
+            assert!(
+                read_request(&mut malformed.as_bytes()).is_err(),
+                "{}",
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_heads_and_bodies_are_refused() {
+        let dir = temp_dir("limits");
+        let (server, _pending) = server(&dir);
+        let long_header = format!(
+            "GET /jobs HTTP/1.1\r\nX-Pad: {}\r\n\r\n",
+            "a".repeat(MAX_HEAD)
+        );
+        assert_eq!(answer(&mut long_header.as_bytes(), &server).status, 431);
+        let long_body = format!(
+            "POST /jobs HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert_eq!(answer(&mut long_body.as_bytes(), &server).status, 413);
+        // A head just inside the limit is read
+        let padding = MAX_HEAD - "GET /jobs HTTP/1.1\r\nX-Pad: \r\n\r\n".len();
+        let fits = format!(
+            "GET /jobs HTTP/1.1\r\nX-Pad: {}\r\n\r\n",
+            "a".repeat(padding)
+        );
+        assert_eq!(answer(&mut fits.as_bytes(), &server).status, 200);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reserved_options_are_told_from_their_neighbours() {
+        for (arg, name) in [
+            ("-o", "-o"),
+            ("-ofoo", "-ofoo"),
+            ("-fcsv", "-fcsv"),
+            ("-q", "-q"),
+            ("--output-dir=/tmp", "--output-dir"),
+            ("--format", "--format"),
+            ("--summary", "--summary"),
+            ("--checksum=sums.tsv", "--checksum"),
+        ] {
+            assert_eq!(reserved(arg), Some(name), "{}", arg);
+        }
+        // Long options sharing a short option's letter, and sidecars beside
+        // the table
+        for arg in [
+            "--orient",
+            "--other-row",
+            "--quality-bins",
+            "--format-version",
+            "--checksum",
+            "--write-dedup",
+            "2",
+            "seq[0:8]",
+        ] {
+            assert_eq!(reserved(arg), None, "{}", arg);
+        }
+    }
+
+    #[test]
+    fn submitted_inputs_stay_in_the_data_directory() {
+        let dir = temp_dir("confine");
+        let data = dir.join("data");
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::write(data.join("reads.fq"), "@r\nACGT\n+\nIIII\n").unwrap();
+        std::fs::write(dir.join("secret.fq"), "@r\nACGT\n+\nIIII\n").unwrap();
+        let (server, pending) = server(&data);
+        let submit = |body: Value| send(&server, "POST", "/jobs", &body.to_string());
+
+        let accepted = submit(json!({ "input": "reads.fq", "args": ["--min-count", "2"] }));
+        assert_eq!(accepted.status, 202);
+        assert_eq!(pending.try_recv(), Ok(0));
+        let canonical = data.canonicalize().unwrap().join("reads.fq");
+        assert_eq!(body(&accepted)["input"], canonical.display().to_string());
+        let absolute = canonical.display().to_string();
+        assert_eq!(submit(json!({ "input": absolute })).status, 202);
+
+        let outside = dir.join("secret.fq").display().to_string();
+        for input in ["../secret.fq", outside.as_str()] {
+            assert_eq!(submit(json!({ "input": input })).status, 403, "{}", input);
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret.fq"), data.join("link.fq")).unwrap();
+            assert_eq!(submit(json!({ "input": "link.fq" })).status, 403);
+        }
+        assert_eq!(submit(json!({ "input": "missing.fq" })).status, 400);
+        assert_eq!(submit(json!({ "input": "." })).status, 400);
+
+        // Positional values would be counted as inputs of their own
+        for args in [
+            json!(["../secret.fq"]),
+            json!(["--min-count", "2", "reads.fq"]),
+            json!(["--", "--data"]),
+            json!(["merge", "a.parquet"]),
+        ] {
+            let response = submit(json!({ "input": "reads.fq", "args": args }));
+            assert_eq!(response.status, 400, "{}", args);
+        }
+        assert_eq!(
+            submit(json!({ "input": "reads.fq", "args": ["-ofoo"] })).status,
+            400
+        );
+        assert_eq!(pending.try_iter().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn jobs_go_from_queued_to_running_to_done_or_failed() {
+        let dir = temp_dir("jobs");
+        std::fs::write(dir.join("reads.fq"), "@r\nACGT\n+\nIIII\n").unwrap();
+        let (server, _pending) = server(&dir);
+        for _ in 0..2 {
+            let submitted = send(&server, "POST", "/jobs", r#"{"input": "reads.fq"}"#);
+            assert_eq!(submitted.status, 202);
+            assert_eq!(body(&submitted)["state"], "queued");
+        }
+        let state =
+            |id: u64| body(&send(&server, "GET", &format!("/jobs/{}", id), ""))["state"].clone();
+        let results = |id: u64, format: &str| {
+            send(
+                &server,
+                "GET",
+                &format!("/jobs/{}/results?format={}", id, format),
+                "",
+            )
+        };
+        assert_eq!(state(1), "queued");
+        assert_eq!(results(1, "csv").status, 409);
+
+        server.jobs.lock().unwrap()[0].start();
+        assert_eq!(state(1), "running");
+        let running = results(1, "csv");
+        assert_eq!(running.status, 409);
+        assert_eq!(body(&running)["error"], "Job 1 is running");
+
+        let table = dir.join("reads_counts.csv");
+        std::fs::write(&table, "sequence,count\nACGT,1\n").unwrap();
+        server.jobs.lock().unwrap()[0].finish(Ok(JobOutcome::Done(vec![table])));
+        let done = body(&send(&server, "GET", "/jobs/1", ""));
+        assert_eq!(done["state"], "done");
+        assert_eq!(done["exit_code"], 0);
+        assert_eq!(done["outputs"], json!(["reads_counts.csv"]));
+        assert_eq!(results(1, "csv").body, b"sequence,count\nACGT,1\n");
+        assert_eq!(
+            body(&results(1, "json")),
+            json!([{ "sequence": "ACGT", "count": 1 }])
+        );
+        assert_eq!(results(1, "parquet").status, 404);
+        assert_eq!(results(1, "xml").status, 400);
+        let file = send(&server, "GET", "/jobs/1/files/reads_counts.csv", "");
+        assert_eq!(file.status, 200);
+
+        server.jobs.lock().unwrap()[1].start();
+        let error = json!({ "message": "bad input", "exit_code": 3 });
+        server.jobs.lock().unwrap()[1].finish(Ok(JobOutcome::Failed(Some(3), error.clone())));
+        let failed = body(&send(&server, "GET", "/jobs/2", ""));
+        assert_eq!(failed["state"], "failed");
+        assert_eq!(failed["exit_code"], 3);
+        assert_eq!(failed["error"], error);
+        assert_eq!(
+            body(&results(2, "csv"))["error"],
+            "Job 2 failed; see /jobs/2"
+        );
+
+        let listed = body(&send(&server, "GET", "/jobs", ""));
+        assert_eq!(listed.as_array().unwrap().len(), 2);
+        assert_eq!(send(&server, "GET", "/jobs/3", "").status, 404);
+        assert_eq!(send(&server, "DELETE", "/jobs/1", "").status, 405);
+        assert_eq!(send(&server, "GET", "/other", "").status, 404);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bare_ports_listen_on_loopback_only() {
+        assert_eq!(listen_addr(":8080"), "127.0.0.1:8080");
+        assert_eq!(listen_addr("0.0.0.0:8080"), "0.0.0.0:8080");
+        assert_eq!(listen_addr("[::1]:8080"), "[::1]:8080");
+    }
+
+    #[test]
+    fn connections_past_the_limit_are_turned_away() {
+        let dir = temp_dir("connections");
+        let server = Arc::new(server(&dir).0);
+        let mut open: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| Connection::open(&server).unwrap())
+            .collect();
+        assert!(Connection::open(&server).is_none());
+        open.pop();
+        assert!(Connection::open(&server).is_some());
+        drop(open);
+        assert_eq!(server.connections.load(Ordering::SeqCst), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parquet_tables_stream_as_arrow_ipc() {
+        let dir = temp_dir("arrow");
+        let path = dir.join("counts.parquet");
+        let columns = [
+            Column::new(
+                "sequence",
+                ColumnData::Text(vec!["ACGT".into(), "TTGA".into()]),
+            ),
+            Column::new("count", ColumnData::Count(vec![12, 7])),
+        ];
+        save_table(&columns, &path, &OutputFormat::Parquet, "none").unwrap();
+
+        let stream = parquet_to_arrow(&path).unwrap();
+        let mut rows = Vec::new();
+        for batch in StreamReader::try_new(stream.as_slice(), None).unwrap() {
+            let batch = batch.unwrap();
+            let (sequence, count) = (&batch.columns()[0], &batch.columns()[1]);
+            for row in 0..batch.num_rows() {
+                rows.push((
+                    array_value_to_string(sequence, row).unwrap(),
+                    array_value_to_string(count, row).unwrap(),
+                ));
+            }
+        }
+        let expected = [("ACGT", "12"), ("TTGA", "7")];
+        assert_eq!(
+            rows,
+            expected.map(|(sequence, count)| (sequence.to_string(), count.to_string()))
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use crate::exit::{Classify, Failure};
+use crate::merge;
+use crate::output::{self, Column, ColumnData, OutputFormat};
+use anyhow::Result;
+use clap::Args as ClapArgs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Sequences present in all, any, or only the first of several tables
+#[derive(ClapArgs, Debug, Clone)]
+#[command(group(clap::ArgGroup::new("operation").required(true)))]
+pub struct SetopArgs {
+    /// Count tables written by seqtable (.parquet/.csv/.tsv); with
+    /// `--subtract`, the first table minus the rest
+    #[arg(required = true, num_args = 2..)]
+    pub input: Vec<PathBuf>,
+
+    /// Sequences present in every table
+    #[arg(long, group = "operation")]
+    pub intersect: bool,
+
+    /// Sequences present in any table
+    #[arg(long, group = "operation")]
+    pub union: bool,
+
+    /// Sequences of the first table present in none of the others
+    #[arg(long, group = "operation")]
+    pub subtract: bool,
+
+    /// Count at which a sequence is present in a table
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u64).range(1..))]
+    pub min_count: u64,
+
+    /// Output file path
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Output format (default: inferred from the output extension, else parquet)
+    #[arg(short = 'f', long)]
+    pub format: Option<OutputFormat>,
+
+    /// Suffix stripped from input file stems to derive sample names
+    #[arg(short = 's', long, default_value = "_counts")]
+    pub suffix: String,
+
+    #[arg(long, default_value = "snappy", help = crate::output::COMPRESSION_HELP)]
+    pub compression: String,
+
+    /// Disable progress output
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+pub fn run(args: &SetopArgs) -> Result<()> {
+    let start_time = Instant::now();
+    let format = args
+        .format
+        .clone()
+        .or_else(|| OutputFormat::from_path(&args.output))
+        .unwrap_or(OutputFormat::Parquet);
+    let operation = if args.intersect {
+        "intersect"
+    } else if args.union {
+        "union"
+    } else {
+        "subtract"
+    };
+
+    if !args.quiet {
+        eprintln!("🧬 seqtable v0.1.1 setop");
+        eprintln!("📁 Input tables: {}", args.input.len());
+        eprintln!("🔣 Operation: {}", operation);
+        eprintln!("📊 Output format: {:?}", format);
+        eprintln!();
+    }
+
+    let matrix = merge::load_matrix(&args.input, &args.suffix, args.quiet)?;
+    let present = |sample: usize, row: usize| matrix.counts[sample][row] >= args.min_count;
+    let others = 1..matrix.samples.len();
+    let rows: Vec<usize> = (0..matrix.sequences.len())
+        .filter(|&row| {
+            if args.intersect {
+                (0..matrix.samples.len()).all(|sample| present(sample, row))
+            } else if args.union {
+                (0..matrix.samples.len()).any(|sample| present(sample, row))
+            } else {
+                present(0, row) && !others.clone().any(|sample| present(sample, row))
+            }
+        })
+        .collect();
+
+    // Rows keep the matrix order (most abundant overall first); a difference
+    // has counts only from the first table
+    let samples = if args.subtract {
+        &matrix.samples[..1]
+    } else {
+        &matrix.samples[..]
+    };
+    let mut columns = vec![Column::new(
+        &matrix.key_column,
+        ColumnData::Text(
+            rows.iter()
+                .map(|&row| matrix.sequences[row].clone())
+                .collect(),
+        ),
+    )];
+    for (sample, counts) in samples.iter().zip(&matrix.counts) {
+        columns.push(Column::new(
+            sample,
+            ColumnData::Count(rows.iter().map(|&row| counts[row]).collect()),
+        ));
+    }
+
+    output::save_table(&columns, &args.output, &format, &args.compression)
+        .classify(Failure::Output)?;
+
+    if !args.quiet {
+        eprintln!(
+            "\n   ✓ {} of {} sequences ({}) → {}",
+            rows.len(),
+            matrix.sequences.len(),
+            operation,
+            args.output.display()
+        );
+        eprintln!(
+            "   ⏱️  Processing time: {:.2}s",
+            start_time.elapsed().as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use crate::exit::{Classify, Failure};
+use crate::merge;
+use crate::output::{self, Column, ColumnData, OutputFormat};
+use crate::stats;
+use anyhow::Result;
+use clap::Args as ClapArgs;
+use rayon::prelude::*;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Pairwise similarity between samples
+#[derive(ClapArgs, Debug, Clone)]
+pub struct SimilarityArgs {
+    /// Count tables written by seqtable (.parquet/.csv/.tsv)
+    #[arg(required = true, num_args = 2..)]
+    pub input: Vec<PathBuf>,
+
+    /// Output file path
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Output format (default: inferred from the output extension, else parquet)
+    #[arg(short = 'f', long)]
+    pub format: Option<OutputFormat>,
+
+    /// Suffix stripped from input file stems to derive sample names
+    #[arg(short = 's', long, default_value = "_counts")]
+    pub suffix: String,
+
+    #[arg(long, default_value = "snappy", help = crate::output::COMPRESSION_HELP)]
+    pub compression: String,
+
+    /// Disable progress output
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+const METRICS: [&str; 3] = ["jaccard", "overlap", "spearman"];
+
+pub fn run(args: &SimilarityArgs) -> Result<()> {
+    let start_time = Instant::now();
+    let format = args
+        .format
+        .clone()
+        .or_else(|| OutputFormat::from_path(&args.output))
+        .unwrap_or(OutputFormat::Parquet);
+
+    if !args.quiet {
+        eprintln!("🧬 seqtable v0.1.1 similarity");
+        eprintln!("📁 Input tables: {}", args.input.len());
+        eprintln!("📊 Output format: {:?}", format);
+        eprintln!();
+    }
+
+    let matrix = merge::load_matrix(&args.input, &args.suffix, args.quiet)?;
+    let n = matrix.samples.len();
+
+    // Spearman over the union of sequences, absent sequences counting as zero
+    let ranks: Vec<Vec<f64>> = matrix
+        .counts
+        .par_iter()
+        .map(|column| stats::average_ranks(column))
+        .collect();
+
+    // values[metric][row][col]
+    let mut values = vec![vec![vec![1.0; n]; n]; METRICS.len()];
+    for i in 0..n {
+        for j in i + 1..n {
+            let pair = pair_metrics(
+                [&matrix.counts[i], &matrix.counts[j]],
+                [&ranks[i], &ranks[j]],
+            );
+            for (metric, value) in pair.into_iter().enumerate() {
+                values[metric][i][j] = value;
+                values[metric][j][i] = value;
+            }
+        }
+    }
+
+    // One stacked block per metric: metric, sample, then a column per sample
+    let mut metric_column = Vec::with_capacity(METRICS.len() * n);
+    let mut sample_column = Vec::with_capacity(METRICS.len() * n);
+    for metric in METRICS {
+        for sample in &matrix.samples {
+            metric_column.push(metric.to_string());
+            sample_column.push(sample.clone());
+        }
+    }
+
+    let mut columns = vec![
+        Column::new("metric", ColumnData::Text(metric_column)),
+        Column::new("sample", ColumnData::Text(sample_column)),
+    ];
+    for (col, sample) in matrix.samples.iter().enumerate() {
+        let column: Vec<f64> = values
+            .iter()
+            .flat_map(|metric| metric.iter().map(move |row| row[col]))
+            .collect();
+        columns.push(Column::new(sample, ColumnData::Float(column, 4)));
+    }
+
+    output::save_table(&columns, &args.output, &format, &args.compression)
+        .classify(Failure::Output)?;
+
+    if !args.quiet {
+        eprintln!(
+            "\n   ✓ {} samples × {} metrics → {}",
+            n,
+            METRICS.len(),
+            args.output.display()
+        );
+        eprintln!(
+            "   ⏱️  Processing time: {:.2}s",
+            start_time.elapsed().as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
+
+/// Jaccard index, overlap coefficient and Spearman correlation (as Pearson
+/// over `ranks`) of two samples' count columns, in `METRICS` order
+fn pair_metrics(counts: [&[u64]; 2], ranks: [&[f64]; 2]) -> [f64; 3] {
+    let (mut shared, mut only_a, mut only_b) = (0u64, 0u64, 0u64);
+    for (&a, &b) in counts[0].iter().zip(counts[1]) {
+        match (a > 0, b > 0) {
+            (true, true) => shared += 1,
+            (true, false) => only_a += 1,
+            (false, true) => only_b += 1,
+            (false, false) => {}
+        }
+    }
+    let union = shared + only_a + only_b;
+    let smaller = (shared + only_a).min(shared + only_b);
+    [
+        shared as f64 / union.max(1) as f64,
+        shared as f64 / smaller.max(1) as f64,
+        stats::pearson(ranks[0], ranks[1]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(a: &[u64], b: &[u64]) -> [f64; 3] {
+        let ranks = [stats::average_ranks(a), stats::average_ranks(b)];
+        pair_metrics([a, b], [&ranks[0], &ranks[1]])
+    }
+
+    #[test]
+    fn identical_samples_are_fully_similar() {
+        let counts = [5, 0, 12, 3, 40];
+        for value in metrics(&counts, &counts) {
+            assert!((value - 1.0).abs() < 1e-12, "{}", value);
+        }
+    }
+
+    #[test]
+    fn disjoint_samples_share_nothing() {
+        let [jaccard, overlap, spearman] = metrics(&[5, 7, 0, 0], &[0, 0, 3, 9]);
+        assert_eq!((jaccard, overlap), (0.0, 0.0));
+        assert!(spearman < 0.0, "{}", spearman);
+    }
+
+    #[test]
+    fn overlap_is_relative_to_the_smaller_sample() {
+        // {0, 1} within {0, 1, 2, 3}
+        let [jaccard, overlap, _] = metrics(&[1, 1, 0, 0], &[2, 2, 2, 2]);
+        assert_eq!((jaccard, overlap), (0.5, 1.0));
+    }
+}
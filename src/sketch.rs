@@ -0,0 +1,295 @@
+use crate::arena::ArenaCounts;
+use crate::bases;
+use crate::interrupt::PartialOutput;
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use md5::{Digest, Md5};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// sourmash hashes k-mers with MurmurHash3 (x64, 128-bit) seeded with 42
+const SEED: u64 = 42;
+
+/// FracMinHash sketch of one input, serialized as a sourmash signature
+pub struct Signature {
+    name: String,
+    filename: String,
+    ksize: usize,
+    max_hash: u64,
+    /// Retained hashes and their read-weighted abundances, sorted by hash
+    hashes: BTreeMap<u64, u64>,
+}
+
+impl Signature {
+    /// Sketch every canonical k-mer of the counted sequences, weighting each
+    /// k-mer's abundance by the read count of the sequence containing it
+    pub fn from_counts(
+        counts: &ArenaCounts,
+        ksize: usize,
+        scaled: u64,
+        name: &str,
+        filename: &str,
+    ) -> Self {
+        let max_hash = max_hash_for_scaled(scaled);
+
+        let counts: Vec<(&str, u64)> = counts.iter().collect();
+        let hashes = counts
+            .par_iter()
+            .fold(
+                AHashMap::new,
+                |mut acc: AHashMap<u64, u64>, &(seq, count)| {
+                    for hash in kmer_hashes(seq.as_bytes(), ksize) {
+                        if hash <= max_hash {
+                            *acc.entry(hash).or_insert(0) += count;
+                        }
+                    }
+                    acc
+                },
+            )
+            .reduce(AHashMap::new, |mut acc, map| {
+                for (hash, count) in map {
+                    *acc.entry(hash).or_insert(0) += count;
+                }
+                acc
+            });
+
+        Self {
+            name: name.to_string(),
+            filename: filename.to_string(),
+            ksize,
+            max_hash,
+            hashes: hashes.into_iter().collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// sourmash's md5sum: the k-mer size followed by each hash, as decimal strings
+    fn md5sum(&self) -> String {
+        let mut hasher = Md5::new();
+        hasher.update(self.ksize.to_string());
+        for hash in self.hashes.keys() {
+            hasher.update(hash.to_string());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "class": "sourmash_signature",
+            "email": "",
+            "hash_function": "0.murmur64",
+            "filename": self.filename,
+            "name": self.name,
+            "license": "CC0",
+            "signatures": [{
+                "num": 0,
+                "ksize": self.ksize,
+                "seed": SEED,
+                "max_hash": self.max_hash,
+                "mins": self.hashes.keys().collect::<Vec<_>>(),
+                "abundances": self.hashes.values().collect::<Vec<_>>(),
+                "md5sum": self.md5sum(),
+                "molecule": "dna",
+            }],
+            "version": 0.4,
+        })
+    }
+}
+
+/// Write all signatures to one sourmash-compatible JSON file
+pub fn save_signatures(signatures: &[Signature], output_path: &Path) -> Result<()> {
+    let partial = PartialOutput::new(output_path);
+    let file = File::create(partial.path())
+        .with_context(|| format!("Failed to create file: {}", output_path.display()))?;
+    let json: Vec<_> = signatures.iter().map(Signature::to_json).collect();
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, &json).context("Failed to write signatures")?;
+    writer.flush()?;
+    partial.finish()?;
+    Ok(())
+}
+
+/// Same rounding as sourmash: round((2^64 - 1) / scaled)
+fn max_hash_for_scaled(scaled: u64) -> u64 {
+    match scaled {
+        0 | 1 => u64::MAX,
+        _ => (u64::MAX as f64 / scaled as f64).round() as u64,
+    }
+}
+
+/// Hashes of canonical k-mers, skipping k-mers with non-ACGT bases; those
+/// spanning an `N` are skipped without being looked at
+fn kmer_hashes(seq: &[u8], ksize: usize) -> impl Iterator<Item = u64> + '_ {
+    let mut kmer = Vec::with_capacity(ksize);
+    let mut revcomp = Vec::with_capacity(ksize);
+
+    let windows = bases::split_at_n(seq).flat_map(move |run| run.windows(ksize));
+    windows.filter_map(move |window| {
+        kmer.clear();
+        revcomp.clear();
+        for &base in window {
+            kmer.push(base.to_ascii_uppercase());
+        }
+        if !bases::is_acgt(&kmer) {
+            return None;
+        }
+        revcomp.extend(kmer.iter().rev().map(|&base| bases::complement(base)));
+        let canonical = if kmer <= revcomp { &kmer } else { &revcomp };
+        Some(murmur3_x64_128(canonical, SEED).0)
+    })
+}
+
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^= k >> 33;
+    k
+}
+
+/// MurmurHash3_x64_128, returning (h1, h2)
+fn murmur3_x64_128(data: &[u8], seed: u64) -> (u64, u64) {
+    const C1: u64 = 0x87c3_7b91_1142_53d5;
+    const C2: u64 = 0x4cf5_ad43_2745_937f;
+
+    let mut h1 = seed;
+    let mut h2 = seed;
+
+    let mut blocks = data.chunks_exact(16);
+    for block in &mut blocks {
+        let mut k1 = u64::from_le_bytes(block[..8].try_into().unwrap());
+        let mut k2 = u64::from_le_bytes(block[8..].try_into().unwrap());
+
+        k1 = k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        h1 ^= k1;
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dc_e729);
+
+        k2 = k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        h2 ^= k2;
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x3849_5ab5);
+    }
+
+    let tail = blocks.remainder();
+    let mut k1 = 0u64;
+    let mut k2 = 0u64;
+    for (i, &byte) in tail.iter().enumerate().rev() {
+        if i >= 8 {
+            k2 ^= (byte as u64) << ((i - 8) * 8);
+        } else {
+            k1 ^= (byte as u64) << (i * 8);
+        }
+    }
+    if tail.len() > 8 {
+        k2 = k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        h2 ^= k2;
+    }
+    if !tail.is_empty() {
+        k1 = k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        h1 ^= k1;
+    }
+
+    let len = data.len() as u64;
+    h1 ^= len;
+    h2 ^= len;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+
+    (h1, h2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K: usize = 21;
+
+    fn random_sequence(state: &mut u64, len: usize) -> String {
+        (0..len)
+            .map(|_| {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                b"ACGT"[(*state % 4) as usize] as char
+            })
+            .collect()
+    }
+
+    fn sketch(seqs: &[&str], scaled: u64) -> Signature {
+        let counts: ArenaCounts = seqs.iter().map(|&seq| (seq, 1)).collect();
+        Signature::from_counts(&counts, K, scaled, "sample", "sample.fq")
+    }
+
+    /// Jaccard index of the retained hashes, as sourmash estimates it
+    fn jaccard(a: &Signature, b: &Signature) -> f64 {
+        let shared = a.hashes.keys().filter(|h| b.hashes.contains_key(h)).count();
+        shared as f64 / (a.len() + b.len() - shared) as f64
+    }
+
+    #[test]
+    fn a_read_and_its_reverse_complement_sketch_alike() {
+        let seq = random_sequence(&mut 0x1234_5678, 500);
+        let revcomp = String::from_utf8(bases::reverse_complement(seq.as_bytes())).unwrap();
+        let (forward, reverse) = (sketch(&[&seq], 1), sketch(&[&revcomp], 1));
+        assert_eq!(forward.hashes, reverse.hashes);
+        assert_eq!(forward.len(), 500 - K + 1);
+        assert_eq!(jaccard(&forward, &sketch(&[&seq.to_lowercase()], 1)), 1.0);
+    }
+
+    #[test]
+    fn kmers_spanning_an_n_are_skipped() {
+        let seq = random_sequence(&mut 0x9abc_def0, 100);
+        let masked = format!("{}N{}", &seq[..50], &seq[51..]);
+        // The 21 k-mers over position 50 are lost
+        assert_eq!(sketch(&[&masked], 1).len(), sketch(&[&seq], 1).len() - K);
+    }
+
+    #[test]
+    fn scaled_estimates_fall_within_their_error_bound() {
+        // Two genomes sharing 60% of A: the exact Jaccard index is known
+        // from the full sketches, and the scaled ones estimate it
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        let a = random_sequence(&mut state, 50_000);
+        let b = format!("{}{}", &a[..30_000], random_sequence(&mut state, 20_000));
+        let exact = jaccard(&sketch(&[&a], 1), &sketch(&[&b], 1));
+        assert!((exact - 30_000.0 / 70_000.0).abs() < 0.01, "{}", exact);
+
+        let scaled = 20;
+        let (a, b) = (sketch(&[&a], scaled), sketch(&[&b], scaled));
+        let estimate = jaccard(&a, &b);
+        // ~3,500 sampled hashes in the union (|A| + |B| = union × (1 + J)):
+        // four standard errors of a proportion over them
+        let union = (a.len() + b.len()) as f64 / (1.0 + estimate);
+        let bound = 4.0 * (exact * (1.0 - exact) / union).sqrt();
+        assert!(
+            (estimate - exact).abs() < bound,
+            "{} vs {} (bound {})",
+            estimate,
+            exact,
+            bound
+        );
+        assert!(
+            a.hashes
+                .keys()
+                .all(|&hash| hash <= max_hash_for_scaled(scaled))
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{Result, bail};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Skipped records described in the end-of-file report; the rest are only counted
+const MAX_REPORTED: usize = 10;
+
+/// A skipped record: where it was and what was wrong with it
+pub struct Skipped {
+    /// Byte offset or line number, for ordering
+    pub position: u64,
+    pub location: String,
+    pub reason: String,
+}
+
+/// Malformed records skipped under `--skip-errors`, shared by all parsing workers
+pub struct SkipBudget {
+    /// Most records to skip before giving up on the file (`None` = no limit)
+    limit: Option<u64>,
+    skipped: AtomicU64,
+    max_reported: usize,
+    /// Earliest skipped records by input position, as parallel workers skip out of order
+    reported: Mutex<Vec<Skipped>>,
+}
+
+impl SkipBudget {
+    /// Budget for `--skip-errors N`, where 0 means no limit
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit: (limit > 0).then_some(limit),
+            skipped: AtomicU64::new(0),
+            max_reported: MAX_REPORTED,
+            reported: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Keep details of up to `max_reported` skipped records
+    pub fn with_report_limit(mut self, max_reported: usize) -> Self {
+        self.max_reported = max_reported;
+        self
+    }
+
+    /// Record a skipped record at `position` (a byte offset or line number,
+    /// described by `location`); fails once the limit is exceeded
+    pub fn skip(&self, position: u64, location: &str, reason: &str) -> Result<()> {
+        let skipped = self.skipped.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(limit) = self.limit {
+            if skipped > limit {
+                bail!(
+                    "More than {} malformed records; last at {}: {}",
+                    limit,
+                    location,
+                    reason
+                );
+            }
+        }
+        let mut reported = self.reported.lock().unwrap();
+        reported.push(Skipped {
+            position,
+            location: location.to_string(),
+            reason: reason.to_string(),
+        });
+        reported.sort_unstable_by_key(|skipped| skipped.position);
+        reported.truncate(self.max_reported);
+        Ok(())
+    }
+
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    /// The first skipped records, in input order
+    pub fn into_reported(self) -> Vec<Skipped> {
+        self.reported.into_inner().unwrap()
+    }
+}
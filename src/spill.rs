@@ -0,0 +1,295 @@
+use crate::interrupt::PartialOutput;
+use anyhow::{Context, Result};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bytes of records held in memory before they are sorted and spilled as a run
+const RUN_BYTES: usize = 256 * 1024 * 1024;
+
+/// Runs read at once; beyond this, runs are first merged into longer ones so
+/// the open files stay well under the usual descriptor limit
+const MERGE_WIDTH: usize = 64;
+
+/// Runs created so far, numbering them apart when sorts share a stem
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// A record an `ExternalSorter` can write to a run file and read back
+pub trait Spill: Ord + Sized {
+    /// Approximate bytes the record takes up in memory
+    fn size(&self) -> usize;
+
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()>;
+
+    /// The next record of a run, or `None` at its end
+    fn read_from(reader: &mut impl BufRead) -> io::Result<Option<Self>>;
+}
+
+/// Sorts more records than fit in memory: each full buffer is sorted and
+/// written as a run next to `stem`, and the runs are read back in one k-way
+/// merge. Run files are temporary outputs, so they are removed when the sort
+/// is dropped or the run is interrupted.
+pub struct ExternalSorter<T> {
+    stem: PathBuf,
+    records: Vec<T>,
+    bytes: usize,
+    runs: Vec<PartialOutput>,
+}
+
+impl<T: Spill> ExternalSorter<T> {
+    pub fn new(stem: &Path) -> Self {
+        Self {
+            stem: stem.to_path_buf(),
+            records: Vec::new(),
+            bytes: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, record: T) -> Result<()> {
+        self.bytes += record.size();
+        self.records.push(record);
+        if self.bytes >= RUN_BYTES {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// The records in order; in memory if they never filled a run
+    pub fn finish(mut self) -> Result<Sorted<T>> {
+        if self.runs.is_empty() {
+            self.records.sort_unstable();
+            return Ok(Sorted {
+                memory: std::mem::take(&mut self.records).into_iter(),
+                heap: BinaryHeap::new(),
+                readers: Vec::new(),
+                _runs: Vec::new(),
+            });
+        }
+        self.spill()?;
+        while self.runs.len() > MERGE_WIDTH {
+            let group: Vec<PartialOutput> = self.runs.drain(..MERGE_WIDTH).collect();
+            let run = self.next_run();
+            let mut writer = create_run(&run)?;
+            for record in Sorted::<T>::merge(group)? {
+                record?.write_to(&mut writer)?;
+            }
+            writer.flush()?;
+            self.runs.push(run);
+        }
+        Sorted::merge(std::mem::take(&mut self.runs))
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        self.records.sort_unstable();
+        let run = self.next_run();
+        let mut writer = create_run(&run)?;
+        for record in self.records.drain(..) {
+            record.write_to(&mut writer)?;
+        }
+        writer
+            .flush()
+            .with_context(|| format!("Failed to write sort run: {}", run.path().display()))?;
+        self.bytes = 0;
+        self.runs.push(run);
+        Ok(())
+    }
+
+    fn next_run(&self) -> PartialOutput {
+        let name = self.stem.file_name().unwrap_or_default().to_string_lossy();
+        let run = RUNS.fetch_add(1, Ordering::Relaxed);
+        PartialOutput::new(&self.stem.with_file_name(format!("{}.run{}", name, run)))
+    }
+}
+
+fn create_run(run: &PartialOutput) -> Result<BufWriter<File>> {
+    let file = File::create(run.path())
+        .with_context(|| format!("Failed to create sort run: {}", run.path().display()))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Records in sorted order, from memory or merged from the runs
+pub struct Sorted<T> {
+    memory: std::vec::IntoIter<T>,
+    heap: BinaryHeap<Reverse<(T, usize)>>,
+    readers: Vec<BufReader<File>>,
+    /// Kept until the merge is done; dropping them removes the run files
+    _runs: Vec<PartialOutput>,
+}
+
+impl<T: Spill> Sorted<T> {
+    fn merge(runs: Vec<PartialOutput>) -> Result<Self> {
+        let mut readers = Vec::with_capacity(runs.len());
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (idx, run) in runs.iter().enumerate() {
+            let file = File::open(run.path())
+                .with_context(|| format!("Failed to open sort run: {}", run.path().display()))?;
+            let mut reader = BufReader::new(file);
+            if let Some(record) = T::read_from(&mut reader)? {
+                heap.push(Reverse((record, idx)));
+            }
+            readers.push(reader);
+        }
+        Ok(Self {
+            memory: Vec::new().into_iter(),
+            heap,
+            readers,
+            _runs: runs,
+        })
+    }
+}
+
+impl<T: Spill> Iterator for Sorted<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(record) = self.memory.next() {
+            return Some(Ok(record));
+        }
+        let Reverse((record, idx)) = self.heap.pop()?;
+        match T::read_from(&mut self.readers[idx]) {
+            Ok(Some(next)) => self.heap.push(Reverse((next, idx))),
+            Ok(None) => {}
+            Err(err) => {
+                return Some(Err(
+                    anyhow::Error::from(err).context("Failed to read sort run")
+                ));
+            }
+        }
+        Some(Ok(record))
+    }
+}
+
+/// Write a length-prefixed string
+pub fn write_str(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    writer.write_all(&(value.len() as u32).to_le_bytes())?;
+    writer.write_all(value.as_bytes())
+}
+
+/// Read a string written by `write_str`, or `None` at the end of the run
+pub fn read_str(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let len = read_u32(reader)? as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A record big enough on paper that every few of them fill a run
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Row {
+        key: String,
+        value: u64,
+    }
+
+    const ROWS_PER_RUN: usize = 4;
+
+    impl Spill for Row {
+        fn size(&self) -> usize {
+            RUN_BYTES / ROWS_PER_RUN
+        }
+
+        fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+            write_str(writer, &self.key)?;
+            writer.write_all(&self.value.to_le_bytes())
+        }
+
+        fn read_from(reader: &mut impl BufRead) -> io::Result<Option<Self>> {
+            let Some(key) = read_str(reader)? else {
+                return Ok(None);
+            };
+            Ok(Some(Row {
+                key,
+                value: read_u64(reader)?,
+            }))
+        }
+    }
+
+    /// `n` rows in a scrambled order, with repeated keys
+    fn rows(n: usize) -> Vec<Row> {
+        let mut state = 17u64;
+        (0..n)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                Row {
+                    key: format!("k{}", (state >> 33) % (n as u64 / 2 + 1)),
+                    value: state >> 60,
+                }
+            })
+            .collect()
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("seqtable-spill-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn sort(dir: &Path, rows: &[Row]) -> Vec<Row> {
+        let mut sorter = ExternalSorter::new(&dir.join("table"));
+        for row in rows {
+            sorter.push(row.clone()).unwrap();
+        }
+        sorter.finish().unwrap().map(Result::unwrap).collect()
+    }
+
+    fn check(name: &str, n: usize) {
+        let dir = scratch(name);
+        let rows = rows(n);
+        let mut expected = rows.clone();
+        expected.sort();
+        assert_eq!(sort(&dir, &rows), expected);
+        // Runs are removed once the merge is dropped
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn sorts_in_memory_without_runs() {
+        check("memory", ROWS_PER_RUN - 1);
+    }
+
+    #[test]
+    fn merges_runs_in_order_keeping_repeats() {
+        check("runs", ROWS_PER_RUN * 10 + 3);
+    }
+
+    #[test]
+    fn merges_more_runs_than_are_read_at_once() {
+        check("wide", ROWS_PER_RUN * (MERGE_WIDTH * 2 + 5));
+    }
+
+    #[test]
+    fn an_empty_sort_yields_nothing() {
+        check("empty", 0);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::checksum::Digests;
+use crate::interrupt::PartialOutput;
+use anyhow::{Context, Result};
+use serde_json::{Map, Value, json};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// State file name, kept in the output directory
+pub const STATE_FILE: &str = ".seqtable-state.json";
+
+/// Per-input completion record of a run, so `--resume` can skip inputs that
+/// an earlier interrupted or failed run already finished
+pub struct RunState {
+    path: PathBuf,
+    completed: Map<String, Value>,
+}
+
+impl RunState {
+    /// Load the state in `output_dir` when resuming, otherwise start afresh
+    pub fn open(output_dir: &Path, resume: bool) -> Result<Self> {
+        let path = output_dir.join(STATE_FILE);
+        let completed = if resume && path.exists() {
+            let file = File::open(&path)
+                .with_context(|| format!("Failed to open state file: {}", path.display()))?;
+            let state: Value = serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("Failed to parse state file: {}", path.display()))?;
+            state
+                .get("completed")
+                .and_then(Value::as_object)
+                .cloned()
+                .unwrap_or_default()
+        } else {
+            Map::new()
+        };
+        Ok(Self { path, completed })
+    }
+
+    /// Whether `input` was completed, is unchanged since, and all of `outputs`
+    /// were written by that run and still exist
+    pub fn is_complete(&self, input: &Path, outputs: &[PathBuf]) -> bool {
+        let Some(entry) = self.completed.get(&key(input)) else {
+            return false;
+        };
+        let Some(listed) = entry.get("outputs").and_then(Value::as_array) else {
+            return false;
+        };
+        let unchanged = fingerprint(input).is_ok_and(|f| entry.get("input") == Some(&f));
+        unchanged
+            && outputs
+                .iter()
+                .all(|output| output.exists() && listed.contains(&output_name(output)))
+    }
+
+    /// Outputs recorded for `input` when it was completed, as files in `dir`
+    pub fn recorded_outputs(&self, input: &Path, dir: &Path) -> Vec<PathBuf> {
+        self.completed
+            .get(&key(input))
+            .and_then(|entry| entry.get("outputs"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(|name| dir.join(name))
+            .collect()
+    }
+
+    /// Checksums of `input` recorded when it was completed, if computed
+    pub fn digests(&self, input: &Path) -> Option<Digests> {
+        let entry = self.completed.get(&key(input))?;
+        let digest = |name| entry.get(name).and_then(Value::as_str).map(str::to_string);
+        Some(Digests {
+            md5: digest("md5")?,
+            sha256: digest("sha256"),
+        })
+    }
+
+    /// Record `input` as complete and save the state file
+    pub fn mark_complete(
+        &mut self,
+        input: &Path,
+        outputs: &[PathBuf],
+        digests: Option<&Digests>,
+    ) -> Result<()> {
+        let mut entry = json!({
+            "input": fingerprint(input)?,
+            "outputs": outputs.iter().map(|p| output_name(p)).collect::<Vec<_>>(),
+        });
+        if let Some(digests) = digests {
+            entry["md5"] = json!(digests.md5);
+            if let Some(ref sha256) = digests.sha256 {
+                entry["sha256"] = json!(sha256);
+            }
+        }
+        self.completed.insert(key(input), entry);
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let partial = PartialOutput::new(&self.path);
+        let file = File::create(partial.path())
+            .with_context(|| format!("Failed to create file: {}", self.path.display()))?;
+        let mut writer = BufWriter::new(file);
+        let state = json!({ "version": 1, "completed": self.completed });
+        serde_json::to_writer_pretty(&mut writer, &state).context("Failed to write state file")?;
+        writeln!(writer)?;
+        writer.flush()?;
+        partial.finish()
+    }
+}
+
+/// Inputs are keyed by absolute path, so a resume from another directory matches
+fn key(input: &Path) -> String {
+    std::fs::canonicalize(input)
+        .unwrap_or_else(|_| input.to_path_buf())
+        .display()
+        .to_string()
+}
+
+/// Outputs sit next to the state file, so their names identify them
+fn output_name(output: &Path) -> Value {
+    json!(output.file_name().unwrap_or_default().to_string_lossy())
+}
+
+/// Size and modification time, to notice an input replaced since it was counted
+fn fingerprint(input: &Path) -> Result<Value> {
+    let metadata = std::fs::metadata(input)
+        .with_context(|| format!("Failed to read metadata: {}", input.display()))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    Ok(json!({ "size": metadata.len(), "modified": modified }))
+}
@@ -0,0 +1,308 @@
+/// Natural log of the gamma function (Lanczos approximation)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut series = 1.000_000_000_190_015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Continued fraction for the incomplete beta function (Lentz's method), or
+/// NaN if it does not converge. It takes on the order of sqrt(max(a, b))
+/// iterations, so the bound grows with the counts.
+fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+    let max_iterations = 300 + (10.0 * a.max(b).sqrt()) as usize;
+    const EPSILON: f64 = 3e-16;
+    const TINY: f64 = 1e-300;
+
+    let qab = a + b;
+    let qap = a + 1.0;
+    let qam = a - 1.0;
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..=max_iterations {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        let aa = m * (b - m) * x / ((qam + m2) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+
+        let aa = -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < EPSILON {
+            return h;
+        }
+    }
+    f64::NAN
+}
+
+/// Regularized incomplete beta function I_x(a, b) (NaN if it cannot be
+/// computed)
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+/// Two-sided binomial test of `k` successes in `n` trials against proportion `p`
+pub fn binomial_test(k: u64, n: u64, p: f64) -> f64 {
+    if n == 0 {
+        return 1.0;
+    }
+    let (k, n) = (k as f64, n as f64);
+
+    // P(X >= k) = I_p(k, n - k + 1); P(X <= k) = 1 - I_p(k + 1, n - k)
+    let upper = if k == 0.0 {
+        1.0
+    } else {
+        incomplete_beta(k, n - k + 1.0, p)
+    };
+    let lower = if k >= n {
+        1.0
+    } else {
+        1.0 - incomplete_beta(k + 1.0, n - k, p)
+    };
+    if upper.is_nan() || lower.is_nan() {
+        return f64::NAN;
+    }
+    (2.0 * upper.min(lower)).min(1.0)
+}
+
+/// Complementary error function (Chebyshev fit, fractional error < 1.2e-7)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * poly.exp();
+    if x >= 0.0 { result } else { 2.0 - result }
+}
+
+/// Pearson chi-square test (1 degree of freedom) on a 2×2 table of
+/// `a`/`b` hits out of `n_a`/`n_b` totals
+pub fn chi_square_test(a: u64, n_a: u64, b: u64, n_b: u64) -> f64 {
+    let table = [
+        [a as f64, n_a.saturating_sub(a) as f64],
+        [b as f64, n_b.saturating_sub(b) as f64],
+    ];
+    let total = (n_a + n_b) as f64;
+    if total == 0.0 {
+        return 1.0;
+    }
+
+    let row_sums = [n_a as f64, n_b as f64];
+    let col_sums = [table[0][0] + table[1][0], table[0][1] + table[1][1]];
+
+    let mut statistic = 0.0;
+    for (row, row_sum) in table.iter().zip(row_sums) {
+        for (observed, col_sum) in row.iter().zip(col_sums) {
+            let expected = row_sum * col_sum / total;
+            if expected > 0.0 {
+                statistic += (observed - expected).powi(2) / expected;
+            }
+        }
+    }
+
+    // Upper tail of chi-square with one degree of freedom
+    erfc((statistic / 2.0).sqrt())
+}
+
+/// Benjamini–Hochberg adjusted q-values, in the input order. NaN p-values
+/// (tests that could not be computed) stay NaN and are not counted.
+pub fn benjamini_hochberg(p_values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..p_values.len())
+        .filter(|&i| !p_values[i].is_nan())
+        .collect();
+    order.sort_by(|&a, &b| p_values[a].total_cmp(&p_values[b]));
+    let m = order.len();
+
+    let mut q_values = vec![f64::NAN; p_values.len()];
+    let mut running_min: f64 = 1.0;
+    for (rank, &idx) in order.iter().enumerate().rev() {
+        let q = p_values[idx] * m as f64 / (rank + 1) as f64;
+        running_min = running_min.min(q);
+        q_values[idx] = running_min;
+    }
+    q_values
+}
+
+/// Ranks starting at 1, with ties sharing their average rank
+pub fn average_ranks(values: &[u64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_unstable_by_key(|&i| values[i]);
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        // Positions start..end (0-based) share ranks start+1..=end
+        let rank = (start + end + 1) as f64 / 2.0;
+        for &idx in &order[start..end] {
+            ranks[idx] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+/// Pearson correlation coefficient (NaN when either input is constant)
+pub fn pearson(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len() as f64;
+    let mean_x = x.iter().sum::<f64>() / n;
+    let mean_y = y.iter().sum::<f64>() / n;
+
+    let (mut covariance, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (a, b) in x.iter().zip(y) {
+        covariance += (a - mean_x) * (b - mean_y);
+        var_x += (a - mean_x).powi(2);
+        var_y += (b - mean_y).powi(2);
+    }
+    covariance / (var_x * var_y).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance * expected.abs().max(1e-300),
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn incomplete_beta_matches_reference_values() {
+        // Reference values from mpmath.betainc(a, b, 0, x, regularized=True)
+        assert_close(incomplete_beta(2.0, 3.0, 0.5), 0.6875, 1e-10);
+        assert_close(incomplete_beta(5.0, 10.0, 0.3), 0.415_798_813_780_65, 1e-10);
+        assert_close(
+            incomplete_beta(0.5, 0.5, 0.2),
+            0.295_167_235_300_866_6,
+            1e-10,
+        );
+        assert_close(
+            incomplete_beta(100.0, 200.0, 0.4),
+            0.991_684_464_926_086_1,
+            1e-10,
+        );
+        assert_eq!(incomplete_beta(2.0, 3.0, 0.0), 0.0);
+        assert_eq!(incomplete_beta(2.0, 3.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn binomial_test_matches_exact_tails() {
+        // Twice the smaller exact binomial tail, summed term by term
+        assert_close(binomial_test(7, 10, 0.5), 0.343_75, 1e-10);
+        assert_close(binomial_test(3, 20, 0.05), 0.150_967_347_576_992_6, 1e-8);
+        assert_close(
+            binomial_test(4_990, 10_000, 0.5),
+            0.849_310_326_628_435_3,
+            1e-8,
+        );
+        assert_eq!(binomial_test(0, 0, 0.5), 1.0);
+    }
+
+    #[test]
+    fn binomial_test_converges_on_large_counts() {
+        assert_close(
+            binomial_test(499_000, 1_000_000, 0.5),
+            0.045_608_299_836_845_31,
+            1e-6,
+        );
+        assert_eq!(binomial_test(2_000, 1_000_000, 0.002), 1.0);
+        // 300 iterations, a fixed bound, fall short here
+        assert_close(
+            binomial_test(499_990_000, 1_000_000_000, 0.5),
+            0.527_109_683,
+            1e-5,
+        );
+    }
+
+    #[test]
+    fn chi_square_test_matches_reference_values() {
+        // 10/100 vs 20/100: statistic 200/51, upper tail erfc(sqrt(100/51))
+        assert_close(
+            chi_square_test(10, 100, 20, 100),
+            0.047_670_380_656_161,
+            1.2e-7,
+        );
+        assert_close(chi_square_test(10, 100, 10, 100), 1.0, 1e-6);
+    }
+
+    #[test]
+    fn benjamini_hochberg_keeps_input_order() {
+        let q = benjamini_hochberg(&[0.04, 0.01, 0.03, 0.02]);
+        for (actual, expected) in q.iter().zip([0.04, 0.04, 0.04, 0.04]) {
+            assert_close(*actual, expected, 1e-12);
+        }
+        let q = benjamini_hochberg(&[0.01, f64::NAN, 0.5]);
+        assert_close(q[0], 0.02, 1e-12);
+        assert!(q[1].is_nan());
+        assert_close(q[2], 0.5, 1e-12);
+    }
+
+    #[test]
+    fn average_ranks_share_ties() {
+        assert_eq!(average_ranks(&[10, 20, 10, 30]), vec![1.5, 3.0, 1.5, 4.0]);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::artifacts::ArtifactCounts;
+use crate::diversity::Diversity;
+use crate::exit::{Classify, Failure};
+use crate::interrupt::PartialOutput;
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What counting one input took and produced
+pub struct FileSummary {
+    pub input: PathBuf,
+    pub outputs: Vec<PathBuf>,
+    pub total_reads: u64,
+    pub unique_sequences: usize,
+    /// Input bytes as stored on disk (compressed, if it is)
+    pub bytes_read: u64,
+    pub elapsed: Duration,
+    /// Share of the count map's capacity in use when counting finished
+    pub map_load_factor: Option<f64>,
+    /// Process peak resident memory once the input was done
+    pub peak_rss: Option<u64>,
+    /// Reads matching each `--contaminants` contaminant, by name
+    pub contaminant_reads: Vec<(String, u64)>,
+    /// `--diversity` of each table written for the input
+    pub diversity: Vec<Diversity>,
+    /// `--artifacts` breakdown of the input's reads
+    pub artifacts: Option<ArtifactCounts>,
+}
+
+impl FileSummary {
+    pub fn reads_per_sec(&self) -> f64 {
+        self.total_reads as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "input": self.input.display().to_string(),
+            "outputs": self.outputs.iter().map(|p| p.display().to_string()).collect::<Vec<_>>(),
+            "total_reads": self.total_reads,
+            "unique_sequences": self.unique_sequences,
+            "bytes_read": self.bytes_read,
+            "elapsed_secs": self.elapsed.as_secs_f64(),
+            "reads_per_sec": self.reads_per_sec(),
+            "map_load_factor": self.map_load_factor,
+            "peak_rss_bytes": self.peak_rss,
+            "contaminant_reads": self
+                .contaminant_reads
+                .iter()
+                .map(|(name, reads)| (name.clone(), json!(reads)))
+                .collect::<serde_json::Map<_, _>>(),
+            "diversity": self.diversity.iter().map(Diversity::to_json).collect::<Vec<_>>(),
+            "artifacts": self.artifacts.as_ref().map(|a| a.to_json(self.total_reads)),
+        })
+    }
+}
+
+/// Failures file `--keep-going` writes into the output directory
+pub const FAILURES_FILE: &str = "failures.json";
+
+/// An input `--keep-going` gave up on, and why
+pub struct FailedFile {
+    pub input: PathBuf,
+    pub error: String,
+}
+
+impl FailedFile {
+    fn to_json(&self) -> Value {
+        json!({
+            "input": self.input.display().to_string(),
+            "error": self.error,
+        })
+    }
+}
+
+/// Totals over a run's inputs, for the closing summary and `--summary`
+pub struct RunSummary {
+    pub files: Vec<FileSummary>,
+    /// Inputs a resumed run found already complete
+    pub skipped: Vec<PathBuf>,
+    /// Inputs that failed under `--keep-going`
+    pub failed: Vec<FailedFile>,
+    /// The run's resolved configuration, from `config::resolved`
+    pub config: Value,
+}
+
+impl RunSummary {
+    pub fn total_reads(&self) -> u64 {
+        self.files.iter().map(|file| file.total_reads).sum()
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.files.iter().map(|file| file.bytes_read).sum()
+    }
+
+    /// One line of totals: reads, bytes, rate, and peak memory
+    pub fn describe(&self, elapsed: Duration) -> String {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let mut line = format!(
+            "{} reads, {} read in {:.2}s ({:.0} reads/s)",
+            self.total_reads(),
+            format_bytes(self.bytes_read()),
+            secs,
+            self.total_reads() as f64 / secs
+        );
+        if let Some(rss) = peak_rss() {
+            line.push_str(&format!(", peak memory {}", format_bytes(rss)));
+        }
+        line
+    }
+
+    pub fn to_json(&self, elapsed: Duration) -> Value {
+        let secs = elapsed.as_secs_f64();
+        json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "elapsed_secs": secs,
+            "total_reads": self.total_reads(),
+            "bytes_read": self.bytes_read(),
+            "reads_per_sec": self.total_reads() as f64 / secs.max(f64::EPSILON),
+            "peak_rss_bytes": peak_rss(),
+            "files": self.files.iter().map(FileSummary::to_json).collect::<Vec<_>>(),
+            "skipped": self.skipped.iter().map(|p| p.display().to_string()).collect::<Vec<_>>(),
+            "failed": self.failed.iter().map(FailedFile::to_json).collect::<Vec<_>>(),
+            "config": self.config,
+        })
+    }
+
+    /// The failed inputs and their errors, for `FAILURES_FILE`
+    pub fn failures_json(&self) -> Value {
+        json!({
+            "inputs": self.files.len() + self.skipped.len() + self.failed.len(),
+            "failed": self.failed.iter().map(FailedFile::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Peak resident set size of this process, where the OS reports it (Linux)
+pub fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Memory the OS could give this process without swapping (Linux)
+pub fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Bytes in binary units (e.g. 1.5 GiB)
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Write the summary as pretty JSON, or to stdout for `-`
+pub fn write(path: &Path, summary: &Value) -> Result<()> {
+    if path == Path::new("-") {
+        let mut stdout = std::io::stdout().lock();
+        return write_json(&mut stdout, summary).classify(Failure::Output);
+    }
+    let partial = PartialOutput::new(path);
+    let file = File::create(partial.path())
+        .with_context(|| format!("Failed to create file: {}", path.display()))
+        .classify(Failure::Output)?;
+    let mut writer = BufWriter::new(file);
+    write_json(&mut writer, summary).classify(Failure::Output)?;
+    partial.finish().classify(Failure::Output)
+}
+
+fn write_json(writer: &mut impl Write, summary: &Value) -> Result<()> {
+    serde_json::to_writer_pretty(&mut *writer, summary).context("Failed to write summary")?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,250 @@
+use crate::input::{self, Input};
+use crate::provenance::{SAMPLE_KEY, SCHEMA_KEY, SORT_KEY};
+use crate::schema::SCHEMA_VERSION;
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use arrow::array::{Array, AsArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, UInt64Type};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Extensions of count tables, which the counting command reads back as inputs
+pub const TABLE_EXTENSIONS: [&str; 3] = ["parquet", "csv", "tsv"];
+
+/// Whether `path` names a count table rather than reads, by its extension
+pub fn is_count_table(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| TABLE_EXTENSIONS.iter().any(|t| ext.eq_ignore_ascii_case(t)))
+}
+
+/// A count table previously written by seqtable (Parquet/CSV/TSV)
+pub struct CountTable {
+    /// Name of the key column (`sequence`, or `group` for grouped counts)
+    pub key_column: String,
+    pub counts: AHashMap<String, u64>,
+    /// Sample name recorded in the table (Parquet metadata or a `sample`
+    /// column), if any
+    pub sample: Option<String>,
+}
+
+impl CountTable {
+    /// Read a count table, detecting the format from the file extension
+    pub fn read(path: &Path) -> Result<Self> {
+        let rows = TableRows::open(path)?;
+        let key_column = rows.key_column.clone();
+        let sample = rows.sample.clone();
+        let mut counts = AHashMap::new();
+        for row in rows {
+            let (key, count) =
+                row.with_context(|| format!("Failed to read count table: {}", path.display()))?;
+            *counts.entry(key).or_insert(0) += count;
+        }
+        Ok(CountTable {
+            key_column,
+            counts,
+            sample,
+        })
+    }
+
+    /// Read headerless `sequence<TAB>count` lines, as other tools write
+    /// them; blank lines are skipped and repeated sequences add up
+    pub fn read_text(input: &Input) -> Result<Self> {
+        let path = input.path();
+        let (stream, _) = input::open_decoded(input, None)?;
+        let mut counts = AHashMap::new();
+        for (line, text) in BufReader::new(stream).lines().enumerate() {
+            let text =
+                text.with_context(|| format!("Failed to read counts: {}", path.display()))?;
+            let text = text.trim_end_matches('\r');
+            if text.is_empty() {
+                continue;
+            }
+            let parsed = text
+                .split_once('\t')
+                .and_then(|(key, count)| Some((key, count.trim().parse::<u64>().ok()?)));
+            let Some((key, count)) = parsed else {
+                anyhow::bail!(
+                    "{} line {}: expected sequence<TAB>count, got '{}'",
+                    path.display(),
+                    line + 1,
+                    text
+                );
+            };
+            *counts.entry(key.to_string()).or_insert(0) += count;
+        }
+        Ok(CountTable {
+            key_column: "sequence".to_string(),
+            counts,
+            sample: None,
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+/// The (key, count) rows of a count table in file order, read a batch at a
+/// time so tables larger than memory can be streamed
+pub struct TableRows {
+    /// Name of the key column (`sequence`, or `group` for grouped counts)
+    pub key_column: String,
+    /// Sample name recorded in the table, as for `CountTable`
+    pub sample: Option<String>,
+    /// `seqtable.sort` from a Parquet footer: how the rows were ordered
+    pub sort: Option<String>,
+    rows: Box<dyn Iterator<Item = Result<(String, u64)>>>,
+}
+
+impl TableRows {
+    pub fn open(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase)
+            .unwrap_or_default();
+
+        let rows = match extension.as_str() {
+            "parquet" => parquet_rows(path),
+            "csv" => delimited_rows(path, b','),
+            "tsv" | "txt" => delimited_rows(path, b'\t'),
+            _ => anyhow::bail!(
+                "Unrecognized count table extension (expected .parquet/.csv/.tsv): {}",
+                path.display()
+            ),
+        };
+        rows.with_context(|| format!("Failed to read count table: {}", path.display()))
+    }
+}
+
+impl Iterator for TableRows {
+    type Item = Result<(String, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next()
+    }
+}
+
+/// Find the key (`sequence`/`group`, else the first column) and `count` columns
+fn locate_columns(names: &[&str]) -> Result<(usize, usize)> {
+    let found = || format!("found columns: {}", names.join(", "));
+    let count = names
+        .iter()
+        .position(|&name| name == "count")
+        .with_context(|| format!("Missing `count` column ({})", found()))?;
+    let key = names
+        .iter()
+        .position(|&name| name == "sequence" || name == "group")
+        .or_else(|| (count != 0).then_some(0))
+        .with_context(|| format!("Missing key column (`sequence` or `group`; {})", found()))?;
+    Ok((key, count))
+}
+
+fn parquet_rows(path: &Path) -> Result<TableRows> {
+    let file = File::open(path)?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+
+    let schema = builder.schema().clone();
+    let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    let (key_idx, count_idx) = locate_columns(&names)?;
+    let sample_idx = names.iter().position(|&name| name == "sample");
+    let footer = |key: &str| {
+        builder
+            .metadata()
+            .file_metadata()
+            .key_value_metadata()
+            .and_then(|metadata| {
+                metadata
+                    .iter()
+                    .find(|kv| kv.key == key)
+                    .and_then(|kv| kv.value.clone())
+            })
+    };
+    // Columns of a newer schema may have changed meaning under the same name
+    if let Some(version) = footer(SCHEMA_KEY).and_then(|v| v.parse::<u32>().ok()) {
+        if version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "Written with count table schema version {}; this seqtable reads up to {}",
+                version,
+                SCHEMA_VERSION
+            );
+        }
+    }
+    let mut sample = footer(SAMPLE_KEY);
+    let sort = footer(SORT_KEY);
+    let key_column = names[key_idx].to_string();
+
+    let mut batches = builder.build()?.peekable();
+    if let (None, Some(idx), Some(Ok(batch))) = (&sample, sample_idx, batches.peek()) {
+        let samples = cast(batch.column(idx), &DataType::Utf8)?;
+        let samples = samples.as_string::<i32>();
+        sample =
+            (batch.num_rows() > 0 && samples.is_valid(0)).then(|| samples.value(0).to_string());
+    }
+
+    let rows = batches.flat_map(move |batch| {
+        let rows = batch
+            .map_err(anyhow::Error::from)
+            .and_then(|batch| batch_rows(&batch, key_idx, count_idx));
+        let rows: Box<dyn Iterator<Item = Result<(String, u64)>>> = match rows {
+            Ok(rows) => Box::new(rows.into_iter().map(Ok)),
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        };
+        rows
+    });
+    Ok(TableRows {
+        key_column,
+        sample,
+        sort,
+        rows: Box::new(rows),
+    })
+}
+
+/// The rows of one Parquet batch with a key
+fn batch_rows(batch: &RecordBatch, key_idx: usize, count_idx: usize) -> Result<Vec<(String, u64)>> {
+    let keys = cast(batch.column(key_idx), &DataType::Utf8)?;
+    let keys = keys.as_string::<i32>();
+    let values = cast(batch.column(count_idx), &DataType::UInt64)?;
+    let values = values.as_primitive::<UInt64Type>();
+    Ok((0..batch.num_rows())
+        .filter(|&row| !keys.is_null(row))
+        .map(|row| (keys.value(row).to_string(), values.value(row)))
+        .collect())
+}
+
+fn delimited_rows(path: &Path, delimiter: u8) -> Result<TableRows> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_path(path)?;
+
+    let headers = reader.headers()?.clone();
+    let names: Vec<&str> = headers.iter().collect();
+    let (key_idx, count_idx) = locate_columns(&names)?;
+    let sample_idx = names.iter().position(|&name| name == "sample");
+    let key_column = names[key_idx].to_string();
+
+    let mut records = reader.into_records().peekable();
+    let sample = match (sample_idx, records.peek()) {
+        (Some(idx), Some(Ok(row))) => row.get(idx).map(str::to_string),
+        _ => None,
+    };
+
+    let rows = records.enumerate().map(move |(line, row)| {
+        let row = row?;
+        let count: u64 = row[count_idx]
+            .parse()
+            .with_context(|| format!("Invalid count on data row {}", line + 1))?;
+        Ok((row[key_idx].to_string(), count))
+    });
+    Ok(TableRows {
+        key_column,
+        sample,
+        sort: None,
+        rows: Box::new(rows),
+    })
+}
@@ -0,0 +1,246 @@
+use crate::table::TABLE_EXTENSIONS;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Compression extensions stripped before the sequence format extension
+const COMPRESSION_EXTENSIONS: [&str; 6] = ["gz", "bgz", "bz2", "xz", "zst", "zstd"];
+
+/// Sequence format extensions stripped from input names
+const SEQUENCE_EXTENSIONS: [&str; 5] = ["fastq", "fq", "fasta", "fa", "fna"];
+
+const PLACEHOLDERS: [&str; 5] = ["stem", "sample", "suffix", "date", "ext"];
+
+/// Output file name pattern, e.g. `{stem}_{sample}_{date}.{ext}`
+#[derive(Debug, Clone)]
+pub struct OutputTemplate {
+    pattern: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// Values substituted for one input's outputs
+pub struct Names<'a> {
+    pub stem: &'a str,
+    pub sample: &'a str,
+    pub suffix: &'a str,
+}
+
+impl FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, String> {
+        if pattern.contains(['/', '\\']) {
+            return Err("must be a file name (set the directory with -o)".to_string());
+        }
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_string()));
+            }
+            let Some(close) = rest[open..].find('}') else {
+                return Err(format!("unclosed '{{' in '{}'", pattern));
+            };
+            let name = &rest[open + 1..open + close];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder '{{{}}}' (expected {})",
+                    name,
+                    PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+                ));
+            }
+            parts.push(Part::Placeholder(name.to_string()));
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for OutputTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+impl OutputTemplate {
+    /// Whether outputs of different formats get different names
+    pub fn has_ext(&self) -> bool {
+        self.parts.contains(&Part::Placeholder("ext".to_string()))
+    }
+
+    /// File name of the output with extension `ext`
+    pub fn render(&self, names: &Names, ext: &str) -> String {
+        self.render_parts(&self.parts, names, ext)
+    }
+
+    /// File name of a companion report: the table's name with `tail` in place
+    /// of a final `.{ext}`, or appended when the template has none
+    pub fn render_companion(&self, names: &Names, tail: &str) -> String {
+        let ext = Part::Placeholder("ext".to_string());
+        match self.parts.as_slice() {
+            [head @ .., Part::Literal(dot), last] if *last == ext && dot.ends_with('.') => {
+                let mut name = self.render_parts(head, names, "");
+                name.push_str(&dot[..dot.len() - 1]);
+                name + tail
+            }
+            _ => self.render(names, "") + tail,
+        }
+    }
+
+    fn render_parts(&self, parts: &[Part], names: &Names, ext: &str) -> String {
+        parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(text) => text.as_str(),
+                Part::Placeholder(name) => match name.as_str() {
+                    "stem" => names.stem,
+                    "sample" => names.sample,
+                    "suffix" => names.suffix,
+                    "date" => run_date(),
+                    _ => ext,
+                },
+            })
+            .collect()
+    }
+}
+
+/// Input file name without its compression and FASTA/FASTQ extensions, so
+/// `a.fastq.gz`, `a.fq` and `a.fasta` are all `a` (as are count tables
+/// `a.parquet` and `a.tsv.gz`)
+pub fn stem(input_path: &Path) -> String {
+    let name = input_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("output");
+    let name = strip_extension(name, &COMPRESSION_EXTENSIONS);
+    let name = strip_extension(name, &TABLE_EXTENSIONS);
+    let name = strip_extension(name, &SEQUENCE_EXTENSIONS);
+    if name.is_empty() {
+        "output".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// `name` without a final `.ext` from `extensions` (ignoring case), if present
+fn strip_extension<'a>(name: &'a str, extensions: &[&str]) -> &'a str {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)) => stem,
+        _ => name,
+    }
+}
+
+/// UTC date the run started (YYYY-MM-DD), the same for all of its outputs
+fn run_date() -> &'static str {
+    static DATE: OnceLock<String> = OnceLock::new();
+    DATE.get_or_init(|| {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    })
+}
+
+/// Gregorian date of a day count since 1970-01-01 (Howard Hinnant's algorithm)
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names<'a>(stem: &'a str) -> Names<'a> {
+        Names {
+            stem,
+            sample: "s1",
+            suffix: "_counts",
+        }
+    }
+
+    #[test]
+    fn stems_drop_compression_then_format_extensions() {
+        let stems = [
+            ("reads.fastq.gz", "reads"),
+            ("reads.fq.bz2", "reads"),
+            ("reads.FQ.ZST", "reads"),
+            ("reads.fasta", "reads"),
+            ("a.1_counts.tsv", "a.1_counts"),
+            ("a.1_counts.tsv.gz", "a.1_counts"),
+            ("a.1.fq.gz", "a.1"),
+            ("sample.v2", "sample.v2"),
+            (".fq.gz", "output"),
+        ];
+        for (name, stem) in stems {
+            assert_eq!(super::stem(Path::new(name)), stem, "{}", name);
+        }
+        assert_eq!(super::stem(Path::new("/data/run.7/x.fq")), "x");
+    }
+
+    #[test]
+    fn placeholders_are_filled_in_for_each_output() {
+        let template: OutputTemplate = "{stem}{suffix}.{ext}".parse().unwrap();
+        assert!(template.has_ext());
+        let stem = super::stem(Path::new("a.1.fastq.gz"));
+        assert_eq!(
+            template.render(&names(&stem), "parquet"),
+            "a.1_counts.parquet"
+        );
+        assert_eq!(
+            template.render_companion(&names(&stem), "_qc.json"),
+            "a.1_counts_qc.json"
+        );
+
+        let template: OutputTemplate = "{sample}-{stem}.tsv".parse().unwrap();
+        assert!(!template.has_ext());
+        assert_eq!(template.render(&names("a.1"), "parquet"), "s1-a.1.tsv");
+        assert_eq!(
+            template.render_companion(&names("a.1"), "_qc.json"),
+            "s1-a.1.tsv_qc.json"
+        );
+
+        let dated = OutputTemplate::from_str("{stem}_{date}.{ext}").unwrap();
+        let name = dated.render(&names("x"), "csv");
+        assert_eq!(name.len(), "x_YYYY-MM-DD.csv".len());
+        assert_eq!(name, dated.render(&names("x"), "csv"));
+    }
+
+    #[test]
+    fn bad_templates_are_refused() {
+        for pattern in ["{stem", "{name}.{ext}", "out/{stem}", "{}"] {
+            assert!(pattern.parse::<OutputTemplate>().is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn day_counts_become_calendar_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(59), (1970, 3, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+}
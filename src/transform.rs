@@ -0,0 +1,264 @@
+use crate::amplicon::Amplicons;
+use crate::contaminants::{ContaminantAction, Contaminants};
+use crate::key::{KeySpec, Mask};
+use crate::orient::Orientation;
+use std::borrow::Cow;
+
+/// Where in the chain a transform runs. Stages run in this order, and the
+/// transforms of one stage in the order they were given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Change bases in place, at their positions as sequenced
+    Trim,
+    /// Leave reads out, flag them, or put them on the reference strand,
+    /// while they are whole
+    Filter,
+    /// Cut out the part of the read that is counted
+    Extract,
+    /// Bring the counted parts of reads of one molecule to one form
+    Normalize,
+}
+
+/// A read on its way through the transforms
+pub struct Read<'s> {
+    pub seq: Cow<'s, [u8]>,
+    /// Amplicon of a panel the insert came from
+    pub amplicon: Option<&'s str>,
+    /// Contaminant a flagged read matched, empty for none
+    pub contaminant: Option<&'s str>,
+}
+
+impl<'s> Read<'s> {
+    /// Replace the bases with what `step` makes of them; false if it leaves
+    /// the read uncounted
+    pub fn map(&mut self, step: impl FnOnce(&[u8]) -> Option<Cow<'_, [u8]>>) -> bool {
+        let next = match self.seq {
+            Cow::Borrowed(seq) => step(seq),
+            Cow::Owned(ref seq) => match step(seq) {
+                // Left as it was
+                Some(Cow::Borrowed(part)) if part.len() == seq.len() => return true,
+                next => next.map(|part| Cow::Owned(part.into_owned())),
+            },
+        };
+        match next {
+            Some(seq) => {
+                self.seq = seq;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// One preprocessing step between a read and its counting keys
+pub trait ReadTransform: Sync {
+    fn stage(&self) -> Stage;
+
+    /// Transform `read` in place; false leaves it uncounted. With `tally`,
+    /// the read counts toward the step's report.
+    fn apply<'s>(&'s self, read: &mut Read<'s>, tally: bool) -> bool;
+
+    /// Whether the step names something in the read that is keyed ahead of
+    /// its sequence (a panel amplicon, a flagged contaminant)
+    fn adds_field(&self) -> bool {
+        false
+    }
+}
+
+/// The transforms a run puts reads through, in stage order
+#[derive(Default)]
+pub struct Transforms<'a> {
+    steps: Vec<&'a dyn ReadTransform>,
+}
+
+impl<'a> Transforms<'a> {
+    pub fn new(mut steps: Vec<&'a dyn ReadTransform>) -> Self {
+        steps.sort_by_key(|step| step.stage());
+        Self { steps }
+    }
+
+    /// The chain with `step` added, after the steps of its stage given so far
+    pub fn with(mut self, step: &'a dyn ReadTransform) -> Self {
+        self.steps.push(step);
+        self.steps.sort_by_key(|step| step.stage());
+        self
+    }
+
+    /// `seq` through every transform, or `None` if one leaves it uncounted
+    pub fn apply<'s>(&self, seq: &'s [u8], tally: bool) -> Option<Read<'s>>
+    where
+        'a: 's,
+    {
+        let mut read = Read {
+            seq: Cow::Borrowed(seq),
+            amplicon: None,
+            contaminant: None,
+        };
+        self.steps
+            .iter()
+            .all(|step| step.apply(&mut read, tally))
+            .then_some(read)
+    }
+
+    pub fn adds_fields(&self) -> bool {
+        self.steps.iter().any(|step| step.adds_field())
+    }
+}
+
+impl ReadTransform for Contaminants {
+    fn stage(&self) -> Stage {
+        Stage::Filter
+    }
+
+    fn apply<'s>(&'s self, read: &mut Read<'s>, tally: bool) -> bool {
+        let hit = self.screen(&read.seq, tally);
+        match self.action {
+            ContaminantAction::Remove => hit.is_none(),
+            ContaminantAction::Flag => {
+                read.contaminant = Some(hit.unwrap_or_default());
+                true
+            }
+        }
+    }
+
+    fn adds_field(&self) -> bool {
+        self.action == ContaminantAction::Flag
+    }
+}
+
+impl ReadTransform for Vec<Mask> {
+    fn stage(&self) -> Stage {
+        Stage::Trim
+    }
+
+    fn apply<'s>(&'s self, read: &mut Read<'s>, _tally: bool) -> bool {
+        read.map(|seq| Some(Mask::apply(self, seq)))
+    }
+}
+
+impl ReadTransform for Orientation {
+    /// Reads are oriented before `--key` slices them, so slices are taken
+    /// from the oriented read
+    fn stage(&self) -> Stage {
+        Stage::Filter
+    }
+
+    fn apply<'s>(&'s self, read: &mut Read<'s>, tally: bool) -> bool {
+        read.map(|seq| Some(self.orient(seq, tally)))
+    }
+}
+
+impl ReadTransform for KeySpec {
+    fn stage(&self) -> Stage {
+        Stage::Extract
+    }
+
+    fn apply<'s>(&'s self, read: &mut Read<'s>, _tally: bool) -> bool {
+        read.map(|seq| self.extract(seq))
+    }
+}
+
+impl ReadTransform for Amplicons {
+    fn stage(&self) -> Stage {
+        Stage::Extract
+    }
+
+    fn apply<'s>(&'s self, read: &mut Read<'s>, _tally: bool) -> bool {
+        let mut amplicon = None;
+        let found = read.map(|seq| {
+            let (name, insert) = self.insert(seq)?;
+            amplicon = Some(name);
+            Some(insert)
+        });
+        if self.named() {
+            read.amplicon = amplicon;
+        }
+        found
+    }
+
+    fn adds_field(&self) -> bool {
+        self.named()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Logs the stage it runs in, and leaves out reads starting with `drop`
+    struct Step<'l> {
+        stage: Stage,
+        log: &'l Mutex<Vec<Stage>>,
+        drop: &'static [u8],
+    }
+
+    impl<'l> Step<'l> {
+        fn new(stage: Stage, log: &'l Mutex<Vec<Stage>>) -> Self {
+            Self {
+                stage,
+                log,
+                drop: b"-",
+            }
+        }
+    }
+
+    impl ReadTransform for Step<'_> {
+        fn stage(&self) -> Stage {
+            self.stage
+        }
+
+        fn apply<'s>(&'s self, read: &mut Read<'s>, _tally: bool) -> bool {
+            self.log.lock().unwrap().push(self.stage);
+            !read.seq.starts_with(self.drop)
+        }
+    }
+
+    #[test]
+    fn stages_run_trim_filter_extract_normalize() {
+        let log = Mutex::new(Vec::new());
+        let steps = [
+            Step::new(Stage::Normalize, &log),
+            Step::new(Stage::Extract, &log),
+            Step::new(Stage::Filter, &log),
+            Step::new(Stage::Trim, &log),
+        ];
+        let transforms = Transforms::new(steps.iter().map(|s| s as &dyn ReadTransform).collect());
+        assert!(transforms.apply(b"ACGT", true).is_some());
+        assert_eq!(
+            *log.lock().unwrap(),
+            [Stage::Trim, Stage::Filter, Stage::Extract, Stage::Normalize]
+        );
+    }
+
+    #[test]
+    fn reads_a_filter_drops_never_reach_extraction() {
+        let log = Mutex::new(Vec::new());
+        let filter = Step {
+            drop: b"GG",
+            ..Step::new(Stage::Filter, &log)
+        };
+        let extract = Step::new(Stage::Extract, &log);
+        let transforms = Transforms::new(vec![&extract, &filter]);
+
+        assert!(transforms.apply(b"GGACGT", true).is_none());
+        assert_eq!(*log.lock().unwrap(), [Stage::Filter]);
+        assert!(transforms.apply(b"ACGTGG", true).is_some());
+        assert_eq!(
+            *log.lock().unwrap(),
+            [Stage::Filter, Stage::Filter, Stage::Extract]
+        );
+    }
+
+    #[test]
+    fn masks_apply_before_the_key_is_cut() {
+        let masks: Vec<Mask> = vec!["1:3".parse().unwrap()];
+        let key: KeySpec = "seq[0:4]".parse().unwrap();
+        let transforms = Transforms::new(vec![&key, &masks]);
+        let read = transforms.apply(b"ACGTACGT", true).unwrap();
+        assert_eq!(&read.seq[..], b"ANNT");
+
+        // A read too short for the key is left uncounted
+        assert!(transforms.apply(b"ACG", true).is_none());
+    }
+}
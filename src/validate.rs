@@ -0,0 +1,230 @@
+use crate::exit::{Classify, Failure};
+use crate::input::{self, Input, ReadOptions};
+use crate::interrupt::PartialOutput;
+use crate::progress;
+use crate::quality::{self, Detection, PhredOffset};
+use crate::skip::SkipBudget;
+use anyhow::{Context, Result, anyhow};
+use clap::Args as ClapArgs;
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+/// Check FASTA/FASTQ files for malformed records and corrupt compression
+#[derive(ClapArgs, Debug, Clone)]
+pub struct ValidateArgs {
+    /// Input files (FASTA/FASTQ, optionally compressed)
+    #[arg(required = true)]
+    pub input: Vec<PathBuf>,
+
+    /// Write a JSON report of all problems found (`-` for stdout)
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Malformed records described per file; the rest are only counted
+    #[arg(long, value_name = "N", default_value = "100")]
+    pub max_reported: usize,
+
+    /// FASTQ quality encoding to check for; detected unless given
+    #[arg(long, value_name = "OFFSET", default_value = "auto")]
+    pub phred_offset: PhredOffset,
+
+    /// Disable progress output
+    #[arg(short, long)]
+    pub quiet: bool,
+}
+
+/// Outcome of validating one file
+struct FileReport {
+    path: PathBuf,
+    records: u64,
+    malformed: u64,
+    errors: Vec<Value>,
+    /// Error that stopped the read: a corrupt or truncated stream, or an
+    /// input that is not FASTA/FASTQ at all
+    fatal: Option<String>,
+    /// Quality encoding of a FASTQ input
+    quality: Option<Detection>,
+}
+
+impl FileReport {
+    fn is_valid(&self) -> bool {
+        self.malformed == 0 && self.fatal.is_none()
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "path": self.path.display().to_string(),
+            "valid": self.is_valid(),
+            "records": self.records,
+            "malformed_records": self.malformed,
+            "errors": self.errors,
+            "fatal_error": self.fatal,
+            "quality_encoding": self.quality.map(|q| q.encoding.name()),
+            "quality_warning": self.quality.as_ref().and_then(quality::warning),
+        })
+    }
+}
+
+pub fn run(args: &ValidateArgs) -> Result<()> {
+    let start_time = Instant::now();
+    let verbose = !args.quiet;
+
+    if verbose {
+        eprintln!("🧬 seqtable v0.1.1 validate");
+        eprintln!("📁 Input files: {}", args.input.len());
+        eprintln!();
+    }
+
+    let mut reports = Vec::with_capacity(args.input.len());
+    for path in &args.input {
+        if verbose {
+            eprintln!("📄 Validating: {}", path.display());
+        }
+        let report = validate_file(path, args, verbose);
+        if verbose {
+            print_summary(&report);
+        }
+        reports.push(report);
+    }
+
+    let invalid = reports.iter().filter(|r| !r.is_valid()).count();
+    if let Some(ref report_path) = args.report {
+        let report = json!({
+            "valid": invalid == 0,
+            "files": reports.iter().map(FileReport::to_json).collect::<Vec<_>>(),
+        });
+        if report_path == Path::new("-") {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &report)?;
+            writeln!(stdout)?;
+        } else {
+            write_report(report_path, &report)?;
+            if verbose {
+                eprintln!("📋 Report: {}", report_path.display());
+            }
+        }
+    }
+
+    if verbose {
+        eprintln!("⏱️  Total time: {:.2}s", start_time.elapsed().as_secs_f64());
+    }
+    if invalid > 0 {
+        return Err(anyhow!(
+            "{} of {} files failed validation",
+            invalid,
+            reports.len()
+        ))
+        .classify(Failure::Input);
+    }
+    Ok(())
+}
+
+/// Read every record of `path`, collecting malformed records rather than
+/// stopping at the first
+fn validate_file(path: &Path, args: &ValidateArgs, show_progress: bool) -> FileReport {
+    let budget = SkipBudget::new(0).with_report_limit(args.max_reported);
+    let input = Input::new(path);
+    let mut records = 0;
+    let fatal = read_all(&input, &budget, show_progress, &mut records)
+        .err()
+        .map(|e| format!("{:#}", e));
+    let malformed = budget.skipped();
+    let errors = budget
+        .into_reported()
+        .into_iter()
+        .map(|skipped| {
+            json!({
+                "line": skipped.position,
+                "message": skipped.reason,
+            })
+        })
+        .collect();
+    FileReport {
+        path: path.to_path_buf(),
+        records,
+        malformed,
+        errors,
+        fatal,
+        quality: quality::resolve(&input, args.phred_offset),
+    }
+}
+
+fn read_all(
+    input: &Input,
+    budget: &SkipBudget,
+    show_progress: bool,
+    records: &mut u64,
+) -> Result<()> {
+    let file_size = input.file_size()?;
+    let (mut reader, consumed) = input::open_records(
+        input,
+        ReadOptions {
+            skip: Some(budget),
+            ..Default::default()
+        },
+    )?;
+
+    let pb = show_progress.then(|| progress::byte_bar(file_size));
+    while reader.next_record(|_, _, _| {})? {
+        *records += 1;
+        if let Some(ref pb) = pb {
+            if records.is_multiple_of(10000) {
+                pb.set_position(consumed.load(Ordering::Relaxed));
+                progress::set_read_rate(pb, *records);
+            }
+        }
+    }
+    if let Some(pb) = pb {
+        pb.finish_and_clear();
+    }
+    Ok(())
+}
+
+fn print_summary(report: &FileReport) {
+    if let Some(ref detection) = report.quality {
+        eprintln!("   🎚️  Quality encoding: {}", detection.encoding.name());
+        if let Some(warning) = quality::warning(detection) {
+            eprintln!("   ⚠️  {}", warning);
+        }
+    }
+    if report.is_valid() {
+        eprintln!("   ✓ {} records, no problems found", report.records);
+        return;
+    }
+    if report.malformed > 0 {
+        eprintln!(
+            "   ❌ {} malformed records (of {} read)",
+            report.malformed,
+            report.records + report.malformed
+        );
+        let shown = report.errors.len().min(10);
+        for error in &report.errors[..shown] {
+            eprintln!(
+                "      line {}: {}",
+                error["line"],
+                error["message"].as_str().unwrap_or_default()
+            );
+        }
+        if report.malformed > shown as u64 {
+            eprintln!("      ... and {} more", report.malformed - shown as u64);
+        }
+    }
+    if let Some(ref fatal) = report.fatal {
+        eprintln!("   ❌ {}", fatal);
+    }
+}
+
+pub fn write_report(path: &Path, report: &Value) -> Result<()> {
+    let partial = PartialOutput::new(path);
+    let file = File::create(partial.path())
+        .with_context(|| format!("Failed to create file: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, report).context("Failed to write report")?;
+    writeln!(writer)?;
+    writer.flush()?;
+    partial.finish()
+}
@@ -0,0 +1,184 @@
+use crate::exit::{self, Classify, Failure};
+use crate::input::Input;
+use crate::state::RunState;
+use anyhow::{Context, Result, bail};
+use clap::{Args as ClapArgs, Parser};
+use glob::Pattern;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{RecvTimeoutError, channel};
+use std::time::{Duration, Instant};
+
+/// How often pending files are checked for having settled
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Count new FASTA/FASTQ files as they appear in a directory
+#[derive(ClapArgs, Debug, Clone)]
+pub struct WatchArgs {
+    /// Directory to monitor
+    #[arg(long)]
+    pub dir: PathBuf,
+
+    /// File name pattern of inputs to count (glob)
+    #[arg(long, default_value = "*.f*q.gz")]
+    pub pattern: Pattern,
+
+    /// Seconds a file's size must stay unchanged before it is counted,
+    /// so files still being copied are not read half-written
+    #[arg(long, default_value = "5")]
+    pub settle: u64,
+
+    /// Also count matching files already in the directory
+    #[arg(long)]
+    pub existing: bool,
+
+    /// Counting options for each file, after `--` (e.g. `-- -o results/ -f csv`)
+    #[arg(last = true, value_name = "OPTIONS")]
+    pub count_args: Vec<String>,
+}
+
+/// A matching file waiting for its size to settle
+struct Pending {
+    size: u64,
+    since: Instant,
+}
+
+pub fn run(watch: &WatchArgs) -> Result<()> {
+    if !watch.dir.is_dir() {
+        return exit::usage(format!("Not a directory: {}", watch.dir.display()));
+    }
+
+    // Parse the counting options as a normal run; the watched directory only
+    // fills the required input slot, as files are processed one at a time
+    let args = crate::Args::parse_from(
+        std::iter::once("seqtable".into())
+            .chain(watch.count_args.iter().cloned())
+            .chain(std::iter::once(watch.dir.display().to_string())),
+    );
+    if args.sketch.is_some() {
+        return exit::usage("--sketch is not supported in watch mode".to_string());
+    }
+    if args.file_list.is_some()
+        || args.manifest.is_some()
+        || !args.output.is_empty()
+        || !args.sample_names.is_empty()
+        || args.checksum.is_some()
+        || args.summary.is_some()
+    {
+        return exit::usage(
+            "--file-list, --manifest, --output, --sample-name, --checksum and --summary are not supported in watch mode"
+                .to_string(),
+        );
+    }
+    if let Some(option) = crate::named_sidecars(&args).first() {
+        return exit::usage(format!("{}=FILE is not supported in watch mode", option));
+    }
+    crate::output::check_columns(&args).classify(Failure::Usage)?;
+    let shared = crate::setup(&args)?;
+
+    // Completions are recorded like a resumable run, so restarting the
+    // watcher never recounts a file
+    let mut state = RunState::open(&args.output_dir, true).classify(Failure::Input)?;
+
+    let (sender, events) = channel();
+    let mut watcher = notify::recommended_watcher(sender).context("Failed to start watcher")?;
+    watcher
+        .watch(&watch.dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch directory: {}", watch.dir.display()))
+        .classify(Failure::Input)?;
+
+    if !args.quiet {
+        eprintln!("🧬 seqtable v0.1.1 watch");
+        eprintln!(
+            "👀 Watching {} for {} (settle {}s)",
+            watch.dir.display(),
+            watch.pattern,
+            watch.settle
+        );
+        eprintln!("📂 Output directory: {}", args.output_dir.display());
+        eprintln!();
+    }
+
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    if watch.existing {
+        let entries = std::fs::read_dir(&watch.dir)
+            .with_context(|| format!("Failed to read directory: {}", watch.dir.display()))
+            .classify(Failure::Input)?;
+        for entry in entries {
+            let entry = entry
+                .with_context(|| format!("Failed to read directory: {}", watch.dir.display()))
+                .classify(Failure::Input)?;
+            queue(&mut pending, entry.path(), &watch.pattern);
+        }
+    }
+
+    let settle = Duration::from_secs(watch.settle);
+    loop {
+        match events.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    for path in event.paths {
+                        queue(&mut pending, path, &watch.pattern);
+                    }
+                }
+            }
+            Ok(Err(e)) => eprintln!("⚠️  Watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => bail!("Directory watcher stopped"),
+        }
+
+        for path in settled(&mut pending, settle) {
+            let input = Input::new(path);
+            let path = input.path();
+            let outputs = crate::resume_outputs(&state, &input, &args);
+            if state.is_complete(path, &outputs) {
+                continue;
+            }
+            // A bad file is reported without stopping the watcher
+            match crate::process_file(&input, &args, &shared, None, &mut Vec::new()) {
+                Ok((digests, file)) => state
+                    .mark_complete(path, &file.outputs, digests.as_ref())
+                    .classify(Failure::Output)?,
+                Err(e) => eprintln!("❌ {}: {:#}\n", path.display(), e),
+            }
+        }
+    }
+}
+
+/// Track `path` if its name matches; hidden files (partial copies) are ignored
+fn queue(pending: &mut HashMap<PathBuf, Pending>, path: PathBuf, pattern: &Pattern) {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return;
+    };
+    if name.starts_with('.') || !pattern.matches(name) || !path.is_file() {
+        return;
+    }
+    pending.entry(path).or_insert(Pending {
+        size: u64::MAX,
+        since: Instant::now(),
+    });
+}
+
+/// Remove and return pending files whose size has not changed for `settle`
+fn settled(pending: &mut HashMap<PathBuf, Pending>, settle: Duration) -> Vec<PathBuf> {
+    let mut ready = Vec::new();
+    pending.retain(|path, file| {
+        let Ok(size) = std::fs::metadata(path).map(|m| m.len()) else {
+            // Removed or renamed away before it settled
+            return false;
+        };
+        if size != file.size {
+            file.size = size;
+            file.since = Instant::now();
+            return true;
+        }
+        if file.since.elapsed() < settle {
+            return true;
+        }
+        ready.push(path.clone());
+        false
+    });
+    ready.sort();
+    ready
+}